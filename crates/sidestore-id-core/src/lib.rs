@@ -0,0 +1,9 @@
+//! Signed reviews and replies of SideStore ID: the signed data, its payload encodings and signature verification.
+//!
+//! The server signs reviews with this crate, and sources and mirrors can use it to verify them offline.
+//! The formats are described in `docs/review-signatures.md`.
+
+pub mod payload;
+pub mod reply;
+pub mod review;
+pub mod signature;
@@ -0,0 +1,179 @@
+//! Encoding of signed data into the exact bytes that get signed
+
+use derive_more::Display;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Compact JSON with the keys of every object sorted by their bytes, without a format version. This is the
+/// encoding of `serde_json::Value`, which all reviews were signed with before format versions were introduced.
+pub const LEGACY_FORMAT_VERSION: i32 = 1;
+/// JSON Canonicalization Scheme (RFC 8785) with a `format_version` field
+pub const CANONICAL_FORMAT_VERSION: i32 = 2;
+pub const FORMAT_VERSIONS: [i32; 2] = [LEGACY_FORMAT_VERSION, CANONICAL_FORMAT_VERSION];
+
+#[derive(Debug, Display)]
+pub enum PayloadError {
+    #[display(fmt = "Signed data must be a JSON object")]
+    NotAnObject,
+    #[display(fmt = "Unsupported number {}", _0)]
+    UnsupportedNumber(serde_json::Number),
+    #[display(fmt = "Unknown payload format version {}", _0)]
+    UnknownFormatVersion(i32),
+    #[display(fmt = "{}", _0)]
+    Json(serde_json::Error),
+}
+
+impl std::error::Error for PayloadError {}
+
+pub fn encode<T: Serialize>(data: &T, format_version: i32) -> Result<String, PayloadError> {
+    let mut data_object = match serde_json::to_value(data).map_err(PayloadError::Json)? {
+        Value::Object(object) => object,
+        _ => return Err(PayloadError::NotAnObject),
+    };
+
+    match format_version {
+        LEGACY_FORMAT_VERSION => {
+            let mut payload = String::new();
+            encode_sorted(&Value::Object(data_object), &mut payload);
+            Ok(payload)
+        },
+        CANONICAL_FORMAT_VERSION => {
+            data_object.insert("format_version".to_string(), Value::from(format_version));
+            let mut payload = String::new();
+            canonicalize_object(&data_object, &mut payload)?;
+            Ok(payload)
+        },
+        _ => Err(PayloadError::UnknownFormatVersion(format_version)),
+    }
+}
+
+/// Sorts the keys itself, so the payload doesn't depend on whether serde_json keeps the insertion order
+fn encode_sorted(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<(&String, &Value)> = object.iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push(':');
+                encode_sorted(value, out);
+            }
+            out.push('}');
+        },
+        Value::Array(array) => {
+            out.push('[');
+            for (i, item) in array.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                encode_sorted(item, out);
+            }
+            out.push(']');
+        },
+        _ => out.push_str(&value.to_string()),
+    }
+}
+
+fn canonicalize(value: &Value, out: &mut String) -> Result<(), PayloadError> {
+    match value {
+        Value::Object(object) => canonicalize_object(object, out)?,
+        Value::Array(array) => {
+            out.push('[');
+            for (i, item) in array.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                canonicalize(item, out)?;
+            }
+            out.push(']');
+        },
+        // Only integers in the range where they are exact IEEE 754 doubles have the same
+        // representation in JSON and ECMAScript, signed data never contains other numbers.
+        Value::Number(number) => match number.as_i64() {
+            Some(n) if n.unsigned_abs() <= (1 << 53) => out.push_str(&n.to_string()),
+            _ => return Err(PayloadError::UnsupportedNumber(number.clone())),
+        },
+        // serde_json escapes strings like RFC 8785 requires and writes literals in their shortest form
+        _ => out.push_str(&value.to_string()),
+    }
+    Ok(())
+}
+
+fn canonicalize_object(object: &Map<String, Value>, out: &mut String) -> Result<(), PayloadError> {
+    let mut entries: Vec<(&String, &Value)> = object.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+
+    out.push('{');
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str(&Value::from(key.as_str()).to_string());
+        out.push(':');
+        canonicalize(value, out)?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_canonical_encoding() {
+        let data = json!({
+            "numbers": [0, -1, 9007199254740992i64],
+            "string": "\u{20ac}$\u{000F}\u{000a}A'B\"\\\\\"/",
+            "literals": [null, true, false],
+            "\u{20ac}": "Euro Sign",
+            "\r": "Carriage Return",
+            "\u{fb33}": "Hebrew Letter Dalet With Dagesh",
+            "1": "One",
+            "\u{1f600}": "Emoji: Grinning Face",
+            "\u{0080}": "Control",
+            "\u{00f6}": "Latin Small Letter O With Diaeresis",
+        });
+
+        // Adapted from the examples in RFC 8785, sections 3.2.2 and 3.2.3
+        assert_eq!(
+            encode(&data, CANONICAL_FORMAT_VERSION).unwrap(),
+            "{\"\\r\":\"Carriage Return\",\"1\":\"One\",\"format_version\":2,\"literals\":[null,true,false],\
+             \"numbers\":[0,-1,9007199254740992],\"string\":\"\u{20ac}$\\u000f\\nA'B\\\"\\\\\\\\\\\"/\",\
+             \"\u{0080}\":\"Control\",\"\u{00f6}\":\"Latin Small Letter O With Diaeresis\",\
+             \"\u{20ac}\":\"Euro Sign\",\"\u{1f600}\":\"Emoji: Grinning Face\",\
+             \"\u{fb33}\":\"Hebrew Letter Dalet With Dagesh\"}"
+        );
+
+        assert!(encode(&json!({ "float": 1.5 }), CANONICAL_FORMAT_VERSION).is_err());
+        assert!(encode(&json!({ "large": 9007199254740993i64 }), CANONICAL_FORMAT_VERSION).is_err());
+        assert!(encode(&json!({}), 3).is_err());
+    }
+
+    #[test]
+    fn test_legacy_encoding() {
+        #[derive(Serialize)]
+        struct Data {
+            sequence_number: i32,
+            app_bundle_identifier: &'static str,
+            nested: Value,
+        }
+
+        let data = Data {
+            sequence_number: 1,
+            app_bundle_identifier: "com.SideStore.SideStore",
+            nested: json!({ "b": [1.5, null], "a": "\u{20ac}\n", "B": true }),
+        };
+        assert_eq!(
+            encode(&data, LEGACY_FORMAT_VERSION).unwrap(),
+            "{\"app_bundle_identifier\":\"com.SideStore.SideStore\",\"nested\":{\"B\":true,\"a\":\"\u{20ac}\\n\",\"b\":[1.5,null]},\"sequence_number\":1}"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+
+use crate::payload::{encode, PayloadError};
+use crate::review::AppReviewStatus;
+use crate::signature;
+
+/// Type of reply payloads, which keeps a reply's signature from being taken for a review's
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+pub enum ReplyPayloadType {
+    #[serde(rename = "review_reply")]
+    ReviewReply,
+}
+
+/// The data of a source maintainer's reply to a review, or of its deletion, that gets signed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppReviewReplySignatureData {
+    #[serde(rename = "type")]
+    pub payload_type: ReplyPayloadType,
+    /// `published` or `deleted`
+    pub status: AppReviewStatus,
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    /// Sequence number of the review that is answered
+    pub review_sequence_number: i32,
+    /// SideStore ID user id of the maintainer who wrote the reply
+    pub maintainer_id: String,
+    /// Not set for deletions
+    pub reply_body: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub key_id: String,
+}
+
+/// Reply data with its signature
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedReviewReply {
+    pub data: AppReviewReplySignatureData,
+    pub format_version: i32,
+    pub signature: String,
+}
+
+impl SignedReviewReply {
+    /// The exact payload that was signed
+    pub fn payload(&self) -> Result<String, PayloadError> {
+        encode(&self.data, self.format_version)
+    }
+
+    /// Check the signature with the key named by `data.key_id`
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<bool, PayloadError> {
+        Ok(signature::verify(&self.payload()?, &self.signature, verifying_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::pkcs8::DecodePublicKey;
+    use serde_json::Value;
+
+    use crate::review::SignedReview;
+    use super::*;
+
+    #[test]
+    fn test_reply_vectors() {
+        let vectors: Value = serde_json::from_str(include_str!("../../../docs/review-signature-vectors.json")).unwrap();
+        let verifying_key = VerifyingKey::from_public_key_pem(vectors["public_key"].as_str().unwrap()).unwrap();
+
+        for vector in vectors["reply_vectors"].as_array().unwrap() {
+            let mut reply: SignedReviewReply = serde_json::from_value(vector.clone()).unwrap();
+            assert_eq!(reply.payload().unwrap(), vector["payload"].as_str().unwrap(), "{}", vector["description"]);
+            assert!(reply.verify(&verifying_key).unwrap(), "{}", vector["description"]);
+
+            reply.data.review_sequence_number += 1;
+            assert!(!reply.verify(&verifying_key).unwrap(), "{}", vector["description"]);
+        }
+
+        // Reply payloads aren't review payloads
+        for vector in vectors["reply_vectors"].as_array().unwrap() {
+            assert!(serde_json::from_value::<SignedReview>(vector.clone()).is_err(), "{}", vector["description"]);
+        }
+        for vector in vectors["vectors"].as_array().unwrap() {
+            assert!(serde_json::from_value::<SignedReviewReply>(vector.clone()).is_err(), "{}", vector["description"]);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::str::FromStr;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use derive_more::Display;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::payload::{encode, PayloadError, LEGACY_FORMAT_VERSION};
+use crate::signature;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToResponse, utoipa::ToSchema))]
+pub enum AppReviewStatus {
+    #[serde(rename = "published")]
+    Published,
+    #[serde(rename = "deleted")]
+    Deleted,
+    /// Hidden by a moderator until it is restored
+    #[serde(rename = "hidden")]
+    Hidden,
+    /// Removed by a moderator
+    #[serde(rename = "removed")]
+    Removed,
+    /// Held for moderation by the fraud checks until a moderator publishes or removes it
+    #[serde(rename = "held")]
+    Held,
+}
+
+impl AppReviewStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppReviewStatus::Published => "published",
+            AppReviewStatus::Deleted => "deleted",
+            AppReviewStatus::Hidden => "hidden",
+            AppReviewStatus::Removed => "removed",
+            AppReviewStatus::Held => "held",
+        }
+    }
+}
+
+impl From<AppReviewStatus> for String {
+    fn from(status: AppReviewStatus) -> Self {
+        status.as_str().to_string()
+    }
+}
+
+#[derive(Debug, Display)]
+#[display(fmt = "Unknown review status {}", _0)]
+pub struct UnknownStatus(pub String);
+
+impl std::error::Error for UnknownStatus {}
+
+impl FromStr for AppReviewStatus {
+    type Err = UnknownStatus;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        [AppReviewStatus::Published, AppReviewStatus::Deleted, AppReviewStatus::Hidden, AppReviewStatus::Removed, AppReviewStatus::Held]
+            .into_iter()
+            .find(|known| known.as_str() == status)
+            .ok_or_else(|| UnknownStatus(status.to_string()))
+    }
+}
+
+/// The data of a review or deletion that gets signed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppReviewSignatureData {
+    pub sidestore_user_id: String,
+    pub status: AppReviewStatus,
+    pub sequence_number: i32,
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    pub version_number: Option<String>,
+    pub review_rating: Option<u8>,
+    /// Only set in signatures made before content commitments were introduced
+    pub review_title: Option<String>,
+    /// Only set in signatures made before content commitments were introduced
+    pub review_body: Option<String>,
+    /// Commitment to the title and body of a published review, see [`content_commitment`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_commitment: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Only missing in signatures made before key identifiers were introduced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+impl AppReviewSignatureData {
+    /// Whether the signed data commits to the given title and body
+    pub fn commits_to(&self, salt: &[u8], title: &str, body: &str) -> bool {
+        self.content_commitment.as_deref() == Some(content_commitment(salt, title, body).as_str())
+    }
+}
+
+/// Base64 encoded SHA-256 hash of the salt, followed by the title and the body, each prefixed with
+/// its length in bytes as a big-endian 64-bit integer
+pub fn content_commitment(salt: &[u8], title: &str, body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    for text in [title, body] {
+        hasher.update((text.len() as u64).to_be_bytes());
+        hasher.update(text.as_bytes());
+    }
+    BASE64.encode(hasher.finalize())
+}
+
+/// Review data with its signature, the format of review files and of the published test vectors
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedReview {
+    pub data: AppReviewSignatureData,
+    #[serde(default = "legacy_format_version")]
+    pub format_version: i32,
+    pub signature: String,
+}
+
+fn legacy_format_version() -> i32 {
+    LEGACY_FORMAT_VERSION
+}
+
+impl SignedReview {
+    /// The exact payload that was signed
+    pub fn payload(&self) -> Result<String, PayloadError> {
+        encode(&self.data, self.format_version)
+    }
+
+    /// Check the signature with the key named by `data.key_id`. Signatures without a key id were
+    /// made with the first review signing key.
+    pub fn verify(&self, verifying_key: &VerifyingKey) -> Result<bool, PayloadError> {
+        Ok(signature::verify(&self.payload()?, &self.signature, verifying_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::pkcs8::DecodePublicKey;
+    use serde_json::Value;
+
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        let vectors: Value = serde_json::from_str(include_str!("../../../docs/review-signature-vectors.json")).unwrap();
+        let verifying_key = VerifyingKey::from_public_key_pem(vectors["public_key"].as_str().unwrap()).unwrap();
+        assert_eq!(vectors["key_id"], signature::key_id(&verifying_key));
+
+        for vector in vectors["vectors"].as_array().unwrap() {
+            let mut review: SignedReview = serde_json::from_value(vector.clone()).unwrap();
+            assert_eq!(review.payload().unwrap(), vector["payload"].as_str().unwrap(), "{}", vector["description"]);
+            assert!(review.verify(&verifying_key).unwrap(), "{}", vector["description"]);
+
+            if let Some(content) = vector.get("content") {
+                let salt = BASE64.decode(content["salt"].as_str().unwrap()).unwrap();
+                let (title, body) = (content["title"].as_str().unwrap(), content["body"].as_str().unwrap());
+                assert!(review.data.commits_to(&salt, title, body), "{}", vector["description"]);
+                assert!(!review.data.commits_to(&salt, title, "Changed"), "{}", vector["description"]);
+            }
+
+            review.data.sequence_number += 1;
+            assert!(!review.verify(&verifying_key).unwrap(), "{}", vector["description"]);
+        }
+    }
+
+    #[test]
+    fn test_status() {
+        for status in [AppReviewStatus::Published, AppReviewStatus::Deleted, AppReviewStatus::Hidden, AppReviewStatus::Removed, AppReviewStatus::Held] {
+            assert_eq!(String::from(status).parse::<AppReviewStatus>().unwrap(), status);
+            assert_eq!(serde_json::to_value(status).unwrap(), status.as_str());
+        }
+        assert!("unknown".parse::<AppReviewStatus>().is_err());
+    }
+
+    #[test]
+    fn test_content_commitment() {
+        let commitment = content_commitment(b"salt", "Title", "Body");
+        assert_eq!(BASE64.decode(&commitment).unwrap().len(), 32);
+        assert_ne!(commitment, content_commitment(b"other salt", "Title", "Body"));
+        // The lengths keep the boundary between title and body from moving
+        assert_ne!(commitment, content_commitment(b"salt", "TitleB", "ody"));
+        assert_ne!(content_commitment(b"", "", "salt"), content_commitment(b"salt", "", ""));
+    }
+}
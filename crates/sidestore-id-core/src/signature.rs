@@ -0,0 +1,21 @@
+use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use sha2::{Digest, Sha256};
+
+/// Identifier of a review signing key: the hex encoded first 8 bytes of the SHA-256 hash of the raw public key
+pub fn key_id(verifying_key: &VerifyingKey) -> String {
+    Sha256::digest(verifying_key.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Sign a payload, the signature is base64 encoded
+pub fn sign(payload: &str, signing_key: &SigningKey) -> String {
+    let signature = signing_key.sign(payload.as_bytes());
+    base64_engine.encode(signature.to_bytes())
+}
+
+/// Check a base64 encoded signature of a payload
+pub fn verify(payload: &str, signature: &str, verifying_key: &VerifyingKey) -> bool {
+    base64_engine.decode(signature).ok()
+        .and_then(|signature| Signature::from_slice(&signature).ok())
+        .is_some_and(|signature| verifying_key.verify(payload.as_bytes(), &signature).is_ok())
+}
@@ -0,0 +1,154 @@
+//! Verify signed SideStore ID reviews offline
+//!
+//! Usage: `sidestore-review-verify <reviews.json> <public_key.pem>`
+//!
+//! The reviews file contains a signed review, or an array of them, in the format described in
+//! `docs/review-signatures.md`. Exits with 1 if a signature is invalid and with 2 if the files can't be read.
+
+use std::error::Error;
+use std::fs;
+use std::process::ExitCode;
+
+use ed25519_dalek::VerifyingKey;
+use ed25519_dalek::pkcs8::DecodePublicKey;
+use serde_json::Value;
+use sidestore_id_core::review::SignedReview;
+use sidestore_id_core::signature::key_id;
+
+fn main() -> ExitCode {
+    run(&std::env::args().collect::<Vec<_>>())
+}
+
+/// Verify the files named by the arguments and return the exit code
+fn run(args: &[String]) -> ExitCode {
+    let [_, reviews_path, public_key_path] = args else {
+        eprintln!("Usage: sidestore-review-verify <reviews.json> <public_key.pem>");
+        return ExitCode::from(2);
+    };
+
+    match verify_file(reviews_path, public_key_path) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Verify all reviews in the file, returns whether all signatures are valid
+fn verify_file(reviews_path: &str, public_key_path: &str) -> Result<bool, Box<dyn Error>> {
+    let verifying_key = VerifyingKey::from_public_key_pem(&fs::read_to_string(public_key_path)?)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let public_key_id = key_id(&verifying_key);
+
+    let reviews: Vec<SignedReview> = match serde_json::from_str(&fs::read_to_string(reviews_path)?)? {
+        Value::Array(reviews) => reviews.into_iter().map(serde_json::from_value).collect::<Result<_, _>>()?,
+        review => vec![serde_json::from_value(review)?],
+    };
+
+    let mut all_valid = true;
+    for review in &reviews {
+        let description = format!(
+            "{} {} #{} (format {})",
+            review.data.source_identifier, review.data.app_bundle_identifier, review.data.sequence_number, review.format_version
+        );
+
+        match &review.data.key_id {
+            Some(review_key_id) if *review_key_id != public_key_id => {
+                println!("INVALID {}: signed with key {}, not {}", description, review_key_id, public_key_id);
+                all_valid = false;
+            },
+            _ if review.verify(&verifying_key)? => println!("valid   {}", description),
+            _ => {
+                println!("INVALID {}", description);
+                all_valid = false;
+            },
+        }
+    }
+
+    Ok(all_valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    const VECTORS: &str = include_str!("../../../docs/review-signature-vectors.json");
+
+    /// Write the contents to a file in the temporary directory and return its path
+    fn write_temp(name: &str, contents: &str) -> String {
+        let path: PathBuf = std::env::temp_dir().join(format!("sidestore-review-verify-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn vectors() -> Value {
+        serde_json::from_str(VECTORS).unwrap()
+    }
+
+    fn args(reviews_path: &str, public_key_path: &str) -> Vec<String> {
+        ["sidestore-review-verify", reviews_path, public_key_path].map(String::from).to_vec()
+    }
+
+    #[test]
+    fn test_args() {
+        let public_key_path = write_temp("args.pem", vectors()["public_key"].as_str().unwrap());
+        assert_eq!(run(&["sidestore-review-verify".to_string()]), ExitCode::from(2));
+        assert_eq!(run(&args("reviews.json", &public_key_path)[..2]), ExitCode::from(2));
+        let mut too_many = args("reviews.json", &public_key_path);
+        too_many.push("extra".to_string());
+        assert_eq!(run(&too_many), ExitCode::from(2));
+    }
+
+    #[test]
+    fn test_vectors() {
+        let vectors = vectors();
+        let public_key_path = write_temp("vectors.pem", vectors["public_key"].as_str().unwrap());
+
+        // An array of reviews and a single review
+        let reviews_path = write_temp("vectors.json", &vectors["vectors"].to_string());
+        assert!(verify_file(&reviews_path, &public_key_path).unwrap());
+        assert_eq!(run(&args(&reviews_path, &public_key_path)), ExitCode::SUCCESS);
+        let review_path = write_temp("vector.json", &vectors["vectors"][0].to_string());
+        assert!(verify_file(&review_path, &public_key_path).unwrap());
+
+        // A changed review fails the whole file
+        let mut changed = vectors["vectors"].clone();
+        changed[1]["data"]["sequence_number"] = (changed[1]["data"]["sequence_number"].as_i64().unwrap() + 1).into();
+        let changed_path = write_temp("changed.json", &changed.to_string());
+        assert!(!verify_file(&changed_path, &public_key_path).unwrap());
+        assert_eq!(run(&args(&changed_path, &public_key_path)), ExitCode::FAILURE);
+
+        // So does a review signed with another key
+        let mut other_key = vectors["vectors"][0].clone();
+        other_key["data"]["key_id"] = "0000000000000000".into();
+        let other_key_path = write_temp("other-key.json", &other_key.to_string());
+        assert!(!verify_file(&other_key_path, &public_key_path).unwrap());
+        assert_eq!(run(&args(&other_key_path, &public_key_path)), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let vectors = vectors();
+        let public_key_path = write_temp("invalid.pem", vectors["public_key"].as_str().unwrap());
+        let reviews_path = write_temp("invalid-reviews.json", &vectors["vectors"].to_string());
+        let missing_path = std::env::temp_dir().join("sidestore-review-verify-missing.json").to_str().unwrap().to_string();
+
+        for (reviews_path, public_key_path) in [
+            (missing_path.as_str(), public_key_path.as_str()),
+            (reviews_path.as_str(), missing_path.as_str()),
+            // Keys have to be PEM encoded Ed25519 public keys
+            (reviews_path.as_str(), &write_temp("not-pem.pem", vectors["key_id"].as_str().unwrap())),
+            (reviews_path.as_str(), &write_temp("truncated.pem", &vectors["public_key"].as_str().unwrap()[..60])),
+            // Reviews have to be JSON in the format of the vectors
+            (&write_temp("not-json.json", "reviews"), public_key_path.as_str()),
+            (&write_temp("replies.json", &vectors["reply_vectors"].to_string()), public_key_path.as_str()),
+        ] {
+            assert!(verify_file(reviews_path, public_key_path).is_err(), "{} {}", reviews_path, public_key_path);
+            assert_eq!(run(&args(reviews_path, public_key_path)), ExitCode::from(2));
+        }
+    }
+}
@@ -0,0 +1,2 @@
+ALTER TABLE oauth_authorizations
+    DROP COLUMN last_used_at;
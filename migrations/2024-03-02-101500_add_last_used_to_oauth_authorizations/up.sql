@@ -0,0 +1,2 @@
+ALTER TABLE oauth_authorizations
+    ADD COLUMN last_used_at TIMESTAMP;
@@ -0,0 +1,3 @@
+ALTER TABLE oauth_authorizations
+    DROP COLUMN scope,
+    DROP COLUMN expires_at;
@@ -0,0 +1,7 @@
+-- Existing authorizations were all given for the profile scope
+ALTER TABLE oauth_authorizations
+    ADD COLUMN scope      VARCHAR(255) NOT NULL DEFAULT 'profile',
+    ADD COLUMN expires_at TIMESTAMP;
+
+ALTER TABLE oauth_authorizations
+    ALTER COLUMN scope DROP DEFAULT;
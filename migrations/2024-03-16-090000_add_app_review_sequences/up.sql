@@ -0,0 +1,13 @@
+CREATE TABLE app_review_sequences
+(
+    source_id            VARCHAR(255)    NOT NULL,
+    app_bundle_id        VARCHAR(255)    NOT NULL,
+    last_sequence_number INTEGER         NOT NULL,
+    PRIMARY KEY (source_id, app_bundle_id)
+);
+
+-- Continue after the highest sequence number that was already handed out for each app
+INSERT INTO app_review_sequences (source_id, app_bundle_id, last_sequence_number)
+SELECT source_id, app_bundle_id, MAX(sequence_number)
+FROM app_review_signatures
+GROUP BY source_id, app_bundle_id;
@@ -0,0 +1,3 @@
+DROP TABLE review_log_tree_heads;
+DROP TABLE review_log_entries;
+DROP FUNCTION reject_review_log_modification;
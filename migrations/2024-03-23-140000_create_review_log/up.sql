@@ -0,0 +1,40 @@
+CREATE TABLE review_log_entries
+(
+    source_id       VARCHAR(255)    NOT NULL,
+    app_bundle_id   VARCHAR(255)    NOT NULL,
+    leaf_index      BIGINT          NOT NULL,
+    leaf_hash       BYTEA           NOT NULL,
+    sequence_number INTEGER         NOT NULL,
+    status          VARCHAR(255)    NOT NULL,
+    signature       VARCHAR(255)    NOT NULL,
+    created_at      TIMESTAMP       NOT NULL,
+    PRIMARY KEY (source_id, app_bundle_id, leaf_index)
+);
+
+CREATE INDEX review_log_entries_leaf_hash_idx ON review_log_entries (source_id, app_bundle_id, leaf_hash);
+
+CREATE TABLE review_log_tree_heads
+(
+    source_id       VARCHAR(255)    NOT NULL,
+    app_bundle_id   VARCHAR(255)    NOT NULL,
+    tree_size       BIGINT          NOT NULL,
+    root_hash       BYTEA           NOT NULL,
+    signature       VARCHAR(255)    NOT NULL,
+    created_at      TIMESTAMP       NOT NULL,
+    PRIMARY KEY (source_id, app_bundle_id, tree_size)
+);
+
+-- The log is append-only, published entries and tree heads can never change
+CREATE FUNCTION reject_review_log_modification() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'The review log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER review_log_entries_append_only
+    BEFORE UPDATE OR DELETE ON review_log_entries
+    FOR EACH ROW EXECUTE FUNCTION reject_review_log_modification();
+
+CREATE TRIGGER review_log_tree_heads_append_only
+    BEFORE UPDATE OR DELETE ON review_log_tree_heads
+    FOR EACH ROW EXECUTE FUNCTION reject_review_log_modification();
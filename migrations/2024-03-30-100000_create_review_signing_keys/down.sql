@@ -0,0 +1,5 @@
+ALTER TABLE review_log_tree_heads DROP COLUMN key_id;
+ALTER TABLE review_log_entries DROP COLUMN key_id;
+ALTER TABLE app_review_signatures DROP COLUMN key_id;
+
+DROP TABLE review_signing_keys;
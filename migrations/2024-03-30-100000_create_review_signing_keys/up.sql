@@ -0,0 +1,12 @@
+CREATE TABLE review_signing_keys
+(
+    key_id      VARCHAR(255)    PRIMARY KEY,
+    public_key  TEXT            NOT NULL,
+    valid_from  TIMESTAMP       NOT NULL,
+    valid_until TIMESTAMP
+);
+
+-- Signatures made before key identifiers existed have no key id, they were made with the first key
+ALTER TABLE app_review_signatures ADD COLUMN key_id VARCHAR(255);
+ALTER TABLE review_log_entries ADD COLUMN key_id VARCHAR(255);
+ALTER TABLE review_log_tree_heads ADD COLUMN key_id VARCHAR(255);
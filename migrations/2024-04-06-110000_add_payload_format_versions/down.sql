@@ -0,0 +1,3 @@
+ALTER TABLE review_log_tree_heads DROP COLUMN format_version;
+ALTER TABLE review_log_entries DROP COLUMN format_version;
+ALTER TABLE app_review_signatures DROP COLUMN format_version;
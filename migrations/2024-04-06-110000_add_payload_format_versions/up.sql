@@ -0,0 +1,8 @@
+-- Everything signed so far used the legacy payload format
+ALTER TABLE app_review_signatures ADD COLUMN format_version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE review_log_entries ADD COLUMN format_version INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE review_log_tree_heads ADD COLUMN format_version INTEGER NOT NULL DEFAULT 1;
+
+ALTER TABLE app_review_signatures ALTER COLUMN format_version DROP DEFAULT;
+ALTER TABLE review_log_entries ALTER COLUMN format_version DROP DEFAULT;
+ALTER TABLE review_log_tree_heads ALTER COLUMN format_version DROP DEFAULT;
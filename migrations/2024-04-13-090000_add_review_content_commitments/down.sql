@@ -0,0 +1,2 @@
+ALTER TABLE app_review_signatures DROP COLUMN content_commitment;
+ALTER TABLE app_review_signatures DROP COLUMN content_salt;
@@ -0,0 +1,4 @@
+-- Salted SHA-256 commitment to the title and body of a published review, so it can be signed again
+-- without storing the text. Not set for deleted reviews and reviews signed before commitments existed.
+ALTER TABLE app_review_signatures ADD COLUMN content_salt VARCHAR(255);
+ALTER TABLE app_review_signatures ADD COLUMN content_commitment VARCHAR(255);
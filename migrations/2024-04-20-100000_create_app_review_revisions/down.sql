@@ -0,0 +1,3 @@
+DROP TABLE app_review_revisions;
+DROP FUNCTION reject_review_revision_modification;
+ALTER TABLE app_review_signatures DROP COLUMN pinned_version;
@@ -0,0 +1,43 @@
+-- Reviews kept per app version have the version they were written for, the user's review of the app as a whole has none
+ALTER TABLE app_review_signatures ADD COLUMN pinned_version VARCHAR(255);
+
+CREATE TABLE app_review_revisions
+(
+    id                  VARCHAR(255)    PRIMARY KEY,
+    review_id           VARCHAR(255)    NOT NULL REFERENCES app_review_signatures (id) ON DELETE NO ACTION,
+    revision_number     INTEGER         NOT NULL,
+    user_id             VARCHAR(255)    NOT NULL,
+    source_id           VARCHAR(255)    NOT NULL,
+    app_bundle_id       VARCHAR(255)    NOT NULL,
+    sequence_number     INTEGER         NOT NULL,
+    status              VARCHAR(255)    NOT NULL,
+    app_version         VARCHAR(255)    ,
+    review_rating       INTEGER         ,
+    content_salt        VARCHAR(255)    ,
+    content_commitment  VARCHAR(255)    ,
+    signature           VARCHAR(255)    NOT NULL,
+    key_id              VARCHAR(255)    ,
+    format_version      INTEGER         NOT NULL,
+    created_at          TIMESTAMP       NOT NULL,
+    UNIQUE (review_id, revision_number)
+);
+
+CREATE INDEX app_review_revisions_user_idx ON app_review_revisions (user_id, source_id, app_bundle_id);
+
+-- The current state of existing reviews is their first known revision
+INSERT INTO app_review_revisions
+SELECT gen_random_uuid()::TEXT, id, 1, user_id, source_id, app_bundle_id, sequence_number, status, app_version, review_rating,
+       content_salt, content_commitment, signature, key_id, format_version, updated_at
+FROM app_review_signatures
+WHERE signature IS NOT NULL;
+
+-- Revisions are a history, they can never change
+CREATE FUNCTION reject_review_revision_modification() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'Review revisions are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER app_review_revisions_immutable
+    BEFORE UPDATE OR DELETE ON app_review_revisions
+    FOR EACH ROW EXECUTE FUNCTION reject_review_revision_modification();
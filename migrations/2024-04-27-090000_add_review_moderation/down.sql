@@ -0,0 +1,4 @@
+DROP TABLE review_reports;
+DROP TABLE review_moderation_actions;
+DROP FUNCTION reject_review_moderation_action_modification;
+ALTER TABLE users DROP COLUMN is_moderator;
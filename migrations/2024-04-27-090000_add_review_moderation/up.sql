@@ -0,0 +1,41 @@
+ALTER TABLE users ADD COLUMN is_moderator BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Moderator actions on reviews, with the moderator's reason, as an audit log
+CREATE TABLE review_moderation_actions
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    review_id       VARCHAR(255)    NOT NULL REFERENCES app_review_signatures (id) ON DELETE NO ACTION,
+    moderator_id    VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE NO ACTION,
+    action          VARCHAR(255)    NOT NULL,
+    reason          TEXT            NOT NULL,
+    previous_status VARCHAR(255)    NOT NULL,
+    status          VARCHAR(255)    NOT NULL,
+    created_at      TIMESTAMP       NOT NULL
+);
+
+CREATE INDEX review_moderation_actions_review_idx ON review_moderation_actions (review_id, created_at);
+
+CREATE FUNCTION reject_review_moderation_action_modification() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'Review moderation actions are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER review_moderation_actions_immutable
+    BEFORE UPDATE OR DELETE ON review_moderation_actions
+    FOR EACH ROW EXECUTE FUNCTION reject_review_moderation_action_modification();
+
+-- Reports of reviews by users, open until a moderator acts on the review
+CREATE TABLE review_reports
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    review_id       VARCHAR(255)    NOT NULL REFERENCES app_review_signatures (id) ON DELETE NO ACTION,
+    reporter_id     VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE NO ACTION,
+    reason          VARCHAR(255)    NOT NULL,
+    comment         TEXT            ,
+    created_at      TIMESTAMP       NOT NULL,
+    resolved_by     VARCHAR(255)    REFERENCES review_moderation_actions (id) ON DELETE NO ACTION,
+    UNIQUE (review_id, reporter_id)
+);
+
+CREATE INDEX review_reports_open_idx ON review_reports (created_at) WHERE resolved_by IS NULL;
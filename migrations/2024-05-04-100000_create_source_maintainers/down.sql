@@ -0,0 +1,2 @@
+DROP TABLE source_maintainers;
+DROP TABLE sources;
@@ -0,0 +1,25 @@
+-- Sources that have verified maintainers, with the settings the maintainers chose
+CREATE TABLE sources
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    accepts_reviews BOOLEAN         NOT NULL DEFAULT TRUE,
+    created_at      TIMESTAMP       NOT NULL,
+    updated_at      TIMESTAMP       NOT NULL
+);
+
+-- Users' claims to maintain a source, verified with a challenge served at the source's URL
+CREATE TABLE source_maintainers
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    source_id       VARCHAR(255)    NOT NULL,
+    user_id         VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    source_url      VARCHAR(2048)   NOT NULL,
+    challenge       VARCHAR(255)    NOT NULL,
+    method          VARCHAR(255)    ,
+    verified_at     TIMESTAMP       ,
+    created_at      TIMESTAMP       NOT NULL,
+    updated_at      TIMESTAMP       NOT NULL,
+    UNIQUE (source_id, user_id)
+);
+
+CREATE INDEX source_maintainers_user_idx ON source_maintainers (user_id);
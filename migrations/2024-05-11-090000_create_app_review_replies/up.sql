@@ -0,0 +1,20 @@
+-- Replies of source maintainers to reviews, one per review. Deleted replies are kept with their signed deletion.
+CREATE TABLE app_review_replies
+(
+    id                      VARCHAR(255)    PRIMARY KEY,
+    review_id               VARCHAR(255)    NOT NULL UNIQUE REFERENCES app_review_signatures (id) ON DELETE NO ACTION,
+    source_id               VARCHAR(255)    NOT NULL,
+    app_bundle_id           VARCHAR(255)    NOT NULL,
+    review_sequence_number  INTEGER         NOT NULL,
+    maintainer_id           VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE NO ACTION,
+    status                  VARCHAR(255)    NOT NULL,
+    body                    TEXT            ,
+    signature               VARCHAR(255)    NOT NULL,
+    key_id                  VARCHAR(255)    NOT NULL,
+    format_version          INTEGER         NOT NULL,
+    -- Number of the change among the changes of replies to reviews of the app, for snapshot deltas
+    change_number           BIGINT          NOT NULL,
+    created_at              TIMESTAMP       NOT NULL,
+    updated_at              TIMESTAMP       NOT NULL,
+    UNIQUE (source_id, app_bundle_id, change_number)
+);
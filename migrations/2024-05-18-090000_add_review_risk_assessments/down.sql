@@ -0,0 +1,2 @@
+DROP TABLE review_risk_assessments;
+ALTER TABLE app_review_signatures DROP COLUMN risk_score;
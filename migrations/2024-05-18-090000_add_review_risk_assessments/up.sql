@@ -0,0 +1,24 @@
+-- Risk score of the latest submission of a review, not set for reviews submitted before the fraud checks
+ALTER TABLE app_review_signatures ADD COLUMN risk_score INTEGER;
+
+-- Fraud check of every review submission, including rejected ones, which have no review
+CREATE TABLE review_risk_assessments
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    review_id       VARCHAR(255)    REFERENCES app_review_signatures (id) ON DELETE NO ACTION,
+    user_id         VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    source_id       VARCHAR(255)    NOT NULL,
+    app_bundle_id   VARCHAR(255)    NOT NULL,
+    -- Keyed hash of the client's network, so submissions from the same network can be grouped
+    network_hash    VARCHAR(255)    ,
+    review_rating   INTEGER         NOT NULL,
+    risk_score      INTEGER         NOT NULL,
+    -- JSON object with the score of every signal that saw a risk
+    signals         TEXT            NOT NULL,
+    decision        VARCHAR(255)    NOT NULL,
+    created_at      TIMESTAMP       NOT NULL
+);
+
+CREATE INDEX review_risk_assessments_app_idx ON review_risk_assessments (source_id, app_bundle_id, created_at);
+CREATE INDEX review_risk_assessments_network_idx ON review_risk_assessments (network_hash, created_at);
+CREATE INDEX review_risk_assessments_review_idx ON review_risk_assessments (review_id, created_at);
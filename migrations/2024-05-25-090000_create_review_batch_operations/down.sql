@@ -0,0 +1,2 @@
+DROP TABLE review_batch_operations;
+ALTER TABLE app_review_signatures DROP COLUMN client_changed_at;
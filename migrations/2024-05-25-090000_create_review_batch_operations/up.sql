@@ -0,0 +1,15 @@
+-- Time the user made the latest change on their device, for changes queued offline and applied in a batch
+ALTER TABLE app_review_signatures ADD COLUMN client_changed_at TIMESTAMP;
+
+-- Applied batch operations by their idempotency key, so replayed operations aren't applied twice
+CREATE TABLE review_batch_operations
+(
+    user_id         VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    idempotency_key VARCHAR(255)    NOT NULL,
+    -- SHA-256 of the operation, replays with the same key have to send the same operation
+    request_hash    VARCHAR(255)    NOT NULL,
+    -- JSON of the signed review that was returned for the operation
+    response        TEXT            NOT NULL,
+    created_at      TIMESTAMP       NOT NULL,
+    PRIMARY KEY (user_id, idempotency_key)
+);
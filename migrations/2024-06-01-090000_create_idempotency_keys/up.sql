@@ -0,0 +1,19 @@
+-- Responses of mutating requests by the Idempotency-Key header they were sent with, so retries can be answered
+-- without applying the request again
+CREATE TABLE idempotency_keys
+(
+    -- Authenticated user of the request, the nil UUID for unauthenticated requests
+    user_id         VARCHAR(255)    NOT NULL,
+    idempotency_key VARCHAR(255)    NOT NULL,
+    -- SHA-256 of the request's method, path, query and body
+    fingerprint     VARCHAR(255)    NOT NULL,
+    -- The response, not set while the first request is processed
+    status_code     INTEGER         ,
+    -- JSON list of the response's header names and values
+    headers         TEXT            ,
+    body            BYTEA           ,
+    created_at      TIMESTAMP       NOT NULL,
+    PRIMARY KEY (user_id, idempotency_key)
+);
+
+CREATE INDEX idempotency_keys_created_at_idx ON idempotency_keys (created_at);
@@ -0,0 +1,44 @@
+-- Endpoints that are sent events, either of a source's maintainers or of an OAuth client from the OAuth configuration
+CREATE TABLE webhook_subscriptions
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    source_id       VARCHAR(255)    ,
+    client_id       VARCHAR(255)    UNIQUE,
+    -- Maintainer who subscribed, not set for subscriptions of clients
+    created_by      VARCHAR(255)    REFERENCES users (id) ON DELETE SET NULL,
+    url             VARCHAR(2048)   NOT NULL,
+    -- Key of the HMAC signatures of the deliveries
+    secret          VARCHAR(255)    NOT NULL,
+    -- Space separated list of the event types that are sent
+    events          VARCHAR(1024)   NOT NULL,
+    created_at      TIMESTAMP       NOT NULL,
+    updated_at      TIMESTAMP       NOT NULL,
+    CHECK ((source_id IS NULL) <> (client_id IS NULL))
+);
+
+CREATE INDEX webhook_subscriptions_source_idx ON webhook_subscriptions (source_id);
+
+-- Queue and log of the events sent to the subscriptions. Deliveries are retried with exponential backoff until
+-- they succeed or run out of attempts and are dead-lettered.
+CREATE TABLE webhook_deliveries
+(
+    id                      VARCHAR(255)    PRIMARY KEY,
+    subscription_id         VARCHAR(255)    NOT NULL REFERENCES webhook_subscriptions (id) ON DELETE CASCADE,
+    event_id                VARCHAR(255)    NOT NULL,
+    event_type              VARCHAR(255)    NOT NULL,
+    -- JSON of the event, sent as the body of every attempt
+    payload                 TEXT            NOT NULL,
+    -- pending, delivered or dead
+    status                  VARCHAR(255)    NOT NULL,
+    attempts                INTEGER         NOT NULL DEFAULT 0,
+    next_attempt_at         TIMESTAMP       NOT NULL,
+    last_attempt_at         TIMESTAMP       ,
+    last_response_status    INTEGER         ,
+    last_error              TEXT            ,
+    created_at              TIMESTAMP       NOT NULL,
+    delivered_at            TIMESTAMP
+);
+
+CREATE INDEX webhook_deliveries_due_idx ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX webhook_deliveries_subscription_idx ON webhook_deliveries (subscription_id, created_at);
+CREATE INDEX webhook_deliveries_created_at_idx ON webhook_deliveries (created_at);
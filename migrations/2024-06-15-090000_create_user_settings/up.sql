@@ -0,0 +1,15 @@
+-- Settings that are synced across a user's devices, as JSON values by namespace and key
+CREATE TABLE user_settings
+(
+    user_id         VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- Dotted name of the app or feature that owns the setting, like `sidestore.app`
+    namespace       VARCHAR(64)     NOT NULL,
+    key             VARCHAR(128)    NOT NULL,
+    -- JSON of the value
+    value           TEXT            NOT NULL,
+    -- Counts the changes of the value, starting at 1
+    version         BIGINT          NOT NULL,
+    created_at      TIMESTAMP       NOT NULL,
+    updated_at      TIMESTAMP       NOT NULL,
+    PRIMARY KEY (user_id, namespace, key)
+);
@@ -0,0 +1,9 @@
+DELETE FROM user_settings WHERE deleted_at IS NOT NULL;
+
+ALTER TABLE user_settings
+    DROP COLUMN clock,
+    DROP COLUMN change_seq,
+    DROP COLUMN deleted_at;
+
+DROP TABLE user_settings_sync;
+DROP TABLE user_devices;
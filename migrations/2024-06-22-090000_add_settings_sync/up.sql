@@ -0,0 +1,46 @@
+-- Devices of a user that sync settings
+CREATE TABLE user_devices
+(
+    id              VARCHAR(255)    PRIMARY KEY,
+    user_id         VARCHAR(255)    NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    name            VARCHAR(255)    NOT NULL,
+    created_at      TIMESTAMP       NOT NULL,
+    last_synced_at  TIMESTAMP
+);
+
+CREATE INDEX user_devices_user_idx ON user_devices (user_id);
+
+-- Counter of the changes of a user's settings. Changes lock the row, so they are numbered in the order in which
+-- they are committed.
+CREATE TABLE user_settings_sync
+(
+    user_id             VARCHAR(255)    PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    -- Number of the latest change
+    change_seq          BIGINT          NOT NULL,
+    -- Latest change whose deletion was forgotten, devices that synced before have to sync everything again
+    purged_change_seq   BIGINT          NOT NULL DEFAULT 0
+);
+
+-- Deleted settings are kept for a while, so devices learn about the deletion
+ALTER TABLE user_settings
+    ADD COLUMN clock        TEXT        NOT NULL DEFAULT '{}',
+    ADD COLUMN change_seq   BIGINT      NOT NULL DEFAULT 0,
+    ADD COLUMN deleted_at   TIMESTAMP;
+
+UPDATE user_settings
+SET change_seq = numbered.change_seq
+FROM (
+    SELECT user_id, namespace, key, ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY namespace, key) AS change_seq
+    FROM user_settings
+) AS numbered
+WHERE user_settings.user_id = numbered.user_id
+  AND user_settings.namespace = numbered.namespace
+  AND user_settings.key = numbered.key;
+
+INSERT INTO user_settings_sync (user_id, change_seq)
+SELECT user_id, MAX(change_seq)
+FROM user_settings
+GROUP BY user_id;
+
+CREATE INDEX user_settings_changes_idx ON user_settings (user_id, change_seq);
+CREATE INDEX user_settings_deleted_at_idx ON user_settings (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,41 @@
+-- Hashes of the complete subtrees of every review log, so proofs don't have to rebuild the tree from all leaves.
+-- Level 0 are the leaf hashes, the subtree at `level` and `subtree_index` covers the 2^level leaves from
+-- subtree_index * 2^level on.
+CREATE TABLE review_log_subtrees
+(
+    source_id       VARCHAR(255)    NOT NULL,
+    app_bundle_id   VARCHAR(255)    NOT NULL,
+    level           INTEGER         NOT NULL,
+    subtree_index   BIGINT          NOT NULL,
+    hash            BYTEA           NOT NULL,
+    PRIMARY KEY (source_id, app_bundle_id, level, subtree_index)
+);
+
+CREATE TRIGGER review_log_subtrees_append_only
+    BEFORE UPDATE OR DELETE ON review_log_subtrees
+    FOR EACH ROW EXECUTE FUNCTION reject_review_log_modification();
+
+-- Hash the subtrees of the existing logs level by level, as in RFC 9162: SHA-256(0x01 || left || right)
+DO $$
+DECLARE
+    current_level INTEGER := 0;
+    inserted      INTEGER;
+BEGIN
+    INSERT INTO review_log_subtrees (source_id, app_bundle_id, level, subtree_index, hash)
+    SELECT source_id, app_bundle_id, 0, leaf_index, leaf_hash FROM review_log_entries;
+
+    LOOP
+        INSERT INTO review_log_subtrees (source_id, app_bundle_id, level, subtree_index, hash)
+        SELECT l.source_id, l.app_bundle_id, current_level + 1, l.subtree_index / 2, sha256('\x01'::BYTEA || l.hash || r.hash)
+        FROM review_log_subtrees l
+        JOIN review_log_subtrees r ON r.source_id = l.source_id
+            AND r.app_bundle_id = l.app_bundle_id
+            AND r.level = l.level
+            AND r.subtree_index = l.subtree_index + 1
+        WHERE l.level = current_level AND l.subtree_index % 2 = 0;
+
+        GET DIAGNOSTICS inserted = ROW_COUNT;
+        EXIT WHEN inserted = 0;
+        current_level := current_level + 1;
+    END LOOP;
+END $$;
@@ -0,0 +1,10 @@
+-- Claims of a source are only verified at the origin its first maintainer verified it at
+ALTER TABLE sources ADD COLUMN origin VARCHAR(255);
+
+UPDATE sources SET origin = (
+    SELECT substring(source_maintainers.source_url from '^[a-z]+://[^/?#]+')
+    FROM source_maintainers
+    WHERE source_maintainers.source_id = sources.id AND source_maintainers.verified_at IS NOT NULL
+    ORDER BY source_maintainers.verified_at ASC
+    LIMIT 1
+);
@@ -0,0 +1,5 @@
+DELETE FROM idempotency_keys;
+
+ALTER TABLE idempotency_keys RENAME COLUMN response TO body;
+ALTER TABLE idempotency_keys ADD COLUMN headers TEXT;
+ALTER TABLE idempotency_keys RENAME COLUMN owner TO user_id;
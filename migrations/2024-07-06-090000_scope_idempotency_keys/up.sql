@@ -0,0 +1,9 @@
+-- Unauthenticated requests shared their keys and responses were kept in plaintext, the kept responses can't be
+-- told apart or encrypted afterwards
+DELETE FROM idempotency_keys;
+
+-- The authenticated user of the request, or a keyed hash of the client's credentials or network
+ALTER TABLE idempotency_keys RENAME COLUMN user_id TO owner;
+-- The response's headers and body, encrypted with a key derived from the request
+ALTER TABLE idempotency_keys DROP COLUMN headers;
+ALTER TABLE idempotency_keys RENAME COLUMN body TO response;
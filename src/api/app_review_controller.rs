@@ -1,30 +1,34 @@
 use std::path::Path;
-use actix_web::{web, http::header::{ContentDisposition, DispositionType, DispositionParam}, HttpResponse};
+use actix_web::{web, http::header::{self, ContentDisposition, DispositionType, DispositionParam, EntityTag, Header}, HttpRequest, HttpResponse};
 use actix_files::NamedFile;
-use log::debug;
 
 use crate::{
     AppState,
     errors::{ServiceError, ErrorResponse},
     constants::REVIEWS_SIGNING_PUBLIC_KEY_NAME,
-    util::review_signing::sign_review,
     middlewares::auth::JwtMiddleware,
-    db::models::{app_review::AppReviewSignature, DbModel},
 };
 use crate::api::utils::enforce_scope;
 use crate::auth::JwtTokenScope;
+use crate::services::{app_review_service, review_batch_service, review_snapshot_service, review_stats_service};
+use crate::services::review_snapshot_service::SnapshotResult;
 
 use super::models::app_reviews::{
     AppReviewSignatureRequest, AppReviewSignatureResponse,
-    AppReviewSignatureData, AppReviewStatus,
     AppReviewDeletionRequest,
-    UserAppReview, UserAppReviewList,
+    AppReviewRevisionList, AppReviewRevisionsQuery,
+    ReviewPublicKey, ReviewPublicKeyList,
+    ReviewVerificationRequest, ReviewVerificationResponse,
+    UserAppReview, UserAppReviewPage, UserAppReviewsQuery,
 };
+use super::models::review_batch::{AppReviewBatchRequest, AppReviewBatchResponse, AppReviewBatchStatus};
+use super::models::review_snapshot::{ReviewSnapshot, ReviewSnapshotQuery};
+use super::models::review_stats::{ReviewStats, ReviewStatsQuery};
 
 
 /// Get public signing key
 /// 
-/// Get public key to verify app review signatures, as an X.509 PEM certificate.
+/// Get the active public key to verify app review signatures, as an X.509 PEM certificate.
 #[utoipa::path(
     get,
     path = "/api/reviews/public_key",
@@ -50,113 +54,237 @@ pub async fn get_public_key(data: web::Data<AppState>) -> Result<NamedFile, Serv
 }
 
 
+/// Get the history of review signing keys
+///
+/// Lists all public keys that signed reviews, including retired keys, which still verify the reviews they
+/// signed. Signatures without a key id were made with the first key.
+#[utoipa::path(
+    get,
+    path = "/api/reviews/keys",
+    responses(
+        (status = 200, response = ReviewPublicKeyList),
+    ),
+)]
+pub async fn get_keys(data: web::Data<AppState>) -> HttpResponse {
+    let keys: Vec<ReviewPublicKey> = data.review_keys.keys()
+        .iter()
+        .map(|key| ReviewPublicKey {
+            key_id: key.key_id.clone(),
+            public_key: key.public_key_pem.clone(),
+            valid_from: key.valid_from.timestamp(),
+            valid_until: key.valid_until.map(|t| t.timestamp()),
+        })
+        .collect();
+
+    HttpResponse::Ok().json(keys)
+}
+
+
 /// Sign an app review
 /// 
 /// Save the app review metadata only and generate a signature for the app review that can be verified with the public key.
+/// Submissions are checked for fraud. Risky reviews are signed with the status `held` until a moderator publishes them,
+/// or rejected.
 #[utoipa::path(
     post,
     path = "/api/reviews/sign",
     request_body = AppReviewSignatureRequest,
     responses(
         (status = 200, response = AppReviewSignatureResponse),
+        (status = 400, response = ErrorResponse),
         (status = 401, description = "User authentication failed."),
         (status = 500, response = ErrorResponse),
     )
 )]
-pub async fn sign(body: web::Json<AppReviewSignatureRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+pub async fn sign(req: HttpRequest, body: web::Json<AppReviewSignatureRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
     enforce_scope(&jwt, JwtTokenScope::Full)?;
 
-    let mut review = match AppReviewSignature::find_by_user_id(
-        &jwt.user_id, &body.source_identifier, &body.app_bundle_id, &mut data.db.get().unwrap()
-    ) {
-        Ok(mut review) => {
-            debug!("User already has a review: {:?}. Update it.", review);
-            review.review_rating = Some(body.review_rating.into());
-            review.app_version = Some(body.version_number.to_string());
-            review
-                .update(&mut data.db.get().unwrap())
-                .map_err(|e| {
-                    debug!("Error updating review: {}", e);
-                    ServiceError::InternalServerError { error_message: "Failed to update review".to_string() }
-                })?
-        },
-        Err(_) => {
-            debug!("User didn't leave a review for this app yet. Create one.");
-
-            let mut review = AppReviewSignature::new(&body, &jwt.user_id);
-            review.sequence_number = AppReviewSignature::find_latest_sequence_number(
-                &body.source_identifier, &body.app_bundle_id, &mut data.db.get().unwrap()
-            ).map_err(|e| {
-                debug!("Error finding latest sequence number: {}", e);
-                ServiceError::InternalServerError { error_message: "Failed to find latest sequence number".to_string() }
-            })?;
-
-            debug!("Latest sequence number: {}", review.sequence_number);
-            review
-                .insert(&mut data.db.get().unwrap())
-                .map_err(|e| {
-                    debug!("Error inserting review: {}", e);
-                    ServiceError::InternalServerError { error_message: "Failed to save review".to_string() }
-                })?
-        }
-    };
+    let client_ip = req.peer_addr().map(|addr| addr.ip());
+    let response = app_review_service::sign(&body, &jwt.user_id, client_ip, &data.db, &data.env, &data.review_keys, &data.review_risk)?;
+    data.review_stats.invalidate(&body.source_identifier, &body.app_bundle_id);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+
+/// Sign and delete app reviews in a batch
+///
+/// Applies review changes a client queued while offline, in their order, in one transaction, and returns a result
+/// for every operation. Every operation has an idempotency key, replayed operations return their first result
+/// instead of being applied again. Operations the user made on the device before the review's latest change are
+/// `stale` and aren't applied. An operation that fails doesn't stop the others.
+#[utoipa::path(
+    post,
+    path = "/api/reviews/batch",
+    request_body = AppReviewBatchRequest,
+    responses(
+        (status = 200, response = AppReviewBatchResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn batch(req: HttpRequest, body: web::Json<AppReviewBatchRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
 
-    let review_data = AppReviewSignatureData::from_signature_request(&body, &review);
-    let signature = sign_review(&review_data, &data.review_signing_key)
-        .map_err(|e| {
-            debug!("Error signing review: {}", e);
-            ServiceError::InternalServerError { error_message: "Failed to sign review".to_string() }
-        })?;
-
-    review.signature = Some(signature.clone().to_string());
-    match review.update(&mut data.db.get().unwrap()) {
-        Ok(_) => Ok(HttpResponse::Ok().json(AppReviewSignatureResponse {
-            sequence_number: review.sequence_number,
-            review_date: review_data.updated_at,
-            signature
-        })),
-        Err(e) => {
-            debug!("Error updating review: {}", e);
-            return Err(ServiceError::InternalServerError { error_message: "Failed to update review".to_string() })
+    let client_ip = req.peer_addr().map(|addr| addr.ip());
+    let response = review_batch_service::apply(&body, &jwt.user_id, client_ip, &data.db, &data.env, &data.review_keys, &data.review_risk)?;
+    for (operation, result) in body.operations.iter().zip(&response.results) {
+        if result.status == AppReviewBatchStatus::Applied {
+            let (source_id, app_bundle_id) = review_batch_service::app(&operation.action);
+            data.review_stats.invalidate(source_id, app_bundle_id);
         }
     }
+    Ok(HttpResponse::Ok().json(response))
+}
+
+
+/// Verify a signed app review
+///
+/// Check that a review payload was signed by one of the review signing keys, and whether the review is still the
+/// user's current review of the app. Doesn't need authentication, but is rate limited per client.
+#[utoipa::path(
+    post,
+    path = "/api/reviews/verify",
+    request_body = ReviewVerificationRequest,
+    responses(
+        (status = 200, response = ReviewVerificationResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 429, description = "Too many requests."),
+    )
+)]
+pub async fn verify(body: web::Json<ReviewVerificationRequest>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let response = app_review_service::verify(&body, &data.db, &data.review_keys)?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+
+/// Get rating statistics of an app
+///
+/// Count, average and number of reviews per star of an app's published reviews, in total and per app version.
+/// Reviews held for moderation only count if `include_held` is set. The statistics are signed with the review
+/// signing key, so sources can embed them.
+#[utoipa::path(
+    get,
+    path = "/api/reviews/stats",
+    params(ReviewStatsQuery),
+    responses(
+        (status = 200, response = ReviewStats),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_stats(query: web::Query<ReviewStatsQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let stats = review_stats_service::stats(
+        &query.source_identifier, &query.app_bundle_id, query.include_held, &data.db, &data.env, &data.review_keys, &data.review_stats
+    )?;
+    Ok(HttpResponse::Ok().json(stats))
 }
 
 
+/// Get a signed snapshot of a source's reviews
+///
+/// Lists the current published reviews of all apps of a source with their signatures and the latest signed tree
+/// heads of the apps' review logs. With the `cursor` of a previous snapshot as `since`, only the reviews that were
+/// published, changed or deleted since are included. Supports `If-None-Match` with the returned `ETag`.
+#[utoipa::path(
+    get,
+    path = "/api/reviews/snapshot",
+    params(ReviewSnapshotQuery),
+    responses(
+        (status = 200, response = ReviewSnapshot),
+        (status = 304, description = "The snapshot didn't change."),
+        (status = 400, response = ErrorResponse),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_snapshot(req: HttpRequest, query: web::Query<ReviewSnapshotQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let if_none_match = header::IfNoneMatch::parse(&req).ok();
+    let is_current = |etag: &str| match &if_none_match {
+        Some(header::IfNoneMatch::Any) => true,
+        Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&EntityTag::new_weak(etag.to_string()))),
+        None => false,
+    };
+
+    match review_snapshot_service::snapshot(&query.source_identifier, query.since.as_deref(), is_current, &data.db, &data.env, &data.review_keys)? {
+        SnapshotResult::NotModified { etag } => Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(EntityTag::new_weak(etag)))
+            .finish()),
+        SnapshotResult::Modified { snapshot, etag } => Ok(HttpResponse::Ok()
+            .insert_header(header::ETag(EntityTag::new_weak(etag)))
+            .json(snapshot)),
+    }
+}
+
 
-/// Get all app reviews for the current user
+/// Get the current user's app reviews
+///
+/// Lists the user's reviews a page at a time, newest first unless another order is requested. Pass the returned
+/// `next_cursor` as `cursor` with the same filters and order to get the next page.
 #[utoipa::path(
     get,
     path = "/api/reviews",
+    params(UserAppReviewsQuery),
     responses(
-        (status = 200, response = UserAppReviewList),
+        (status = 200, response = UserAppReviewPage),
+        (status = 400, response = ErrorResponse),
         (status = 401, description = "User authentication failed."),
         (status = 500, response = ErrorResponse),
     )
 )]
-pub async fn get(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+pub async fn get(query: web::Query<UserAppReviewsQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
     enforce_scope(&jwt, JwtTokenScope::Full)?;
 
-    let reviews: Vec<UserAppReview> = AppReviewSignature::find_all_by_user_id(&jwt.user_id, &mut data.db.get().unwrap())
-        .map_err(|e| {
-            log::debug!("Failed to get app reviews for user {:?}: {:?}", jwt.user_id, e);
-            ServiceError::NotFound { error_message: "Couldn't find any reviews for the requesting user".to_string() }
-        })?
-        .iter()
-        .map(|r| UserAppReview::from(r))
-        .collect();
+    let page = app_review_service::reviews(&jwt.user_id, &query, &data.db)?;
+    Ok(HttpResponse::Ok().json(page))
+}
+
+
+/// Get one of the current user's app reviews
+#[utoipa::path(
+    get,
+    path = "/api/reviews/{id}",
+    params(("id" = String, Path, description = "Id of the review")),
+    responses(
+        (status = 200, response = UserAppReview),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn get_by_id(path: web::Path<String>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let review = app_review_service::review(&jwt.user_id, &path, &data.db)?;
+    Ok(HttpResponse::Ok().json(review))
+}
+
+
+/// Get the revision history of the current user's reviews of an app
+///
+/// Lists every signed state of the user's review of the app and of the reviews kept per app version, oldest first.
+#[utoipa::path(
+    get,
+    path = "/api/reviews/revisions",
+    params(AppReviewRevisionsQuery),
+    responses(
+        (status = 200, response = AppReviewRevisionList),
+        (status = 401, description = "User authentication failed."),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_revisions(query: web::Query<AppReviewRevisionsQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
 
-    Ok(HttpResponse::Ok().json(reviews))
+    let revisions = app_review_service::revisions(&jwt.user_id, &query.source_identifier, &query.app_bundle_id, &data.db)?;
+    Ok(HttpResponse::Ok().json(revisions))
 }
 
 
 /// Delete the app review for an app
 #[utoipa::path(
     delete,
-    path = "/api/reviews",
+    path = "/api/reviews/delete",
     request_body = AppReviewDeletionRequest,
     responses(
-        (status = 200, response = UserAppReviewList),
+        (status = 200, response = AppReviewSignatureResponse),
         (status = 401, description = "User authentication failed."),
         (status = 500, response = ErrorResponse),
     )
@@ -164,34 +292,7 @@ pub async fn get(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpRe
 pub async fn delete(body: web::Json<AppReviewDeletionRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
     enforce_scope(&jwt, JwtTokenScope::Full)?;
 
-    let mut review = AppReviewSignature::find_by_user_id(
-        &jwt.user_id, &body.source_identifier, &body.app_bundle_id, &mut data.db.get().unwrap()
-    ).map_err(|_| ServiceError::NotFound { error_message: "You didn't review this app yet.".to_string() })?;
-
-    debug!("Found the user's review: {:?}. Delete it.", review);
-    review.status = AppReviewStatus::Deleted.into();
-    review.review_rating = None;
-    review.app_version = None;
-
-    let mut review = review.update(&mut data.db.get().unwrap())
-        .map_err(|e| {
-            debug!("Error updating review: {}", e);
-            ServiceError::InternalServerError { error_message: "Failed to update review".to_string() }
-        })?;
-
-    let review_data = AppReviewSignatureData::from_deletion_request(&body, &review);
-    let signature = sign_review(&review_data, &data.review_signing_key)?;
-    review.signature = Some(signature.clone().to_string());
-
-    match review.update(&mut data.db.get().unwrap()) {
-        Ok(_) => Ok(HttpResponse::Ok().json(AppReviewSignatureResponse {
-            sequence_number: review.sequence_number,
-            review_date: review_data.updated_at,
-            signature
-        })),
-        Err(e) => {
-            debug!("Error updating review: {}", e);
-            return Err(ServiceError::InternalServerError { error_message: "Failed to update review".to_string() })
-        }
-    }
+    let response = app_review_service::delete(&body, &jwt.user_id, &data.db, &data.env, &data.review_keys)?;
+    data.review_stats.invalidate(&body.source_identifier, &body.app_bundle_id);
+    Ok(HttpResponse::Ok().json(response))
 }
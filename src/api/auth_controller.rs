@@ -23,7 +23,7 @@ use super::models::MessageResponse;
 )]
 pub async fn signup(body: web::Json<SignupRequest>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
     let user_dto = UserDTO { email: body.email.clone(), password: body.password.clone(), username: None };
-    let (user, access_token, refresh_token) = auth_service::login(user_dto, &data.db, &data.env)?;
+    let (user, access_token, refresh_token) = auth_service::signup(user_dto, &data.db, &data.env)?;
     let (access_token_cookie, refresh_token_cookie) = get_auth_cookies(&access_token, &refresh_token);
 
     Ok(HttpResponse::Ok()
@@ -122,7 +122,7 @@ pub async fn me(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpRes
     Ok(HttpResponse::Ok().json(user))
 }
 
-fn get_auth_cookies<'a>(access_token: &'a str, refresh_token: &'a str) -> (Cookie<'a>, Cookie<'a>) {
+pub(crate) fn get_auth_cookies<'a>(access_token: &'a str, refresh_token: &'a str) -> (Cookie<'a>, Cookie<'a>) {
     let access_token_cookie = Cookie::build("access_token", access_token)
         .path("/")
         .secure(true)
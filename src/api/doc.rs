@@ -1,9 +1,25 @@
 use utoipa::OpenApi;
 use crate::api::auth_controller as Authentication;
+use crate::api::oauth2_controller as OAuth2;
 use crate::api::app_review_controller as AppReviews;
+use crate::api::review_log_controller as ReviewLog;
+use crate::api::moderation_controller as Moderation;
+use crate::api::source_controller as Sources;
+use crate::api::settings_controller as Settings;
+use crate::api::webhook_controller as Webhooks;
 use crate::api::ping_controller as Health;
 use crate::api::models::auth as AuthModels;
+use crate::api::models::oauth2 as OAuth2Models;
 use crate::api::models::app_reviews as AppReviewModels;
+use crate::api::models::review_batch as ReviewBatchModels;
+use crate::api::models::review_log as ReviewLogModels;
+use crate::api::models::moderation as ModerationModels;
+use crate::api::models::review_replies as ReviewReplyModels;
+use crate::api::models::review_snapshot as ReviewSnapshotModels;
+use crate::api::models::review_stats as ReviewStatsModels;
+use crate::api::models::settings as SettingModels;
+use crate::api::models::sources as SourceModels;
+use crate::api::models::webhooks as WebhookModels;
 use crate::db::models as DBModels;
 use crate::errors::ErrorResponse;
 
@@ -17,10 +33,61 @@ use crate::errors::ErrorResponse;
         Authentication::logout,
         Authentication::me,
 
+        OAuth2::metadata,
+        OAuth2::jwks,
+        OAuth2::get_authorizations,
+        OAuth2::delete_authorization,
+
         AppReviews::get_public_key,
+        AppReviews::get_keys,
         AppReviews::sign,
+        AppReviews::batch,
+        AppReviews::verify,
+        AppReviews::get_stats,
+        AppReviews::get_snapshot,
         AppReviews::get,
+        AppReviews::get_by_id,
+        AppReviews::get_revisions,
         AppReviews::delete,
+
+        ReviewLog::get_tree_head,
+        ReviewLog::get_entries,
+        ReviewLog::get_inclusion_proof,
+        ReviewLog::get_consistency_proof,
+
+        Moderation::report,
+        Moderation::get_reports,
+        Moderation::get_held,
+        Moderation::moderate,
+        Moderation::get_actions,
+
+        Sources::claim,
+        Sources::get_claims,
+        Sources::verify_claim,
+        Sources::get_reviews,
+        Sources::reply,
+        Sources::delete_reply,
+        Sources::get_stats,
+        Sources::get_settings,
+        Sources::put_settings,
+
+        Settings::get_all,
+        Settings::get_namespace,
+        Settings::patch_namespace,
+        Settings::delete_namespace,
+        Settings::get,
+        Settings::put,
+        Settings::delete,
+        Settings::sync,
+        Settings::get_devices,
+        Settings::register_device,
+        Settings::delete_device,
+
+        Webhooks::create,
+        Webhooks::get,
+        Webhooks::delete,
+        Webhooks::get_deliveries,
+        Webhooks::get_client_deliveries,
         
         Health::ping,
     ),
@@ -29,10 +96,80 @@ use crate::errors::ErrorResponse;
             AuthModels::LoginRequest,
             AuthModels::SignupRequest,
 
+            OAuth2Models::OAuthClientAuthorization,
+            OAuth2Models::OAuthAuthorizationServerMetadata,
+            OAuth2Models::JsonWebKey,
+
             AppReviewModels::AppReviewSignatureRequest,
             AppReviewModels::AppReviewDeletionRequest,
             AppReviewModels::UserAppReview,
+            AppReviewModels::UserAppReviewSort,
             AppReviewModels::AppReviewStatus,
+            AppReviewModels::ReviewPublicKey,
+            AppReviewModels::AppReviewPayloadSignature,
+            AppReviewModels::ReviewVerificationRequest,
+            AppReviewModels::ReviewVerificationStatus,
+            AppReviewModels::AppReviewRevision,
+
+            ReviewBatchModels::AppReviewBatchRequest,
+            ReviewBatchModels::AppReviewBatchOperation,
+            ReviewBatchModels::AppReviewBatchAction,
+            ReviewBatchModels::AppReviewBatchStatus,
+            ReviewBatchModels::AppReviewBatchResult,
+
+            ReviewStatsModels::ReviewStatsData,
+            ReviewStatsModels::RatingStats,
+            ReviewStatsModels::VersionRatingStats,
+
+            ReviewSnapshotModels::ReviewSnapshotData,
+            ReviewSnapshotModels::AppReviewSnapshot,
+            ReviewSnapshotModels::ReviewSnapshotEntry,
+
+            ReviewLogModels::ReviewLogTreeHead,
+            ReviewLogModels::ReviewLogEntry,
+            ReviewLogModels::ReviewLogInclusionProof,
+            ReviewLogModels::ReviewLogConsistencyProof,
+
+            ModerationModels::ReviewReportRequest,
+            ModerationModels::ReviewReportReason,
+            ModerationModels::ReviewReportResponse,
+            ModerationModels::ModerationAction,
+            ModerationModels::ModerationActionRequest,
+            ModerationModels::ModerationActionRecord,
+            ModerationModels::HeldReview,
+            AppReviewModels::AppReviewSignatureResponse,
+
+            SourceModels::SourceClaimRequest,
+            SourceModels::SourceVerificationRequest,
+            SourceModels::SourceVerificationMethod,
+            SourceModels::SourceClaim,
+            SourceModels::SourceReview,
+            SourceModels::SourceSettings,
+
+            ReviewReplyModels::ReviewReplyRequest,
+            ReviewReplyModels::ReviewReplyDeletionRequest,
+            ReviewReplyModels::ReviewReply,
+
+            SettingModels::Setting,
+            SettingModels::SettingRequest,
+            SettingModels::SettingsPatchRequest,
+            SettingModels::SettingChange,
+            SettingModels::SettingsSyncRequest,
+            SettingModels::SyncedSetting,
+            SettingModels::SettingConflict,
+            SettingModels::SettingsSyncResponse,
+            SettingModels::DeviceRequest,
+            SettingModels::Device,
+
+            WebhookModels::WebhookRequest,
+            WebhookModels::Webhook,
+            WebhookModels::WebhookEventType,
+            WebhookModels::WebhookEvent,
+            WebhookModels::WebhookEventData,
+            WebhookModels::WebhookReviewData,
+            WebhookModels::WebhookAccountData,
+            WebhookModels::WebhookDeliveryStatus,
+            WebhookModels::WebhookDeliveryRecord,
         ),
         responses(
             ErrorResponse,
@@ -40,11 +177,52 @@ use crate::errors::ErrorResponse;
             AuthModels::LoginResponse,
             DBModels::user::User,
 
+            OAuth2Models::OAuthClientAuthorizationList,
+            OAuth2Models::OAuthAuthorizationServerMetadata,
+            OAuth2Models::JsonWebKeySet,
+
             AppReviewModels::AppReviewSignatureResponse,
-            AppReviewModels::UserAppReviewList,
+            AppReviewModels::UserAppReviewPage,
+            AppReviewModels::AppReviewRevisionList,
             AppReviewModels::UserAppReview,
             AppReviewModels::AppReviewStatus,
+            AppReviewModels::ReviewPublicKeyList,
+            AppReviewModels::ReviewVerificationResponse,
+            ReviewBatchModels::AppReviewBatchResponse,
+            ReviewStatsModels::ReviewStats,
+            ReviewSnapshotModels::ReviewSnapshot,
+
+            ReviewLogModels::ReviewLogTreeHead,
+            ReviewLogModels::ReviewLogEntryList,
+            ReviewLogModels::ReviewLogInclusionProof,
+            ReviewLogModels::ReviewLogConsistencyProof,
+
+            ModerationModels::ReviewReportResponse,
+            ModerationModels::ReviewReportList,
+            ModerationModels::ModerationActionResponse,
+            ModerationModels::ModerationActionRecordList,
+            ModerationModels::HeldReview,
+            ModerationModels::HeldReviewList,
+
+            SourceModels::SourceClaim,
+            SourceModels::SourceClaimList,
+            SourceModels::SourceReviewList,
+            SourceModels::SourceStatsList,
+            SourceModels::SourceSettings,
+
+            ReviewReplyModels::ReviewReply,
+
+            SettingModels::Setting,
+            SettingModels::SettingList,
+            SettingModels::SettingsSyncResponse,
+            SettingModels::Device,
+            SettingModels::DeviceList,
+
+            WebhookModels::Webhook,
+            WebhookModels::WebhookList,
+            WebhookModels::WebhookDeliveryList,
         ),
     ),
 )]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
 pub struct ApiDoc;
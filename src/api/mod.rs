@@ -1,8 +1,17 @@
 pub mod auth_controller;
 pub mod oauth2_controller;
 pub mod app_review_controller;
+pub mod moderation_controller;
+pub mod review_log_controller;
+pub mod source_controller;
+pub mod settings_controller;
+pub mod webhook_controller;
 pub mod ping_controller;
 pub mod models;
 pub mod oauth2;
 pub mod doc;
 pub mod utils;
+#[cfg(feature = "consent-ui")]
+pub mod ui;
+#[cfg(feature = "consent-ui")]
+pub mod ui_controller;
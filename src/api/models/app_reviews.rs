@@ -1,7 +1,12 @@
 use serde::{Deserialize, Serialize};
-use utoipa::{ToResponse, ToSchema};
+use utoipa::{IntoParams, ToResponse, ToSchema};
 
 use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::app_review_revision::AppReviewRevision as AppReviewRevisionRow;
+
+use super::review_replies::ReviewReply;
+
+pub use sidestore_id_core::review::{AppReviewSignatureData, AppReviewStatus};
 
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -12,85 +17,175 @@ pub struct AppReviewSignatureRequest {
     pub review_rating: u8,
     pub review_title: String,
     pub review_body: String,
+    /// Keep the review for `version_number` next to the user's other reviews of the app, instead of
+    /// replacing the user's review of the app
+    #[serde(default)]
+    pub per_version: bool,
 }
 
 
-#[derive(Serialize, Deserialize, ToResponse)]
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
 pub struct AppReviewSignatureResponse {
     pub sequence_number: i32,
     pub review_date: i64,
     pub signature: String,
+    /// Identifier of the review signing key that made the signature
+    pub key_id: String,
+    /// Payload format of `signature`
+    pub format_version: i32,
+    /// `held` if the fraud checks held the review for moderation
+    pub status: AppReviewStatus,
+    /// Signatures of the review in all payload formats the service currently signs in
+    pub signatures: Vec<AppReviewPayloadSignature>,
+    /// Base64 encoded salt of the content commitment, not set for deletions. Keep it with the title
+    /// and body to show that the signature covers them.
+    pub content_salt: Option<String>,
+    /// Signed commitment to the title and body of the review, not set for deletions
+    pub content_commitment: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
-pub struct AppReviewDeletionRequest {
-    pub source_identifier: String,
-    pub app_bundle_id: String,
+pub struct AppReviewPayloadSignature {
+    pub format_version: i32,
+    pub signature: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, ToResponse, ToSchema)]
-pub enum AppReviewStatus {
-    #[serde(rename = "published")]
-    Published,
+/// A public key that signs or signed reviews
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewPublicKey {
+    pub key_id: String,
+    /// PEM encoded Ed25519 public key
+    pub public_key: String,
+    /// Time from which new reviews were signed with this key
+    pub valid_from: i64,
+    /// Time until which new reviews were signed with this key, not set for the active key
+    pub valid_until: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct ReviewPublicKeyList(Vec<ReviewPublicKey>);
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewVerificationRequest {
+    /// The exact payload that was signed
+    pub payload: String,
+    pub signature: String,
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+pub struct ReviewVerificationResponse {
+    /// Whether the signature was made by a review signing key over the payload
+    pub valid: bool,
+    /// Identifier of the key that made the signature, if it is valid
+    pub key_id: Option<String>,
+    /// Payload format of the signed review, if the signature is valid
+    pub format_version: Option<i32>,
+    /// State of the signed review compared to the user's current review of the app, if the signature is valid
+    pub status: Option<ReviewVerificationStatus>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, ToSchema)]
+pub enum ReviewVerificationStatus {
+    /// The payload is the latest state of the user's review
+    #[serde(rename = "current")]
+    Current,
+    /// The user changed the review since
+    #[serde(rename = "superseded")]
+    Superseded,
+    /// The user deleted the review since
     #[serde(rename = "deleted")]
-    Deleted
+    Deleted,
+    /// A moderator hid the review since
+    #[serde(rename = "hidden")]
+    Hidden,
+    /// A moderator removed the review since
+    #[serde(rename = "removed")]
+    Removed,
+    /// The review is held for moderation since
+    #[serde(rename = "held")]
+    Held,
 }
 
-impl From<AppReviewStatus> for String {
-    fn from(status: AppReviewStatus) -> Self {
-        match status {
-            AppReviewStatus::Published => "published".to_string(),
-            AppReviewStatus::Deleted => "deleted".to_string()
-        }
-    }
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AppReviewDeletionRequest {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    /// Delete the review kept for this app version instead of the user's review of the app
+    #[serde(default)]
+    pub version_number: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct AppReviewSignatureData {
-    pub sidestore_user_id: String,
-    pub status: AppReviewStatus,
-    pub sequence_number: i32,
+#[derive(Deserialize, IntoParams)]
+pub struct AppReviewRevisionsQuery {
     pub source_identifier: String,
-    pub app_bundle_identifier: String,
+    pub app_bundle_id: String,
+}
+
+/// A signed state of one of the user's reviews
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AppReviewRevision {
+    /// Id of the review the revision belongs to
+    pub review_id: String,
+    pub revision_number: i32,
+    pub sequence_number: i32,
+    pub status: String,
     pub version_number: Option<String>,
-    pub review_rating: Option<u8>,
-    pub review_title: Option<String>,
-    pub review_body: Option<String>,
-    pub created_at: i64,
-    pub updated_at: i64,
-}
-
-impl AppReviewSignatureData {
-    pub fn from_signature_request(request: &AppReviewSignatureRequest, review: &AppReviewSignature) -> AppReviewSignatureData {
-        AppReviewSignatureData {
-            sidestore_user_id: review.user_id.to_string(),
-            status: AppReviewStatus::Published,
-            sequence_number: review.sequence_number.clone(),
-            source_identifier: review.source_id.clone(),
-            app_bundle_identifier: review.app_bundle_id.clone(),
-            version_number: Some(request.version_number.clone()),
-            review_rating: Some(request.review_rating),
-            review_title: Some(request.review_title.clone()),
-            review_body: Some(request.review_body.clone()),
-            created_at: review.created_at.timestamp(),
-            updated_at: review.updated_at.timestamp()
+    /// App version the review is kept for, not set for the user's review of the app
+    pub pinned_version: Option<String>,
+    pub review_rating: Option<i32>,
+    pub content_salt: Option<String>,
+    pub content_commitment: Option<String>,
+    pub signature: String,
+    pub key_id: Option<String>,
+    pub format_version: i32,
+    /// Time the revision was signed
+    pub date: i64,
+}
+
+impl AppReviewRevision {
+    pub fn new(revision: AppReviewRevisionRow, pinned_version: Option<String>) -> Self {
+        AppReviewRevision {
+            review_id: revision.review_id,
+            revision_number: revision.revision_number,
+            sequence_number: revision.sequence_number,
+            status: revision.status,
+            version_number: revision.app_version,
+            pinned_version,
+            review_rating: revision.review_rating,
+            content_salt: revision.content_salt,
+            content_commitment: revision.content_commitment,
+            signature: revision.signature,
+            key_id: revision.key_id,
+            format_version: revision.format_version,
+            date: revision.created_at.timestamp(),
         }
     }
+}
 
-    pub fn from_deletion_request(request: &AppReviewDeletionRequest, review: &AppReviewSignature) -> AppReviewSignatureData {
-        AppReviewSignatureData {
-            sidestore_user_id: review.user_id.to_string(),
-            status: AppReviewStatus::Deleted,
-            sequence_number: review.sequence_number.clone(),
-            source_identifier: review.source_id.clone(),
-            app_bundle_identifier: request.app_bundle_id.clone(),
-            version_number: None,
-            review_rating: None,
-            review_title: None,
-            review_body: None,
-            created_at: review.created_at.timestamp(),
-            updated_at: review.updated_at.timestamp()
-        }
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct AppReviewRevisionList(Vec<AppReviewRevision>);
+
+/// Review data as it is signed, built from the stored review alone so it can be signed again. The title
+/// and body are only signed through the content commitment.
+pub fn review_data(review: &AppReviewSignature) -> AppReviewSignatureData {
+    let status = review.status();
+    let published = status == AppReviewStatus::Published;
+    AppReviewSignatureData {
+        sidestore_user_id: review.user_id.to_string(),
+        status,
+        sequence_number: review.sequence_number,
+        source_identifier: review.source_id.clone(),
+        app_bundle_identifier: review.app_bundle_id.clone(),
+        version_number: review.app_version.clone().filter(|_| published),
+        review_rating: review.review_rating.and_then(|rating| u8::try_from(rating).ok()).filter(|_| published),
+        review_title: None,
+        review_body: None,
+        content_commitment: review.content_commitment.clone().filter(|_| published),
+        created_at: review.created_at.timestamp(),
+        updated_at: review.updated_at.timestamp(),
+        key_id: review.key_id.clone(),
     }
 }
 
@@ -104,25 +199,67 @@ pub struct UserAppReview {
     pub app_bundle_identifier: String,
     pub version_number: Option<String>,
     pub review_rating: Option<i32>,
+    /// App version the review is kept for, not set for the user's review of the app
+    pub pinned_version: Option<String>,
     pub date: i64,
     pub signature: Option<String>,
+    /// Signed reply of the source's maintainers
+    pub reply: Option<ReviewReply>,
 }
 
-impl From<&AppReviewSignature> for UserAppReview {
-    fn from(value: &AppReviewSignature) -> Self {
+impl UserAppReview {
+    pub fn new(value: &AppReviewSignature, reply: Option<ReviewReply>) -> Self {
         UserAppReview {
             id: value.id.clone(),
-            status: AppReviewStatus::Published,  // TODO: Make this dynamic
+            status: value.status(),
             source_identifier: value.source_id.clone(),
             app_bundle_identifier: value.app_bundle_id.clone(),
             version_number: value.app_version.clone(),
             review_rating: value.review_rating,
+            pinned_version: value.pinned_version.clone(),
             date: value.updated_at.timestamp(),
-            signature: value.signature.clone()
+            signature: value.signature.clone(),
+            reply,
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, ToResponse)]
+/// Order of a user's reviews. Reviews with the same rating are ordered by date.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserAppReviewSort {
+    /// Latest changed first
+    #[default]
+    Newest,
+    Oldest,
+    HighestRated,
+    LowestRated,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct UserAppReviewsQuery {
+    pub source_identifier: Option<String>,
+    pub app_bundle_id: Option<String>,
+    pub status: Option<AppReviewStatus>,
+    /// Only reviews changed at or after this Unix timestamp
+    pub from: Option<i64>,
+    /// Only reviews changed before this Unix timestamp
+    pub until: Option<i64>,
+    #[serde(default)]
+    pub sort: UserAppReviewSort,
+    /// `nextCursor` of the previous page, with the same filters and order
+    pub cursor: Option<String>,
+    /// Reviews per page, 50 by default and at most 200
+    pub limit: Option<i64>,
+}
+
+/// A page of the user's reviews
+#[derive(Debug, Serialize, Deserialize, ToSchema, ToResponse)]
 #[serde(rename_all = "camelCase")]
-pub struct UserAppReviewList(Vec<UserAppReview>);
+pub struct UserAppReviewPage {
+    pub reviews: Vec<UserAppReview>,
+    /// Number of the user's reviews that match the filters, on all pages
+    pub total_count: i64,
+    /// Cursor of the next page, not set on the last page
+    pub next_cursor: Option<String>,
+}
@@ -1,5 +1,6 @@
 pub mod auth;
 pub mod app_reviews;
+pub mod moderation;
 pub mod oauth2;
 
 use serde::{Deserialize, Serialize};
@@ -9,3 +10,11 @@ use serde::{Deserialize, Serialize};
 pub struct MessageResponse {
     pub message: String,
 }
+pub mod review_batch;
+pub mod review_log;
+pub mod review_replies;
+pub mod review_snapshot;
+pub mod review_stats;
+pub mod settings;
+pub mod sources;
+pub mod webhooks;
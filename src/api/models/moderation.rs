@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::review_moderation::{ReviewModerationAction, ReviewReport};
+use crate::db::models::review_risk_assessment::ReviewRiskAssessment;
+
+use super::app_reviews::{AppReviewSignatureResponse, AppReviewStatus};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewReportReason {
+    Spam,
+    Abuse,
+    /// Fake or bought ratings
+    Fraud,
+    OffTopic,
+    Other,
+}
+
+impl From<ReviewReportReason> for String {
+    fn from(reason: ReviewReportReason) -> Self {
+        match reason {
+            ReviewReportReason::Spam => "spam",
+            ReviewReportReason::Abuse => "abuse",
+            ReviewReportReason::Fraud => "fraud",
+            ReviewReportReason::OffTopic => "off_topic",
+            ReviewReportReason::Other => "other",
+        }.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewReportRequest {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    /// Sequence number of the reported review
+    pub sequence_number: i32,
+    pub reason: ReviewReportReason,
+    /// At most 1000 characters
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewReportResponse {
+    pub id: String,
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    pub sequence_number: i32,
+    /// Current status of the reported review
+    pub review_status: AppReviewStatus,
+    pub reason: String,
+    pub comment: Option<String>,
+    pub created_at: i64,
+    /// Id of the moderation action that resolved the report
+    pub resolved_by: Option<String>,
+}
+
+impl ReviewReportResponse {
+    pub fn new(report: ReviewReport, review: &AppReviewSignature) -> Self {
+        ReviewReportResponse {
+            id: report.id,
+            source_identifier: review.source_id.clone(),
+            app_bundle_id: review.app_bundle_id.clone(),
+            sequence_number: review.sequence_number,
+            review_status: review.status(),
+            reason: report.reason,
+            comment: report.comment,
+            created_at: report.created_at.timestamp(),
+            resolved_by: report.resolved_by,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct ReviewReportList(Vec<ReviewReportResponse>);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    /// Hide a published review until it is restored
+    Hide,
+    /// Publish a hidden, removed or held review
+    Restore,
+    /// Remove a published, hidden or held review
+    Remove,
+}
+
+impl From<ModerationAction> for String {
+    fn from(action: ModerationAction) -> Self {
+        match action {
+            ModerationAction::Hide => "hide",
+            ModerationAction::Restore => "restore",
+            ModerationAction::Remove => "remove",
+        }.to_string()
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ModerationActionRequest {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    pub sequence_number: i32,
+    pub action: ModerationAction,
+    /// Reason for the audit log, at most 1000 characters
+    pub reason: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ModerationActionsQuery {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    pub sequence_number: i32,
+}
+
+/// Audit record of a moderator's action on a review
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ModerationActionRecord {
+    pub id: String,
+    pub review_id: String,
+    pub moderator_id: String,
+    pub action: String,
+    pub reason: String,
+    pub previous_status: String,
+    pub status: String,
+    pub created_at: i64,
+}
+
+impl From<ReviewModerationAction> for ModerationActionRecord {
+    fn from(action: ReviewModerationAction) -> Self {
+        ModerationActionRecord {
+            id: action.id,
+            review_id: action.review_id,
+            moderator_id: action.moderator_id,
+            action: action.action,
+            reason: action.reason,
+            previous_status: action.previous_status,
+            status: action.status,
+            created_at: action.created_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct ModerationActionRecordList(Vec<ModerationActionRecord>);
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ModerationActionResponse {
+    pub action: ModerationActionRecord,
+    /// The review signed again with its new status
+    pub review: AppReviewSignatureResponse,
+    /// Number of open reports of the review that the action resolved
+    pub resolved_reports: usize,
+}
+
+
+/// What happens to a review submission, decided by its risk score
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RiskDecision {
+    Publish,
+    /// Sign the review with the status `held` until a moderator publishes or removes it
+    Hold,
+    /// Don't store the review
+    Reject,
+}
+
+impl From<RiskDecision> for String {
+    fn from(decision: RiskDecision) -> Self {
+        match decision {
+            RiskDecision::Publish => "publish",
+            RiskDecision::Hold => "hold",
+            RiskDecision::Reject => "reject",
+        }.to_string()
+    }
+}
+
+/// A review that the fraud checks held for moderation
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct HeldReview {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    pub sequence_number: i32,
+    pub version_number: Option<String>,
+    pub review_rating: Option<i32>,
+    pub risk_score: Option<i32>,
+    /// Score of every signal that saw a risk in the latest submission
+    pub signals: BTreeMap<String, i32>,
+    /// Time of the latest submission
+    pub date: i64,
+}
+
+impl HeldReview {
+    pub fn new(review: &AppReviewSignature, assessment: Option<&ReviewRiskAssessment>) -> Self {
+        HeldReview {
+            source_identifier: review.source_id.clone(),
+            app_bundle_id: review.app_bundle_id.clone(),
+            sequence_number: review.sequence_number,
+            version_number: review.app_version.clone(),
+            review_rating: review.review_rating,
+            risk_score: review.risk_score,
+            signals: assessment
+                .and_then(|assessment| serde_json::from_str(&assessment.signals).ok())
+                .unwrap_or_default(),
+            date: review.updated_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct HeldReviewList(Vec<HeldReview>);
@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{ToResponse, ToSchema};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -17,3 +17,53 @@ pub enum OAuth2AuthorizationResult {
 pub struct OAuthRedirectResponse {
     pub redirect_url: String
 }
+
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, ToResponse)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthClientAuthorization {
+    pub client_id: String,
+    pub client_name: Option<String>,
+    pub scopes: Vec<String>,
+    pub first_used_at: i64,
+    pub last_used_at: Option<i64>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct OAuthClientAuthorizationList(Vec<OAuthClientAuthorization>);
+
+
+/// Authorization server metadata as described in RFC 8414
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct OAuthAuthorizationServerMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    /// Keys that sign the access tokens
+    pub jwks_uri: String,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub scopes_supported: Vec<String>,
+}
+
+
+/// Public key of a JSON Web Key Set (RFC 7517), an Ed25519 key as described in RFC 8037
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct JsonWebKey {
+    pub kty: String,
+    pub crv: String,
+    /// Base64url encoded public key
+    pub x: String,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct JsonWebKeySet {
+    pub keys: Vec<JsonWebKey>,
+}
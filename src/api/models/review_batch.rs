@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{ToResponse, ToSchema};
+
+use super::app_reviews::{AppReviewDeletionRequest, AppReviewSignatureRequest, AppReviewSignatureResponse};
+
+
+/// Review changes a client queued while offline, applied in their order
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AppReviewBatchRequest {
+    /// At most 100 operations
+    pub operations: Vec<AppReviewBatchOperation>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AppReviewBatchOperation {
+    /// Key the client made up for the operation. An operation is applied once, replays with the same key
+    /// return the first result.
+    pub idempotency_key: String,
+    /// Unix timestamp of the time the user made the change on the device
+    pub client_timestamp: i64,
+    #[serde(flatten)]
+    pub action: AppReviewBatchAction,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AppReviewBatchAction {
+    Sign(AppReviewSignatureRequest),
+    Delete(AppReviewDeletionRequest),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AppReviewBatchStatus {
+    /// The operation was applied and signed
+    Applied,
+    /// The operation was applied before, the result is the one of the first time
+    Replayed,
+    /// The review changed after the user made the change on the device, the operation wasn't applied
+    Stale,
+    /// The operation wasn't applied, see `error_message`
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AppReviewBatchResult {
+    pub idempotency_key: String,
+    pub status: AppReviewBatchStatus,
+    /// The signed review or deletion, for applied and replayed operations
+    pub review: Option<AppReviewSignatureResponse>,
+    pub error_message: Option<String>,
+}
+
+/// Result of every operation of a batch, in the order of the operations
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct AppReviewBatchResponse {
+    pub results: Vec<AppReviewBatchResult>,
+}
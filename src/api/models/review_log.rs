@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReviewLogQuery {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReviewLogEntriesQuery {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    /// Index of the first entry
+    pub start: i64,
+    /// Index after the last entry, at most 1000 entries are returned at once
+    pub end: i64,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReviewLogInclusionProofQuery {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    /// Base64 encoded leaf hash of the signed review payload
+    pub leaf_hash: String,
+    /// Size of the tree to prove inclusion in, defaults to the latest signed tree head
+    pub tree_size: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReviewLogConsistencyProofQuery {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    pub first_tree_size: i64,
+    /// Defaults to the size of the latest signed tree head
+    pub second_tree_size: Option<i64>,
+}
+
+
+/// The data of a tree head that is signed with the review signing key
+#[derive(Serialize, Deserialize)]
+pub struct ReviewLogTreeHeadData {
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    pub tree_size: i64,
+    pub root_hash: String,
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewLogTreeHead {
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    pub tree_size: i64,
+    /// Base64 encoded root hash of the Merkle tree
+    pub root_hash: String,
+    pub timestamp: i64,
+    /// Signature of the tree head data, made with the review signing key
+    pub signature: String,
+    pub key_id: Option<String>,
+    /// Payload format of the signed tree head data
+    pub format_version: i32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewLogEntry {
+    pub leaf_index: i64,
+    /// Base64 encoded hash of the signed review payload, as defined in RFC 9162
+    pub leaf_hash: String,
+    pub sequence_number: i32,
+    pub status: String,
+    pub signature: String,
+    pub key_id: Option<String>,
+    /// Payload format of the signed review that the leaf hash is computed over
+    pub format_version: i32,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct ReviewLogEntryList(Vec<ReviewLogEntry>);
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewLogInclusionProof {
+    pub leaf_index: i64,
+    pub tree_size: i64,
+    /// Base64 encoded hashes of the audit path
+    pub audit_path: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewLogConsistencyProof {
+    pub first_tree_size: i64,
+    pub second_tree_size: i64,
+    /// Base64 encoded hashes of the consistency proof
+    pub proof: Vec<String>,
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{ToResponse, ToSchema};
+
+use crate::db::models::app_review_reply::AppReviewReply;
+
+use super::app_reviews::AppReviewStatus;
+
+pub use sidestore_id_core::reply::{AppReviewReplySignatureData, ReplyPayloadType};
+
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewReplyRequest {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    /// Sequence number of the answered review
+    pub sequence_number: i32,
+    /// At most 5000 characters
+    pub body: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewReplyDeletionRequest {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    pub sequence_number: i32,
+}
+
+/// A source maintainer's signed reply to a review. The signature is made over the reply's data,
+/// see `docs/review-signatures.md`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewReply {
+    pub status: AppReviewStatus,
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    pub review_sequence_number: i32,
+    pub maintainer_id: String,
+    /// Not set for deleted replies
+    pub body: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub signature: String,
+    pub key_id: String,
+    pub format_version: i32,
+}
+
+impl From<&AppReviewReply> for ReviewReply {
+    fn from(reply: &AppReviewReply) -> Self {
+        let data = reply_data(reply);
+        ReviewReply {
+            status: data.status,
+            source_identifier: data.source_identifier,
+            app_bundle_identifier: data.app_bundle_identifier,
+            review_sequence_number: data.review_sequence_number,
+            maintainer_id: data.maintainer_id,
+            body: data.reply_body,
+            created_at: data.created_at,
+            updated_at: data.updated_at,
+            signature: reply.signature.clone(),
+            key_id: data.key_id,
+            format_version: reply.format_version,
+        }
+    }
+}
+
+/// Reply data as it is signed, built from the stored reply
+pub fn reply_data(reply: &AppReviewReply) -> AppReviewReplySignatureData {
+    let status = reply.status.parse().unwrap_or(AppReviewStatus::Deleted);
+    AppReviewReplySignatureData {
+        payload_type: ReplyPayloadType::ReviewReply,
+        status,
+        source_identifier: reply.source_id.clone(),
+        app_bundle_identifier: reply.app_bundle_id.clone(),
+        review_sequence_number: reply.review_sequence_number,
+        maintainer_id: reply.maintainer_id.clone(),
+        reply_body: reply.body.clone().filter(|_| status == AppReviewStatus::Published),
+        created_at: reply.created_at.timestamp(),
+        updated_at: reply.updated_at.timestamp(),
+        key_id: reply.key_id.clone(),
+    }
+}
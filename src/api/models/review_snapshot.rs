@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+use super::review_log::ReviewLogTreeHead;
+use super::review_replies::ReviewReply;
+
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReviewSnapshotQuery {
+    pub source_identifier: String,
+    /// `cursor` of a previous snapshot, to only get the reviews that changed since
+    pub since: Option<String>,
+}
+
+
+/// The current reviews of all apps of a source, the data that is signed with the review signing key
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewSnapshotData {
+    pub source_identifier: String,
+    /// Cursor of the snapshot this is a delta to, not set for full snapshots
+    pub since: Option<String>,
+    /// Position of the snapshot in the review logs and reply changes of the apps, pass it as `since` to get the
+    /// next delta
+    pub cursor: String,
+    pub timestamp: i64,
+    /// Apps with reviews, or with changed reviews in a delta
+    pub apps: Vec<AppReviewSnapshot>,
+    pub key_id: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AppReviewSnapshot {
+    pub app_bundle_identifier: String,
+    /// Number of entries in the review log of the app that the snapshot covers
+    pub log_size: i64,
+    /// Latest signed tree head of the review log, if one was signed yet
+    pub tree_head: Option<ReviewLogTreeHead>,
+    /// Published reviews, in a delta only the ones that were published or changed since, or whose reply changed
+    pub reviews: Vec<ReviewSnapshotEntry>,
+    /// Sequence numbers of reviews that were deleted, hidden or removed since, only set in deltas
+    pub deleted_sequence_numbers: Vec<i32>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReviewSnapshotEntry {
+    pub sequence_number: i32,
+    pub version_number: Option<String>,
+    pub review_rating: Option<i32>,
+    /// Signed commitment to the title and body, not set for reviews signed before commitments were introduced
+    pub content_commitment: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Signature of the review as returned when it was signed
+    pub signature: Option<String>,
+    pub key_id: Option<String>,
+    pub format_version: i32,
+    /// Signed reply of the source's maintainers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply: Option<ReviewReply>,
+}
+
+/// A signed review snapshot. The signature is made over the snapshot without `signature`
+/// and `format_version`.
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct ReviewSnapshot {
+    #[serde(flatten)]
+    pub snapshot: ReviewSnapshotData,
+    pub signature: String,
+    /// Payload format of the signature
+    pub format_version: i32,
+}
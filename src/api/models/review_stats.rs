@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+
+#[derive(Deserialize, IntoParams)]
+pub struct ReviewStatsQuery {
+    pub source_identifier: String,
+    pub app_bundle_id: String,
+    /// Count the reviews that are held for moderation too
+    #[serde(default)]
+    pub include_held: bool,
+}
+
+
+/// Rating statistics of an app's published reviews, and of its held reviews if they're included, the data that is signed with the review signing key
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReviewStatsData {
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    #[serde(flatten)]
+    pub ratings: RatingStats,
+    /// Statistics per app version, in the order of the version numbers
+    pub versions: Vec<VersionRatingStats>,
+    /// Whether reviews held for moderation were counted too, only signed if they were
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub held_reviews_included: bool,
+    /// Time the statistics were computed
+    pub timestamp: i64,
+    pub key_id: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RatingStats {
+    pub review_count: i64,
+    /// Average rating rounded to two decimals, not set without reviews. It is a string because signed
+    /// payloads only contain integers.
+    pub average_rating: Option<String>,
+    /// Number of reviews with 1 to 5 stars
+    pub rating_counts: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VersionRatingStats {
+    pub version_number: Option<String>,
+    #[serde(flatten)]
+    pub ratings: RatingStats,
+}
+
+/// Signed rating statistics of an app. The signature is made over the statistics without
+/// `signature` and `format_version`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToResponse, ToSchema)]
+pub struct ReviewStats {
+    #[serde(flatten)]
+    pub stats: ReviewStatsData,
+    pub signature: String,
+    /// Payload format of the signature
+    pub format_version: i32,
+}
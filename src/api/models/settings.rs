@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+use crate::db::models::user_device::UserDevice;
+use crate::db::models::user_setting::UserSetting;
+use crate::util::vector_clock::VectorClock;
+
+
+/// A setting that is synced across the user's devices
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct Setting {
+    pub namespace: String,
+    pub key: String,
+    #[schema(value_type = Object)]
+    pub value: serde_json::Value,
+    /// Counts the changes of the value, starting at 1
+    pub version: i64,
+    pub updated_at: i64,
+}
+
+impl From<UserSetting> for Setting {
+    fn from(setting: UserSetting) -> Self {
+        Setting {
+            value: serde_json::from_str(&setting.value).unwrap_or_default(),
+            namespace: setting.namespace,
+            key: setting.key,
+            version: setting.version,
+            updated_at: setting.updated_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct SettingList(Vec<Setting>);
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SettingRequest {
+    /// Any JSON value but `null`
+    #[schema(value_type = Object)]
+    pub value: serde_json::Value,
+    /// The version the device last saw, so changes of other devices aren't overwritten. 0 if the setting shouldn't
+    /// exist yet.
+    pub version: Option<i64>,
+}
+
+/// Changes of several settings of a namespace, which are made all or none
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SettingsPatchRequest {
+    /// New values by key, `null` deletes the setting
+    #[schema(value_type = Object)]
+    pub settings: HashMap<String, Option<serde_json::Value>>,
+    /// The versions the device last saw by key, like the version of a single change
+    #[serde(default)]
+    pub versions: HashMap<String, i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SettingVersionQuery {
+    /// The version the device last saw, the setting isn't deleted if it changed since
+    pub version: Option<i64>,
+}
+
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DeviceRequest {
+    /// Name that the user recognizes the device by
+    pub name: String,
+}
+
+/// A device that syncs the user's settings
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct Device {
+    pub id: String,
+    pub name: String,
+    pub created_at: i64,
+    pub last_synced_at: Option<i64>,
+}
+
+impl From<UserDevice> for Device {
+    fn from(device: UserDevice) -> Self {
+        Device {
+            id: device.id,
+            name: device.name,
+            created_at: device.created_at.timestamp(),
+            last_synced_at: device.last_synced_at.map(|time| time.timestamp()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct DeviceList(Vec<Device>);
+
+/// A change of a setting on a device
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct SettingChange {
+    pub namespace: String,
+    pub key: String,
+    /// The new value, `null` if the setting was deleted
+    #[schema(value_type = Option<Object>)]
+    pub value: Option<serde_json::Value>,
+    /// Number of changes of the setting by device id that the change was made on top of, including this change
+    #[schema(value_type = HashMap<String, i64>)]
+    pub clock: VectorClock,
+}
+
+/// The local changes of a device since its last sync
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SettingsSyncRequest {
+    pub device_id: String,
+    /// Change token of the device's last sync, none for the first sync
+    pub change_token: Option<String>,
+    #[serde(default)]
+    pub changes: Vec<SettingChange>,
+}
+
+/// The state of a setting on the server
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SyncedSetting {
+    pub namespace: String,
+    pub key: String,
+    /// `null` if the setting was deleted
+    #[schema(value_type = Option<Object>)]
+    pub value: Option<serde_json::Value>,
+    #[schema(value_type = HashMap<String, i64>)]
+    pub clock: VectorClock,
+    pub version: i64,
+    pub updated_at: i64,
+}
+
+impl From<UserSetting> for SyncedSetting {
+    fn from(setting: UserSetting) -> Self {
+        SyncedSetting {
+            value: match setting.is_deleted() {
+                true => None,
+                false => serde_json::from_str(&setting.value).ok(),
+            },
+            clock: setting.vector_clock(),
+            namespace: setting.namespace,
+            key: setting.key,
+            version: setting.version,
+            updated_at: setting.updated_at.timestamp(),
+        }
+    }
+}
+
+/// A local change that was made concurrently with a change on the server and wasn't applied
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SettingConflict {
+    /// The local change
+    pub change: SettingChange,
+    /// The setting on the server, with a clock that includes the local change. Changes based on it replace it.
+    pub server: SyncedSetting,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct SettingsSyncResponse {
+    /// Token to send with the next sync
+    pub change_token: String,
+    /// Whether the changes start over with all settings instead of the changes since the last sync. The device
+    /// forgets the settings that aren't among them, or among the changes of the following syncs with `has_more`.
+    pub full_sync: bool,
+    /// Whether there are more server changes, which the next sync returns
+    pub has_more: bool,
+    /// Settings that changed on the server since the last sync, not including the device's applied changes
+    pub changes: Vec<SyncedSetting>,
+    pub conflicts: Vec<SettingConflict>,
+}
@@ -0,0 +1,140 @@
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::source::SourceMaintainer;
+
+use super::app_reviews::AppReviewStatus;
+use super::review_replies::ReviewReply;
+use super::review_stats::ReviewStats;
+
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SourceClaimRequest {
+    pub source_identifier: String,
+    /// URL of the source's JSON, whose `identifier` has to be `source_identifier`
+    pub source_url: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SourceVerificationRequest {
+    pub source_identifier: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceVerificationMethod {
+    /// The challenge is a line of the well-known file at the source's origin
+    WellKnownFile,
+    /// The challenge is in the `sidestoreIdVerification` field of the source's JSON
+    SourceField,
+}
+
+impl From<SourceVerificationMethod> for String {
+    fn from(method: SourceVerificationMethod) -> Self {
+        match method {
+            SourceVerificationMethod::WellKnownFile => "well_known_file",
+            SourceVerificationMethod::SourceField => "source_field",
+        }.to_string()
+    }
+}
+
+/// A user's claim to maintain a source
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct SourceClaim {
+    pub source_identifier: String,
+    pub source_url: String,
+    /// Token to serve in the well-known file or the source's JSON
+    pub challenge: String,
+    /// URL of the well-known file that can contain the challenge
+    pub well_known_url: Option<String>,
+    pub verified: bool,
+    pub verification_method: Option<String>,
+    pub verified_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl SourceClaim {
+    pub fn new(claim: SourceMaintainer, well_known_url: Option<String>) -> Self {
+        SourceClaim {
+            verified: claim.is_verified(),
+            source_identifier: claim.source_id,
+            source_url: claim.source_url,
+            challenge: claim.challenge,
+            well_known_url,
+            verification_method: claim.method,
+            verified_at: claim.verified_at.map(|verified_at| verified_at.timestamp()),
+            created_at: claim.created_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct SourceClaimList(Vec<SourceClaim>);
+
+
+#[derive(Deserialize, IntoParams)]
+pub struct SourceQuery {
+    pub source_identifier: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SourceStatsQuery {
+    pub source_identifier: String,
+    /// Count the reviews that are held for moderation too
+    #[serde(default)]
+    pub include_held: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct SourceReviewsQuery {
+    pub source_identifier: String,
+    /// Only list the reviews of this app
+    pub app_bundle_id: Option<String>,
+}
+
+/// A review of an app of the maintainer's source. Titles and bodies aren't stored, they are only
+/// committed to.
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct SourceReview {
+    pub app_bundle_identifier: String,
+    pub sequence_number: i32,
+    pub status: AppReviewStatus,
+    pub version_number: Option<String>,
+    pub review_rating: Option<i32>,
+    pub content_commitment: Option<String>,
+    pub date: i64,
+    pub reply: Option<ReviewReply>,
+}
+
+impl SourceReview {
+    pub fn new(review: &AppReviewSignature, reply: Option<ReviewReply>) -> Self {
+        SourceReview {
+            app_bundle_identifier: review.app_bundle_id.clone(),
+            sequence_number: review.sequence_number,
+            status: review.status(),
+            version_number: review.app_version.clone(),
+            review_rating: review.review_rating,
+            content_commitment: review.content_commitment.clone(),
+            date: review.updated_at.timestamp(),
+            reply,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct SourceReviewList(Vec<SourceReview>);
+
+/// Signed rating statistics of every reviewed app of a source
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct SourceStatsList(Vec<ReviewStats>);
+
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct SourceSettings {
+    pub source_identifier: String,
+    /// Whether users can review the apps of the source. Existing reviews stay and can still be deleted.
+    pub accepts_reviews: bool,
+}
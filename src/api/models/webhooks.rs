@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+use crate::db::models::webhook::{WebhookDelivery, WebhookSubscription};
+
+use super::app_reviews::AppReviewStatus;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum WebhookEventType {
+    /// A review was published, changed or restored by a moderator
+    #[serde(rename = "review.published")]
+    ReviewPublished,
+    /// A review was deleted by its author, held for moderation, or hidden or removed by a moderator
+    #[serde(rename = "review.deleted")]
+    ReviewDeleted,
+    /// A user deleted their account. Only sent to clients.
+    #[serde(rename = "account.deleted")]
+    AccountDeleted,
+}
+
+impl WebhookEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventType::ReviewPublished => "review.published",
+            WebhookEventType::ReviewDeleted => "review.deleted",
+            WebhookEventType::AccountDeleted => "account.deleted",
+        }
+    }
+
+    /// Whether maintainers can subscribe to the event for their source
+    pub fn is_source_event(&self) -> bool {
+        *self != WebhookEventType::AccountDeleted
+    }
+}
+
+impl FromStr for WebhookEventType {
+    type Err = ();
+
+    fn from_str(event_type: &str) -> Result<Self, Self::Err> {
+        [WebhookEventType::ReviewPublished, WebhookEventType::ReviewDeleted, WebhookEventType::AccountDeleted]
+            .into_iter()
+            .find(|known| known.as_str() == event_type)
+            .ok_or(())
+    }
+}
+
+/// Body of a webhook delivery. Every attempt of a delivery sends the same event, receivers can drop events whose
+/// `id` they already handled.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WebhookEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub event_type: WebhookEventType,
+    pub created_at: i64,
+    pub data: WebhookEventData,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum WebhookEventData {
+    Review(WebhookReviewData),
+    Account(WebhookAccountData),
+}
+
+/// The review that was published or deleted, with its signature
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WebhookReviewData {
+    pub source_identifier: String,
+    pub app_bundle_identifier: String,
+    pub sequence_number: i32,
+    pub status: AppReviewStatus,
+    /// The signed payload, see the documentation of review signatures
+    pub payload: String,
+    pub signature: String,
+    pub key_id: String,
+    pub format_version: i32,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WebhookAccountData {
+    pub user_id: String,
+}
+
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WebhookRequest {
+    pub source_identifier: String,
+    /// URL that events are posted to, has to use HTTPS
+    pub url: String,
+    pub events: Vec<WebhookEventType>,
+}
+
+/// A subscription of a source or an OAuth client to events
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct Webhook {
+    pub id: String,
+    pub source_identifier: Option<String>,
+    pub client_id: Option<String>,
+    pub url: String,
+    pub events: Vec<WebhookEventType>,
+    /// Key of the HMAC signatures of the deliveries, only returned when the webhook is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: i64,
+}
+
+impl Webhook {
+    pub fn new(subscription: WebhookSubscription, with_secret: bool) -> Self {
+        Webhook {
+            events: subscription.event_types(),
+            id: subscription.id,
+            source_identifier: subscription.source_id,
+            client_id: subscription.client_id,
+            url: subscription.url,
+            secret: with_secret.then_some(subscription.secret),
+            created_at: subscription.created_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct WebhookList(Vec<Webhook>);
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// Not delivered yet, the next attempt is scheduled
+    Pending,
+    Delivered,
+    /// Every attempt failed, the delivery isn't retried
+    Dead,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookDeliveryStatus::Pending => "pending",
+            WebhookDeliveryStatus::Delivered => "delivered",
+            WebhookDeliveryStatus::Dead => "dead",
+        }
+    }
+}
+
+impl From<WebhookDeliveryStatus> for String {
+    fn from(status: WebhookDeliveryStatus) -> Self {
+        status.as_str().to_string()
+    }
+}
+
+impl FromStr for WebhookDeliveryStatus {
+    type Err = ();
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        [WebhookDeliveryStatus::Pending, WebhookDeliveryStatus::Delivered, WebhookDeliveryStatus::Dead]
+            .into_iter()
+            .find(|known| known.as_str() == status)
+            .ok_or(())
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct WebhookDeliveriesQuery {
+    /// Only list deliveries with this status
+    pub status: Option<WebhookDeliveryStatus>,
+    /// Number of deliveries, 50 by default and at most 200
+    pub limit: Option<i64>,
+}
+
+/// A delivery of an event to a webhook and the outcome of its latest attempt
+#[derive(Serialize, Deserialize, ToSchema, ToResponse)]
+pub struct WebhookDeliveryRecord {
+    pub id: String,
+    pub event_id: String,
+    pub event_type: String,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    /// Time of the next attempt of a pending delivery
+    pub next_attempt_at: Option<i64>,
+    pub last_attempt_at: Option<i64>,
+    /// HTTP status the receiver responded with to the latest attempt
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+}
+
+impl From<WebhookDelivery> for WebhookDeliveryRecord {
+    fn from(delivery: WebhookDelivery) -> Self {
+        let status = delivery.status();
+        WebhookDeliveryRecord {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            event_type: delivery.event_type,
+            status,
+            attempts: delivery.attempts,
+            next_attempt_at: (status == WebhookDeliveryStatus::Pending).then_some(delivery.next_attempt_at.timestamp()),
+            last_attempt_at: delivery.last_attempt_at.map(|time| time.timestamp()),
+            last_response_status: delivery.last_response_status,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at.timestamp(),
+            delivered_at: delivery.delivered_at.map(|time| time.timestamp()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToResponse)]
+#[cfg_attr(not(feature = "swagger"), allow(dead_code))]
+pub struct WebhookDeliveryList(Vec<WebhookDeliveryRecord>);
@@ -0,0 +1,128 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    AppState,
+    errors::{ServiceError, ErrorResponse},
+    middlewares::auth::JwtMiddleware,
+};
+use crate::api::utils::enforce_scope;
+use crate::auth::JwtTokenScope;
+use crate::services::moderation_service;
+
+use super::models::moderation::{
+    HeldReviewList, ModerationActionRecordList, ModerationActionRequest, ModerationActionResponse, ModerationActionsQuery,
+    ReviewReportList, ReviewReportRequest, ReviewReportResponse,
+};
+
+
+/// Report an app review
+///
+/// Report another user's published review to the moderators. Reporting a review again returns the first report.
+#[utoipa::path(
+    post,
+    path = "/api/reviews/report",
+    request_body = ReviewReportRequest,
+    responses(
+        (status = 200, response = ReviewReportResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn report(body: web::Json<ReviewReportRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let report = moderation_service::report(&body, &jwt.user_id, &data.db)?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+
+/// Get open review reports
+///
+/// Lists the oldest open reports, up to 100. Only for moderators.
+#[utoipa::path(
+    get,
+    path = "/api/moderation/reports",
+    responses(
+        (status = 200, response = ReviewReportList),
+        (status = 401, description = "User authentication failed or the user isn't a moderator."),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_reports(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    moderation_service::require_moderator(&jwt.user_id, &data.db)?;
+
+    let reports = moderation_service::open_reports(&data.db)?;
+    Ok(HttpResponse::Ok().json(reports))
+}
+
+
+/// Get reviews held for moderation
+///
+/// Lists the reviews that the fraud checks held, the longest held first, up to 100, with the risk score and signals
+/// of their latest submission. Restore a held review to publish it. Only for moderators.
+#[utoipa::path(
+    get,
+    path = "/api/moderation/held",
+    responses(
+        (status = 200, response = HeldReviewList),
+        (status = 401, description = "User authentication failed or the user isn't a moderator."),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_held(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    moderation_service::require_moderator(&jwt.user_id, &data.db)?;
+
+    let reviews = moderation_service::held_reviews(&data.db)?;
+    Ok(HttpResponse::Ok().json(reviews))
+}
+
+
+/// Moderate an app review
+///
+/// Hide, restore or remove a review. The review is signed again with its new status, so clients drop hidden and
+/// removed reviews, and the action is recorded with the reason. Resolves the open reports of the review. Only for
+/// moderators.
+#[utoipa::path(
+    post,
+    path = "/api/moderation/actions",
+    request_body = ModerationActionRequest,
+    responses(
+        (status = 200, response = ModerationActionResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed or the user isn't a moderator."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn moderate(body: web::Json<ModerationActionRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    moderation_service::require_moderator(&jwt.user_id, &data.db)?;
+
+    let response = moderation_service::moderate(&body, &jwt.user_id, &data.db, &data.env, &data.review_keys)?;
+    data.review_stats.invalidate(&body.source_identifier, &body.app_bundle_id);
+    Ok(HttpResponse::Ok().json(response))
+}
+
+
+/// Get the moderation history of an app review
+///
+/// Lists the moderation actions on a review with their reasons, oldest first. Only for moderators.
+#[utoipa::path(
+    get,
+    path = "/api/moderation/actions",
+    params(ModerationActionsQuery),
+    responses(
+        (status = 200, response = ModerationActionRecordList),
+        (status = 401, description = "User authentication failed or the user isn't a moderator."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn get_actions(query: web::Query<ModerationActionsQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    moderation_service::require_moderator(&jwt.user_id, &data.db)?;
+
+    let actions = moderation_service::actions(&query, &data.db)?;
+    Ok(HttpResponse::Ok().json(actions))
+}
@@ -1,34 +1,95 @@
+use std::collections::BTreeSet;
+use std::fs;
+
 use oxide_auth::primitives::prelude::Client;
 use oxide_auth::primitives::registrar::{ExactUrl, RegisteredUrl};
+use oxide_auth::primitives::scope::Scope;
 use serde::Deserialize;
 
-use crate::auth::JwtTokenScope;
+use crate::api::models::webhooks::WebhookEventType;
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct OAuthConfig {
     pub clients: Vec<OAuthClient>
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct OAuthClient {
-    client_id: String,
+    pub client_id: String,
+    /// Human readable name shown to users, e.g. in their list of connected apps
+    pub client_name: Option<String>,
     client_secret: String,
-    scope: JwtTokenScope,
+    /// Space separated list of all scopes the client may request
+    pub scope: String,
     redirect_uri: String,
     additional_redirect_uris: Option<Vec<String>>,
+    /// Endpoint that is sent events of all sources and accounts
+    pub webhook: Option<OAuthClientWebhook>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAuthClientWebhook {
+    pub url: String,
+    /// Key of the HMAC signatures of the deliveries
+    pub secret: String,
+    pub events: Vec<WebhookEventType>,
+}
+
+impl OAuthConfig {
+    /// Load the oauth configuration from a TOML file. Falls back to a configuration without any
+    /// clients if the file doesn't exist or can't be parsed.
+    pub fn load(path: &str) -> OAuthConfig {
+        match fs::read_to_string(path) {
+            Ok(oauth_config_toml_string) => toml::from_str::<OAuthConfig>(&oauth_config_toml_string)
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to read oauth configuration: {:?}", e);
+                    OAuthConfig::default()
+                }),
+            Err(e) => {
+                log::warn!("Couldn't load oauth config from path {}: {:?}", path, e);
+                OAuthConfig::default()
+            }
+        }
+    }
+
+    pub fn client(&self, client_id: &str) -> Option<&OAuthClient> {
+        self.clients.iter().find(|c| c.client_id == client_id)
+    }
+
+    /// All scopes that at least one configured client may request
+    pub fn supported_scopes(&self) -> Vec<String> {
+        self.clients.iter()
+            .flat_map(|c| c.scope.split_whitespace())
+            .map(|s| s.to_string())
+            .collect::<BTreeSet<String>>()
+            .into_iter()
+            .collect()
+    }
+}
+
+impl OAuthClient {
+    pub fn allowed_scope(&self) -> Scope {
+        self.scope.parse().unwrap()
+    }
+
+    /// Compare the secret in constant time
+    pub fn has_secret(&self, secret: &str) -> bool {
+        let (expected, secret) = (self.client_secret.as_bytes(), secret.as_bytes());
+        expected.len() == secret.len() && expected.iter().zip(secret).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
 }
 
-impl Into<Client> for &OAuthClient {
-    fn into(self) -> Client {
+impl From<&OAuthClient> for Client {
+    fn from(value: &OAuthClient) -> Self {
         let mut client = Client::confidential(
-            &self.client_id,
-            RegisteredUrl::Exact(ExactUrl::new(self.redirect_uri.to_string()).unwrap()),
-            self.scope.clone().into_oauth_scope().unwrap(),
-            self.client_secret.as_bytes(),
+            &value.client_id,
+            RegisteredUrl::Exact(ExactUrl::new(value.redirect_uri.to_string()).unwrap()),
+            value.allowed_scope(),
+            value.client_secret.as_bytes(),
         );
 
-        if let Some(additional_redirect_urls) = self.additional_redirect_uris.clone() {
+        if let Some(additional_redirect_urls) = value.additional_redirect_uris.clone() {
             client = client.with_additional_redirect_uris(
                 additional_redirect_urls
                     .iter()
@@ -39,4 +100,52 @@ impl Into<Client> for &OAuthClient {
 
         client
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_scopes() {
+        let config: OAuthConfig = toml::from_str(r#"
+            [[clients]]
+            client_id = "a"
+            client_secret = "secret"
+            scope = "profile settings"
+            redirect_uri = "https://a.example/callback"
+
+            [[clients]]
+            client_id = "b"
+            client_secret = "secret"
+            scope = "reviews profile"
+            redirect_uri = "https://b.example/callback"
+        "#).unwrap();
+
+        assert_eq!(config.supported_scopes(), vec!["profile", "reviews", "settings"]);
+        assert!(OAuthConfig::default().supported_scopes().is_empty());
+    }
+
+    #[test]
+    fn test_client_webhooks() {
+        let config: OAuthConfig = toml::from_str(r#"
+            [[clients]]
+            client_id = "a"
+            client_secret = "secret"
+            scope = "profile"
+            redirect_uri = "https://a.example/callback"
+
+            [clients.webhook]
+            url = "https://a.example/webhook"
+            secret = "webhook-secret"
+            events = ["account.deleted", "review.published"]
+        "#).unwrap();
+
+        let client = config.client("a").unwrap();
+        let webhook = client.webhook.as_ref().unwrap();
+        assert_eq!(webhook.events, vec![WebhookEventType::AccountDeleted, WebhookEventType::ReviewPublished]);
+        assert!(client.has_secret("secret"));
+        assert!(!client.has_secret("secreT"));
+        assert!(!client.has_secret("secret2"));
+    }
+}
@@ -1,6 +1,8 @@
 pub mod state;
 pub mod oxide_auth_actix;
 mod operations;
-mod config;
+pub mod config;
 mod prelude;
+pub mod registrar;
+mod solicitor;
 mod token_issuer;
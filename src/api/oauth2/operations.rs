@@ -61,6 +61,7 @@ impl OAuthOperation for ClientCredentials {
 }
 
 /// Refresh-related operations
+#[allow(dead_code)]
 pub struct Refresh(pub OAuthRequest);
 
 impl OAuthOperation for Refresh {
@@ -79,6 +80,7 @@ impl OAuthOperation for Refresh {
 }
 
 /// Resource-related operations
+#[allow(dead_code)]
 pub struct Resource(pub OAuthRequest);
 
 impl OAuthOperation for Resource {
@@ -3,6 +3,7 @@
 //! Use the provided methods to use code grant methods in an asynchronous fashion, or use an
 //! `AsActor<_>` to create an actor implementing endpoint functionality via messages.
 #![warn(missing_docs)]
+#![allow(dead_code)]
 
 use actix::{MailboxError, Message};
 use actix_web::{
@@ -24,7 +25,9 @@ use oxide_auth::{
 use std::{borrow::Cow, convert::TryFrom, error, fmt};
 use url::Url;
 
-pub use super::operations::{Authorize, Refresh, Resource, Token, ClientCredentials};
+use crate::errors::ErrorResponse;
+
+pub use super::operations::{Authorize, Token, ClientCredentials};
 
 /// Describes an operation that can be performed in the presence of an `Endpoint`
 ///
@@ -117,6 +120,11 @@ pub struct OAuthRequest {
 }
 
 impl OAuthResponse {
+    /// Get the status code from `OAuthResponse`
+    pub fn get_status(&self) -> StatusCode {
+        self.status
+    }
+
     /// Get the headers from `OAuthResponse`
     pub fn get_headers(&self) -> HeaderMap {
         self.headers.clone()
@@ -283,21 +291,21 @@ impl WebRequest for OAuthRequest {
     type Error = WebError;
     type Response = OAuthResponse;
 
-    fn query(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+    fn query(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
         self.query
             .as_ref()
             .map(|q| Cow::Borrowed(q as &dyn QueryParameter))
             .ok_or(WebError::Query)
     }
 
-    fn urlbody(&mut self) -> Result<Cow<dyn QueryParameter + 'static>, Self::Error> {
+    fn urlbody(&mut self) -> Result<Cow<'_, dyn QueryParameter + 'static>, Self::Error> {
         self.body
             .as_ref()
             .map(|b| Cow::Borrowed(b as &dyn QueryParameter))
             .ok_or(WebError::Body)
     }
 
-    fn authheader(&mut self) -> Result<Option<Cow<str>>, Self::Error> {
+    fn authheader(&mut self) -> Result<Option<Cow<'_, str>>, Self::Error> {
         Ok(self.auth.as_deref().map(Cow::Borrowed))
     }
 }
@@ -469,5 +477,25 @@ impl error::Error for WebError {
 }
 
 impl ResponseError for WebError {
-    // Default to 500 for now
+    fn status_code(&self) -> StatusCode {
+        match *self {
+            WebError::Endpoint(_)
+            | WebError::Encoding
+            | WebError::Form
+            | WebError::Query
+            | WebError::Body => StatusCode::BAD_REQUEST,
+            WebError::Authorization => StatusCode::UNAUTHORIZED,
+            WebError::Header(_)
+            | WebError::Canceled
+            | WebError::Mailbox
+            | WebError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .json(ErrorResponse {
+                error_message: self.to_string(),
+            })
+    }
 }
\ No newline at end of file
@@ -3,10 +3,38 @@ use crate::auth::JwtTokenScope;
 
 impl JwtTokenScope {
     pub fn into_oauth_scope(self) -> Result<Scope, ParseScopeErr> {
-        let scope_name = match self {
+        self.oauth_scope_name().parse()
+    }
+
+    /// The most privileged token scope of the granted OAuth scopes. Clients never get full access.
+    pub fn from_oauth_scope(scope: &Scope) -> JwtTokenScope {
+        [JwtTokenScope::Settings, JwtTokenScope::SettingsRead]
+            .into_iter()
+            .find(|token_scope| scope.iter().any(|granted| granted == token_scope.oauth_scope_name()))
+            .unwrap_or(JwtTokenScope::Profile)
+    }
+
+    fn oauth_scope_name(&self) -> &'static str {
+        match self {
             JwtTokenScope::Full => "full",
+            JwtTokenScope::Settings => "settings",
+            JwtTokenScope::SettingsRead => "settings.read",
             JwtTokenScope::Profile => "profile",
-        };
-        scope_name.parse()
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_oauth_scope() {
+        let token_scope = |scope: &str| JwtTokenScope::from_oauth_scope(&scope.parse().unwrap());
+        assert_eq!(token_scope("profile"), JwtTokenScope::Profile);
+        assert_eq!(token_scope("full"), JwtTokenScope::Profile);
+        assert_eq!(token_scope("profile settings.read"), JwtTokenScope::SettingsRead);
+        assert_eq!(token_scope("settings.read settings"), JwtTokenScope::Settings);
     }
 }
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use oxide_auth::endpoint::{PreGrant, Registrar};
+use oxide_auth::primitives::prelude::ClientMap;
+use oxide_auth::primitives::registrar::{BoundClient, ClientUrl, RegistrarError};
+use oxide_auth::primitives::scope::Scope;
+
+use crate::api::oauth2::config::OAuthConfig;
+
+
+/// A client registrar that, unlike a plain `ClientMap`, honors the scope requested by a client
+/// as long as it is covered by the scope the client was configured with.
+pub struct ScopedClientMap {
+    clients: ClientMap,
+    allowed_scopes: HashMap<String, Scope>,
+}
+
+impl From<&OAuthConfig> for ScopedClientMap {
+    fn from(config: &OAuthConfig) -> Self {
+        ScopedClientMap {
+            clients: config.clients.iter().map(|c| c.into()).collect(),
+            allowed_scopes: config.clients
+                .iter()
+                .map(|c| (c.client_id.clone(), c.allowed_scope()))
+                .collect(),
+        }
+    }
+}
+
+impl Registrar for ScopedClientMap {
+    fn bound_redirect<'a>(&self, bound: ClientUrl<'a>) -> Result<BoundClient<'a>, RegistrarError> {
+        self.clients.bound_redirect(bound)
+    }
+
+    fn negotiate(&self, bound: BoundClient, scope: Option<Scope>) -> Result<PreGrant, RegistrarError> {
+        let allowed_scope = self.allowed_scopes
+            .get(bound.client_id.as_ref())
+            .ok_or(RegistrarError::Unspecified)?;
+
+        // Oxide-auth answers an unspecified error of the negotiation with `invalid_scope`
+        let scope = negotiate_scope(allowed_scope, scope.as_ref()).ok_or(RegistrarError::Unspecified)?;
+
+        Ok(PreGrant {
+            client_id: bound.client_id.into_owned(),
+            redirect_uri: bound.redirect_uri.into_owned(),
+            scope,
+        })
+    }
+
+    fn check(&self, client_id: &str, passphrase: Option<&[u8]>) -> Result<(), RegistrarError> {
+        self.clients.check(client_id, passphrase)
+    }
+}
+
+/// Determine the scope that is granted for a request. Requested scopes the client isn't allowed
+/// to use are dropped, and the full allowed scope is used if none was requested. Returns `None`
+/// if the client isn't allowed to use any of the requested scopes.
+pub fn negotiate_scope(allowed: &Scope, requested: Option<&Scope>) -> Option<Scope> {
+    let Some(requested) = requested else {
+        return Some(allowed.clone());
+    };
+
+    let granted: Vec<&str> = requested.iter().filter(|s| allowed.iter().any(|a| a == *s)).collect();
+    if granted.is_empty() {
+        None
+    } else {
+        granted.join(" ").parse().ok()
+    }
+}
+
+/// Scopes of `requested` which are not covered by `granted`
+pub fn missing_scopes<'a>(granted: &Scope, requested: &'a Scope) -> Vec<&'a str> {
+    requested.iter().filter(|s| !granted.iter().any(|g| g == *s)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_scope() {
+        let allowed: Scope = "profile settings".parse().unwrap();
+
+        assert_eq!(negotiate_scope(&allowed, None), Some(allowed.clone()));
+        assert_eq!(negotiate_scope(&allowed, Some(&"profile".parse().unwrap())), Some("profile".parse().unwrap()));
+        assert_eq!(negotiate_scope(&allowed, Some(&"profile full".parse().unwrap())), Some("profile".parse().unwrap()));
+        assert_eq!(negotiate_scope(&allowed, Some(&"full".parse().unwrap())), None);
+    }
+
+    #[test]
+    fn test_missing_scopes() {
+        let granted: Scope = "profile".parse().unwrap();
+        let requested: Scope = "profile settings".parse().unwrap();
+
+        assert_eq!(missing_scopes(&granted, &requested), vec!["settings"]);
+        assert!(missing_scopes(&requested, &granted).is_empty());
+    }
+}
@@ -0,0 +1,63 @@
+use oxide_auth::endpoint::{OwnerConsent, OwnerSolicitor, Solicitation, WebResponse};
+use url::Url;
+
+use crate::api::oauth2::oxide_auth_actix::{OAuthRequest, OAuthResponse};
+use crate::api::oauth2::registrar::missing_scopes;
+use crate::api::oauth2::state::ConsentGrant;
+
+
+/// Sends the user to the consent page of the deployment, unless they already granted
+/// everything the client is requesting.
+pub struct ConsentPageSolicitor {
+    consent_url: Url,
+    consent: Option<ConsentGrant>,
+}
+
+impl ConsentPageSolicitor {
+    pub fn new(consent_url: Url, consent: Option<ConsentGrant>) -> Self {
+        ConsentPageSolicitor { consent_url, consent }
+    }
+}
+
+impl OwnerSolicitor<OAuthRequest> for ConsentPageSolicitor {
+    fn check_consent(&mut self, _: &mut OAuthRequest, solicitation: Solicitation) -> OwnerConsent<OAuthResponse> {
+        let grant = solicitation.pre_grant();
+
+        // Skip the consent page if the user already granted everything that's requested
+        if let Some(consent) = &self.consent {
+            if grant.scope <= consent.scope {
+                return OwnerConsent::Authorized(consent.user_id.clone());
+            }
+        }
+
+        let mut consent_url = self.consent_url.clone();
+        {
+            let mut query = consent_url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &grant.client_id)
+                .append_pair("redirect_uri", grant.redirect_uri.as_str())
+                .append_pair("scope", &grant.scope.to_string());
+
+            if let Some(state) = solicitation.state() {
+                query.append_pair("state", state);
+            }
+
+            // Let the consent page highlight which scopes are requested in addition to
+            // the ones the user granted before.
+            if let Some(consent) = &self.consent {
+                query
+                    .append_pair("granted_scope", &consent.scope.to_string())
+                    .append_pair("added_scope", &missing_scopes(&consent.scope, &grant.scope).join(" "));
+            }
+        }
+
+        // This will display a page to the user asking for their permission to proceed. The submitted form
+        // will then trigger the other authorization handler which actually completes the flow.
+        let mut response = OAuthResponse::ok();
+        match response.redirect(consent_url) {
+            Ok(()) => OwnerConsent::InProgress(response),
+            Err(e) => OwnerConsent::Error(e),
+        }
+    }
+}
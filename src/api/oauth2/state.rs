@@ -1,62 +1,63 @@
-use std::fs;
-
 use actix::{Actor, Context, Handler};
 use oxide_auth::{
-    endpoint::{Endpoint, OwnerConsent, OwnerSolicitor, Solicitation, WebResponse},
+    endpoint::{Endpoint, OwnerConsent, OwnerSolicitor, Solicitation},
     frontends::simple::endpoint::{ErrorInto, FnSolicitor, Generic, Vacant},
-    primitives::prelude::{AuthMap, ClientMap, RandomGenerator, Scope},
+    primitives::prelude::{AuthMap, RandomGenerator, Scope},
 };
+use url::Url;
 
 use crate::api::models::oauth2::OAuth2AuthorizationResult;
 use crate::api::oauth2::config::OAuthConfig;
 use crate::api::oauth2::oxide_auth_actix::{
     OAuthMessage, OAuthOperation, OAuthRequest, OAuthResponse, WebError
 };
+use crate::api::oauth2::registrar::ScopedClientMap;
+use crate::api::oauth2::solicitor::ConsentPageSolicitor;
 use crate::api::oauth2::token_issuer::JwtTokenIssuer;
-use crate::auth::JwtTokenScope;
+use crate::auth::{JwtTokenScope, OAuthTokenKey};
 use crate::config::Config;
+use crate::db::Pool;
+
+type OAuth2Endpoint = Generic<
+    ScopedClientMap,
+    AuthMap<RandomGenerator>,
+    JwtTokenIssuer,
+    Vacant,
+    Vec<Scope>,
+    fn() -> OAuthResponse,
+>;
 
 pub struct OAuth2State {
-    endpoint: Generic<
-        ClientMap,
-        AuthMap<RandomGenerator>,
-        JwtTokenIssuer,
-        Vacant,
-        Vec<Scope>,
-        fn() -> OAuthResponse,
-    >,
+    endpoint: OAuth2Endpoint,
+    consent_url: Url,
+}
+
+/// A consent the user gave to a client earlier
+#[derive(Debug, Clone)]
+pub struct ConsentGrant {
+    pub user_id: String,
+    pub scope: Scope,
 }
 
 #[derive(Debug, Clone)]
 pub enum Extras {
-    AuthGet,
+    AuthGet(Option<ConsentGrant>),
     AuthPost(String, OAuth2AuthorizationResult),
     ClientCredentials,
     Nothing,
 }
 
 impl OAuth2State {
-    pub fn preconfigured(config: Config) -> Self {
-        let jwt_issuer = JwtTokenIssuer::new(config.clone());
-
-        let oauth_config = match fs::read_to_string(&config.oauth_config_path) {
-            Ok(oauth_config_toml_string) => toml::from_str::<OAuthConfig>(&oauth_config_toml_string)
-                .map_err(|e| {
-                    log::error!("Failed to read oauth configuration: {:?}", e)
-                })
-                .ok(),
-            Err(e) => {
-                log::warn!("Couldn't load oauth config from path {}: {:?}", &config.oauth_config_path, e);
-                None
-            }
-        };
-
-        OAuth2State {
+    pub fn preconfigured(config: Config, oauth_config: &OAuthConfig, oauth_token_key: OAuthTokenKey, pool: Pool) -> Result<Self, String> {
+        let consent_url = config.oauth_consent_url()
+            .map_err(|e| format!("Invalid consent page url {}: {}", &config.oauth_consent_url_template, e))?;
+        let jwt_issuer = JwtTokenIssuer::new(config, oauth_token_key, pool);
+
+        Ok(OAuth2State {
+            consent_url,
             endpoint: Generic {
-                // A registrar with one pre-registered client
-                registrar: oauth_config.map_or(ClientMap::default(), |config|
-                    config.clients.iter().map(|c| c.into()).collect()
-                ),
+                // A registrar with the clients from the oauth configuration
+                registrar: oauth_config.into(),
                 // Authorization tokens are 16 byte random keys to a memory hash map.
                 authorizer: AuthMap::new(RandomGenerator::new(16)),
                 // Bearer tokens are also random generated but 256-bit tokens, since they live longer
@@ -74,7 +75,7 @@ impl OAuth2State {
 
                 response: OAuthResponse::ok,
             },
-        }
+        })
     }
 
     pub fn with_solicitor<'a, S>(
@@ -107,39 +108,8 @@ where
     fn handle(&mut self, msg: OAuthMessage<Operation, Extras>, _: &mut Self::Context) -> Self::Result {
         let (op, ex) = msg.into_inner();
         match ex {
-            Extras::AuthGet => {
-                let solicitor = FnSolicitor(move |_: &mut OAuthRequest, solicitation: Solicitation| {
-                    let grant = solicitation.pre_grant();
-                    let state = solicitation.state();
-
-                    let scope = grant.scope.to_string();
-                    let mut extra = vec![
-                        ("response_type", "code"),
-                        ("client_id", grant.client_id.as_str()),
-                        ("redirect_uri", grant.redirect_uri.as_str()),
-                        ("scope", &scope),
-                    ];
-
-                    if let Some(state) = state {
-                        extra.push(("state", state));
-                    }
-
-                    let public_url = std::env::var("PUBLIC_URL").unwrap();
-                    let redirect_url = url::Url::parse_with_params(
-                        &format!("{}/auth/authorize", &public_url), &extra
-                    )
-                    .unwrap();
-
-                    let mut response = OAuthResponse::ok();
-                    response.redirect(redirect_url)
-                        .map_err(|_| WebError::InternalError(Some("Failed to redirect".to_string())))
-                        .unwrap();
-
-                    // This will display a page to the user asking for his permission to proceed. The submitted form
-                    // will then trigger the other authorization handler which actually completes the flow.
-                    OwnerConsent::InProgress(response)
-                });
-
+            Extras::AuthGet(consent) => {
+                let solicitor = ConsentPageSolicitor::new(self.consent_url.clone(), consent);
                 op.run(self.with_solicitor(solicitor))
             },
             Extras::AuthPost(user_id, result) => {
@@ -164,11 +134,209 @@ where
             //
             //     op.run(self.with_solicitor(solicitor))
             // },
-            Extras::Nothing => {
-                let result = op.run(&mut self.endpoint);
-                result
-            },
+            Extras::Nothing => op.run(&mut self.endpoint),
             _ => op.run(&mut self.endpoint),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix::Addr;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test::TestRequest;
+    use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+    use diesel::r2d2::{self, ConnectionManager};
+    use ed25519_dalek::SigningKey;
+    use jsonwebtoken::{decode, Algorithm, Validation};
+
+    use crate::api::oauth2::oxide_auth_actix::{Authorize, Token};
+    use crate::auth::OAuthAccessToken;
+    use super::*;
+
+    const CLIENT_ID: &str = "test-client";
+    const CLIENT_SECRET: &str = "test-secret";
+    const REDIRECT_URI: &str = "https://client.example.com/callback";
+    const USER_ID: &str = "8b0a6d2e-5a5c-4c1e-9d0e-2f4a4f0f7c11";
+
+    fn token_key() -> OAuthTokenKey {
+        OAuthTokenKey::new(&SigningKey::from_bytes(&[7; 32])).unwrap()
+    }
+
+    fn start_state(config: &Config) -> Addr<OAuth2State> {
+        let oauth_config: OAuthConfig = toml::from_str(&format!(r#"
+            [[clients]]
+            client_id = "{CLIENT_ID}"
+            client_name = "Test Client"
+            client_secret = "{CLIENT_SECRET}"
+            scope = "profile settings"
+            redirect_uri = "{REDIRECT_URI}"
+        "#)).unwrap();
+
+        // The issuer only uses the database to record when an authorization was last used,
+        // failing to connect to it doesn't affect the flows.
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .connection_timeout(Duration::from_millis(10))
+            .build_unchecked(ConnectionManager::new("postgres://localhost:1/unavailable"));
+
+        OAuth2State::preconfigured(config.clone(), &oauth_config, token_key(), pool).unwrap().start()
+    }
+
+    async fn authorize_request(scope: &str) -> OAuthRequest {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("response_type", "code")
+            .append_pair("client_id", CLIENT_ID)
+            .append_pair("redirect_uri", REDIRECT_URI)
+            .append_pair("scope", scope)
+            .append_pair("state", "test-state")
+            .finish();
+        let (req, payload) = TestRequest::get()
+            .uri(&format!("/api/auth/oauth2/authorize?{}", query))
+            .to_http_parts();
+        OAuthRequest::new(req, payload).await.unwrap()
+    }
+
+    fn location(response: &OAuthResponse) -> Url {
+        let headers = response.get_headers();
+        Url::parse(headers.get(header::LOCATION).unwrap().to_str().unwrap()).unwrap()
+    }
+
+    fn query_value(url: &Url, key: &str) -> Option<String> {
+        url.query_pairs().find(|(k, _)| k == key).map(|(_, v)| v.to_string())
+    }
+
+    #[actix_web::test]
+    async fn test_authorize_redirects_to_consent_page() {
+        let mut config = Config::for_tests();
+        config.oauth_consent_url_template = "{public_url}/consent?theme=dark".to_string();
+        let state = start_state(&config);
+
+        let response = state.send(Authorize(authorize_request("profile").await).wrap(Extras::AuthGet(None)))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(response.get_status(), StatusCode::FOUND);
+        let consent_url = location(&response);
+        assert_eq!(consent_url.path(), "/consent");
+        assert_eq!(query_value(&consent_url, "theme").as_deref(), Some("dark"));
+        assert_eq!(query_value(&consent_url, "client_id").as_deref(), Some(CLIENT_ID));
+        assert_eq!(query_value(&consent_url, "scope").as_deref(), Some("profile"));
+        assert_eq!(query_value(&consent_url, "state").as_deref(), Some("test-state"));
+        assert_eq!(query_value(&consent_url, "granted_scope"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_authorize_with_existing_consent() {
+        let state = start_state(&Config::for_tests());
+        let consent = ConsentGrant { user_id: USER_ID.to_string(), scope: "profile".parse().unwrap() };
+
+        // Requests within the granted scope are approved without asking the user again
+        let response = state.send(Authorize(authorize_request("profile").await).wrap(Extras::AuthGet(Some(consent.clone()))))
+            .await
+            .unwrap()
+            .unwrap();
+        let redirect_url = location(&response);
+        assert!(redirect_url.as_str().starts_with(REDIRECT_URI));
+        assert!(query_value(&redirect_url, "code").is_some());
+
+        // Broader requests need another consent, with the difference for the consent page
+        let response = state.send(Authorize(authorize_request("profile settings").await).wrap(Extras::AuthGet(Some(consent))))
+            .await
+            .unwrap()
+            .unwrap();
+        let consent_url = location(&response);
+        assert_eq!(consent_url.path(), "/auth/authorize");
+        assert_eq!(query_value(&consent_url, "granted_scope").as_deref(), Some("profile"));
+        assert_eq!(query_value(&consent_url, "added_scope").as_deref(), Some("settings"));
+    }
+
+    #[actix_web::test]
+    async fn test_authorize_with_disallowed_scope() {
+        let state = start_state(&Config::for_tests());
+
+        // Clients don't get their allowed scope instead of what they asked for
+        let response = state.send(Authorize(authorize_request("full").await).wrap(Extras::AuthGet(None)))
+            .await
+            .unwrap()
+            .unwrap();
+        let redirect_url = location(&response);
+        assert!(redirect_url.as_str().starts_with(REDIRECT_URI));
+        assert_eq!(query_value(&redirect_url, "error").as_deref(), Some("invalid_scope"));
+        assert_eq!(query_value(&redirect_url, "code"), None);
+    }
+
+    #[actix_web::test]
+    async fn test_authorization_code_flow() {
+        let config = Config::for_tests();
+        let state = start_state(&config);
+
+        let response = state.send(Authorize(authorize_request("profile").await)
+            .wrap(Extras::AuthPost(USER_ID.to_string(), OAuth2AuthorizationResult::Allow)))
+            .await
+            .unwrap()
+            .unwrap();
+        let redirect_url = location(&response);
+        assert_eq!(query_value(&redirect_url, "state").as_deref(), Some("test-state"));
+        let code = query_value(&redirect_url, "code").unwrap();
+
+        let credentials = base64_engine.encode(format!("{}:{}", CLIENT_ID, CLIENT_SECRET));
+        let (req, mut payload) = TestRequest::post()
+            .uri("/api/auth/oauth2/token")
+            .insert_header((header::AUTHORIZATION, format!("Basic {}", credentials)))
+            .set_form([
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", REDIRECT_URI),
+            ])
+            .to_http_parts();
+        let token_request = OAuthRequest::new(req, payload.take()).await.unwrap();
+
+        let response = state.send(Token(token_request).wrap(Extras::Nothing))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.get_status(), StatusCode::OK);
+
+        let body: serde_json::Value = serde_json::from_str(&response.get_body().unwrap()).unwrap();
+        let access_token = body["access_token"].as_str().unwrap();
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.set_audience(&[config.oauth_issuer()]);
+        validation.set_issuer(&[config.oauth_issuer()]);
+        let token = decode::<OAuthAccessToken>(access_token, token_key().decoding_key(), &validation).unwrap();
+        assert_eq!(token.header.typ.as_deref(), Some("at+jwt"));
+        assert_eq!(token.header.kid.as_deref(), Some(token_key().key_id()));
+        assert_eq!(token.claims.sub, USER_ID);
+        assert_eq!(token.claims.client_id, CLIENT_ID);
+        assert_eq!(token.claims.scope, "profile");
+        assert!(!token.claims.jti.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_denied_authorization() {
+        let state = start_state(&Config::for_tests());
+
+        let response = state.send(Authorize(authorize_request("profile").await)
+            .wrap(Extras::AuthPost(USER_ID.to_string(), OAuth2AuthorizationResult::Deny)))
+            .await
+            .unwrap()
+            .unwrap();
+        let redirect_url = location(&response);
+        assert_eq!(query_value(&redirect_url, "error").as_deref(), Some("access_denied"));
+        assert!(query_value(&redirect_url, "code").is_none());
+    }
+
+    #[test]
+    fn test_invalid_consent_url() {
+        let mut config = Config::for_tests();
+        config.oauth_consent_url_template = "not a url".to_string();
+        let pool = r2d2::Pool::builder()
+            .build_unchecked(ConnectionManager::new("postgres://localhost:1/unavailable"));
+
+        assert!(OAuth2State::preconfigured(config, &OAuthConfig::default(), token_key(), pool).is_err());
+    }
+}
@@ -3,30 +3,50 @@ use chrono::Utc;
 use oxide_auth::endpoint::Issuer;
 use oxide_auth::primitives::grant::Grant;
 use oxide_auth::primitives::issuer::{IssuedToken, RefreshedToken, TokenType};
-use crate::auth::{create_jwt_token, JwtTokenScope, JwtTokenType};
+use crate::auth::{create_jwt_token, create_oauth_access_token, JwtTokenScope, JwtTokenType, OAuthTokenKey};
 use crate::config::Config;
+use crate::db::models::oauth_authorization::OAuthAuthorization;
+use crate::db::Pool;
 
 pub struct JwtTokenIssuer {
     config: Config,
+    key: OAuthTokenKey,
+    pool: Pool,
 }
 
 impl JwtTokenIssuer {
-    pub fn new(config: Config) -> JwtTokenIssuer {
-        JwtTokenIssuer { config }
+    pub fn new(config: Config, key: OAuthTokenKey, pool: Pool) -> JwtTokenIssuer {
+        JwtTokenIssuer { config, key, pool }
+    }
+
+    fn mark_authorization_used(&self, grant: &Grant) {
+        let result = self.pool.get()
+            .map_err(|e| e.to_string())
+            .and_then(|mut conn|
+                OAuthAuthorization::mark_used(&grant.owner_id, &grant.client_id, &mut conn)
+                    .map_err(|e| e.to_string())
+            );
+
+        if let Err(e) = result {
+            log::warn!("Failed to update last usage of oauth authorization for user {}: {}", &grant.owner_id, e);
+        }
     }
 }
 
 impl Issuer for JwtTokenIssuer {
     fn issue(&mut self, grant: Grant) -> Result<IssuedToken, ()> {
-        let token = create_jwt_token(&grant.owner_id, JwtTokenType::Access, &JwtTokenScope::Profile, &self.config)
+        let scope = JwtTokenScope::from_oauth_scope(&grant.scope);
+        let token = create_oauth_access_token(&grant.owner_id, &grant.client_id, &grant.scope, &self.key, &self.config)
             .map_err(|e| {
                 log::error!("Failed to create oauth access token for user {}: {:?}", &grant.owner_id, e)
             })?;
-        let refresh = create_jwt_token(&grant.owner_id, JwtTokenType::Refresh, &JwtTokenScope::Profile, &self.config)
+        let refresh = create_jwt_token(&grant.owner_id, JwtTokenType::Refresh, &scope, Some(&grant.client_id), &self.config)
             .map_err(|e|
                 log::error!("Failed to create oauth access token for user {}: {:?}", &grant.owner_id, e)
             )?;
 
+        self.mark_authorization_used(&grant);
+
         Ok(IssuedToken {
             token,
             refresh: Some(refresh),
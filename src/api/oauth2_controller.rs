@@ -1,45 +1,51 @@
 use std::ops::Deref;
 
 use actix::Addr;
-use actix_web::web;
+use chrono::Utc;
+use actix_web::{HttpRequest, HttpResponse, web};
 use oxide_auth::endpoint::{QueryParameter, WebResponse};
 
 use crate::{api::oauth2::state::OAuth2State, AppState, middlewares::auth::JwtMiddleware};
-use crate::api::models::oauth2::{OAuth2AuthorizationResult, OAuthRedirectResponse};
+use crate::api::models::MessageResponse;
+use crate::api::models::oauth2::{
+    JsonWebKeySet, OAuth2AuthorizationResult, OAuthAuthorizationServerMetadata, OAuthClientAuthorization, OAuthClientAuthorizationList, OAuthRedirectResponse,
+};
 use crate::api::utils::enforce_scope;
 use crate::auth::JwtTokenScope;
+use crate::constants::{OAUTH_AUTHORIZE_ROUTE_NAME, OAUTH_JWKS_ROUTE_NAME, OAUTH_TOKEN_ROUTE_NAME};
+use crate::errors::{ErrorResponse, ServiceError};
 use crate::services::auth_service;
 
-use super::oauth2::oxide_auth_actix::{Authorize, ClientCredentials, OAuthOperation, OAuthRequest, OAuthResponse, Refresh, Token, WebError};
-use super::oauth2::state::Extras;
+use super::oauth2::oxide_auth_actix::{Authorize, ClientCredentials, OAuthOperation, OAuthRequest, OAuthResponse, Token, WebError};
+use super::oauth2::registrar::negotiate_scope;
+use super::oauth2::state::{ConsentGrant, Extras};
 
 pub async fn get_authorize(req: OAuthRequest, state: web::Data<Addr<OAuth2State>>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<OAuthResponse, WebError> {
-    // Check if the user has authorized this client before
-    if let Some(user) = auth_service::user_details(&data.db, jwt.user_id).ok() {
-        let client_id = req
-            .query()
-            .and_then(|params| params.unique_value("client_id"))
-            .unwrap_or_default();
-
-        let conn = &mut data.db.get().unwrap();
-        if user.has_authorized_oauth_client(&client_id, conn) {
-            return state.send(Authorize(req)
-                .wrap(Extras::AuthPost(user.id, OAuth2AuthorizationResult::Allow))
-            ).await?
-        }
-    }
+    // Check if the user has authorized this client before. The consent is only reused
+    // if it covers all requested scopes, which is decided by the solicitor.
+    let consent = auth_service::user_details(&data.db, jwt.user_id)
+        .ok()
+        .and_then(|user| {
+            let client_id = req
+                .query()
+                .and_then(|params| params.unique_value("client_id"))
+                .unwrap_or_default();
+
+            let conn = &mut data.db.get().unwrap();
+            user.authorization_for_oauth_client(&client_id, conn)
+        })
+        .filter(|authorization| !authorization.is_expired())
+        .and_then(|authorization| Some(ConsentGrant {
+            scope: authorization.granted_scope()?,
+            user_id: authorization.user_id,
+        }));
 
     state.send(Authorize(req)
-        .wrap(Extras::AuthGet))
+        .wrap(Extras::AuthGet(consent)))
         .await?
 }
 
 pub async fn post_authorize(req: OAuthRequest, state: web::Data<Addr<OAuth2State>>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<OAuthResponse, WebError> {
-    enforce_scope(&jwt, JwtTokenScope::Full).map_err(|_| WebError::Authorization)?;
-
-    let mut user = auth_service::user_details(&data.db, jwt.user_id)
-        .map_err(|_| WebError::Authorization)?;
-
     let result_string = req.query()
         .and_then(|q| q.unique_value("result"))
         .ok_or(WebError::Query)?;
@@ -50,38 +56,18 @@ pub async fn post_authorize(req: OAuthRequest, state: web::Data<Addr<OAuth2State
         _ => Err(WebError::Query),
     }?;
 
-    let response = state.send(Authorize(req.clone())
-        .wrap(Extras::AuthPost(user.id.to_string(), result.clone())))
-        .await?;
-
-    match response {
+    match complete_authorization(req, result, &state, &data, &jwt).await {
         Ok(r) => {
-            // Save the authorization
-            let client_id = req
-                .query()
-                .and_then(|params| params.unique_value("client_id"))
-                .unwrap_or_default();
-            let conn = &mut data.db.get().unwrap();
-
-            match result {
-                OAuth2AuthorizationResult::Allow => {
-                    user.save_oauth_client_authorization(&client_id, conn)
-                        .map_err(|_| WebError::Authorization)?;
-                },
-                OAuth2AuthorizationResult::Deny => {
-                    user.remove_oauth_client_authorization(&client_id, conn)
-                        .map_err(|_| WebError::Authorization)?;
-                }
-            };
-
             let headers = r.get_headers();
             let location = headers.get("location");
             if let Some(redirect_url) = location {
+                let redirect_url = redirect_url.to_str()
+                    .map_err(|_| WebError::InternalError(Some("Invalid redirect url".to_string())))?;
                 let body = serde_json::to_string(&OAuthRedirectResponse {
-                    redirect_url: redirect_url.to_str().unwrap().to_string()
-                }).unwrap();
+                    redirect_url: redirect_url.to_string()
+                }).map_err(|_| WebError::Encoding)?;
                 let mut modified_response = OAuthResponse::ok();
-                _ = modified_response.body_json(&body);
+                modified_response.body_json(&body)?;
                 Ok(modified_response)
             } else {
                 Ok(r)
@@ -91,6 +77,53 @@ pub async fn post_authorize(req: OAuthRequest, state: web::Data<Addr<OAuth2State
     }
 }
 
+/// Finish an authorization request with the user's decision and remember it for
+/// subsequent requests of the same client.
+pub async fn complete_authorization(
+    req: OAuthRequest, result: OAuth2AuthorizationResult,
+    state: &Addr<OAuth2State>, data: &AppState, jwt: &JwtMiddleware,
+) -> Result<OAuthResponse, WebError> {
+    enforce_scope(jwt, JwtTokenScope::Full).map_err(|_| WebError::Authorization)?;
+
+    let mut user = auth_service::user_details(&data.db, jwt.user_id)
+        .map_err(|_| WebError::Authorization)?;
+
+    let response = state.send(Authorize(req.clone())
+        .wrap(Extras::AuthPost(user.id.to_string(), result.clone())))
+        .await??;
+
+    // Save the authorization
+    let client_id = req
+        .query()
+        .and_then(|params| params.unique_value("client_id"))
+        .unwrap_or_default();
+    let conn = &mut data.db.get().unwrap();
+
+    match result {
+        OAuth2AuthorizationResult::Allow => {
+            let allowed_scope = data.oauth_config.client(&client_id)
+                .ok_or(WebError::Query)?
+                .allowed_scope();
+            let requested_scope = req.query()
+                .and_then(|params| params.unique_value("scope"))
+                .and_then(|scope| scope.parse().ok());
+            let granted_scope = negotiate_scope(&allowed_scope, requested_scope.as_ref())
+                .ok_or(WebError::Query)?;
+            let expires_at = data.env.oauth_consent_expiration
+                .map(|seconds| Utc::now().naive_utc() + chrono::Duration::seconds(seconds));
+
+            user.save_oauth_client_authorization(&client_id, &granted_scope, expires_at, conn)
+                .map_err(|_| WebError::Authorization)?;
+        },
+        OAuth2AuthorizationResult::Deny => {
+            user.remove_oauth_client_authorization(&client_id, conn)
+                .map_err(|_| WebError::Authorization)?;
+        }
+    };
+
+    Ok(response)
+}
+
 pub async fn token(req: OAuthRequest, state: web::Data<Addr<OAuth2State>>) -> Result<OAuthResponse, WebError> {
     let grant_type = req.body().and_then(|body| body.unique_value("grant_type"));
 
@@ -106,8 +139,124 @@ pub async fn token(req: OAuthRequest, state: web::Data<Addr<OAuth2State>>) -> Re
     }
 }
 
-async fn refresh(
-    (req, state): (OAuthRequest, web::Data<Addr<OAuth2State>>),
-) -> Result<OAuthResponse, WebError> {
-    state.send(Refresh(req).wrap(Extras::Nothing)).await?
+
+
+/// Get all OAuth clients the current user has authorized
+#[utoipa::path(
+    get,
+    path = "/api/auth/oauth2/authorizations",
+    responses(
+        (status = 200, response = OAuthClientAuthorizationList),
+        (status = 401, description = "User authentication failed."),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_authorizations(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let user = auth_service::user_details(&data.db, jwt.user_id)?;
+    let authorizations: Vec<OAuthClientAuthorization> = user.oauth_client_authorizations(&mut data.db.get().unwrap())
+        .map_err(|e| {
+            log::debug!("Failed to get oauth authorizations for user {:?}: {:?}", jwt.user_id, e);
+            ServiceError::InternalServerError { error_message: "Failed to get authorized clients".to_string() }
+        })?
+        .iter()
+        .map(|authorization| {
+            let client = data.oauth_config.client(&authorization.client_id);
+            OAuthClientAuthorization {
+                client_id: authorization.client_id.clone(),
+                client_name: client.and_then(|c| c.client_name.clone()),
+                scopes: authorization.scope.split_whitespace().map(|s| s.to_string()).collect(),
+                first_used_at: authorization.created_at.timestamp(),
+                last_used_at: authorization.last_used_at.map(|t| t.timestamp()),
+                expires_at: authorization.expires_at.map(|t| t.timestamp()),
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(authorizations))
+}
+
+
+/// Revoke the current user's authorization for an OAuth client
+///
+/// All tokens that were previously issued to the client on behalf of the user become invalid.
+#[utoipa::path(
+    delete,
+    path = "/api/auth/oauth2/authorizations/{client_id}",
+    params(
+        ("client_id" = String, Path, description = "Identifier of the OAuth client"),
+    ),
+    responses(
+        (status = 200, description = "The authorization was revoked."),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn delete_authorization(path: web::Path<String>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let client_id = path.into_inner();
+    let mut user = auth_service::user_details(&data.db, jwt.user_id)?;
+    let conn = &mut data.db.get().unwrap();
+
+    if !user.has_authorized_oauth_client(&client_id, conn) {
+        return Err(ServiceError::NotFound { error_message: "You didn't authorize this client.".to_string() })
+    }
+
+    user.remove_oauth_client_authorization(&client_id, conn)
+        .map_err(|e| {
+            log::debug!("Failed to remove oauth authorization for user {:?}: {:?}", jwt.user_id, e);
+            ServiceError::InternalServerError { error_message: "Failed to revoke authorization".to_string() }
+        })?;
+
+    Ok(HttpResponse::Ok().json(MessageResponse { message: "Authorization revoked".to_string() }))
+}
+
+
+/// Get the OAuth 2.0 authorization server metadata
+///
+/// Endpoint locations are resolved from the registered routes, so clients don't have to hardcode them.
+#[utoipa::path(
+    get,
+    path = "/.well-known/oauth-authorization-server",
+    responses(
+        (status = 200, response = OAuthAuthorizationServerMetadata),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn metadata(req: HttpRequest, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let issuer = data.env.oauth_issuer();
+    let endpoint_url = |route_name: &str| {
+        req.url_for_static(route_name)
+            .map(|url| format!("{}{}", issuer, url.path()))
+            .map_err(|e| {
+                log::error!("Failed to resolve route {}: {:?}", route_name, e);
+                ServiceError::InternalServerError { error_message: "Failed to resolve OAuth endpoints".to_string() }
+            })
+    };
+
+    Ok(HttpResponse::Ok().json(OAuthAuthorizationServerMetadata {
+        authorization_endpoint: endpoint_url(OAUTH_AUTHORIZE_ROUTE_NAME)?,
+        token_endpoint: endpoint_url(OAUTH_TOKEN_ROUTE_NAME)?,
+        jwks_uri: endpoint_url(OAUTH_JWKS_ROUTE_NAME)?,
+        issuer,
+        response_types_supported: vec!["code".to_string()],
+        grant_types_supported: vec!["authorization_code".to_string(), "client_credentials".to_string()],
+        token_endpoint_auth_methods_supported: vec!["client_secret_basic".to_string()],
+        scopes_supported: data.oauth_config.supported_scopes(),
+    }))
+}
+
+/// Get the keys that sign the OAuth access tokens
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, response = JsonWebKeySet),
+    )
+)]
+pub async fn jwks(data: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(JsonWebKeySet { keys: vec![data.oauth_token_key.jwk()] })
 }
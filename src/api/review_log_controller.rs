@@ -0,0 +1,86 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{AppState, errors::{ServiceError, ErrorResponse}};
+use crate::services::review_log_service;
+
+use super::models::review_log::{
+    ReviewLogQuery, ReviewLogEntriesQuery, ReviewLogInclusionProofQuery, ReviewLogConsistencyProofQuery,
+    ReviewLogTreeHead, ReviewLogEntryList, ReviewLogInclusionProof, ReviewLogConsistencyProof,
+};
+
+
+/// Get the latest signed tree head of an app's review log
+///
+/// Every signed review and deletion of an app is appended to its transparency log. Tree heads
+/// are signed periodically with the review signing key.
+#[utoipa::path(
+    get,
+    path = "/api/reviews/log/tree_head",
+    params(ReviewLogQuery),
+    responses(
+        (status = 200, response = ReviewLogTreeHead),
+        (status = 404, response = ErrorResponse),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_tree_head(query: web::Query<ReviewLogQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let tree_head = review_log_service::latest_tree_head(&query.source_identifier, &query.app_bundle_id, &data.db)?;
+    Ok(HttpResponse::Ok().json(tree_head))
+}
+
+
+/// Get entries of an app's review log
+#[utoipa::path(
+    get,
+    path = "/api/reviews/log/entries",
+    params(ReviewLogEntriesQuery),
+    responses(
+        (status = 200, response = ReviewLogEntryList),
+        (status = 400, response = ErrorResponse),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_entries(query: web::Query<ReviewLogEntriesQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let entries = review_log_service::entries(&query.source_identifier, &query.app_bundle_id, query.start, query.end, &data.db)?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+
+/// Get the proof that a signed review is included in an app's review log
+#[utoipa::path(
+    get,
+    path = "/api/reviews/log/inclusion_proof",
+    params(ReviewLogInclusionProofQuery),
+    responses(
+        (status = 200, response = ReviewLogInclusionProof),
+        (status = 400, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_inclusion_proof(query: web::Query<ReviewLogInclusionProofQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let proof = review_log_service::inclusion_proof(
+        &query.source_identifier, &query.app_bundle_id, &query.leaf_hash, query.tree_size, &data.db
+    )?;
+    Ok(HttpResponse::Ok().json(proof))
+}
+
+
+/// Get the proof that an older version of an app's review log is a prefix of a newer one
+#[utoipa::path(
+    get,
+    path = "/api/reviews/log/consistency_proof",
+    params(ReviewLogConsistencyProofQuery),
+    responses(
+        (status = 200, response = ReviewLogConsistencyProof),
+        (status = 400, response = ErrorResponse),
+        (status = 404, response = ErrorResponse),
+        (status = 500, response = ErrorResponse),
+    )
+)]
+pub async fn get_consistency_proof(query: web::Query<ReviewLogConsistencyProofQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let proof = review_log_service::consistency_proof(
+        &query.source_identifier, &query.app_bundle_id, query.first_tree_size, query.second_tree_size, &data.db
+    )?;
+    Ok(HttpResponse::Ok().json(proof))
+}
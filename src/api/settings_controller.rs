@@ -0,0 +1,279 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    AppState,
+    errors::{ServiceError, ErrorResponse},
+    middlewares::auth::JwtMiddleware,
+};
+use crate::api::utils::enforce_scope;
+use crate::auth::JwtTokenScope;
+use crate::services::{settings_service, settings_sync_service};
+
+use super::models::MessageResponse;
+use super::models::settings::{
+    Device, DeviceList, DeviceRequest, Setting, SettingList, SettingRequest, SettingsPatchRequest, SettingsSyncRequest,
+    SettingsSyncResponse, SettingVersionQuery,
+};
+
+
+/// Get all settings of the current user
+///
+/// Needs the `settings.read` scope.
+#[utoipa::path(
+    get,
+    path = "/api/settings",
+    responses(
+        (status = 200, response = SettingList),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn get_all(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::SettingsRead)?;
+
+    let settings = settings_service::settings(&jwt.user_id, None, &data.db)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+
+/// Get the settings of a namespace
+///
+/// Needs the `settings.read` scope.
+#[utoipa::path(
+    get,
+    path = "/api/settings/{namespace}",
+    params(
+        ("namespace" = String, Path, description = "Dotted name of the namespace, like `sidestore.app`"),
+    ),
+    responses(
+        (status = 200, response = SettingList),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn get_namespace(path: web::Path<String>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::SettingsRead)?;
+
+    let settings = settings_service::settings(&jwt.user_id, Some(&path), &data.db)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+
+/// Change several settings of a namespace
+///
+/// Sets the settings of the body and deletes those that are `null`, all at once. If a change is refused, like
+/// because the setting changed since the given version, none are made. Returns all settings of the namespace.
+/// Needs the `settings` scope.
+#[utoipa::path(
+    patch,
+    path = "/api/settings/{namespace}",
+    params(
+        ("namespace" = String, Path, description = "Dotted name of the namespace, like `sidestore.app`"),
+    ),
+    request_body = SettingsPatchRequest,
+    responses(
+        (status = 200, response = SettingList),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 409, response = ErrorResponse),
+    )
+)]
+pub async fn patch_namespace(path: web::Path<String>, body: web::Json<SettingsPatchRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let settings = settings_service::patch(&jwt.user_id, &path, &body, &data.db)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+
+/// Delete all settings of a namespace
+///
+/// Needs the `settings` scope.
+#[utoipa::path(
+    delete,
+    path = "/api/settings/{namespace}",
+    params(
+        ("namespace" = String, Path, description = "Dotted name of the namespace, like `sidestore.app`"),
+    ),
+    responses(
+        (status = 200, description = "The settings were deleted."),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn delete_namespace(path: web::Path<String>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let deleted = settings_service::delete_namespace(&jwt.user_id, &path, &data.db)?;
+    Ok(HttpResponse::Ok().json(MessageResponse { message: format!("Deleted {} settings", deleted) }))
+}
+
+
+/// Get a setting
+///
+/// Needs the `settings.read` scope.
+#[utoipa::path(
+    get,
+    path = "/api/settings/{namespace}/{key}",
+    params(
+        ("namespace" = String, Path, description = "Dotted name of the namespace, like `sidestore.app`"),
+        ("key" = String, Path, description = "Key of the setting in the namespace"),
+    ),
+    responses(
+        (status = 200, response = Setting),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn get(path: web::Path<(String, String)>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::SettingsRead)?;
+
+    let (namespace, key) = path.into_inner();
+    let setting = settings_service::setting(&jwt.user_id, &namespace, &key, &data.db)?;
+    Ok(HttpResponse::Ok().json(setting))
+}
+
+
+/// Set a setting
+///
+/// Values are JSON of up to 16 KiB, users have up to 1000 settings. Every change increments the setting's
+/// version. With the version the device last saw the change is refused if another device changed the setting
+/// since. Needs the `settings` scope.
+#[utoipa::path(
+    put,
+    path = "/api/settings/{namespace}/{key}",
+    params(
+        ("namespace" = String, Path, description = "Dotted name of the namespace, like `sidestore.app`"),
+        ("key" = String, Path, description = "Key of the setting in the namespace"),
+    ),
+    request_body = SettingRequest,
+    responses(
+        (status = 200, response = Setting),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 409, response = ErrorResponse),
+    )
+)]
+pub async fn put(path: web::Path<(String, String)>, body: web::Json<SettingRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let (namespace, key) = path.into_inner();
+    let setting = settings_service::put(&jwt.user_id, &namespace, &key, &body, &data.db)?;
+    Ok(HttpResponse::Ok().json(setting))
+}
+
+
+/// Delete a setting
+///
+/// Needs the `settings` scope.
+#[utoipa::path(
+    delete,
+    path = "/api/settings/{namespace}/{key}",
+    params(
+        ("namespace" = String, Path, description = "Dotted name of the namespace, like `sidestore.app`"),
+        ("key" = String, Path, description = "Key of the setting in the namespace"),
+        SettingVersionQuery,
+    ),
+    responses(
+        (status = 200, description = "The setting was deleted."),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+        (status = 409, response = ErrorResponse),
+    )
+)]
+pub async fn delete(path: web::Path<(String, String)>, query: web::Query<SettingVersionQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let (namespace, key) = path.into_inner();
+    settings_service::delete(&jwt.user_id, &namespace, &key, query.version, &data.db)?;
+    Ok(HttpResponse::Ok().json(MessageResponse { message: "Setting deleted".to_string() }))
+}
+
+
+/// Sync the settings of a device
+///
+/// The device sends the change token of its last sync and its local changes since, with the vector clocks of the
+/// changed settings, and gets the settings that changed on the server since and a new change token. Local changes
+/// made concurrently with a change on the server aren't applied and are returned as conflicts. Needs the `settings`
+/// scope.
+#[utoipa::path(
+    post,
+    path = "/api/settings/sync",
+    request_body = SettingsSyncRequest,
+    responses(
+        (status = 200, response = SettingsSyncResponse),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn sync(body: web::Json<SettingsSyncRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let response = settings_sync_service::sync(&jwt.user_id, &body, &data.db)?;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+
+/// Get the devices that sync settings
+///
+/// Needs the `settings.read` scope.
+#[utoipa::path(
+    get,
+    path = "/api/settings/devices",
+    responses(
+        (status = 200, response = DeviceList),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn get_devices(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::SettingsRead)?;
+
+    let devices = settings_sync_service::devices(&jwt.user_id, &data.db)?;
+    Ok(HttpResponse::Ok().json(devices))
+}
+
+
+/// Register a device to sync settings
+///
+/// Users have up to 50 devices. Needs the `settings` scope.
+#[utoipa::path(
+    post,
+    path = "/api/settings/devices",
+    request_body = DeviceRequest,
+    responses(
+        (status = 200, response = Device),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn register_device(body: web::Json<DeviceRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let device = settings_sync_service::register_device(&jwt.user_id, &body, &data.db)?;
+    Ok(HttpResponse::Ok().json(device))
+}
+
+
+/// Delete a device
+///
+/// The device can't sync anymore. Needs the `settings` scope.
+#[utoipa::path(
+    delete,
+    path = "/api/settings/devices/{id}",
+    params(
+        ("id" = String, Path, description = "Id of the device"),
+    ),
+    responses(
+        (status = 200, response = Device),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn delete_device(path: web::Path<String>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Settings)?;
+
+    let device = settings_sync_service::delete_device(&jwt.user_id, &path, &data.db)?;
+    Ok(HttpResponse::Ok().json(device))
+}
@@ -0,0 +1,214 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    AppState,
+    errors::{ServiceError, ErrorResponse},
+    middlewares::auth::JwtMiddleware,
+};
+use crate::api::utils::enforce_scope;
+use crate::auth::JwtTokenScope;
+use crate::services::{review_reply_service, source_service};
+
+use super::models::review_replies::{ReviewReply, ReviewReplyDeletionRequest, ReviewReplyRequest};
+use super::models::sources::{
+    SourceClaim, SourceClaimList, SourceClaimRequest, SourceQuery, SourceReviewList, SourceReviewsQuery, SourceSettings,
+    SourceStatsList, SourceStatsQuery, SourceVerificationRequest,
+};
+
+
+/// Claim to maintain a source
+///
+/// Returns a challenge to prove the claim with. Serve it in the `sidestoreIdVerification` field of the source's
+/// JSON, as a string or in a list, or as a line of the well-known file at the source's origin, and verify the claim.
+#[utoipa::path(
+    post,
+    path = "/api/sources/claims",
+    request_body = SourceClaimRequest,
+    responses(
+        (status = 200, response = SourceClaim),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn claim(body: web::Json<SourceClaimRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let claim = source_service::claim(&body, &jwt.user_id, &data.db, &data.env)?;
+    Ok(HttpResponse::Ok().json(claim))
+}
+
+
+/// Get the user's source claims
+#[utoipa::path(
+    get,
+    path = "/api/sources/claims",
+    responses(
+        (status = 200, response = SourceClaimList),
+        (status = 401, description = "User authentication failed."),
+    )
+)]
+pub async fn get_claims(data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let claims = source_service::claims(&jwt.user_id, &data.db)?;
+    Ok(HttpResponse::Ok().json(claims))
+}
+
+
+/// Verify a source claim
+///
+/// Fetches the source's JSON, which has to have the claimed identifier, and looks for the challenge in it and
+/// in the well-known file. Verified maintainers can use the other source endpoints.
+#[utoipa::path(
+    post,
+    path = "/api/sources/claims/verify",
+    request_body = SourceVerificationRequest,
+    responses(
+        (status = 200, response = SourceClaim),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn verify_claim(body: web::Json<SourceVerificationRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+
+    let (pool, config, user_id) = (data.db.clone(), data.env.clone(), jwt.user_id);
+    let claim = web::block(move || source_service::verify(&body, &user_id, &pool, &config))
+        .await
+        .map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })??;
+    Ok(HttpResponse::Ok().json(claim))
+}
+
+
+/// Get the reviews of a source
+///
+/// Lists the reviews of the source's apps, or of one app, with their status. Only for verified maintainers.
+#[utoipa::path(
+    get,
+    path = "/api/sources/reviews",
+    params(SourceReviewsQuery),
+    responses(
+        (status = 200, response = SourceReviewList),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+    )
+)]
+pub async fn get_reviews(query: web::Query<SourceReviewsQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&query.source_identifier, &jwt.user_id, &data.db)?;
+
+    let reviews = source_service::reviews(&query, &data.db)?;
+    Ok(HttpResponse::Ok().json(reviews))
+}
+
+
+/// Get the rating statistics of a source
+///
+/// Signed rating statistics of every reviewed app of the source, which include reviews held for moderation if
+/// `include_held` is set. Only for verified maintainers.
+#[utoipa::path(
+    get,
+    path = "/api/sources/stats",
+    params(SourceStatsQuery),
+    responses(
+        (status = 200, response = SourceStatsList),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+    )
+)]
+pub async fn get_stats(query: web::Query<SourceStatsQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&query.source_identifier, &jwt.user_id, &data.db)?;
+
+    let stats = source_service::stats(&query, &data.db, &data.env, &data.review_keys, &data.review_stats)?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+
+/// Get the settings of a source
+///
+/// Only for verified maintainers.
+#[utoipa::path(
+    get,
+    path = "/api/sources/settings",
+    params(SourceQuery),
+    responses(
+        (status = 200, response = SourceSettings),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+    )
+)]
+pub async fn get_settings(query: web::Query<SourceQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&query.source_identifier, &jwt.user_id, &data.db)?;
+
+    let settings = source_service::settings(&query.source_identifier, &data.db)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+
+/// Change the settings of a source
+///
+/// A source that doesn't accept reviews rejects new reviews and changes of reviews, existing reviews can still
+/// be deleted. Only for verified maintainers.
+#[utoipa::path(
+    put,
+    path = "/api/sources/settings",
+    request_body = SourceSettings,
+    responses(
+        (status = 200, response = SourceSettings),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+    )
+)]
+pub async fn put_settings(body: web::Json<SourceSettings>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&body.source_identifier, &jwt.user_id, &data.db)?;
+
+    let settings = source_service::update_settings(&body, &data.db)?;
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+
+/// Reply to a review
+///
+/// Answers a published review of an app of the source, or changes the answer. A review has one reply, which any
+/// maintainer of the source can change. The reply is signed with its own payload type, which references the
+/// review's sequence number. Only for verified maintainers.
+#[utoipa::path(
+    post,
+    path = "/api/sources/replies",
+    request_body = ReviewReplyRequest,
+    responses(
+        (status = 200, response = ReviewReply),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn reply(body: web::Json<ReviewReplyRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&body.source_identifier, &jwt.user_id, &data.db)?;
+
+    let reply = review_reply_service::reply(&body, &jwt.user_id, &data.db, &data.env, &data.review_keys)?;
+    Ok(HttpResponse::Ok().json(reply))
+}
+
+
+/// Delete the reply to a review
+///
+/// The deletion is signed like the reply. Only for verified maintainers.
+#[utoipa::path(
+    delete,
+    path = "/api/sources/replies",
+    request_body = ReviewReplyDeletionRequest,
+    responses(
+        (status = 200, response = ReviewReply),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn delete_reply(body: web::Json<ReviewReplyDeletionRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&body.source_identifier, &jwt.user_id, &data.db)?;
+
+    let reply = review_reply_service::delete(&body, &jwt.user_id, &data.db, &data.env, &data.review_keys)?;
+    Ok(HttpResponse::Ok().json(reply))
+}
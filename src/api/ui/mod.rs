@@ -0,0 +1,157 @@
+use std::fs;
+use std::path::Path;
+
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::{header::ContentType, StatusCode};
+use actix_web::{HttpRequest, HttpResponse};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as base64_engine};
+use log::{debug, info};
+use rand::RngCore;
+use tera::{Context, Tera};
+
+use crate::config::Config;
+use crate::constants::{CSRF_COOKIE_NAME, TEMPLATES_DIRECTORY_NAME};
+use crate::errors::ServiceError;
+
+/// Templates shipped with the service. Each of them can be replaced by a file with the
+/// same name in the `templates` directory of the storage path.
+const DEFAULT_TEMPLATES: [(&str, &str); 3] = [
+    ("base.html", include_str!("../../../templates/base.html")),
+    ("login.html", include_str!("../../../templates/login.html")),
+    ("consent.html", include_str!("../../../templates/consent.html")),
+];
+
+pub struct Templates {
+    tera: Tera,
+}
+
+impl Templates {
+    pub fn load(config: &Config) -> Result<Templates, String> {
+        let templates_path = Path::new(&config.storage_path).join(TEMPLATES_DIRECTORY_NAME);
+
+        let templates: Vec<(&str, String)> = DEFAULT_TEMPLATES
+            .iter()
+            .map(|(name, default_template)| {
+                let custom_template_path = templates_path.join(name);
+                match fs::read_to_string(&custom_template_path) {
+                    Ok(custom_template) => {
+                        info!("Using custom template {}", custom_template_path.display());
+                        (*name, custom_template)
+                    },
+                    Err(_) => (*name, default_template.to_string()),
+                }
+            })
+            .collect();
+
+        let mut tera = Tera::default();
+        tera.add_raw_templates(templates)
+            .map_err(|e| format!("Failed to parse templates: {:?}", e))?;
+
+        Ok(Templates { tera })
+    }
+
+    pub fn render(&self, status: StatusCode, name: &str, context: &Context) -> Result<HttpResponse, ServiceError> {
+        let body = self.tera.render(name, context)
+            .map_err(|e| {
+                debug!("Failed to render template {}: {:?}", name, e);
+                ServiceError::InternalServerError { error_message: "Failed to render page".to_string() }
+            })?;
+
+        Ok(HttpResponse::build(status)
+            .insert_header(ContentType::html())
+            // Prevent the pages from being embedded to trick users into clicking buttons
+            .insert_header(("X-Frame-Options", "DENY"))
+            .insert_header(("Content-Security-Policy", "frame-ancestors 'none'"))
+            .body(body))
+    }
+}
+
+
+/// Get the CSRF token of the current browser session, or create a new one. The token is
+/// stored in a cookie and has to be submitted with every form (double submit cookie).
+pub fn csrf_token(req: &HttpRequest) -> (String, Cookie<'static>) {
+    let token = req.cookie(CSRF_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_string())
+        .filter(|token| !token.is_empty())
+        .unwrap_or_else(|| {
+            let mut bytes = [0u8; 32];
+            rand::thread_rng().fill_bytes(&mut bytes);
+            base64_engine.encode(bytes)
+        });
+
+    let cookie = Cookie::build(CSRF_COOKIE_NAME, token.clone())
+        .path("/auth")
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish();
+
+    (token, cookie)
+}
+
+pub fn verify_csrf_token(req: &HttpRequest, submitted_token: Option<&str>) -> Result<(), ServiceError> {
+    let expected_token = req.cookie(CSRF_COOKIE_NAME).map(|cookie| cookie.value().to_string());
+
+    match (expected_token, submitted_token) {
+        (Some(expected), Some(submitted)) if !expected.is_empty() && constant_time_eq(expected.as_bytes(), submitted.as_bytes()) => Ok(()),
+        _ => Err(ServiceError::BadRequest { error_message: "Invalid CSRF token".to_string() }),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Only allow redirects to paths on this service after signing in
+pub fn safe_redirect_target(next: Option<&str>) -> String {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => next.to_string(),
+        _ => "/".to_string(),
+    }
+}
+
+/// Human readable description of an OAuth scope for the consent page
+pub fn scope_description(scope: &str) -> String {
+    match scope {
+        "full" => "Full access to your SideStore ID account".to_string(),
+        "profile" => "See your email address and username".to_string(),
+        "settings" => "See and change your synced settings".to_string(),
+        "settings.read" => "See your synced settings".to_string(),
+        _ => format!("Access \"{}\"", scope),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn test_safe_redirect_target() {
+        let consent_page = "/auth/authorize?client_id=test-client&scope=profile";
+        assert_eq!(safe_redirect_target(Some(consent_page)), consent_page);
+
+        for next in [None, Some(""), Some("https://example.com/"), Some("//example.com/"), Some("/\\example.com"), Some("javascript:alert(1)")] {
+            assert_eq!(safe_redirect_target(next), "/", "{:?}", next);
+        }
+    }
+
+    #[test]
+    fn test_csrf_token() {
+        let req = TestRequest::default().cookie(Cookie::new(CSRF_COOKIE_NAME, "token")).to_http_request();
+        assert!(verify_csrf_token(&req, Some("token")).is_ok());
+        assert!(verify_csrf_token(&req, Some("other")).is_err());
+        assert!(verify_csrf_token(&req, None).is_err());
+        assert_eq!(csrf_token(&req).0, "token");
+
+        // Without a cookie, or with an empty one, no token is accepted
+        let req = TestRequest::default().to_http_request();
+        assert!(verify_csrf_token(&req, Some("token")).is_err());
+        let req = TestRequest::default().cookie(Cookie::new(CSRF_COOKIE_NAME, "")).to_http_request();
+        assert!(verify_csrf_token(&req, Some("")).is_err());
+        assert!(!csrf_token(&req).0.is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use actix::Addr;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpRequest, HttpResponse, Responder, web};
+use oxide_auth::endpoint::QueryParameter;
+use serde::{Deserialize, Serialize};
+use tera::Context;
+
+use crate::AppState;
+use crate::api::auth_controller::get_auth_cookies;
+use crate::api::models::oauth2::OAuth2AuthorizationResult;
+use crate::api::oauth2::oxide_auth_actix::OAuthRequest;
+use crate::api::oauth2::state::OAuth2State;
+use crate::api::oauth2_controller::complete_authorization;
+use crate::api::ui::{csrf_token, safe_redirect_target, scope_description, verify_csrf_token, Templates};
+use crate::db::models::user::UserDTO;
+use crate::errors::ServiceError;
+use crate::middlewares::auth::JwtMiddleware;
+use crate::services::auth_service;
+
+/// Parameters of the authorization request that are passed through the consent form
+const AUTHORIZATION_PARAMETERS: [&str; 5] = ["response_type", "client_id", "redirect_uri", "scope", "state"];
+
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct LoginForm {
+    email: String,
+    password: String,
+    csrf_token: String,
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeQuery {
+    client_id: String,
+    scope: Option<String>,
+    granted_scope: Option<String>,
+    added_scope: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ConsentScope {
+    name: String,
+    description: String,
+    is_new: bool,
+}
+
+
+/// Login page
+pub async fn get_login(req: HttpRequest, query: web::Query<LoginQuery>, templates: web::Data<Templates>) -> Result<HttpResponse, ServiceError> {
+    render_login(&req, &templates, StatusCode::OK, query.next.as_deref(), "", None)
+}
+
+pub async fn post_login(req: HttpRequest, form: web::Form<LoginForm>, data: web::Data<AppState>, templates: web::Data<Templates>) -> Result<HttpResponse, ServiceError> {
+    verify_csrf_token(&req, Some(&form.csrf_token))?;
+
+    let user_dto = UserDTO { email: form.email.clone(), password: form.password.clone(), username: None };
+    match auth_service::login(user_dto, &data.db, &data.env) {
+        Ok((_, access_token, refresh_token)) => {
+            let (access_token_cookie, refresh_token_cookie) = get_auth_cookies(&access_token, &refresh_token);
+
+            Ok(HttpResponse::SeeOther()
+                .cookie(access_token_cookie)
+                .cookie(refresh_token_cookie)
+                .insert_header((header::LOCATION, safe_redirect_target(form.next.as_deref())))
+                .finish())
+        },
+        Err(ServiceError::Unauthorized { .. }) => render_login(
+            &req, &templates, StatusCode::UNAUTHORIZED, form.next.as_deref(), &form.email,
+            Some("The email or password is incorrect."),
+        ),
+        Err(e) => Err(e),
+    }
+}
+
+
+/// Consent page for OAuth authorization requests
+pub async fn get_authorize(
+    req: HttpRequest, query: web::Query<AuthorizeQuery>,
+    data: web::Data<AppState>, templates: web::Data<Templates>, jwt: Option<JwtMiddleware>,
+) -> Result<HttpResponse, ServiceError> {
+    let user = match jwt.and_then(|jwt| auth_service::user_details(&data.db, jwt.user_id).ok()) {
+        Some(user) => user,
+        None => return Ok(redirect_to_login(&req)),
+    };
+
+    let client = data.oauth_config.client(&query.client_id)
+        .ok_or(ServiceError::BadRequest { error_message: "Unknown client".to_string() })?;
+
+    let added_scopes: Vec<&str> = query.added_scope.as_deref().unwrap_or_default().split_whitespace().collect();
+    let scopes: Vec<ConsentScope> = query.scope.as_deref()
+        .unwrap_or(&client.scope)
+        .split_whitespace()
+        .map(|scope| ConsentScope {
+            name: scope.to_string(),
+            description: scope_description(scope),
+            is_new: added_scopes.contains(&scope),
+        })
+        .collect();
+
+    // Only pass on the parameters of the original authorization request
+    let query_string = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(
+            url::form_urlencoded::parse(req.query_string().as_bytes())
+                .filter(|(key, _)| AUTHORIZATION_PARAMETERS.contains(&key.as_ref()))
+        )
+        .finish();
+
+    let (csrf_token, csrf_cookie) = csrf_token(&req);
+    let mut context = Context::new();
+    context.insert("client_name", client.client_name.as_deref().unwrap_or(&client.client_id));
+    context.insert("user_email", &user.email);
+    context.insert("scopes", &scopes);
+    context.insert("granted_scopes", &query.granted_scope.is_some());
+    context.insert("query", &query_string);
+    context.insert("csrf_token", &csrf_token);
+
+    let mut response = templates.render(StatusCode::OK, "consent.html", &context)?;
+    response.add_cookie(&csrf_cookie)
+        .map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })?;
+    Ok(response)
+}
+
+pub async fn post_authorize(
+    http_req: HttpRequest, req: OAuthRequest, state: web::Data<Addr<OAuth2State>>,
+    data: web::Data<AppState>, jwt: Option<JwtMiddleware>,
+) -> Result<HttpResponse, ServiceError> {
+    let jwt = match jwt {
+        Some(jwt) => jwt,
+        None => return Ok(redirect_to_login(&http_req)),
+    };
+
+    let body = req.body().ok_or(ServiceError::BadRequest { error_message: "Missing form data".to_string() })?;
+    verify_csrf_token(&http_req, body.unique_value("csrf_token").as_deref())?;
+
+    let result = match body.unique_value("result").as_deref() {
+        Some("allow") => OAuth2AuthorizationResult::Allow,
+        Some("deny") => OAuth2AuthorizationResult::Deny,
+        _ => return Err(ServiceError::BadRequest { error_message: "Invalid authorization result".to_string() }),
+    };
+
+    let response = complete_authorization(req, result, &state, &data, &jwt)
+        .await
+        .map_err(|e| ServiceError::BadRequest { error_message: e.to_string() })?;
+
+    Ok(response.respond_to(&http_req))
+}
+
+
+fn render_login(req: &HttpRequest, templates: &Templates, status: StatusCode, next: Option<&str>, email: &str, error: Option<&str>) -> Result<HttpResponse, ServiceError> {
+    let (csrf_token, csrf_cookie) = csrf_token(req);
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("next", &safe_redirect_target(next));
+    context.insert("email", email);
+    context.insert("error", &error);
+
+    let mut response = templates.render(status, "login.html", &context)?;
+    response.add_cookie(&csrf_cookie)
+        .map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })?;
+    Ok(response)
+}
+
+fn redirect_to_login(req: &HttpRequest) -> HttpResponse {
+    // Come back to the consent page after signing in. Posted forms are shown again.
+    let next = match req.query_string() {
+        "" => req.path().to_string(),
+        query => format!("{}?{}", req.path(), query),
+    };
+    let login_url = format!(
+        "/auth/login?{}",
+        url::form_urlencoded::Serializer::new(String::new()).append_pair("next", &next).finish()
+    );
+
+    HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, login_url))
+        .finish()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use actix::Actor;
+    use actix_web::{test, App};
+    use actix_web::cookie::Cookie;
+    use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+    use diesel::r2d2::{self, ConnectionManager};
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use url::Url;
+
+    use crate::api::oauth2::config::OAuthConfig;
+    use crate::auth::{create_jwt_token, JwtTokenScope, JwtTokenType, OAuthTokenKey};
+    use crate::config::Config;
+    use crate::constants::CSRF_COOKIE_NAME;
+    use crate::db::Pool;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::review_risk_service::RiskPipeline;
+    use crate::services::review_stats_service::ReviewStatsCache;
+    use crate::util::review_signing::ReviewKeyRing;
+    use super::*;
+
+    const CLIENT_ID: &str = "test-client";
+    const REDIRECT_URI: &str = "https://client.example.com/callback";
+
+    /// Pages as they are served with the `consent-ui` feature
+    fn ui_app(pool: Pool) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+        let config = Config::for_tests();
+        let oauth_config: OAuthConfig = toml::from_str(&format!(r#"
+            [[clients]]
+            client_id = "{CLIENT_ID}"
+            client_name = "Test Client"
+            client_secret = "test-secret"
+            scope = "profile settings"
+            redirect_uri = "{REDIRECT_URI}"
+        "#)).unwrap();
+        let oauth_token_key = OAuthTokenKey::new(&SigningKey::generate(&mut OsRng)).unwrap();
+        let oauth2_state = OAuth2State::preconfigured(config.clone(), &oauth_config, oauth_token_key.clone(), pool.clone()).unwrap().start();
+
+        App::new()
+            .app_data(web::Data::new(AppState {
+                db: pool,
+                env: config.clone(),
+                oauth_config,
+                oauth_token_key: oauth_token_key.clone(),
+                review_keys: ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng)),
+                review_stats: ReviewStatsCache::default(),
+                review_risk: Arc::new(RiskPipeline::default()),
+            }))
+            .app_data(web::Data::new(oauth2_state))
+            .app_data(web::Data::new(Templates::load(&config).unwrap()))
+            .service(web::resource("/auth/login")
+                .route(web::get().to(get_login))
+                .route(web::post().to(post_login)))
+            .service(web::resource("/auth/authorize")
+                .route(web::get().to(get_authorize))
+                .route(web::post().to(post_authorize)))
+    }
+
+    fn csrf_cookie(res: &ServiceResponse) -> Cookie<'static> {
+        res.response().cookies().find(|cookie| cookie.name() == CSRF_COOKIE_NAME).unwrap().into_owned()
+    }
+
+    fn location(res: &ServiceResponse) -> String {
+        res.headers().get(header::LOCATION).unwrap().to_str().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn test_login_csrf() {
+        // The form is refused before the credentials are checked, so the database isn't needed
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .min_idle(Some(0))
+            .connection_timeout(Duration::from_millis(10))
+            .build_unchecked(ConnectionManager::new("postgres://localhost:1/unavailable"));
+        let app = test::init_service(ui_app(pool)).await;
+
+        let page = test::call_service(&app, test::TestRequest::get().uri("/auth/login?next=https://example.com/").to_request()).await;
+        assert_eq!(page.status(), StatusCode::OK);
+        let cookie = csrf_cookie(&page);
+        let body = String::from_utf8(test::read_body(page).await.to_vec()).unwrap();
+        assert!(body.contains(&format!("name=\"csrf_token\" value=\"{}\"", cookie.value())));
+        // Off-site targets are replaced with the start page, which Tera escapes
+        assert!(body.contains("name=\"next\" value=\"&#x2F;\""));
+
+        let login = |token: Option<&str>, cookie: Option<Cookie<'static>>| {
+            let mut form = vec![("email", "user@example.com"), ("password", "password")];
+            form.extend(token.map(|token| ("csrf_token", token)));
+            let mut req = test::TestRequest::post().uri("/auth/login").set_form(form);
+            if let Some(cookie) = cookie {
+                req = req.cookie(cookie);
+            }
+            req.to_request()
+        };
+        let status = |req| async { test::call_service(&app, req).await.status() };
+        assert_eq!(status(login(Some(cookie.value()), None)).await, StatusCode::BAD_REQUEST);
+        assert_eq!(status(login(Some("other"), Some(cookie.clone()))).await, StatusCode::BAD_REQUEST);
+        assert_eq!(status(login(None, Some(cookie.clone()))).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    async fn test_consent() {
+        let pool = test_pool();
+        let user_id = create_user(&pool);
+        let token = create_jwt_token(&user_id.to_string(), JwtTokenType::Access, &JwtTokenScope::Full, None, &Config::for_tests()).unwrap();
+        let app = test::init_service(ui_app(pool.clone())).await;
+        let authorize_uri = format!(
+            "/auth/authorize?{}",
+            url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("response_type", "code")
+                .append_pair("client_id", CLIENT_ID)
+                .append_pair("redirect_uri", REDIRECT_URI)
+                .append_pair("scope", "profile")
+                .append_pair("state", "test-state")
+                .finish()
+        );
+
+        // Users that aren't signed in are sent to the login page and back
+        let res = test::call_service(&app, test::TestRequest::get().uri(&authorize_uri).to_request()).await;
+        assert_eq!(res.status(), StatusCode::SEE_OTHER);
+        let login_url = Url::parse(&format!("http://localhost{}", location(&res))).unwrap();
+        assert_eq!(login_url.path(), "/auth/login");
+        assert_eq!(login_url.query_pairs().find(|(key, _)| key == "next").unwrap().1, authorize_uri);
+
+        let page = test::call_service(&app, test::TestRequest::get()
+            .uri(&authorize_uri)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request()
+        ).await;
+        assert_eq!(page.status(), StatusCode::OK);
+        assert_eq!(page.headers().get("X-Frame-Options").unwrap(), "DENY");
+        let cookie = csrf_cookie(&page);
+        let body = String::from_utf8(test::read_body(page).await.to_vec()).unwrap();
+        assert!(body.contains("Authorize Test Client"));
+        assert!(body.contains(&scope_description("profile")));
+        assert!(!body.contains(&scope_description("settings")));
+
+        let decide = |token_value: &str, result: &str| test::TestRequest::post()
+            .uri(&authorize_uri)
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .cookie(cookie.clone())
+            .set_form([("csrf_token", token_value), ("result", result)])
+            .to_request();
+
+        // Forms with another token don't complete the authorization
+        let res = test::call_service(&app, decide("other", "allow")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let res = test::call_service(&app, decide(cookie.value(), "allow")).await;
+        assert_eq!(res.status(), StatusCode::FOUND);
+        let redirect_url = Url::parse(&location(&res)).unwrap();
+        assert!(redirect_url.as_str().starts_with(REDIRECT_URI));
+        let query: Vec<(String, String)> = redirect_url.query_pairs().into_owned().collect();
+        assert!(query.contains(&("state".to_string(), "test-state".to_string())));
+        assert!(query.iter().any(|(key, _)| key == "code"));
+
+        let user = auth_service::user_details(&pool, user_id).unwrap();
+        let authorizations = user.oauth_client_authorizations(&mut pool.get().unwrap()).unwrap();
+        assert_eq!(authorizations.len(), 1);
+        assert_eq!(authorizations[0].client_id, CLIENT_ID);
+        assert_eq!(authorizations[0].scope, "profile");
+    }
+}
@@ -1,3 +1,7 @@
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+
 use crate::auth::JwtTokenScope;
 use crate::errors::ServiceError;
 use crate::middlewares::auth::JwtMiddleware;
@@ -9,3 +13,11 @@ pub fn enforce_scope(jwt: &JwtMiddleware, scope: JwtTokenScope) -> Result<(), Se
         Ok(())
     }
 }
+
+/// User name and password of HTTP Basic authentication
+pub fn basic_credentials(req: &HttpRequest) -> Option<(String, String)> {
+    let credentials = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?.strip_prefix("Basic ")?;
+    let credentials = String::from_utf8(base64_engine.decode(credentials.trim()).ok()?).ok()?;
+    let (user, password) = credentials.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
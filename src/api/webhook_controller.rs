@@ -0,0 +1,128 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::{
+    AppState,
+    errors::{ServiceError, ErrorResponse},
+    middlewares::auth::JwtMiddleware,
+};
+use crate::api::utils::{basic_credentials, enforce_scope};
+use crate::auth::JwtTokenScope;
+use crate::services::{source_service, webhook_service};
+
+use super::models::sources::SourceQuery;
+use super::models::webhooks::{Webhook, WebhookDeliveriesQuery, WebhookDeliveryList, WebhookList, WebhookRequest};
+
+
+/// Add a webhook to a source
+///
+/// Events of the source's reviews are posted to the URL. Every delivery is signed with HMAC-SHA256 using the
+/// returned `secret`, which isn't shown again. Failed deliveries are retried with exponential backoff. Only for
+/// verified maintainers.
+#[utoipa::path(
+    post,
+    path = "/api/webhooks",
+    request_body = WebhookRequest,
+    responses(
+        (status = 200, response = Webhook),
+        (status = 400, response = ErrorResponse),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+    )
+)]
+pub async fn create(body: web::Json<WebhookRequest>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&body.source_identifier, &jwt.user_id, &data.db)?;
+
+    let webhook = webhook_service::subscribe(&body, &jwt.user_id, &data.db, &data.env)?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+
+/// Get the webhooks of a source
+///
+/// Only for verified maintainers.
+#[utoipa::path(
+    get,
+    path = "/api/webhooks",
+    params(SourceQuery),
+    responses(
+        (status = 200, response = WebhookList),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+    )
+)]
+pub async fn get(query: web::Query<SourceQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    source_service::require_maintainer(&query.source_identifier, &jwt.user_id, &data.db)?;
+
+    let webhooks = webhook_service::webhooks(&query.source_identifier, &data.db)?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+
+/// Delete a webhook of a source
+///
+/// Deletes its delivery log too. Only for verified maintainers.
+#[utoipa::path(
+    delete,
+    path = "/api/webhooks/{id}",
+    responses(
+        (status = 200, response = Webhook),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn delete(path: web::Path<String>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    let subscription = webhook_service::find_source_webhook(&path, &data.db)?;
+    source_service::require_maintainer(subscription.source_id.as_deref().unwrap_or_default(), &jwt.user_id, &data.db)?;
+
+    let webhook = webhook_service::unsubscribe(subscription, &data.db)?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+
+/// Get the deliveries of a webhook of a source
+///
+/// The log of the events sent to the webhook with the outcome of their latest attempt, newest first. Deliveries
+/// are kept for 30 days. Only for verified maintainers.
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/{id}/deliveries",
+    params(WebhookDeliveriesQuery),
+    responses(
+        (status = 200, response = WebhookDeliveryList),
+        (status = 401, description = "User authentication failed or the user doesn't maintain the source."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn get_deliveries(path: web::Path<String>, query: web::Query<WebhookDeliveriesQuery>, data: web::Data<AppState>, jwt: JwtMiddleware) -> Result<HttpResponse, ServiceError> {
+    enforce_scope(&jwt, JwtTokenScope::Full)?;
+    let subscription = webhook_service::find_source_webhook(&path, &data.db)?;
+    source_service::require_maintainer(subscription.source_id.as_deref().unwrap_or_default(), &jwt.user_id, &data.db)?;
+
+    let deliveries = webhook_service::deliveries(&subscription.id, &query, &data.db)?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+
+/// Get the deliveries of an OAuth client's webhook
+///
+/// Like the deliveries of a source's webhook, for the webhook of a client from the OAuth configuration. The
+/// client authenticates with HTTP Basic authentication using its client id and secret.
+#[utoipa::path(
+    get,
+    path = "/api/webhooks/client/deliveries",
+    params(WebhookDeliveriesQuery),
+    responses(
+        (status = 200, response = WebhookDeliveryList),
+        (status = 401, description = "Client authentication failed."),
+        (status = 404, response = ErrorResponse),
+    )
+)]
+pub async fn get_client_deliveries(req: HttpRequest, query: web::Query<WebhookDeliveriesQuery>, data: web::Data<AppState>) -> Result<HttpResponse, ServiceError> {
+    let client = basic_credentials(&req)
+        .and_then(|(client_id, secret)| data.oauth_config.client(&client_id).filter(|client| client.has_secret(&secret)))
+        .ok_or(ServiceError::Unauthorized { error_message: "Client authentication failed.".to_string() })?;
+
+    let deliveries = webhook_service::client_deliveries(&client.client_id, &query, &data.db)?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
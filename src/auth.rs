@@ -1,8 +1,22 @@
-use chrono::Utc;
-use jsonwebtoken::{encode, Header, EncodingKey};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as base64url_engine};
+use chrono::{NaiveDateTime, Utc};
+use ed25519_dalek::SigningKey;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, EncodePrivateKey};
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use jsonwebtoken::{encode, Algorithm, DecodingKey, Header, EncodingKey};
+use log::info;
+use oxide_auth::primitives::scope::Scope;
+use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use sidestore_id_core::signature::key_id;
 
+use crate::api::models::oauth2::JsonWebKey;
 use crate::config::Config;
+use crate::constants::{OAUTH_ACCESS_TOKEN_TYPE, OAUTH_TOKEN_SIGNING_PRIVATE_KEY_NAME};
 use crate::db::models::user::User;
 
 
@@ -14,13 +28,22 @@ pub enum JwtTokenType {
     Refresh,
 }
 
+/// Scopes are ordered from the most privileged, a token can do everything the scopes after its own allow
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, PartialOrd)]
 pub enum JwtTokenScope {
     #[serde(rename = "full")]
     Full = 0,
 
+    /// Read and change the synced settings
+    #[serde(rename = "settings")]
+    Settings = 1,
+
+    /// Read the synced settings
+    #[serde(rename = "settings.read")]
+    SettingsRead = 2,
+
     #[serde(rename = "profile")]
-    Profile = 1,
+    Profile = 3,
 }
 
 #[derive (Debug, Clone, Deserialize, Serialize)]
@@ -30,17 +53,137 @@ pub struct JwtToken {
     pub iss: String,
     pub sub: String,
     pub iat: i64,
+    /// Time the token was issued in milliseconds, to tell it apart from authorizations granted in the same second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
     pub exp: i64,
     pub fresh: bool,
     pub scope: JwtTokenScope,
+    /// The OAuth client this token was issued to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+/// Claims of an access token issued to an OAuth client, as defined by the JWT profile for OAuth 2.0 access
+/// tokens (RFC 9068)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuthAccessToken {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub client_id: String,
+    pub iat: i64,
+    /// Time the token was issued in milliseconds, to tell it apart from authorizations granted in the same second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
+    pub exp: i64,
+    pub jti: String,
+    /// Granted scopes, space delimited
+    pub scope: String,
+}
+
+impl JwtToken {
+    /// Whether the token was issued before the time, in milliseconds. Tokens without `iat_ms` count as issued at
+    /// the start of their second.
+    pub fn is_issued_before(&self, time: NaiveDateTime) -> bool {
+        self.iat_ms.unwrap_or(self.iat * 1000) < time.and_utc().timestamp_millis()
+    }
+}
+
+impl From<OAuthAccessToken> for JwtToken {
+    fn from(token: OAuthAccessToken) -> Self {
+        let scope = token.scope.parse::<Scope>()
+            .map(|scope| JwtTokenScope::from_oauth_scope(&scope))
+            .unwrap_or(JwtTokenScope::Profile);
+
+        JwtToken {
+            type_: JwtTokenType::Access,
+            iss: token.iss,
+            sub: token.sub,
+            iat: token.iat,
+            iat_ms: token.iat_ms,
+            exp: token.exp,
+            fresh: false,
+            scope,
+            client_id: Some(token.client_id),
+        }
+    }
+}
+
+/// Ed25519 key that signs the access tokens of OAuth clients. Its public key is published as a JWK, so resource
+/// servers can validate the tokens without a secret that could issue them.
+#[derive(Clone)]
+pub struct OAuthTokenKey {
+    key_id: String,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    /// Base64url encoded public key
+    public_key: String,
+}
+
+impl OAuthTokenKey {
+    pub fn new(signing_key: &SigningKey) -> Result<Self, String> {
+        let private_key_der = signing_key.to_pkcs8_der()
+            .map_err(|e| format!("Failed to encode private key: {}", e))?;
+        let public_key = base64url_engine.encode(signing_key.verifying_key().as_bytes());
+
+        Ok(OAuthTokenKey {
+            key_id: key_id(&signing_key.verifying_key()),
+            encoding_key: EncodingKey::from_ed_der(private_key_der.as_bytes()),
+            decoding_key: DecodingKey::from_ed_components(&public_key)
+                .map_err(|e| format!("Failed to decode public key: {}", e))?,
+            public_key,
+        })
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    pub fn jwk(&self) -> JsonWebKey {
+        JsonWebKey {
+            kty: "OKP".to_string(),
+            crv: "Ed25519".to_string(),
+            x: self.public_key.clone(),
+            kid: self.key_id.clone(),
+            alg: "EdDSA".to_string(),
+            use_: "sig".to_string(),
+        }
+    }
+}
+
+pub fn create_or_load_oauth_token_key(config: &Config) -> Result<OAuthTokenKey, String> {
+    let private_key_path = Path::new(&config.storage_path).join(OAUTH_TOKEN_SIGNING_PRIVATE_KEY_NAME);
+
+    let signing_key = match fs::read_to_string(&private_key_path) {
+        Ok(private_key_pem) => SigningKey::from_pkcs8_pem(&private_key_pem)
+            .map_err(|e| format!("Failed to decode private key: {}", e))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!("Generating new oauth token signing key...");
+            let signing_key = SigningKey::generate(&mut OsRng);
+            let private_key_pem = signing_key.to_pkcs8_pem(LineEnding::LF)
+                .map_err(|e| format!("Failed to encode private key: {}", e))?;
+            fs::write(&private_key_path, private_key_pem.as_bytes())
+                .map_err(|e| format!("Failed to write private key to file: {}", e))?;
+            info!("Private key written to {}", private_key_path.display());
+            signing_key
+        },
+        Err(e) => return Err(format!("Failed to read private key from file: {}", e)),
+    };
+
+    OAuthTokenKey::new(&signing_key)
 }
 
 pub fn create_auth_tokens(user: &User, config: &Config, scope: JwtTokenScope) -> Result<(String, String), String> {
-    let access_token = match create_jwt_token(&user.id.to_string(), JwtTokenType::Access, &scope, config) {
+    let access_token = match create_jwt_token(&user.id.to_string(), JwtTokenType::Access, &scope, None, config) {
         Ok(t) => t,
         Err(_) => return Err("Error generating access token".to_string())
     };
-    let refresh_token = match create_jwt_token(&user.id.to_string(), JwtTokenType::Refresh, &scope, config) {
+    let refresh_token = match create_jwt_token(&user.id.to_string(), JwtTokenType::Refresh, &scope, None, config) {
         Ok(t) => t,
         Err(_) => return Err("Error generating refresh token".to_string())
     };
@@ -48,26 +191,87 @@ pub fn create_auth_tokens(user: &User, config: &Config, scope: JwtTokenScope) ->
     Ok((access_token, refresh_token))
 }
 
-pub fn create_jwt_token(user_id: &str, type_: JwtTokenType, scope: &JwtTokenScope, config: &Config) -> Result<String, String> {
+pub fn create_jwt_token(user_id: &str, type_: JwtTokenType, scope: &JwtTokenScope, client_id: Option<&str>, config: &Config) -> Result<String, String> {
     let expiration_seconds = match type_ {
         JwtTokenType::Access => config.jwt_expiration,
         JwtTokenType::Refresh => config.jwt_refresh_expiration,
     };
     let now = Utc::now();
-    let iat = now.timestamp() as i64;
-    let exp = (now + chrono::Duration::seconds(expiration_seconds)).timestamp() as i64;
+    let iat = now.timestamp();
+    let exp = (now + chrono::Duration::seconds(expiration_seconds)).timestamp();
     let token: JwtToken = JwtToken {
         type_,
         iss: config.jwt_issuer.clone(),
         sub: user_id.to_string(),
         iat,
+        iat_ms: Some(now.timestamp_millis()),
         exp,
         fresh: false,
         scope: scope.clone(),
+        client_id: client_id.map(|c| c.to_string()),
     };
 
     match encode(&Header::default(), &token, &EncodingKey::from_secret(config.jwt_secret.as_ref())) {
         Ok(t) => Ok(t),
-        Err(_) => return Err("Error generating jwt token".to_string())
+        Err(_) => Err("Error generating jwt token".to_string())
     }
-}
\ No newline at end of file
+}
+
+/// Create an access token for an OAuth client following the JWT profile for OAuth 2.0
+/// access tokens (RFC 9068), so resource servers can validate it with common libraries.
+pub fn create_oauth_access_token(user_id: &str, client_id: &str, scope: &Scope, key: &OAuthTokenKey, config: &Config) -> Result<String, String> {
+    let now = Utc::now();
+    let token = OAuthAccessToken {
+        iss: config.oauth_issuer(),
+        sub: user_id.to_string(),
+        aud: config.oauth_issuer(),
+        client_id: client_id.to_string(),
+        iat: now.timestamp(),
+        iat_ms: Some(now.timestamp_millis()),
+        exp: (now + chrono::Duration::seconds(config.jwt_expiration)).timestamp(),
+        jti: uuid::Uuid::new_v4().to_string(),
+        scope: scope.to_string(),
+    };
+
+    let header = Header {
+        typ: Some(OAUTH_ACCESS_TOKEN_TYPE.to_string()),
+        kid: Some(key.key_id().to_string()),
+        ..Header::new(Algorithm::EdDSA)
+    };
+
+    match encode(&header, &token, &key.encoding_key) {
+        Ok(t) => Ok(t),
+        Err(_) => Err("Error generating oauth access token".to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_issued_before() {
+        let time = |millis| NaiveDateTime::from_timestamp_millis(millis).unwrap();
+        let token = JwtToken {
+            type_: JwtTokenType::Access,
+            iss: "issuer".to_string(),
+            sub: uuid::Uuid::new_v4().to_string(),
+            iat: 1_700_000_000,
+            iat_ms: Some(1_700_000_000_500),
+            exp: 1_700_003_600,
+            fresh: false,
+            scope: JwtTokenScope::Profile,
+            client_id: Some("client".to_string()),
+        };
+
+        // Authorizations granted again later in the same second revoke the token
+        assert!(token.is_issued_before(time(1_700_000_000_900)));
+        assert!(!token.is_issued_before(time(1_700_000_000_500)));
+        assert!(!token.is_issued_before(time(1_700_000_000_100)));
+
+        let without_millis = JwtToken { iat_ms: None, ..token };
+        assert!(without_millis.is_issued_before(time(1_700_000_000_100)));
+        assert!(!without_millis.is_issued_before(time(1_700_000_000_000)));
+    }
+}
@@ -1,9 +1,47 @@
+use actix_governor::{Governor, GovernorConfig, GovernorConfigBuilder, PeerIpKeyExtractor};
+use actix_governor::governor::middleware::NoOpMiddleware;
 use actix_web::web;
 use log::debug;
 
 use crate::api::*;
+use crate::config::Config;
+use crate::constants::{OAUTH_AUTHORIZE_ROUTE_NAME, OAUTH_JWKS_ROUTE_NAME, OAUTH_TOKEN_ROUTE_NAME};
+use crate::middlewares::idempotency::Idempotency;
 
-pub fn config_services(cfg: &mut web::ServiceConfig) {
+/// Rate limit per client IP address. Clones share their state, so a limit has to be created
+/// once and not per worker.
+pub type RateLimit = GovernorConfig<PeerIpKeyExtractor, NoOpMiddleware>;
+
+/// The rate limits of the public endpoints, each with its own state
+#[derive(Clone)]
+pub struct RateLimits {
+    pub review_verify: RateLimit,
+    pub review_snapshot: RateLimit,
+    pub review_log: RateLimit,
+    pub review_stats: RateLimit,
+}
+
+impl RateLimits {
+    pub fn new(config: &Config) -> Self {
+        RateLimits {
+            review_verify: rate_limit(config.review_verify_rate_limit),
+            review_snapshot: rate_limit(config.review_snapshot_rate_limit),
+            review_log: rate_limit(config.review_log_rate_limit),
+            review_stats: rate_limit(config.review_stats_rate_limit),
+        }
+    }
+}
+
+/// Requests per minute, which can all be made at once
+fn rate_limit(requests_per_minute: u32) -> RateLimit {
+    GovernorConfigBuilder::default()
+        .requests_per_minute(requests_per_minute.into())
+        .burst_size(requests_per_minute)
+        .finish()
+        .expect("Rate limits are positive")
+}
+
+pub fn config_services(cfg: &mut web::ServiceConfig, rate_limits: &RateLimits) {
     debug!("Configuring routes...");
     cfg.service(
         web::scope("/api")
@@ -11,7 +49,9 @@ pub fn config_services(cfg: &mut web::ServiceConfig) {
             .service(
                 web::scope("/auth")
                     .service(
-                        web::resource("/signup").route(web::post().to(auth_controller::signup)),
+                        web::resource("/signup")
+                            .wrap(Idempotency)
+                            .route(web::post().to(auth_controller::signup)),
                     )
                     .service(
                         web::resource("/login").route(web::post().to(auth_controller::login)),
@@ -29,11 +69,23 @@ pub fn config_services(cfg: &mut web::ServiceConfig) {
                         web::scope("/oauth2")
                             .service(
                                 web::resource("/authorize")
+                                    .name(OAUTH_AUTHORIZE_ROUTE_NAME)
                                     .route(web::get().to(oauth2_controller::get_authorize))
                                     .route(web::post().to(oauth2_controller::post_authorize)),
                             )
                             .service(
-                                web::resource("/token").route(web::post().to(oauth2_controller::token)),
+                                web::resource("/token")
+                                    .name(OAUTH_TOKEN_ROUTE_NAME)
+                                    .wrap(Idempotency)
+                                    .route(web::post().to(oauth2_controller::token)),
+                            )
+                            .service(
+                                web::resource("/authorizations")
+                                    .route(web::get().to(oauth2_controller::get_authorizations)),
+                            )
+                            .service(
+                                web::resource("/authorizations/{client_id}")
+                                    .route(web::delete().to(oauth2_controller::delete_authorization)),
                             )
                     ),
             )
@@ -43,15 +95,176 @@ pub fn config_services(cfg: &mut web::ServiceConfig) {
                         web::resource("/public_key").route(web::get().to(app_review_controller::get_public_key)),
                     )
                     .service(
-                        web::resource("/sign").route(web::post().to(app_review_controller::sign))
+                        web::resource("/keys").route(web::get().to(app_review_controller::get_keys)),
+                    )
+                    .service(
+                        web::resource("/sign")
+                            .wrap(Idempotency)
+                            .route(web::post().to(app_review_controller::sign))
+                    )
+                    .service(
+                        web::resource("/batch").route(web::post().to(app_review_controller::batch))
+                    )
+                    .service(
+                        web::resource("/snapshot")
+                            .wrap(Governor::new(&rate_limits.review_snapshot))
+                            .route(web::get().to(app_review_controller::get_snapshot)),
+                    )
+                    .service(
+                        web::resource("/stats")
+                            .wrap(Governor::new(&rate_limits.review_stats))
+                            .route(web::get().to(app_review_controller::get_stats)),
+                    )
+                    .service(
+                        web::resource("/verify")
+                            .wrap(Governor::new(&rate_limits.review_verify))
+                            .route(web::post().to(app_review_controller::verify)),
                     )
                     .service(
                         web::resource("").route(web::get().to(app_review_controller::get))
                     )
+                    .service(
+                        web::resource("/revisions").route(web::get().to(app_review_controller::get_revisions))
+                    )
+                    .service(
+                        web::resource("/report").route(web::post().to(moderation_controller::report))
+                    )
                     .service(
                         web::resource("/delete").route(web::delete().to(app_review_controller::delete))
+                    )
+                    .service(
+                        web::scope("/log")
+                            .wrap(Governor::new(&rate_limits.review_log))
+                            .service(
+                                web::resource("/tree_head").route(web::get().to(review_log_controller::get_tree_head)),
+                            )
+                            .service(
+                                web::resource("/entries").route(web::get().to(review_log_controller::get_entries)),
+                            )
+                            .service(
+                                web::resource("/inclusion_proof").route(web::get().to(review_log_controller::get_inclusion_proof)),
+                            )
+                            .service(
+                                web::resource("/consistency_proof").route(web::get().to(review_log_controller::get_consistency_proof)),
+                            )
+                    )
+                    .service(
+                        web::resource("/{id}").route(web::get().to(app_review_controller::get_by_id))
+                    ),
+            )
+            .service(
+                web::scope("/moderation")
+                    .service(
+                        web::resource("/reports").route(web::get().to(moderation_controller::get_reports)),
+                    )
+                    .service(
+                        web::resource("/held").route(web::get().to(moderation_controller::get_held)),
+                    )
+                    .service(
+                        web::resource("/actions")
+                            .route(web::get().to(moderation_controller::get_actions))
+                            .route(web::post().to(moderation_controller::moderate)),
                     ),
             )
+            .service(
+                web::scope("/sources")
+                    .service(
+                        web::resource("/claims")
+                            .route(web::get().to(source_controller::get_claims))
+                            .route(web::post().to(source_controller::claim)),
+                    )
+                    .service(
+                        web::resource("/claims/verify").route(web::post().to(source_controller::verify_claim)),
+                    )
+                    .service(
+                        web::resource("/reviews").route(web::get().to(source_controller::get_reviews)),
+                    )
+                    .service(
+                        web::resource("/replies")
+                            .route(web::post().to(source_controller::reply))
+                            .route(web::delete().to(source_controller::delete_reply)),
+                    )
+                    .service(
+                        web::resource("/stats").route(web::get().to(source_controller::get_stats)),
+                    )
+                    .service(
+                        web::resource("/settings")
+                            .route(web::get().to(source_controller::get_settings))
+                            .route(web::put().to(source_controller::put_settings)),
+                    ),
+            )
+            .service(
+                web::scope("/settings")
+                    .service(
+                        web::resource("").route(web::get().to(settings_controller::get_all)),
+                    )
+                    .service(
+                        web::resource("/sync").route(web::post().to(settings_controller::sync)),
+                    )
+                    .service(
+                        web::resource("/devices")
+                            .route(web::get().to(settings_controller::get_devices))
+                            .route(web::post().to(settings_controller::register_device)),
+                    )
+                    .service(
+                        web::resource("/devices/{id}").route(web::delete().to(settings_controller::delete_device)),
+                    )
+                    .service(
+                        web::resource("/{namespace}")
+                            .route(web::get().to(settings_controller::get_namespace))
+                            .route(web::patch().to(settings_controller::patch_namespace))
+                            .route(web::delete().to(settings_controller::delete_namespace)),
+                    )
+                    .service(
+                        web::resource("/{namespace}/{key}")
+                            .route(web::get().to(settings_controller::get))
+                            .route(web::put().to(settings_controller::put))
+                            .route(web::delete().to(settings_controller::delete)),
+                    ),
+            )
+            .service(
+                web::scope("/webhooks")
+                    .service(
+                        web::resource("")
+                            .route(web::get().to(webhook_controller::get))
+                            .route(web::post().to(webhook_controller::create)),
+                    )
+                    .service(
+                        web::resource("/client/deliveries").route(web::get().to(webhook_controller::get_client_deliveries)),
+                    )
+                    .service(
+                        web::resource("/{id}").route(web::delete().to(webhook_controller::delete)),
+                    )
+                    .service(
+                        web::resource("/{id}/deliveries").route(web::get().to(webhook_controller::get_deliveries)),
+                    ),
+            )
+    );
+
+    cfg.service(
+        web::resource("/.well-known/oauth-authorization-server")
+            .route(web::get().to(oauth2_controller::metadata)),
+    );
+
+    cfg.service(
+        web::resource("/.well-known/jwks.json")
+            .name(OAUTH_JWKS_ROUTE_NAME)
+            .route(web::get().to(oauth2_controller::jwks)),
+    );
+
+    #[cfg(feature = "consent-ui")]
+    cfg.service(
+        web::scope("/auth")
+            .service(
+                web::resource("/login")
+                    .route(web::get().to(ui_controller::get_login))
+                    .route(web::post().to(ui_controller::post_login)),
+            )
+            .service(
+                web::resource("/authorize")
+                    .route(web::get().to(ui_controller::get_authorize))
+                    .route(web::post().to(ui_controller::post_authorize)),
+            )
     );
 
     #[cfg(feature = "swagger")]
@@ -1,4 +1,12 @@
-use crate::constants::{DEFAULT_JWT_EXPIRATION, DEFAULT_JWT_REFRESH_EXPIRATION, DEFAULT_OAUTH_CONFIG_PATH};
+use sidestore_id_core::payload::FORMAT_VERSIONS;
+use url::Url;
+
+use crate::constants::{
+    DEFAULT_IDEMPOTENCY_KEY_TTL, DEFAULT_JWT_EXPIRATION, DEFAULT_JWT_REFRESH_EXPIRATION, DEFAULT_OAUTH_CONFIG_PATH, DEFAULT_OAUTH_CONSENT_URL,
+    DEFAULT_REVIEW_LOG_RATE_LIMIT, DEFAULT_REVIEW_LOG_SIGNING_INTERVAL, DEFAULT_REVIEW_PAYLOAD_FORMAT_VERSIONS, DEFAULT_REVIEW_RISK_HOLD_THRESHOLD,
+    DEFAULT_REVIEW_RISK_REJECT_THRESHOLD, DEFAULT_REVIEW_SNAPSHOT_RATE_LIMIT, DEFAULT_REVIEW_STATS_RATE_LIMIT,
+    DEFAULT_REVIEW_VERIFY_RATE_LIMIT,
+};
 
 pub mod app;
 pub mod db;
@@ -12,11 +20,41 @@ pub struct Config {
     pub jwt_issuer: String,
     pub jwt_expiration: i64,
     pub jwt_refresh_expiration: i64,
+    #[allow(dead_code)]
     pub cors_origin: String,
     pub public_url: String,
     pub database_url: String,
     pub storage_path: String,
     pub oauth_config_path: String,
+    pub oauth_consent_expiration: Option<i64>,
+    /// URL of the consent page, `{public_url}` is replaced with the public URL of the service
+    pub oauth_consent_url_template: String,
+    /// Seconds between signing new tree heads of the review transparency logs
+    pub review_log_signing_interval: u64,
+    /// Payload formats that reviews are signed in. The first one is stored and used for the transparency log,
+    /// the others are only returned to clients that haven't migrated yet.
+    pub review_payload_format_versions: Vec<i32>,
+    /// Requests per minute that a client can make to the public review verification endpoint
+    pub review_verify_rate_limit: u32,
+    /// Requests per minute that a client can make to the public source snapshot endpoint
+    pub review_snapshot_rate_limit: u32,
+    /// Requests per minute that a client can make to the public transparency log endpoints
+    pub review_log_rate_limit: u32,
+    /// Requests per minute that a client can make to the public rating statistics endpoint
+    pub review_stats_rate_limit: u32,
+    /// Whether claims of sources that are served over plain HTTP can be verified, for development only
+    pub source_verification_allow_http: bool,
+    /// Whether sources can be fetched from and webhooks sent to loopback, private and link-local addresses, for
+    /// development only
+    pub allow_private_network_addresses: bool,
+    /// Risk score from which review submissions are held for moderation, above 100 to never hold them
+    pub review_risk_hold_threshold: i32,
+    /// Risk score from which review submissions are rejected, above 100 to never reject them
+    pub review_risk_reject_threshold: i32,
+    /// Seconds the responses to requests with an idempotency key are kept for retries
+    pub idempotency_key_ttl: i64,
+    /// Whether webhooks can post events over plain HTTP, for development only
+    pub webhook_allow_http: bool,
 }
 
 impl Config {
@@ -44,6 +82,47 @@ impl Config {
 
         let oauth_config_path = std::env::var("OAUTH_CONFIG_PATH")
             .unwrap_or(DEFAULT_OAUTH_CONFIG_PATH.to_string());
+        let oauth_consent_expiration = std::env::var("OAUTH_CONSENT_EXPIRATION")
+            .ok()
+            .map(|val| val.parse::<i64>().expect("OAUTH_CONSENT_EXPIRATION must be an integer"));
+        let oauth_consent_url_template = std::env::var("OAUTH_CONSENT_URL")
+            .unwrap_or(DEFAULT_OAUTH_CONSENT_URL.to_string());
+
+        let review_log_signing_interval = match std::env::var("REVIEW_LOG_SIGNING_INTERVAL") {
+            Ok(val) => val.parse::<u64>().expect("REVIEW_LOG_SIGNING_INTERVAL must be an integer"),
+            Err(_) => DEFAULT_REVIEW_LOG_SIGNING_INTERVAL,
+        };
+        let review_payload_format_versions = parse_format_versions(
+            &std::env::var("REVIEW_PAYLOAD_FORMAT_VERSIONS").unwrap_or(DEFAULT_REVIEW_PAYLOAD_FORMAT_VERSIONS.to_string())
+        ).expect("REVIEW_PAYLOAD_FORMAT_VERSIONS must be a comma separated list of supported format versions");
+        let review_verify_rate_limit = parse_rate_limit("REVIEW_VERIFY_RATE_LIMIT", DEFAULT_REVIEW_VERIFY_RATE_LIMIT);
+        let review_snapshot_rate_limit = parse_rate_limit("REVIEW_SNAPSHOT_RATE_LIMIT", DEFAULT_REVIEW_SNAPSHOT_RATE_LIMIT);
+        let review_log_rate_limit = parse_rate_limit("REVIEW_LOG_RATE_LIMIT", DEFAULT_REVIEW_LOG_RATE_LIMIT);
+        let review_stats_rate_limit = parse_rate_limit("REVIEW_STATS_RATE_LIMIT", DEFAULT_REVIEW_STATS_RATE_LIMIT);
+        let source_verification_allow_http = match std::env::var("SOURCE_VERIFICATION_ALLOW_HTTP") {
+            Ok(val) => val.parse::<bool>().expect("SOURCE_VERIFICATION_ALLOW_HTTP must be true or false"),
+            Err(_) => false,
+        };
+        let allow_private_network_addresses = match std::env::var("ALLOW_PRIVATE_NETWORK_ADDRESSES") {
+            Ok(val) => val.parse::<bool>().expect("ALLOW_PRIVATE_NETWORK_ADDRESSES must be true or false"),
+            Err(_) => false,
+        };
+        let review_risk_hold_threshold = match std::env::var("REVIEW_RISK_HOLD_THRESHOLD") {
+            Ok(val) => val.parse::<i32>().expect("REVIEW_RISK_HOLD_THRESHOLD must be an integer"),
+            Err(_) => DEFAULT_REVIEW_RISK_HOLD_THRESHOLD,
+        };
+        let review_risk_reject_threshold = match std::env::var("REVIEW_RISK_REJECT_THRESHOLD") {
+            Ok(val) => val.parse::<i32>().expect("REVIEW_RISK_REJECT_THRESHOLD must be an integer"),
+            Err(_) => DEFAULT_REVIEW_RISK_REJECT_THRESHOLD,
+        };
+        let idempotency_key_ttl = match std::env::var("IDEMPOTENCY_KEY_TTL") {
+            Ok(val) => val.parse::<i64>().ok().filter(|ttl| *ttl > 0).expect("IDEMPOTENCY_KEY_TTL must be a positive integer"),
+            Err(_) => DEFAULT_IDEMPOTENCY_KEY_TTL,
+        };
+        let webhook_allow_http = match std::env::var("WEBHOOK_ALLOW_HTTP") {
+            Ok(val) => val.parse::<bool>().expect("WEBHOOK_ALLOW_HTTP must be true or false"),
+            Err(_) => false,
+        };
 
         Config {
             // secret_key,
@@ -58,6 +137,83 @@ impl Config {
             database_url,
             storage_path,
             oauth_config_path,
+            oauth_consent_expiration,
+            oauth_consent_url_template,
+            review_log_signing_interval,
+            review_payload_format_versions,
+            review_verify_rate_limit,
+            review_snapshot_rate_limit,
+            review_log_rate_limit,
+            review_stats_rate_limit,
+            source_verification_allow_http,
+            allow_private_network_addresses,
+            review_risk_hold_threshold,
+            review_risk_reject_threshold,
+            idempotency_key_ttl,
+            webhook_allow_http,
+        }
+    }
+
+    /// Identifier of the service as an OAuth authorization server, which is also the
+    /// audience of the access tokens it issues to clients.
+    pub fn oauth_issuer(&self) -> String {
+        self.public_url.trim_end_matches('/').to_string()
+    }
+
+    /// Payload format of stored signatures
+    pub fn review_payload_format_version(&self) -> i32 {
+        self.review_payload_format_versions[0]
+    }
+
+    pub fn oauth_consent_url(&self) -> Result<Url, url::ParseError> {
+        Url::parse(&self.oauth_consent_url_template.replace("{public_url}", self.public_url.trim_end_matches('/')))
+    }
+
+    #[cfg(test)]
+    pub fn for_tests() -> Config {
+        Config {
+            host: "localhost".to_string(),
+            port: 8080,
+            jwt_secret: "secret".to_string(),
+            jwt_issuer: "io.sidestore.SideStore-ID".to_string(),
+            jwt_expiration: 3600,
+            jwt_refresh_expiration: 86400,
+            cors_origin: "*".to_string(),
+            public_url: "http://localhost:8080".to_string(),
+            database_url: "sqlite://test.db".to_string(),
+            storage_path: "./test-storage".to_string(),
+            oauth_config_path: "./oauth_config.toml".to_string(),
+            oauth_consent_expiration: None,
+            oauth_consent_url_template: DEFAULT_OAUTH_CONSENT_URL.to_string(),
+            review_log_signing_interval: DEFAULT_REVIEW_LOG_SIGNING_INTERVAL,
+            review_payload_format_versions: parse_format_versions(DEFAULT_REVIEW_PAYLOAD_FORMAT_VERSIONS).unwrap(),
+            review_verify_rate_limit: DEFAULT_REVIEW_VERIFY_RATE_LIMIT,
+            review_snapshot_rate_limit: DEFAULT_REVIEW_SNAPSHOT_RATE_LIMIT,
+            review_log_rate_limit: DEFAULT_REVIEW_LOG_RATE_LIMIT,
+            review_stats_rate_limit: DEFAULT_REVIEW_STATS_RATE_LIMIT,
+            source_verification_allow_http: true,
+            // The tests serve sources and receive webhooks on local ports
+            allow_private_network_addresses: true,
+            // Test users are new accounts, the fraud checks are tested with their own thresholds
+            review_risk_hold_threshold: 101,
+            review_risk_reject_threshold: 101,
+            idempotency_key_ttl: DEFAULT_IDEMPOTENCY_KEY_TTL,
+            webhook_allow_http: true,
         }
     }
 }
+
+/// Requests per minute from the environment variable, which has to be a positive integer
+fn parse_rate_limit(name: &str, default: u32) -> u32 {
+    match std::env::var(name) {
+        Ok(val) => val.parse::<u32>().ok().filter(|limit| *limit > 0).unwrap_or_else(|| panic!("{} must be a positive integer", name)),
+        Err(_) => default,
+    }
+}
+
+fn parse_format_versions(value: &str) -> Option<Vec<i32>> {
+    let versions = value.split(',')
+        .map(|version| version.trim().parse::<i32>().ok().filter(|version| FORMAT_VERSIONS.contains(version)))
+        .collect::<Option<Vec<i32>>>()?;
+    (!versions.is_empty()).then_some(versions)
+}
@@ -1,15 +1,148 @@
 pub const DEFAULT_JWT_EXPIRATION: i64 = 3600;
 pub const DEFAULT_JWT_REFRESH_EXPIRATION: i64 = 3600*24*7;
 pub const DEFAULT_OAUTH_CONFIG_PATH: &str = "/config/oauth_config.toml";
+pub const DEFAULT_REVIEW_LOG_SIGNING_INTERVAL: u64 = 300;
+pub const DEFAULT_REVIEW_PAYLOAD_FORMAT_VERSIONS: &str = "1,2";
+pub const DEFAULT_REVIEW_VERIFY_RATE_LIMIT: u32 = 30;
+pub const DEFAULT_REVIEW_SNAPSHOT_RATE_LIMIT: u32 = 10;
+pub const DEFAULT_REVIEW_LOG_RATE_LIMIT: u32 = 60;
+pub const DEFAULT_REVIEW_STATS_RATE_LIMIT: u32 = 60;
+pub const DEFAULT_REVIEW_RISK_HOLD_THRESHOLD: i32 = 50;
+pub const DEFAULT_REVIEW_RISK_REJECT_THRESHOLD: i32 = 90;
+pub const DEFAULT_IDEMPOTENCY_KEY_TTL: i64 = 3600*24;
+pub const DEFAULT_OAUTH_CONSENT_URL: &str = "{public_url}/auth/authorize";
+pub const OAUTH_ACCESS_TOKEN_TYPE: &str = "at+jwt";
+
+pub const OAUTH_AUTHORIZE_ROUTE_NAME: &str = "oauth2_authorize";
+pub const OAUTH_TOKEN_ROUTE_NAME: &str = "oauth2_token";
+pub const OAUTH_JWKS_ROUTE_NAME: &str = "oauth2_jwks";
 
 pub const REFRESH_API_PATH: &str = "/api/auth/refresh";
 pub const OAUTH_GET_API_PATH: &str = "/api/auth/oauth2/authorize";
-pub const UNPROTECTED_API_PATHS: [&str; 4] = [
+pub const UNPROTECTED_API_PATHS: [&str; 12] = [
     "/api/health",
     "/api/auth/signup",
     "/api/auth/login",
     "/api/reviews/public_key",
+    "/api/reviews/keys",
+    "/api/reviews/verify",
+    "/api/reviews/stats",
+    "/api/reviews/snapshot",
+    "/api/reviews/log/tree_head",
+    "/api/reviews/log/entries",
+    "/api/reviews/log/inclusion_proof",
+    "/api/reviews/log/consistency_proof",
 ];
 
+/// Number of reviews on a page of the user's reviews, unless another limit is requested
+pub const DEFAULT_USER_REVIEWS_PAGE_SIZE: i64 = 50;
+pub const MAX_USER_REVIEWS_PAGE_SIZE: i64 = 200;
+
+pub const MAX_REVIEW_BATCH_OPERATIONS: usize = 100;
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Header set on responses that are replayed for a retried request
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+/// Seconds after which the key of a request that wasn't answered, for example because the service stopped, can
+/// be taken by a retry
+pub const IDEMPOTENT_REQUEST_TIMEOUT_SECONDS: i64 = 60;
+/// Largest request body that is read to fingerprint a request with an idempotency key
+pub const MAX_IDEMPOTENT_REQUEST_SIZE: usize = 1024 * 1024;
+pub const IDEMPOTENCY_KEY_PURGE_INTERVAL_SECONDS: u64 = 3600;
+/// Days the idempotency keys of applied batch operations are kept. Older replays are checked against the
+/// review's latest change like new operations.
+pub const REVIEW_BATCH_KEY_RETENTION_DAYS: i64 = 30;
+
+pub const MAX_REVIEW_REPORT_COMMENT_LENGTH: usize = 1000;
+pub const MAX_MODERATION_REASON_LENGTH: usize = 1000;
+pub const MAX_REVIEW_REPLY_LENGTH: usize = 5000;
+/// Number of open reports returned to moderators at once
+pub const MODERATION_REPORTS_LIMIT: i64 = 100;
+
+/// Number of held reviews returned to moderators at once
+pub const MODERATION_HELD_REVIEWS_LIMIT: i64 = 100;
+
+/// Risk scores of the fraud signals. The risk score of a review submission is their sum, at most 100.
+pub const RISK_NEW_ACCOUNT_SCORE: i32 = 40;
+pub const RISK_NEW_ACCOUNT_HOURS: i64 = 24;
+pub const RISK_RECENT_ACCOUNT_SCORE: i32 = 20;
+pub const RISK_RECENT_ACCOUNT_DAYS: i64 = 7;
+/// Score per other user that reviewed the app from the same network
+pub const RISK_SHARED_NETWORK_SCORE: i32 = 25;
+pub const RISK_SHARED_NETWORK_MAX_SCORE: i32 = 75;
+pub const RISK_SHARED_NETWORK_DAYS: i64 = 30;
+/// Submissions for an app in the last hour are suspicious above the minimum and above the factor times the
+/// app's hourly average of the days before
+pub const RISK_VELOCITY_SCORE: i32 = 30;
+pub const RISK_VELOCITY_MIN_SUBMISSIONS: i64 = 10;
+pub const RISK_VELOCITY_FACTOR: i64 = 5;
+pub const RISK_VELOCITY_BASELINE_DAYS: i64 = 7;
+/// Ratings this many stars away from the average of an app with enough reviews are outliers
+pub const RISK_RATING_OUTLIER_SCORE: i32 = 20;
+pub const RISK_RATING_OUTLIER_DISTANCE: f64 = 2.5;
+pub const RISK_RATING_OUTLIER_MIN_REVIEWS: i64 = 5;
+
+/// Path of the file at a source's origin that can contain the challenges of maintainer claims
+pub const SOURCE_VERIFICATION_WELL_KNOWN_PATH: &str = "/.well-known/sidestore-id-source-verification";
+/// Field of a source's JSON that can contain the challenges of maintainer claims
+pub const SOURCE_VERIFICATION_FIELD: &str = "sidestoreIdVerification";
+pub const SOURCE_VERIFICATION_TIMEOUT_SECONDS: u64 = 10;
+/// Largest source JSON that is read to verify a claim
+pub const MAX_SOURCE_VERIFICATION_RESPONSE_SIZE: u64 = 10 * 1024 * 1024;
+
+pub const MAX_WEBHOOKS_PER_SOURCE: usize = 10;
+/// Headers of webhook deliveries. The signature header has the time of the attempt as `t` and the hex encoded
+/// HMAC-SHA256 of the time, a dot and the body as `v1`.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "X-SideStore-Signature";
+pub const WEBHOOK_EVENT_HEADER: &str = "X-SideStore-Event";
+pub const WEBHOOK_DELIVERY_HEADER: &str = "X-SideStore-Delivery";
+pub const WEBHOOK_DELIVERY_INTERVAL_SECONDS: u64 = 5;
+pub const WEBHOOK_DELIVERY_TIMEOUT_SECONDS: u64 = 10;
+/// Deliveries sent at once. They are postponed while they are sent, so another instance doesn't send them too.
+pub const WEBHOOK_DELIVERY_BATCH_SIZE: i64 = 50;
+pub const WEBHOOK_DELIVERY_LEASE_SECONDS: i64 = 60;
+/// Failed attempts are retried after the base delay, doubled after every attempt up to the maximum delay, until
+/// the delivery runs out of attempts and is dead-lettered
+pub const MAX_WEBHOOK_DELIVERY_ATTEMPTS: i32 = 10;
+pub const WEBHOOK_RETRY_BASE_DELAY_SECONDS: i64 = 30;
+pub const WEBHOOK_RETRY_MAX_DELAY_SECONDS: i64 = 3600 * 6;
+/// Longest error of an attempt that is kept in the delivery log
+pub const MAX_WEBHOOK_ERROR_LENGTH: usize = 1000;
+pub const WEBHOOK_DELIVERY_RETENTION_DAYS: i64 = 30;
+pub const WEBHOOK_DELIVERY_PURGE_INTERVAL_SECONDS: u64 = 3600;
+pub const DEFAULT_WEBHOOK_DELIVERIES_PAGE_SIZE: i64 = 50;
+pub const MAX_WEBHOOK_DELIVERIES_PAGE_SIZE: i64 = 200;
+
+/// Namespaces of settings are dotted lowercase names like `sidestore.app`
+pub const MAX_SETTING_NAMESPACE_LENGTH: usize = 64;
+pub const MAX_SETTING_KEY_LENGTH: usize = 128;
+/// Largest JSON of a setting's value
+pub const MAX_SETTING_VALUE_SIZE: usize = 16 * 1024;
+pub const MAX_SETTINGS_PER_USER: i64 = 1000;
+/// Settings that are changed at once with a patch
+pub const MAX_SETTINGS_PER_PATCH: usize = 100;
+/// Device id in the vector clocks of settings for changes made with the settings API instead of a sync
+pub const SETTINGS_API_CLOCK_ID: &str = "api";
+pub const MAX_DEVICES_PER_USER: i64 = 50;
+pub const MAX_DEVICE_NAME_LENGTH: usize = 255;
+/// Local changes a device sends with a sync, and server changes it gets back at once
+pub const MAX_SETTINGS_SYNC_CHANGES: usize = 500;
+pub const MAX_SETTINGS_SYNC_SERVER_CHANGES: i64 = 1000;
+/// Devices counted by the vector clock of a setting
+pub const MAX_SETTING_CLOCK_DEVICES: usize = 100;
+/// Changes a device can count for a setting beyond the server's clock of it, which keeps the counts far from overflowing
+pub const MAX_SETTING_CLOCK_INCREMENT: i64 = 1_000_000;
+/// Deleted settings are kept this long for devices to sync, devices that didn't sync since have to sync everything
+pub const DELETED_SETTINGS_RETENTION_DAYS: i64 = 90;
+pub const DELETED_SETTINGS_PURGE_INTERVAL_SECONDS: u64 = 3600;
+
 pub const REVIEWS_SIGNING_PUBLIC_KEY_NAME: &str = "reviews_public_key.pem";
-pub const REVIEWS_SIGNING_PRIVATE_KEY_NAME: &str = "reviews_private_key.pem";
\ No newline at end of file
+pub const REVIEWS_SIGNING_PRIVATE_KEY_NAME: &str = "reviews_private_key.pem";
+pub const REVIEW_KEYS_DIRECTORY_NAME: &str = "review_keys";
+pub const OAUTH_TOKEN_SIGNING_PRIVATE_KEY_NAME: &str = "oauth_token_private_key.pem";
+
+#[cfg(feature = "consent-ui")]
+pub const TEMPLATES_DIRECTORY_NAME: &str = "templates";
+#[cfg(feature = "consent-ui")]
+pub const CSRF_COOKIE_NAME: &str = "csrf_token";
\ No newline at end of file
@@ -33,3 +33,44 @@ pub fn create_pool(url: &str) -> Pool {
 pub fn run_migration(conn: &mut PgConnection) {
     conn.run_pending_migrations(MIGRATIONS).unwrap();
 }
+
+
+#[cfg(test)]
+pub mod test_utils {
+    use std::sync::OnceLock;
+
+    use super::*;
+
+    /// Pool for tests that need a real Postgres database, configured with `TEST_DATABASE_URL`.
+    /// Those tests are marked as ignored, run them with `cargo test -- --ignored`.
+    pub fn test_pool() -> Pool {
+        static POOL: OnceLock<Pool> = OnceLock::new();
+
+        POOL.get_or_init(|| {
+            let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set to run database tests");
+            let pool = r2d2::Pool::builder()
+                .max_size(16)
+                .build(ConnectionManager::<Connection>::new(url))
+                .expect("Failed to connect to the test database");
+            run_migration(&mut pool.get().unwrap());
+            pool
+        }).clone()
+    }
+
+    /// Connection to the test database whose changes are rolled back when it's dropped
+    pub fn test_connection() -> Connection {
+        use diesel::Connection as _;
+
+        test_pool();
+        let mut conn = PgConnection::establish(&std::env::var("TEST_DATABASE_URL").unwrap())
+            .expect("Failed to connect to the test database");
+        conn.begin_test_transaction().unwrap();
+        conn
+    }
+
+    pub fn create_user(pool: &Pool) -> uuid::Uuid {
+        let mut user = models::user::User::new(&format!("{}@example.com", uuid::Uuid::new_v4()), "hash");
+        user.insert(&mut pool.get().unwrap()).unwrap();
+        user.id.parse().unwrap()
+    }
+}
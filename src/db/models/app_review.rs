@@ -1,11 +1,13 @@
-use diesel::sql_types::Uuid;
 use log::error;
 use chrono::{Utc, NaiveDateTime};
 use serde::{Deserialize, Serialize};
-use diesel::{Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl, ExpressionMethods};
+use diesel::{Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl, ExpressionMethods, BoolExpressionMethods};
+use diesel::pg::Pg;
 use diesel::result::Error;
+use diesel::sql_types::{Int4, Nullable};
+use sidestore_id_core::payload::LEGACY_FORMAT_VERSION;
 
-use crate::api::models::app_reviews::{AppReviewSignatureRequest, AppReviewStatus};
+use crate::api::models::app_reviews::{AppReviewSignatureRequest, AppReviewStatus, UserAppReviewSort, UserAppReviewsQuery};
 use crate::db::Connection;
 use crate::db::schema::app_review_signatures;
 
@@ -13,7 +15,7 @@ use super::{db_model, DbModel};
 
 
 #[derive(Serialize, Deserialize, Debug, Clone, Queryable, Insertable, AsChangeset)]
-#[diesel(table_name = app_review_signatures)]
+#[diesel(table_name = app_review_signatures, treat_none_as_null = true)]
 pub struct AppReviewSignature {
     #[serde(default, skip_serializing)]
     pub id: String,
@@ -27,6 +29,31 @@ pub struct AppReviewSignature {
     pub signature: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
+    /// Review signing key that made the signature, `None` for signatures made with the first key
+    /// before key identifiers were introduced
+    pub key_id: Option<String>,
+    /// Payload format of the signature
+    pub format_version: i32,
+    /// Base64 encoded salt of the content commitment
+    pub content_salt: Option<String>,
+    /// Commitment to the title and body of a published review, which aren't stored
+    pub content_commitment: Option<String>,
+    /// App version a review kept per version was written for, `None` for the user's review of the app
+    pub pinned_version: Option<String>,
+    /// Risk score of the latest submission, `None` for reviews submitted before the fraud checks
+    pub risk_score: Option<i32>,
+    /// Time the user made the latest change on their device, `None` unless the change was queued offline
+    pub client_changed_at: Option<NaiveDateTime>,
+}
+
+/// App version, rating and number of reviews
+pub type RatingCount = (Option<String>, Option<i32>, i64);
+
+/// Position of a review in the order of a user's reviews: its rating, 0 without one, `updated_at` and id
+pub type ReviewPosition = (i32, NaiveDateTime, String);
+
+diesel::sql_function! {
+    fn coalesce(x: Nullable<Int4>, y: Int4) -> Int4;
 }
 
 db_model!(app_review_signatures::dsl::app_review_signatures, app_review_signatures::dsl::id, AppReviewSignature);
@@ -38,11 +65,34 @@ impl AppReviewSignature {
             .get_result::<Self>(conn)
     }
 
+    /// The user's review of the app as a whole
     pub fn find_by_user_id(user_id: &uuid::Uuid, source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<Self, Error> {
         app_review_signatures::dsl::app_review_signatures
             .filter(app_review_signatures::user_id.eq(user_id.to_string()))
             .filter(app_review_signatures::source_id.eq(source_id.to_string()))
             .filter(app_review_signatures::app_bundle_id.eq(app_bundle_id.to_string()))
+            .filter(app_review_signatures::pinned_version.is_null())
+            .get_result::<Self>(conn)
+    }
+
+    /// The user's review of the app, or the one kept for the given app version
+    pub fn find_by_pinned_version(user_id: &uuid::Uuid, source_id: &str, app_bundle_id: &str, pinned_version: Option<&str>, conn: &mut Connection) -> Result<Self, Error> {
+        let Some(pinned_version) = pinned_version else {
+            return Self::find_by_user_id(user_id, source_id, app_bundle_id, conn);
+        };
+        app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::user_id.eq(user_id.to_string()))
+            .filter(app_review_signatures::source_id.eq(source_id.to_string()))
+            .filter(app_review_signatures::app_bundle_id.eq(app_bundle_id.to_string()))
+            .filter(app_review_signatures::pinned_version.eq(pinned_version))
+            .get_result::<Self>(conn)
+    }
+
+    pub fn find_by_sequence_number(source_id: &str, app_bundle_id: &str, sequence_number: i32, conn: &mut Connection) -> Result<Self, Error> {
+        app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::source_id.eq(source_id))
+            .filter(app_review_signatures::app_bundle_id.eq(app_bundle_id))
+            .filter(app_review_signatures::sequence_number.eq(sequence_number))
             .get_result::<Self>(conn)
     }
 
@@ -52,17 +102,155 @@ impl AppReviewSignature {
             .get_results(conn)
     }
 
-    pub fn find_latest_sequence_number(source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<i32, Error> {
+    /// Number of the user's reviews that match the query's filters
+    pub fn count_by_user_id(user_id: &uuid::Uuid, query: &UserAppReviewsQuery, conn: &mut Connection) -> Result<i64, Error> {
+        Self::filter_by_user_id(user_id, query)
+            .count()
+            .get_result(conn)
+    }
+
+    /// The user's reviews that match the query's filters in the query's order, after the given position
+    pub fn find_page_by_user_id(user_id: &uuid::Uuid, query: &UserAppReviewsQuery, after: Option<ReviewPosition>, limit: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        use app_review_signatures::{id, updated_at, review_rating};
+
+        let rating = coalesce(review_rating, 0);
+        let mut page = Self::filter_by_user_id(user_id, query);
+        page = match query.sort {
+            UserAppReviewSort::Newest => page.order((updated_at.desc(), id.desc())),
+            UserAppReviewSort::Oldest => page.order((updated_at.asc(), id.asc())),
+            UserAppReviewSort::HighestRated => page.order((rating.desc(), updated_at.desc(), id.desc())),
+            UserAppReviewSort::LowestRated => page.order((rating.asc(), updated_at.asc(), id.asc())),
+        };
+        if let Some((after_rating, after_updated_at, after_id)) = after {
+            page = match query.sort {
+                UserAppReviewSort::Newest => page.filter(updated_at.lt(after_updated_at)
+                    .or(updated_at.eq(after_updated_at).and(id.lt(after_id)))),
+                UserAppReviewSort::Oldest => page.filter(updated_at.gt(after_updated_at)
+                    .or(updated_at.eq(after_updated_at).and(id.gt(after_id)))),
+                UserAppReviewSort::HighestRated => page.filter(rating.lt(after_rating)
+                    .or(rating.eq(after_rating).and(updated_at.lt(after_updated_at)
+                        .or(updated_at.eq(after_updated_at).and(id.lt(after_id)))))),
+                UserAppReviewSort::LowestRated => page.filter(rating.gt(after_rating)
+                    .or(rating.eq(after_rating).and(updated_at.gt(after_updated_at)
+                        .or(updated_at.eq(after_updated_at).and(id.gt(after_id)))))),
+            };
+        }
+        page.limit(limit).get_results(conn)
+    }
+
+    fn filter_by_user_id<'a>(user_id: &uuid::Uuid, query: &'a UserAppReviewsQuery) -> app_review_signatures::BoxedQuery<'a, Pg> {
+        let mut reviews = app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::user_id.eq(user_id.to_string()))
+            .into_boxed();
+        if let Some(source_id) = &query.source_identifier {
+            reviews = reviews.filter(app_review_signatures::source_id.eq(source_id));
+        }
+        if let Some(app_bundle_id) = &query.app_bundle_id {
+            reviews = reviews.filter(app_review_signatures::app_bundle_id.eq(app_bundle_id));
+        }
+        if let Some(status) = query.status {
+            reviews = reviews.filter(app_review_signatures::status.eq(String::from(status)));
+        }
+        if let Some(from) = query.from {
+            let from = NaiveDateTime::from_timestamp_opt(from, 0).unwrap_or(NaiveDateTime::MAX);
+            reviews = reviews.filter(app_review_signatures::updated_at.ge(from));
+        }
+        if let Some(until) = query.until {
+            let until = NaiveDateTime::from_timestamp_opt(until, 0).unwrap_or(NaiveDateTime::MIN);
+            reviews = reviews.filter(app_review_signatures::updated_at.lt(until));
+        }
+        reviews
+    }
+
+    /// Position of the review in the order of a user's reviews
+    pub fn position(&self) -> ReviewPosition {
+        (self.review_rating.unwrap_or(0), self.updated_at, self.id.clone())
+    }
+
+    /// Reviews that weren't signed with the given key, including the ones signed before key identifiers were introduced
+    pub fn find_all_not_signed_with(key_id: &str, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::key_id.is_null().or(app_review_signatures::key_id.ne(key_id)))
+            .order((app_review_signatures::source_id.asc(), app_review_signatures::app_bundle_id.asc(), app_review_signatures::sequence_number.asc()))
+            .get_results(conn)
+    }
+
+    /// Reviews held for moderation, the longest held first
+    pub fn find_held(limit: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
         app_review_signatures::dsl::app_review_signatures
-            .filter(app_review_signatures::source_id.eq(source_id.to_string()))
-            .filter(app_review_signatures::app_bundle_id.eq(app_bundle_id.to_string()))
-            .select(diesel::dsl::max(app_review_signatures::sequence_number))
-            .first::<Option<i32>>(conn)
-            .map(|n| n.unwrap_or(0))
+            .filter(app_review_signatures::status.eq(String::from(AppReviewStatus::Held)))
+            .order(app_review_signatures::updated_at.asc())
+            .limit(limit)
+            .get_results(conn)
+    }
+
+    /// App bundle ids of all apps of a source that have reviews
+    pub fn find_app_bundle_ids(source_id: &str, conn: &mut Connection) -> Result<Vec<String>, Error> {
+        app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::source_id.eq(source_id))
+            .select(app_review_signatures::app_bundle_id)
+            .distinct()
+            .order(app_review_signatures::app_bundle_id.asc())
+            .get_results(conn)
+    }
+
+    /// Reviews of an app, optionally only the ones with the given sequence numbers, ordered by sequence number
+    pub fn find_all_by_app(source_id: &str, app_bundle_id: &str, sequence_numbers: Option<&[i32]>, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        let mut query = app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::source_id.eq(source_id))
+            .filter(app_review_signatures::app_bundle_id.eq(app_bundle_id))
+            .order(app_review_signatures::sequence_number.asc())
+            .into_boxed();
+        if let Some(sequence_numbers) = sequence_numbers {
+            query = query.filter(app_review_signatures::sequence_number.eq_any(sequence_numbers));
+        }
+        query.get_results(conn)
+    }
+
+    /// Number of published reviews of an app per app version and rating, and of held reviews if they are
+    /// included. Ratings other than 1 to 5 stars are left out.
+    pub fn count_ratings(source_id: &str, app_bundle_id: &str, include_held: bool, conn: &mut Connection) -> Result<Vec<RatingCount>, Error> {
+        let statuses = match include_held {
+            true => vec![String::from(AppReviewStatus::Published), String::from(AppReviewStatus::Held)],
+            false => vec![String::from(AppReviewStatus::Published)],
+        };
+        app_review_signatures::dsl::app_review_signatures
+            .filter(app_review_signatures::source_id.eq(source_id))
+            .filter(app_review_signatures::app_bundle_id.eq(app_bundle_id))
+            .filter(app_review_signatures::status.eq_any(statuses))
+            .filter(app_review_signatures::review_rating.between(1, 5))
+            .group_by((app_review_signatures::app_version, app_review_signatures::review_rating))
+            .select((app_review_signatures::app_version, app_review_signatures::review_rating, diesel::dsl::count_star()))
+            .load(conn)
     }
 }
 
 impl AppReviewSignature {
+    /// Status of the review. Statuses this version doesn't know are treated as deleted, which keeps
+    /// the review's content out of signed data.
+    pub fn status(&self) -> AppReviewStatus {
+        self.status.parse().unwrap_or(AppReviewStatus::Deleted)
+    }
+
+    /// Time of the latest change, as the user's device saw it if the change was queued offline
+    pub fn last_changed_at(&self) -> NaiveDateTime {
+        self.client_changed_at.unwrap_or(self.updated_at)
+    }
+
+    /// Store the signature with the key, payload format and content commitment it was made with,
+    /// without changing `updated_at`, which is part of the signed data
+    pub fn save_signature(&self, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::update(app_review_signatures::dsl::app_review_signatures.find(&self.id))
+            .set((
+                app_review_signatures::signature.eq(&self.signature),
+                app_review_signatures::key_id.eq(&self.key_id),
+                app_review_signatures::format_version.eq(self.format_version),
+                app_review_signatures::content_salt.eq(&self.content_salt),
+                app_review_signatures::content_commitment.eq(&self.content_commitment),
+            ))
+            .execute(conn)
+    }
+
     pub fn new(req: &AppReviewSignatureRequest, user_id: &uuid::Uuid) -> Self {
         AppReviewSignature {
             id: uuid::Uuid::new_v4().to_string(),
@@ -76,6 +264,13 @@ impl AppReviewSignature {
             signature: None,
             created_at: Utc::now().naive_utc(),
             updated_at: Utc::now().naive_utc(),
+            key_id: None,
+            format_version: LEGACY_FORMAT_VERSION,
+            content_salt: None,
+            content_commitment: None,
+            pinned_version: req.per_version.then(|| req.version_number.clone()),
+            risk_score: None,
+            client_changed_at: None,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl, ExpressionMethods, OptionalExtension};
+use diesel::result::Error;
+
+use crate::api::models::app_reviews::AppReviewStatus;
+use crate::db::Connection;
+use crate::db::schema::app_review_replies;
+
+
+/// A source maintainer's reply to a review, with the signature of its current state
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = app_review_replies, treat_none_as_null = true)]
+pub struct AppReviewReply {
+    pub id: String,
+    pub review_id: String,
+    pub source_id: String,
+    pub app_bundle_id: String,
+    pub review_sequence_number: i32,
+    /// Maintainer who wrote the current state of the reply
+    pub maintainer_id: String,
+    pub status: String,
+    /// Not set for deleted replies
+    pub body: Option<String>,
+    pub signature: String,
+    pub key_id: String,
+    pub format_version: i32,
+    /// Number of the change among the changes of replies to reviews of the app
+    pub change_number: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl AppReviewReply {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(app_review_replies::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// Store the reply, without changing `updated_at`, which is part of the signed data
+    pub fn save(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(app_review_replies::table.find(&self.id))
+            .set(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find_by_review_id(review_id: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        app_review_replies::table
+            .filter(app_review_replies::review_id.eq(review_id))
+            .get_result(conn)
+            .optional()
+    }
+
+    /// Published replies to the reviews, by review id
+    pub fn find_published_by_review_ids(review_ids: &[String], conn: &mut Connection) -> Result<HashMap<String, Self>, Error> {
+        Ok(app_review_replies::table
+            .filter(app_review_replies::review_id.eq_any(review_ids))
+            .filter(app_review_replies::status.eq(String::from(AppReviewStatus::Published)))
+            .get_results::<Self>(conn)?
+            .into_iter()
+            .map(|reply| (reply.review_id.clone(), reply))
+            .collect())
+    }
+
+    /// Number of the next change of a reply to a review of the app. The caller has to hold the lock
+    /// of the app's review sequence, so changes are numbered in the order they are committed.
+    pub fn next_change_number(source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<i64, Error> {
+        let last_change_number: Option<i64> = app_review_replies::table
+            .filter(app_review_replies::source_id.eq(source_id))
+            .filter(app_review_replies::app_bundle_id.eq(app_bundle_id))
+            .select(diesel::dsl::max(app_review_replies::change_number))
+            .get_result(conn)?;
+        Ok(last_change_number.unwrap_or(0) + 1)
+    }
+
+    /// Number of the last change of a reply per app of the source
+    pub fn source_change_numbers(source_id: &str, conn: &mut Connection) -> Result<HashMap<String, i64>, Error> {
+        let change_numbers: Vec<(String, Option<i64>)> = app_review_replies::table
+            .filter(app_review_replies::source_id.eq(source_id))
+            .group_by(app_review_replies::app_bundle_id)
+            .select((app_review_replies::app_bundle_id, diesel::dsl::max(app_review_replies::change_number)))
+            .load(conn)?;
+        Ok(change_numbers.into_iter()
+            .filter_map(|(app_bundle_id, change_number)| Some((app_bundle_id, change_number?)))
+            .collect())
+    }
+
+    /// Sequence numbers of the reviews whose replies changed after the given change
+    pub fn changed_sequence_numbers(source_id: &str, app_bundle_id: &str, since: i64, conn: &mut Connection) -> Result<Vec<i32>, Error> {
+        app_review_replies::table
+            .filter(app_review_replies::source_id.eq(source_id))
+            .filter(app_review_replies::app_bundle_id.eq(app_bundle_id))
+            .filter(app_review_replies::change_number.gt(since))
+            .select(app_review_replies::review_sequence_number)
+            .get_results(conn)
+    }
+}
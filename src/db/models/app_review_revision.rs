@@ -0,0 +1,75 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::schema::app_review_revisions;
+
+
+/// A signed state of a review, recorded on every signature. Revisions can't be changed.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = app_review_revisions)]
+pub struct AppReviewRevision {
+    pub id: String,
+    pub review_id: String,
+    /// Number of the revision of the review, starting at 1
+    pub revision_number: i32,
+    pub user_id: String,
+    pub source_id: String,
+    pub app_bundle_id: String,
+    pub sequence_number: i32,
+    pub status: String,
+    pub app_version: Option<String>,
+    pub review_rating: Option<i32>,
+    pub content_salt: Option<String>,
+    pub content_commitment: Option<String>,
+    pub signature: String,
+    pub key_id: Option<String>,
+    pub format_version: i32,
+    pub created_at: NaiveDateTime,
+}
+
+impl AppReviewRevision {
+    /// Record the signed state of the review as its next revision. The caller has to hold the lock of
+    /// the app's review sequence, so revisions of a review are numbered without gaps.
+    pub fn record(review: &AppReviewSignature, signature: &str, conn: &mut Connection) -> Result<Self, Error> {
+        let last_revision_number: Option<i32> = app_review_revisions::table
+            .filter(app_review_revisions::review_id.eq(&review.id))
+            .select(diesel::dsl::max(app_review_revisions::revision_number))
+            .get_result(conn)?;
+
+        let revision = AppReviewRevision {
+            id: uuid::Uuid::new_v4().to_string(),
+            review_id: review.id.clone(),
+            revision_number: last_revision_number.unwrap_or(0) + 1,
+            user_id: review.user_id.clone(),
+            source_id: review.source_id.clone(),
+            app_bundle_id: review.app_bundle_id.clone(),
+            sequence_number: review.sequence_number,
+            status: review.status.clone(),
+            app_version: review.app_version.clone(),
+            review_rating: review.review_rating,
+            content_salt: review.content_salt.clone(),
+            content_commitment: review.content_commitment.clone(),
+            signature: signature.to_string(),
+            key_id: review.key_id.clone(),
+            format_version: review.format_version,
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        diesel::insert_into(app_review_revisions::table)
+            .values(revision.clone())
+            .execute(conn)?;
+        Ok(revision)
+    }
+
+    /// All revisions of the user's reviews of an app, oldest first
+    pub fn find_all_by_user_id(user_id: &uuid::Uuid, source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        app_review_revisions::table
+            .filter(app_review_revisions::user_id.eq(user_id.to_string()))
+            .filter(app_review_revisions::source_id.eq(source_id))
+            .filter(app_review_revisions::app_bundle_id.eq(app_bundle_id))
+            .order((app_review_revisions::created_at.asc(), app_review_revisions::sequence_number.asc(), app_review_revisions::revision_number.asc()))
+            .get_results(conn)
+    }
+}
@@ -0,0 +1,46 @@
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::app_review_sequences;
+
+
+/// Counter of the review sequence numbers handed out for an app
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = app_review_sequences)]
+pub struct AppReviewSequence {
+    pub source_id: String,
+    pub app_bundle_id: String,
+    pub last_sequence_number: i32,
+}
+
+impl AppReviewSequence {
+    /// Get the sequence of an app, creating it if needed, and lock it until the current
+    /// transaction ends. Concurrent transactions for the same app wait for each other here.
+    pub fn lock(source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<Self, Error> {
+        diesel::insert_into(app_review_sequences::table)
+            .values(AppReviewSequence {
+                source_id: source_id.to_string(),
+                app_bundle_id: app_bundle_id.to_string(),
+                last_sequence_number: 0,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        app_review_sequences::table
+            .find((source_id, app_bundle_id))
+            .for_update()
+            .get_result(conn)
+    }
+
+    /// Hand out the next sequence number. The number is only taken once the surrounding
+    /// transaction commits, so a failed insert doesn't leave a gap.
+    pub fn next(&mut self, conn: &mut Connection) -> Result<i32, Error> {
+        self.last_sequence_number = diesel::update(app_review_sequences::table.find((&self.source_id, &self.app_bundle_id)))
+            .set(app_review_sequences::last_sequence_number.eq(app_review_sequences::last_sequence_number + 1))
+            .returning(app_review_sequences::last_sequence_number)
+            .get_result(conn)?;
+
+        Ok(self.last_sequence_number)
+    }
+}
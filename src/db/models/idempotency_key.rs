@@ -0,0 +1,78 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods, BoolExpressionMethods, OptionalExtension};
+use diesel::result::Error;
+use diesel::upsert::excluded;
+
+use crate::db::Connection;
+use crate::db::schema::idempotency_keys;
+
+
+/// The response to a request sent with an idempotency key
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = idempotency_keys)]
+pub struct IdempotencyKey {
+    /// Authenticated user of the request, or a keyed hash of the client's credentials or network
+    pub owner: String,
+    pub idempotency_key: String,
+    /// SHA-256 of the request's method, path, query and body, keyed with the service's secret
+    pub fingerprint: String,
+    /// Not set while the first request is processed
+    pub status_code: Option<i32>,
+    /// The response's headers and body, encrypted with a key derived from the request
+    pub response: Option<Vec<u8>>,
+    pub created_at: NaiveDateTime,
+}
+
+impl IdempotencyKey {
+    /// Take the key for a request that is about to be processed. A key that is taken can only be taken over
+    /// once it expired, or if its request wasn't answered since the given time. Returns whether the key was taken.
+    pub fn claim(&self, expired_before: NaiveDateTime, abandoned_before: NaiveDateTime, conn: &mut Connection) -> Result<bool, Error> {
+        let upsert = diesel::insert_into(idempotency_keys::table)
+            .values(self.clone())
+            .on_conflict((idempotency_keys::owner, idempotency_keys::idempotency_key))
+            .do_update()
+            .set((
+                idempotency_keys::fingerprint.eq(excluded(idempotency_keys::fingerprint)),
+                idempotency_keys::status_code.eq(excluded(idempotency_keys::status_code)),
+                idempotency_keys::response.eq(excluded(idempotency_keys::response)),
+                idempotency_keys::created_at.eq(excluded(idempotency_keys::created_at)),
+            ));
+        // `ON CONFLICT DO UPDATE ... WHERE`, which `QueryDsl` doesn't offer
+        diesel::query_dsl::methods::FilterDsl::filter(upsert, idempotency_keys::created_at.lt(expired_before)
+            .or(idempotency_keys::status_code.is_null().and(idempotency_keys::created_at.lt(abandoned_before))))
+            .execute(conn)
+            .map(|taken| taken == 1)
+    }
+
+    pub fn find(owner: &str, idempotency_key: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        idempotency_keys::table
+            .find((owner, idempotency_key))
+            .first(conn)
+            .optional()
+    }
+
+    /// Store the response of the request that took the key
+    pub fn save_response(&self, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::update(idempotency_keys::table.find((&self.owner, &self.idempotency_key)))
+            .filter(idempotency_keys::fingerprint.eq(&self.fingerprint))
+            .set((
+                idempotency_keys::status_code.eq(self.status_code),
+                idempotency_keys::response.eq(&self.response),
+            ))
+            .execute(conn)
+    }
+
+    /// Give up the key of a request whose response isn't kept, so it can be retried
+    pub fn release(&self, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::delete(idempotency_keys::table.find((&self.owner, &self.idempotency_key)))
+            .filter(idempotency_keys::fingerprint.eq(&self.fingerprint))
+            .filter(idempotency_keys::status_code.is_null())
+            .execute(conn)
+    }
+
+    /// Delete the keys taken before the time. Returns the number of deleted keys.
+    pub fn delete_created_before(before: NaiveDateTime, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::delete(idempotency_keys::table.filter(idempotency_keys::created_at.lt(before)))
+            .execute(conn)
+    }
+}
@@ -1,6 +1,19 @@
 pub mod user;
 pub mod oauth_authorization;
 pub mod app_review;
+pub mod app_review_reply;
+pub mod app_review_revision;
+pub mod app_review_sequence;
+pub mod idempotency_key;
+pub mod review_batch_operation;
+pub mod review_log;
+pub mod review_moderation;
+pub mod review_risk_assessment;
+pub mod review_signing_key;
+pub mod source;
+pub mod user_device;
+pub mod user_setting;
+pub mod webhook;
 
 use diesel::result::Error;
 
@@ -9,6 +22,7 @@ use crate::db::Connection;
 pub trait DbModel {
     fn insert(&mut self, conn: &mut Connection) -> Result<Self, Error> where Self: std::marker::Sized;
     fn update(&mut self, conn: &mut Connection) -> Result<Self, Error> where Self: std::marker::Sized;
+    #[allow(dead_code)]
     fn delete(&mut self, conn: &mut Connection) -> Result<(), Error>;
 }
 
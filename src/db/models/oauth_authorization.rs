@@ -3,6 +3,7 @@ use std::ops::Deref;
 use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 use diesel::result::Error;
+use oxide_auth::primitives::scope::Scope;
 use serde::{Deserialize, Serialize};
 
 use crate::db::Connection;
@@ -20,16 +21,16 @@ pub struct OAuthAuthorization {
     pub created_at: NaiveDateTime,
     #[serde(skip_serializing)]
     pub updated_at: NaiveDateTime,
-}
-
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct UserDTO {
-    pub user_id: String,
-    pub client_id: String,
+    #[serde(skip_serializing)]
+    pub last_used_at: Option<NaiveDateTime>,
+    /// Space separated list of the scopes the user granted to the client
+    pub scope: String,
+    #[serde(skip_serializing)]
+    pub expires_at: Option<NaiveDateTime>,
 }
 
 impl OAuthAuthorization {
-    pub fn new(user: &User, client_id: &str) -> Self {
+    pub fn new(user: &User, client_id: &str, scope: &Scope, expires_at: Option<NaiveDateTime>) -> Self {
         let now = Utc::now().naive_utc();
 
         Self {
@@ -38,8 +39,20 @@ impl OAuthAuthorization {
 
             created_at: now,
             updated_at: now,
+            last_used_at: None,
+
+            scope: scope.to_string(),
+            expires_at,
         }
     }
+
+    pub fn granted_scope(&self) -> Option<Scope> {
+        self.scope.parse().ok()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    }
 }
 
 impl OAuthAuthorization {
@@ -55,6 +68,47 @@ impl OAuthAuthorization {
             .execute(conn)
             .map(|_| ())
     }
+
+    /// Extend the authorization with additional scopes and renew its expiration. Scopes of an
+    /// expired authorization are replaced instead.
+    pub fn extend(&mut self, scope: &Scope, expires_at: Option<NaiveDateTime>, conn: &mut Connection) -> Result<(), Error> {
+        let mut scopes: Vec<&str> = match self.is_expired() {
+            true => vec![],
+            false => self.scope.split_whitespace().collect(),
+        };
+        for s in scope.iter() {
+            if !scopes.contains(&s) {
+                scopes.push(s);
+            }
+        }
+
+        self.scope = scopes.join(" ");
+        self.expires_at = expires_at;
+        self.updated_at = Utc::now().naive_utc();
+
+        diesel::update(self.deref())
+            .set((
+                oauth_authorizations::scope.eq(&self.scope),
+                oauth_authorizations::expires_at.eq(self.expires_at),
+                oauth_authorizations::updated_at.eq(self.updated_at),
+            ))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find(user_id: &str, client_id: &str, conn: &mut Connection) -> Result<Self, Error> {
+        oauth_authorizations::dsl::oauth_authorizations
+            .find((user_id, client_id))
+            .get_result::<Self>(conn)
+    }
+
+    /// Record that tokens were issued to a client on behalf of the user
+    pub fn mark_used(user_id: &str, client_id: &str, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(oauth_authorizations::dsl::oauth_authorizations.find((user_id, client_id)))
+            .set(oauth_authorizations::last_used_at.eq(Some(Utc::now().naive_utc())))
+            .execute(conn)
+            .map(|_| ())
+    }
 }
 
 impl User {
@@ -66,16 +120,23 @@ impl User {
             .ok()
     }
 
+    pub fn oauth_client_authorizations(&self, conn: &mut Connection) -> Result<Vec<OAuthAuthorization>, Error> {
+        OAuthAuthorization::belonging_to(self)
+            .select(OAuthAuthorization::as_select())
+            .order(oauth_authorizations::created_at.asc())
+            .load(conn)
+    }
+
     pub fn has_authorized_oauth_client(&self, client_id: &str, conn: &mut Connection) -> bool {
         self.authorization_for_oauth_client(client_id, conn).is_some()
     }
 
-    pub fn save_oauth_client_authorization(&mut self, client_id: &str, conn: &mut Connection) -> Result<(), Error> {
-        if self.has_authorized_oauth_client(client_id, conn) {
-            return Ok(())
+    pub fn save_oauth_client_authorization(&mut self, client_id: &str, scope: &Scope, expires_at: Option<NaiveDateTime>, conn: &mut Connection) -> Result<(), Error> {
+        if let Some(mut authorization) = self.authorization_for_oauth_client(client_id, conn) {
+            return authorization.extend(scope, expires_at, conn)
         }
 
-        OAuthAuthorization::new(&self, client_id)
+        OAuthAuthorization::new(self, client_id, scope, expires_at)
             .insert(conn)
     }
 
@@ -0,0 +1,44 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods, OptionalExtension};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::review_batch_operations;
+
+
+/// A batch operation that was applied, kept by its idempotency key to answer replays
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_batch_operations)]
+pub struct ReviewBatchOperation {
+    pub user_id: String,
+    pub idempotency_key: String,
+    /// SHA-256 of the operation
+    pub request_hash: String,
+    /// JSON of the signed review that was returned for the operation
+    pub response: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl ReviewBatchOperation {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_batch_operations::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find(user_id: &uuid::Uuid, idempotency_key: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        review_batch_operations::table
+            .find((user_id.to_string(), idempotency_key))
+            .first(conn)
+            .optional()
+    }
+
+    /// Forget the user's operations applied before the time. Returns the number of forgotten operations.
+    pub fn delete_applied_before(user_id: &uuid::Uuid, before: NaiveDateTime, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::delete(review_batch_operations::table
+            .filter(review_batch_operations::user_id.eq(user_id.to_string()))
+            .filter(review_batch_operations::created_at.lt(before)))
+            .execute(conn)
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods, OptionalExtension};
+use diesel::dsl::count_star;
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::{review_log_entries, review_log_subtrees, review_log_tree_heads};
+
+
+/// A signed review in the append-only transparency log of an app
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_log_entries)]
+pub struct ReviewLogEntry {
+    pub source_id: String,
+    pub app_bundle_id: String,
+    pub leaf_index: i64,
+    pub leaf_hash: Vec<u8>,
+    pub sequence_number: i32,
+    pub status: String,
+    pub signature: String,
+    pub created_at: NaiveDateTime,
+    pub key_id: Option<String>,
+    pub format_version: i32,
+}
+
+/// A signed root hash of the first `tree_size` entries in the log of an app
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_log_tree_heads)]
+pub struct ReviewLogTreeHead {
+    pub source_id: String,
+    pub app_bundle_id: String,
+    pub tree_size: i64,
+    pub root_hash: Vec<u8>,
+    pub signature: String,
+    pub created_at: NaiveDateTime,
+    pub key_id: Option<String>,
+    pub format_version: i32,
+}
+
+impl ReviewLogEntry {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_log_entries::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn count(source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<i64, Error> {
+        review_log_entries::table
+            .filter(review_log_entries::source_id.eq(source_id))
+            .filter(review_log_entries::app_bundle_id.eq(app_bundle_id))
+            .select(count_star())
+            .get_result(conn)
+    }
+
+    pub fn find_range(source_id: &str, app_bundle_id: &str, start: i64, end: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        review_log_entries::table
+            .filter(review_log_entries::source_id.eq(source_id))
+            .filter(review_log_entries::app_bundle_id.eq(app_bundle_id))
+            .filter(review_log_entries::leaf_index.ge(start))
+            .filter(review_log_entries::leaf_index.lt(end))
+            .order(review_log_entries::leaf_index.asc())
+            .get_results(conn)
+    }
+
+    pub fn find_by_leaf_hash(source_id: &str, app_bundle_id: &str, leaf_hash: &[u8], conn: &mut Connection) -> Result<Option<Self>, Error> {
+        review_log_entries::table
+            .filter(review_log_entries::source_id.eq(source_id))
+            .filter(review_log_entries::app_bundle_id.eq(app_bundle_id))
+            .filter(review_log_entries::leaf_hash.eq(leaf_hash))
+            .order(review_log_entries::leaf_index.asc())
+            .first(conn)
+            .optional()
+    }
+
+    /// Number of entries in the log of every app of a source
+    pub fn source_sizes(source_id: &str, conn: &mut Connection) -> Result<HashMap<String, i64>, Error> {
+        review_log_entries::table
+            .filter(review_log_entries::source_id.eq(source_id))
+            .group_by(review_log_entries::app_bundle_id)
+            .select((review_log_entries::app_bundle_id, count_star()))
+            .load::<(String, i64)>(conn)
+            .map(|rows| rows.into_iter().collect())
+    }
+
+    /// Sequence numbers of the reviews of an app that were signed or deleted in the log entries from `start` on
+    pub fn changed_sequence_numbers(source_id: &str, app_bundle_id: &str, start: i64, conn: &mut Connection) -> Result<Vec<i32>, Error> {
+        review_log_entries::table
+            .filter(review_log_entries::source_id.eq(source_id))
+            .filter(review_log_entries::app_bundle_id.eq(app_bundle_id))
+            .filter(review_log_entries::leaf_index.ge(start))
+            .select(review_log_entries::sequence_number)
+            .distinct()
+            .get_results(conn)
+    }
+
+    /// Number of entries in the log of every app
+    pub fn sizes(conn: &mut Connection) -> Result<HashMap<(String, String), i64>, Error> {
+        review_log_entries::table
+            .group_by((review_log_entries::source_id, review_log_entries::app_bundle_id))
+            .select((review_log_entries::source_id, review_log_entries::app_bundle_id, count_star()))
+            .load::<(String, String, i64)>(conn)
+            .map(|rows| rows.into_iter().map(|(source_id, app_bundle_id, size)| ((source_id, app_bundle_id), size)).collect())
+    }
+}
+
+/// Hash of a complete subtree in the log of an app, see `merkle::Subtrees`
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_log_subtrees)]
+pub struct ReviewLogSubtree {
+    pub source_id: String,
+    pub app_bundle_id: String,
+    pub level: i32,
+    pub subtree_index: i64,
+    pub hash: Vec<u8>,
+}
+
+impl ReviewLogSubtree {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_log_subtrees::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find(source_id: &str, app_bundle_id: &str, level: i32, subtree_index: i64, conn: &mut Connection) -> Result<Self, Error> {
+        review_log_subtrees::table
+            .find((source_id, app_bundle_id, level, subtree_index))
+            .first(conn)
+    }
+
+    /// Subtrees of the log of an app at any of the levels and any of the indices
+    pub fn find_all(source_id: &str, app_bundle_id: &str, levels: &[i32], subtree_indices: &[i64], conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        review_log_subtrees::table
+            .filter(review_log_subtrees::source_id.eq(source_id))
+            .filter(review_log_subtrees::app_bundle_id.eq(app_bundle_id))
+            .filter(review_log_subtrees::level.eq_any(levels))
+            .filter(review_log_subtrees::subtree_index.eq_any(subtree_indices))
+            .get_results(conn)
+    }
+}
+
+impl ReviewLogTreeHead {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_log_tree_heads::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find_latest(source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        review_log_tree_heads::table
+            .filter(review_log_tree_heads::source_id.eq(source_id))
+            .filter(review_log_tree_heads::app_bundle_id.eq(app_bundle_id))
+            .order(review_log_tree_heads::tree_size.desc())
+            .first(conn)
+            .optional()
+    }
+
+    /// Size of the latest signed tree of every app
+    pub fn latest_sizes(conn: &mut Connection) -> Result<HashMap<(String, String), i64>, Error> {
+        review_log_tree_heads::table
+            .group_by((review_log_tree_heads::source_id, review_log_tree_heads::app_bundle_id))
+            .select((review_log_tree_heads::source_id, review_log_tree_heads::app_bundle_id, diesel::dsl::max(review_log_tree_heads::tree_size)))
+            .load::<(String, String, Option<i64>)>(conn)
+            .map(|rows| rows.into_iter()
+                .filter_map(|(source_id, app_bundle_id, size)| Some(((source_id, app_bundle_id), size?)))
+                .collect())
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::{review_moderation_actions, review_reports};
+
+
+/// A moderator's action on a review, kept as an audit record that can't be changed
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_moderation_actions)]
+pub struct ReviewModerationAction {
+    pub id: String,
+    pub review_id: String,
+    pub moderator_id: String,
+    pub action: String,
+    pub reason: String,
+    pub previous_status: String,
+    pub status: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// A user's report of a review, open until a moderator acts on the review
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_reports)]
+pub struct ReviewReport {
+    pub id: String,
+    pub review_id: String,
+    pub reporter_id: String,
+    pub reason: String,
+    pub comment: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// Moderation action that resolved the report
+    pub resolved_by: Option<String>,
+}
+
+impl ReviewModerationAction {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_moderation_actions::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// Actions on a review, oldest first
+    pub fn find_all_by_review_id(review_id: &str, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        review_moderation_actions::table
+            .filter(review_moderation_actions::review_id.eq(review_id))
+            .order(review_moderation_actions::created_at.asc())
+            .get_results(conn)
+    }
+}
+
+impl ReviewReport {
+    /// Insert the report, unless the user already reported the review. Returns whether it was inserted.
+    pub fn insert(&self, conn: &mut Connection) -> Result<bool, Error> {
+        diesel::insert_into(review_reports::table)
+            .values(self.clone())
+            .on_conflict((review_reports::review_id, review_reports::reporter_id))
+            .do_nothing()
+            .execute(conn)
+            .map(|inserted| inserted > 0)
+    }
+
+    pub fn find_by_reporter_id(review_id: &str, reporter_id: &str, conn: &mut Connection) -> Result<Self, Error> {
+        review_reports::table
+            .filter(review_reports::review_id.eq(review_id))
+            .filter(review_reports::reporter_id.eq(reporter_id))
+            .get_result(conn)
+    }
+
+    /// Open reports, oldest first
+    pub fn find_open(limit: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        review_reports::table
+            .filter(review_reports::resolved_by.is_null())
+            .order(review_reports::created_at.asc())
+            .limit(limit)
+            .get_results(conn)
+    }
+
+    /// Resolve all open reports of a review with a moderation action
+    pub fn resolve(review_id: &str, action_id: &str, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::update(review_reports::table
+            .filter(review_reports::review_id.eq(review_id))
+            .filter(review_reports::resolved_by.is_null()))
+            .set(review_reports::resolved_by.eq(action_id))
+            .execute(conn)
+    }
+}
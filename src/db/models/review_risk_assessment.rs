@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::review_risk_assessments;
+
+
+/// The fraud check of a review submission
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_risk_assessments)]
+pub struct ReviewRiskAssessment {
+    pub id: String,
+    /// Not set for rejected submissions, which aren't stored as reviews
+    pub review_id: Option<String>,
+    pub user_id: String,
+    pub source_id: String,
+    pub app_bundle_id: String,
+    /// Keyed hash of the client's network
+    pub network_hash: Option<String>,
+    pub review_rating: i32,
+    pub risk_score: i32,
+    /// JSON object with the score of every signal that saw a risk
+    pub signals: String,
+    pub decision: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl ReviewRiskAssessment {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_risk_assessments::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// Number of submissions of other users for reviews of the app in the time range
+    pub fn count_app_submissions(source_id: &str, app_bundle_id: &str, except_user_id: &str, from: NaiveDateTime, until: NaiveDateTime, conn: &mut Connection) -> Result<i64, Error> {
+        review_risk_assessments::table
+            .filter(review_risk_assessments::source_id.eq(source_id))
+            .filter(review_risk_assessments::app_bundle_id.eq(app_bundle_id))
+            .filter(review_risk_assessments::user_id.ne(except_user_id))
+            .filter(review_risk_assessments::created_at.ge(from))
+            .filter(review_risk_assessments::created_at.lt(until))
+            .count()
+            .get_result(conn)
+    }
+
+    /// Number of other users that submitted reviews of the app from the network since the time
+    pub fn count_network_users(source_id: &str, app_bundle_id: &str, network_hash: &str, except_user_id: &str, since: NaiveDateTime, conn: &mut Connection) -> Result<i64, Error> {
+        review_risk_assessments::table
+            .filter(review_risk_assessments::source_id.eq(source_id))
+            .filter(review_risk_assessments::app_bundle_id.eq(app_bundle_id))
+            .filter(review_risk_assessments::network_hash.eq(network_hash))
+            .filter(review_risk_assessments::user_id.ne(except_user_id))
+            .filter(review_risk_assessments::created_at.ge(since))
+            .select(diesel::dsl::count_distinct(review_risk_assessments::user_id))
+            .get_result(conn)
+    }
+
+    /// The latest assessment of each of the reviews, by review id
+    pub fn find_latest_by_review_ids(review_ids: &[String], conn: &mut Connection) -> Result<HashMap<String, Self>, Error> {
+        let assessments: Vec<Self> = review_risk_assessments::table
+            .filter(review_risk_assessments::review_id.eq_any(review_ids))
+            .order(review_risk_assessments::created_at.desc())
+            .get_results(conn)?;
+
+        let mut latest = HashMap::new();
+        for assessment in assessments {
+            if let Some(review_id) = assessment.review_id.clone() {
+                latest.entry(review_id).or_insert(assessment);
+            }
+        }
+        Ok(latest)
+    }
+}
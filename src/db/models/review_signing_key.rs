@@ -0,0 +1,53 @@
+use chrono::NaiveDateTime;
+use diesel::{Queryable, Insertable, RunQueryDsl, QueryDsl, ExpressionMethods};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::review_signing_keys;
+
+
+/// Public part and validity window of a key that signs reviews. Private keys are kept in the storage directory.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = review_signing_keys)]
+pub struct ReviewSigningKey {
+    pub key_id: String,
+    /// PEM encoded public key
+    pub public_key: String,
+    pub valid_from: NaiveDateTime,
+    /// The key is active as long as this isn't set. After a rotation, the newest key without it signs and
+    /// the older ones are retired once a server starts signing with it.
+    pub valid_until: Option<NaiveDateTime>,
+}
+
+impl ReviewSigningKey {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(review_signing_keys::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// All keys, from the oldest to the newest
+    pub fn find_all(conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        review_signing_keys::table
+            .order(review_signing_keys::valid_from.asc())
+            .get_results(conn)
+    }
+
+    pub fn retire(&mut self, at: NaiveDateTime, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(review_signing_keys::table.find(&self.key_id))
+            .set(review_signing_keys::valid_until.eq(at))
+            .execute(conn)?;
+        self.valid_until = Some(at);
+        Ok(())
+    }
+
+    /// Start signing with a key that was added by a rotation
+    pub fn activate(&mut self, at: NaiveDateTime, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(review_signing_keys::table.find(&self.key_id))
+            .set(review_signing_keys::valid_from.eq(at))
+            .execute(conn)?;
+        self.valid_from = at;
+        Ok(())
+    }
+}
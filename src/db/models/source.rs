@@ -0,0 +1,134 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Queryable, Insertable, AsChangeset, RunQueryDsl, QueryDsl, ExpressionMethods, OptionalExtension};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::{source_maintainers, sources};
+
+
+/// A source with verified maintainers and the settings they chose
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = sources)]
+pub struct Source {
+    /// Identifier of the source
+    pub id: String,
+    /// Whether users can review the apps of the source
+    pub accepts_reviews: bool,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// Origin of the URL the source was first verified at, where all claims of it have to be verified
+    pub origin: Option<String>,
+}
+
+/// A user's claim to maintain a source, which counts once it is verified
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = source_maintainers, treat_none_as_null = true)]
+pub struct SourceMaintainer {
+    pub id: String,
+    pub source_id: String,
+    pub user_id: String,
+    /// URL of the source's JSON
+    pub source_url: String,
+    /// Token that the maintainer serves at the source's URL to prove the claim
+    pub challenge: String,
+    /// How the claim was verified
+    pub method: Option<String>,
+    pub verified_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Source {
+    pub fn find_by_id(id: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        sources::table
+            .find(id)
+            .get_result(conn)
+            .optional()
+    }
+
+    /// Whether users can review the apps of the source. Sources without maintainers accept reviews.
+    pub fn accepts_reviews(id: &str, conn: &mut Connection) -> Result<bool, Error> {
+        Ok(Self::find_by_id(id, conn)?.is_none_or(|source| source.accepts_reviews))
+    }
+
+    /// Insert the source with the default settings, unless it exists
+    pub fn insert_default(id: &str, conn: &mut Connection) -> Result<Self, Error> {
+        let now = Utc::now().naive_utc();
+        diesel::insert_into(sources::table)
+            .values(Source { id: id.to_string(), accepts_reviews: true, created_at: now, updated_at: now, origin: None })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        sources::table.find(id).get_result(conn)
+    }
+
+    /// Set the origin of the source unless it has one, and return the source with the origin it has
+    pub fn bind_origin(id: &str, origin: &str, conn: &mut Connection) -> Result<Self, Error> {
+        diesel::update(sources::table.find(id))
+            .filter(sources::origin.is_null())
+            .set(sources::origin.eq(origin))
+            .execute(conn)?;
+        sources::table.find(id).get_result(conn)
+    }
+
+    pub fn update(&mut self, conn: &mut Connection) -> Result<Self, Error> {
+        self.updated_at = Utc::now().naive_utc();
+        diesel::update(sources::table.find(&self.id))
+            .set(self.clone())
+            .execute(conn)?;
+        Ok(self.clone())
+    }
+}
+
+impl SourceMaintainer {
+    pub fn new(source_id: &str, user_id: &uuid::Uuid, source_url: &str, challenge: &str) -> Self {
+        let now = Utc::now().naive_utc();
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            source_id: source_id.to_string(),
+            user_id: user_id.to_string(),
+            source_url: source_url.to_string(),
+            challenge: challenge.to_string(),
+            method: None,
+            verified_at: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(source_maintainers::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn update(&mut self, conn: &mut Connection) -> Result<Self, Error> {
+        self.updated_at = Utc::now().naive_utc();
+        diesel::update(source_maintainers::table.find(&self.id))
+            .set(self.clone())
+            .execute(conn)?;
+        Ok(self.clone())
+    }
+
+    /// The user's claim to maintain the source, verified or not
+    pub fn find_by_user_id(source_id: &str, user_id: &uuid::Uuid, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        source_maintainers::table
+            .filter(source_maintainers::source_id.eq(source_id))
+            .filter(source_maintainers::user_id.eq(user_id.to_string()))
+            .get_result(conn)
+            .optional()
+    }
+
+    /// All of the user's claims, oldest first
+    pub fn find_all_by_user_id(user_id: &uuid::Uuid, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        source_maintainers::table
+            .filter(source_maintainers::user_id.eq(user_id.to_string()))
+            .order(source_maintainers::created_at.asc())
+            .get_results(conn)
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified_at.is_some()
+    }
+}
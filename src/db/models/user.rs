@@ -23,6 +23,9 @@ pub struct User {
     pub created_at: NaiveDateTime,
     #[serde(skip_serializing)]
     pub updated_at: NaiveDateTime,
+    /// Moderators can hide, restore and remove reviews
+    #[serde(default)]
+    pub is_moderator: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -45,6 +48,7 @@ impl User {
             password_hash: password_hash.to_string(),
 
             username: None,
+            is_moderator: false,
         }
     }
 }
@@ -72,6 +76,13 @@ impl User {
             .get_result::<User>(conn)
     }
 
+    /// Grant or revoke the moderator role of the user with the email address
+    pub fn set_moderator(email: &str, is_moderator: bool, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::update(users::dsl::users.filter(users::email.eq(email)))
+            .set(users::is_moderator.eq(is_moderator))
+            .execute(conn)
+    }
+
     pub async fn update(&mut self, conn: &mut Connection) -> Result<Self, Error> {
         self.updated_at = Utc::now().naive_utc();
 
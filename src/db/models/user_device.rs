@@ -0,0 +1,64 @@
+use chrono::NaiveDateTime;
+use diesel::{ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl};
+use diesel::result::Error;
+
+use crate::db::Connection;
+use crate::db::schema::user_devices;
+
+
+/// A device that syncs the user's settings
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = user_devices)]
+pub struct UserDevice {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    pub last_synced_at: Option<NaiveDateTime>,
+}
+
+impl UserDevice {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(user_devices::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn mark_synced(&self, at: NaiveDateTime, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(user_devices::table.find(&self.id))
+            .set(user_devices::last_synced_at.eq(at))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn delete(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::delete(user_devices::table.find(&self.id))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// The device if it belongs to the user
+    pub fn find(id: &str, user_id: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        user_devices::table
+            .find(id)
+            .filter(user_devices::user_id.eq(user_id))
+            .first(conn)
+            .optional()
+    }
+
+    /// Devices of the user, oldest first
+    pub fn find_all_by_user_id(user_id: &str, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        user_devices::table
+            .filter(user_devices::user_id.eq(user_id))
+            .order(user_devices::created_at.asc())
+            .get_results(conn)
+    }
+
+    pub fn count_by_user_id(user_id: &str, conn: &mut Connection) -> Result<i64, Error> {
+        user_devices::table
+            .filter(user_devices::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+    }
+}
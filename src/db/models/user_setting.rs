@@ -0,0 +1,198 @@
+use chrono::NaiveDateTime;
+use diesel::{ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl};
+use diesel::result::Error;
+use diesel::upsert::excluded;
+
+use crate::db::Connection;
+use crate::db::schema::{user_settings, user_settings_sync};
+use crate::util::vector_clock::VectorClock;
+
+
+/// A setting that is synced across the user's devices. Deleted settings are kept for a while without a value, so
+/// devices learn about the deletion.
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = user_settings)]
+pub struct UserSetting {
+    pub user_id: String,
+    /// Dotted name of the app or feature that owns the setting, like `sidestore.app`
+    pub namespace: String,
+    pub key: String,
+    /// JSON of the value, `null` if the setting was deleted
+    pub value: String,
+    /// Counts the changes of the value, starting at 1
+    pub version: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    /// JSON of the vector clock of the latest change
+    pub clock: String,
+    /// Number of the latest change among the changes of the user's settings
+    pub change_seq: i64,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+/// Counter of the changes of a user's settings
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = user_settings_sync)]
+pub struct UserSettingsSync {
+    pub user_id: String,
+    /// Number of the latest change
+    pub change_seq: i64,
+    /// Latest change whose deletion was forgotten
+    pub purged_change_seq: i64,
+}
+
+impl UserSetting {
+    /// A setting that doesn't exist yet, it's saved with its first change
+    pub fn new(user_id: &str, namespace: &str, key: &str, now: NaiveDateTime) -> Self {
+        UserSetting {
+            user_id: user_id.to_string(),
+            namespace: namespace.to_string(),
+            key: key.to_string(),
+            value: "null".to_string(),
+            version: 0,
+            created_at: now,
+            updated_at: now,
+            clock: "{}".to_string(),
+            change_seq: 0,
+            deleted_at: Some(now),
+        }
+    }
+
+    /// Set the JSON of the value, or delete the setting without a value, as the change with the number
+    pub fn change(&mut self, value: Option<String>, clock: &VectorClock, change_seq: i64, now: NaiveDateTime) {
+        self.deleted_at = value.is_none().then_some(now);
+        self.value = value.unwrap_or_else(|| "null".to_string());
+        self.version += 1;
+        self.clock = serde_json::to_string(clock).unwrap_or_else(|_| "{}".to_string());
+        self.change_seq = change_seq;
+        self.updated_at = now;
+    }
+
+    pub fn save(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(user_settings::table)
+            .values(self.clone())
+            .on_conflict((user_settings::user_id, user_settings::namespace, user_settings::key))
+            .do_update()
+            .set((
+                user_settings::value.eq(excluded(user_settings::value)),
+                user_settings::version.eq(excluded(user_settings::version)),
+                user_settings::updated_at.eq(excluded(user_settings::updated_at)),
+                user_settings::clock.eq(excluded(user_settings::clock)),
+                user_settings::change_seq.eq(excluded(user_settings::change_seq)),
+                user_settings::deleted_at.eq(excluded(user_settings::deleted_at)),
+            ))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find(user_id: &str, namespace: &str, key: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        user_settings::table
+            .find((user_id, namespace, key))
+            .filter(user_settings::deleted_at.is_null())
+            .first(conn)
+            .optional()
+    }
+
+    /// Settings of the user with the keys, deleted ones too, locked until the end of the transaction
+    pub fn find_all_by_keys_for_update(user_id: &str, namespace: &str, keys: &[&str], conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        user_settings::table
+            .filter(user_settings::user_id.eq(user_id))
+            .filter(user_settings::namespace.eq(namespace))
+            .filter(user_settings::key.eq_any(keys))
+            .for_update()
+            .get_results(conn)
+    }
+
+    /// Settings of the user, of all namespaces or of one, by namespace and key
+    pub fn find_all_by_user_id(user_id: &str, namespace: Option<&str>, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        let mut query = user_settings::table
+            .filter(user_settings::user_id.eq(user_id))
+            .filter(user_settings::deleted_at.is_null())
+            .into_boxed();
+        if let Some(namespace) = namespace {
+            query = query.filter(user_settings::namespace.eq(namespace));
+        }
+        query
+            .order((user_settings::namespace.asc(), user_settings::key.asc()))
+            .get_results(conn)
+    }
+
+    /// Settings of the user that changed after the change with the number, in the order of their changes
+    pub fn find_changed_after(user_id: &str, change_seq: i64, with_deleted: bool, limit: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        let mut query = user_settings::table
+            .filter(user_settings::user_id.eq(user_id))
+            .filter(user_settings::change_seq.gt(change_seq))
+            .into_boxed();
+        if !with_deleted {
+            query = query.filter(user_settings::deleted_at.is_null());
+        }
+        query
+            .order(user_settings::change_seq.asc())
+            .limit(limit)
+            .get_results(conn)
+    }
+
+    pub fn count_by_user_id(user_id: &str, conn: &mut Connection) -> Result<i64, Error> {
+        user_settings::table
+            .filter(user_settings::user_id.eq(user_id))
+            .filter(user_settings::deleted_at.is_null())
+            .count()
+            .get_result(conn)
+    }
+
+    /// Users with settings that were deleted before the time
+    pub fn find_user_ids_deleted_before(before: NaiveDateTime, conn: &mut Connection) -> Result<Vec<String>, Error> {
+        user_settings::table
+            .filter(user_settings::deleted_at.lt(before))
+            .select(user_settings::user_id)
+            .distinct()
+            .get_results(conn)
+    }
+
+    /// Forget the user's settings that were deleted before the time. Returns the numbers of their latest changes.
+    pub fn purge_deleted_before(user_id: &str, before: NaiveDateTime, conn: &mut Connection) -> Result<Vec<i64>, Error> {
+        diesel::delete(user_settings::table
+            .filter(user_settings::user_id.eq(user_id))
+            .filter(user_settings::deleted_at.lt(before)))
+            .returning(user_settings::change_seq)
+            .get_results(conn)
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn vector_clock(&self) -> VectorClock {
+        serde_json::from_str(&self.clock).unwrap_or_default()
+    }
+}
+
+impl UserSettingsSync {
+    /// The counter of the user, locked until the end of the transaction, so the user's changes are made one by one
+    pub fn lock(user_id: &str, conn: &mut Connection) -> Result<Self, Error> {
+        diesel::insert_into(user_settings_sync::table)
+            .values(UserSettingsSync { user_id: user_id.to_string(), change_seq: 0, purged_change_seq: 0 })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        user_settings_sync::table
+            .find(user_id)
+            .for_update()
+            .first(conn)
+    }
+
+    /// Number of a new change
+    pub fn next_change_seq(&mut self) -> i64 {
+        self.change_seq += 1;
+        self.change_seq
+    }
+
+    pub fn update(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(user_settings_sync::table.find(&self.user_id))
+            .set((
+                user_settings_sync::change_seq.eq(self.change_seq),
+                user_settings_sync::purged_change_seq.eq(self.purged_change_seq),
+            ))
+            .execute(conn)
+            .map(|_| ())
+    }
+}
@@ -0,0 +1,196 @@
+use chrono::NaiveDateTime;
+use diesel::{AsChangeset, BoolExpressionMethods, ExpressionMethods, Insertable, OptionalExtension, QueryDsl, Queryable, RunQueryDsl};
+use diesel::upsert::excluded;
+use diesel::result::Error;
+
+use crate::api::models::webhooks::{WebhookDeliveryStatus, WebhookEventType};
+use crate::db::Connection;
+use crate::db::schema::{webhook_deliveries, webhook_subscriptions};
+
+
+/// An endpoint that is sent events of a source, or of all sources and accounts for an OAuth client
+#[derive(Debug, Clone, Queryable, Insertable)]
+#[diesel(table_name = webhook_subscriptions)]
+pub struct WebhookSubscription {
+    pub id: String,
+    pub source_id: Option<String>,
+    pub client_id: Option<String>,
+    /// Maintainer who subscribed
+    pub created_by: Option<String>,
+    pub url: String,
+    /// Key of the HMAC signatures of the deliveries
+    pub secret: String,
+    /// Space separated list of the event types that are sent
+    pub events: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A delivery of an event to a subscription, which is retried until it succeeds or runs out of attempts
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = webhook_deliveries, treat_none_as_null = true)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub subscription_id: String,
+    pub event_id: String,
+    pub event_type: String,
+    /// JSON of the event
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_attempt_at: Option<NaiveDateTime>,
+    /// HTTP status of the response to the latest attempt
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+impl WebhookSubscription {
+    pub fn insert(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(webhook_subscriptions::table)
+            .values(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// Insert the subscription of a client, or replace its URL, secret and events
+    pub fn upsert_client(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::insert_into(webhook_subscriptions::table)
+            .values(self.clone())
+            .on_conflict(webhook_subscriptions::client_id)
+            .do_update()
+            .set((
+                webhook_subscriptions::url.eq(excluded(webhook_subscriptions::url)),
+                webhook_subscriptions::secret.eq(excluded(webhook_subscriptions::secret)),
+                webhook_subscriptions::events.eq(excluded(webhook_subscriptions::events)),
+                webhook_subscriptions::updated_at.eq(excluded(webhook_subscriptions::updated_at)),
+            ))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// Delete the subscriptions of all clients but the given ones. Returns the number of deleted subscriptions.
+    pub fn delete_clients_except(client_ids: &[&str], conn: &mut Connection) -> Result<usize, Error> {
+        diesel::delete(webhook_subscriptions::table
+            .filter(webhook_subscriptions::client_id.is_not_null())
+            .filter(webhook_subscriptions::client_id.ne_all(client_ids)))
+            .execute(conn)
+    }
+
+    pub fn delete(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::delete(webhook_subscriptions::table.find(&self.id))
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    pub fn find_by_id(id: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        webhook_subscriptions::table
+            .find(id)
+            .get_result(conn)
+            .optional()
+    }
+
+    pub fn find_by_client_id(client_id: &str, conn: &mut Connection) -> Result<Option<Self>, Error> {
+        webhook_subscriptions::table
+            .filter(webhook_subscriptions::client_id.eq(client_id))
+            .get_result(conn)
+            .optional()
+    }
+
+    pub fn find_all_by_ids(ids: &[&str], conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        webhook_subscriptions::table
+            .filter(webhook_subscriptions::id.eq_any(ids))
+            .get_results(conn)
+    }
+
+    /// Subscriptions of the source, oldest first
+    pub fn find_all_by_source_id(source_id: &str, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        webhook_subscriptions::table
+            .filter(webhook_subscriptions::source_id.eq(source_id))
+            .order(webhook_subscriptions::created_at.asc())
+            .get_results(conn)
+    }
+
+    /// Subscriptions that can receive events of the source, its own and those of all clients
+    pub fn find_all_for_source(source_id: &str, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        webhook_subscriptions::table
+            .filter(webhook_subscriptions::source_id.eq(source_id).or(webhook_subscriptions::client_id.is_not_null()))
+            .get_results(conn)
+    }
+
+    pub fn find_all_of_clients(conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        webhook_subscriptions::table
+            .filter(webhook_subscriptions::client_id.is_not_null())
+            .get_results(conn)
+    }
+
+    pub fn event_types(&self) -> Vec<WebhookEventType> {
+        self.events.split_whitespace().filter_map(|event| event.parse().ok()).collect()
+    }
+
+    pub fn subscribes_to(&self, event_type: WebhookEventType) -> bool {
+        self.events.split_whitespace().any(|event| event == event_type.as_str())
+    }
+}
+
+impl WebhookDelivery {
+    pub fn insert_all(deliveries: &[Self], conn: &mut Connection) -> Result<usize, Error> {
+        diesel::insert_into(webhook_deliveries::table)
+            .values(deliveries)
+            .execute(conn)
+    }
+
+    /// Save the outcome of an attempt
+    pub fn update(&self, conn: &mut Connection) -> Result<(), Error> {
+        diesel::update(webhook_deliveries::table.find(&self.id))
+            .set(self.clone())
+            .execute(conn)
+            .map(|_| ())
+    }
+
+    /// Pending deliveries whose next attempt is due, the earliest first. Deliveries that are locked by another
+    /// transaction are skipped, the lock is kept until the end of the transaction.
+    pub fn find_due_for_update(now: NaiveDateTime, limit: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        webhook_deliveries::table
+            .filter(webhook_deliveries::status.eq(String::from(WebhookDeliveryStatus::Pending)))
+            .filter(webhook_deliveries::next_attempt_at.le(now))
+            .order(webhook_deliveries::next_attempt_at.asc())
+            .limit(limit)
+            .for_update()
+            .skip_locked()
+            .get_results(conn)
+    }
+
+    /// Move the next attempt of the deliveries to the time
+    pub fn postpone(ids: &[&str], until: NaiveDateTime, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::update(webhook_deliveries::table.filter(webhook_deliveries::id.eq_any(ids)))
+            .set(webhook_deliveries::next_attempt_at.eq(until))
+            .execute(conn)
+    }
+
+    /// Deliveries of the subscription, newest first
+    pub fn find_all_by_subscription_id(subscription_id: &str, status: Option<WebhookDeliveryStatus>, limit: i64, conn: &mut Connection) -> Result<Vec<Self>, Error> {
+        let mut query = webhook_deliveries::table
+            .filter(webhook_deliveries::subscription_id.eq(subscription_id))
+            .into_boxed();
+        if let Some(status) = status {
+            query = query.filter(webhook_deliveries::status.eq(String::from(status)));
+        }
+        query
+            .order((webhook_deliveries::created_at.desc(), webhook_deliveries::id.desc()))
+            .limit(limit)
+            .get_results(conn)
+    }
+
+    /// Returns the number of deleted deliveries
+    pub fn delete_created_before(before: NaiveDateTime, conn: &mut Connection) -> Result<usize, Error> {
+        diesel::delete(webhook_deliveries::table.filter(webhook_deliveries::created_at.lt(before)))
+            .execute(conn)
+    }
+
+    pub fn status(&self) -> WebhookDeliveryStatus {
+        self.status.parse().unwrap_or(WebhookDeliveryStatus::Dead)
+    }
+}
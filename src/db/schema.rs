@@ -1,5 +1,74 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    app_review_replies (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        review_id -> Varchar,
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        review_sequence_number -> Int4,
+        #[max_length = 255]
+        maintainer_id -> Varchar,
+        #[max_length = 255]
+        status -> Varchar,
+        body -> Nullable<Text>,
+        #[max_length = 255]
+        signature -> Varchar,
+        #[max_length = 255]
+        key_id -> Varchar,
+        format_version -> Int4,
+        change_number -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    app_review_revisions (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        review_id -> Varchar,
+        revision_number -> Int4,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        sequence_number -> Int4,
+        #[max_length = 255]
+        status -> Varchar,
+        #[max_length = 255]
+        app_version -> Nullable<Varchar>,
+        review_rating -> Nullable<Int4>,
+        #[max_length = 255]
+        content_salt -> Nullable<Varchar>,
+        #[max_length = 255]
+        content_commitment -> Nullable<Varchar>,
+        #[max_length = 255]
+        signature -> Varchar,
+        #[max_length = 255]
+        key_id -> Nullable<Varchar>,
+        format_version -> Int4,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    app_review_sequences (source_id, app_bundle_id) {
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        last_sequence_number -> Int4,
+    }
+}
+
 diesel::table! {
     app_review_signatures (id) {
         #[max_length = 255]
@@ -20,6 +89,31 @@ diesel::table! {
         signature -> Nullable<Varchar>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        #[max_length = 255]
+        key_id -> Nullable<Varchar>,
+        format_version -> Int4,
+        #[max_length = 255]
+        content_salt -> Nullable<Varchar>,
+        #[max_length = 255]
+        content_commitment -> Nullable<Varchar>,
+        #[max_length = 255]
+        pinned_version -> Nullable<Varchar>,
+        risk_score -> Nullable<Int4>,
+        client_changed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    idempotency_keys (owner, idempotency_key) {
+        #[max_length = 255]
+        owner -> Varchar,
+        #[max_length = 255]
+        idempotency_key -> Varchar,
+        #[max_length = 255]
+        fingerprint -> Varchar,
+        status_code -> Nullable<Int4>,
+        response -> Nullable<Bytea>,
+        created_at -> Timestamp,
     }
 }
 
@@ -31,6 +125,213 @@ diesel::table! {
         client_id -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        last_used_at -> Nullable<Timestamp>,
+        #[max_length = 255]
+        scope -> Varchar,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    review_batch_operations (user_id, idempotency_key) {
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        idempotency_key -> Varchar,
+        #[max_length = 255]
+        request_hash -> Varchar,
+        response -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    review_log_entries (source_id, app_bundle_id, leaf_index) {
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        leaf_index -> Int8,
+        leaf_hash -> Bytea,
+        sequence_number -> Int4,
+        #[max_length = 255]
+        status -> Varchar,
+        #[max_length = 255]
+        signature -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 255]
+        key_id -> Nullable<Varchar>,
+        format_version -> Int4,
+    }
+}
+
+diesel::table! {
+    review_log_subtrees (source_id, app_bundle_id, level, subtree_index) {
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        level -> Int4,
+        subtree_index -> Int8,
+        hash -> Bytea,
+    }
+}
+
+diesel::table! {
+    review_log_tree_heads (source_id, app_bundle_id, tree_size) {
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        tree_size -> Int8,
+        root_hash -> Bytea,
+        #[max_length = 255]
+        signature -> Varchar,
+        created_at -> Timestamp,
+        #[max_length = 255]
+        key_id -> Nullable<Varchar>,
+        format_version -> Int4,
+    }
+}
+
+diesel::table! {
+    review_moderation_actions (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        review_id -> Varchar,
+        #[max_length = 255]
+        moderator_id -> Varchar,
+        #[max_length = 255]
+        action -> Varchar,
+        reason -> Text,
+        #[max_length = 255]
+        previous_status -> Varchar,
+        #[max_length = 255]
+        status -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    review_reports (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        review_id -> Varchar,
+        #[max_length = 255]
+        reporter_id -> Varchar,
+        #[max_length = 255]
+        reason -> Varchar,
+        comment -> Nullable<Text>,
+        created_at -> Timestamp,
+        #[max_length = 255]
+        resolved_by -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    review_risk_assessments (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        review_id -> Nullable<Varchar>,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        app_bundle_id -> Varchar,
+        #[max_length = 255]
+        network_hash -> Nullable<Varchar>,
+        review_rating -> Int4,
+        risk_score -> Int4,
+        signals -> Text,
+        #[max_length = 255]
+        decision -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    review_signing_keys (key_id) {
+        #[max_length = 255]
+        key_id -> Varchar,
+        public_key -> Text,
+        valid_from -> Timestamp,
+        valid_until -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    source_maintainers (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        source_id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 2048]
+        source_url -> Varchar,
+        #[max_length = 255]
+        challenge -> Varchar,
+        #[max_length = 255]
+        method -> Nullable<Varchar>,
+        verified_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    sources (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        accepts_reviews -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        #[max_length = 255]
+        origin -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    user_devices (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        name -> Varchar,
+        created_at -> Timestamp,
+        last_synced_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_settings (user_id, namespace, key) {
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 64]
+        namespace -> Varchar,
+        #[max_length = 128]
+        key -> Varchar,
+        value -> Text,
+        version -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        clock -> Text,
+        change_seq -> Int8,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    user_settings_sync (user_id) {
+        #[max_length = 255]
+        user_id -> Varchar,
+        change_seq -> Int8,
+        purged_change_seq -> Int8,
     }
 }
 
@@ -46,14 +347,95 @@ diesel::table! {
         password_hash -> Varchar,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        is_moderator -> Bool,
+    }
+}
+
+diesel::table! {
+    webhook_deliveries (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        subscription_id -> Varchar,
+        #[max_length = 255]
+        event_id -> Varchar,
+        #[max_length = 255]
+        event_type -> Varchar,
+        payload -> Text,
+        #[max_length = 255]
+        status -> Varchar,
+        attempts -> Int4,
+        next_attempt_at -> Timestamp,
+        last_attempt_at -> Nullable<Timestamp>,
+        last_response_status -> Nullable<Int4>,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        delivered_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    webhook_subscriptions (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        source_id -> Nullable<Varchar>,
+        #[max_length = 255]
+        client_id -> Nullable<Varchar>,
+        #[max_length = 255]
+        created_by -> Nullable<Varchar>,
+        #[max_length = 2048]
+        url -> Varchar,
+        #[max_length = 255]
+        secret -> Varchar,
+        #[max_length = 1024]
+        events -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
+diesel::joinable!(app_review_replies -> app_review_signatures (review_id));
+diesel::joinable!(app_review_replies -> users (maintainer_id));
+diesel::joinable!(app_review_revisions -> app_review_signatures (review_id));
 diesel::joinable!(app_review_signatures -> users (user_id));
 diesel::joinable!(oauth_authorizations -> users (user_id));
+diesel::joinable!(review_batch_operations -> users (user_id));
+diesel::joinable!(review_moderation_actions -> app_review_signatures (review_id));
+diesel::joinable!(review_moderation_actions -> users (moderator_id));
+diesel::joinable!(review_reports -> app_review_signatures (review_id));
+diesel::joinable!(review_reports -> review_moderation_actions (resolved_by));
+diesel::joinable!(review_reports -> users (reporter_id));
+diesel::joinable!(review_risk_assessments -> app_review_signatures (review_id));
+diesel::joinable!(review_risk_assessments -> users (user_id));
+diesel::joinable!(source_maintainers -> users (user_id));
+diesel::joinable!(user_devices -> users (user_id));
+diesel::joinable!(user_settings -> users (user_id));
+diesel::joinable!(user_settings_sync -> users (user_id));
+diesel::joinable!(webhook_deliveries -> webhook_subscriptions (subscription_id));
+diesel::joinable!(webhook_subscriptions -> users (created_by));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    app_review_replies,
+    app_review_revisions,
+    app_review_sequences,
     app_review_signatures,
+    idempotency_keys,
     oauth_authorizations,
+    review_batch_operations,
+    review_log_entries,
+    review_log_subtrees,
+    review_log_tree_heads,
+    review_moderation_actions,
+    review_reports,
+    review_risk_assessments,
+    review_signing_keys,
+    source_maintainers,
+    sources,
+    user_devices,
+    user_settings,
+    user_settings_sync,
     users,
+    webhook_deliveries,
+    webhook_subscriptions,
 );
@@ -21,7 +21,14 @@ pub enum ServiceError {
     #[display(fmt = "{error_message}")]
     NotFound { error_message: String },
 
+    #[display(fmt = "{error_message}")]
+    Conflict { error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    UnprocessableEntity { error_message: String },
+
     #[display(fmt = "Validation error on field: {}", field)]
+    #[allow(dead_code)]
     ValidationError { field: String },
 }
 
@@ -40,6 +47,8 @@ impl error::ResponseError for ServiceError {
             ServiceError::BadRequest { .. } => StatusCode::BAD_REQUEST,
             ServiceError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
             ServiceError::NotFound { .. } => StatusCode::NOT_FOUND,
+            ServiceError::Conflict { .. } => StatusCode::CONFLICT,
+            ServiceError::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
             ServiceError::ValidationError { .. } => StatusCode::BAD_REQUEST,
         }
     }
@@ -51,4 +60,18 @@ impl error::ResponseError for ServiceError {
                 error_message: self.to_string(),
             })
     }
+}
+
+impl From<sidestore_id_core::payload::PayloadError> for ServiceError {
+    fn from(e: sidestore_id_core::payload::PayloadError) -> Self {
+        log::debug!("Error serializing signed data: {}", e);
+        ServiceError::InternalServerError { error_message: "Failed to serialize review data".to_string() }
+    }
+}
+
+impl From<diesel::result::Error> for ServiceError {
+    fn from(e: diesel::result::Error) -> Self {
+        log::debug!("Database error: {:?}", e);
+        ServiceError::InternalServerError { error_message: "Database error".to_string() }
+    }
 }
\ No newline at end of file
@@ -1,14 +1,25 @@
+use std::sync::Arc;
+use std::time::Duration;
+
 use actix::Actor;
 use actix_cors::Cors;
 use actix_web::{App, HttpServer, web};
 use actix_web::middleware::Logger;
-use ed25519_dalek::SigningKey;
 use log::info;
 
+use crate::api::oauth2::config::OAuthConfig;
 use crate::api::oauth2::state::OAuth2State;
+use crate::auth::{create_or_load_oauth_token_key, OAuthTokenKey};
 use crate::config::Config;
+use crate::constants::{
+    DELETED_SETTINGS_PURGE_INTERVAL_SECONDS, IDEMPOTENCY_KEY_HEADER, IDEMPOTENCY_KEY_PURGE_INTERVAL_SECONDS,
+    IDEMPOTENT_REPLAYED_HEADER, WEBHOOK_DELIVERY_INTERVAL_SECONDS, WEBHOOK_DELIVERY_PURGE_INTERVAL_SECONDS,
+};
 use crate::db::Pool;
-use crate::util::review_signing::create_or_load_review_signing_key;
+use crate::db::models::user::User;
+use crate::services::review_risk_service::RiskPipeline;
+use crate::services::review_stats_service::ReviewStatsCache;
+use crate::util::review_signing::{load_review_key_ring, rotate_review_signing_key, ReviewKeyRing};
 
 mod api;
 mod auth;
@@ -23,7 +34,11 @@ mod util;
 pub struct AppState {
     db: Pool,
     env: Config,
-    review_signing_key: SigningKey,
+    oauth_config: OAuthConfig,
+    oauth_token_key: OAuthTokenKey,
+    review_keys: ReviewKeyRing,
+    review_stats: ReviewStatsCache,
+    review_risk: Arc<RiskPipeline>,
 }
 
 
@@ -38,28 +53,119 @@ async fn main() -> std::io::Result<()> {
     let pool = db::create_pool(&config.database_url);
     db::run_migration(&mut pool.get().unwrap());
 
-    let review_signing_key = match create_or_load_review_signing_key(&config) {
-        Ok(review_signing_key) => review_signing_key,
+    if std::env::args().any(|arg| arg == "--rotate-review-key") {
+        match rotate_review_signing_key(&config, &mut pool.get().unwrap()) {
+            Ok(key_id) => {
+                info!("Rotated review signing key, new reviews are signed with key {} after a restart", key_id);
+                return Ok(());
+            },
+            Err(e) => {
+                panic!("Failed to rotate review signing key: {}", e);
+            }
+        }
+    }
+
+    for (flag, is_moderator) in [("--grant-moderator", true), ("--revoke-moderator", false)] {
+        if let Some(email) = std::env::args().skip_while(|arg| arg != flag).nth(1) {
+            match User::set_moderator(&email, is_moderator, &mut pool.get().unwrap()) {
+                Ok(1) => {
+                    info!("{} {} the moderator role", email, if is_moderator { "has" } else { "no longer has" });
+                    return Ok(());
+                },
+                Ok(_) => panic!("No user with the email address {}", email),
+                Err(e) => {
+                    panic!("Failed to change the moderator role: {}", e);
+                }
+            }
+        }
+    }
+
+    let review_keys = match load_review_key_ring(&config, &mut pool.get().unwrap()) {
+        Ok(review_keys) => review_keys,
+        Err(e) => {
+            panic!("Failed to load review signing keys: {}", e);
+        }
+    };
+
+    if std::env::args().any(|arg| arg == "--resign-reviews") {
+        match services::app_review_service::resign_reviews(&pool, &config, &review_keys) {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                panic!("Failed to sign reviews again: {}", e);
+            }
+        }
+    }
+
+    actix_web::rt::spawn(services::review_log_service::sign_tree_heads_periodically(
+        pool.clone(),
+        review_keys.clone(),
+        config.review_payload_format_version(),
+        Duration::from_secs(config.review_log_signing_interval),
+    ));
+    actix_web::rt::spawn(services::idempotency_service::purge_expired_periodically(
+        pool.clone(),
+        config.idempotency_key_ttl,
+        Duration::from_secs(IDEMPOTENCY_KEY_PURGE_INTERVAL_SECONDS),
+    ));
+    actix_web::rt::spawn(services::settings_sync_service::purge_deleted_periodically(
+        pool.clone(),
+        Duration::from_secs(DELETED_SETTINGS_PURGE_INTERVAL_SECONDS),
+    ));
+
+    let oauth_config = OAuthConfig::load(&config.oauth_config_path);
+    if let Err(e) = services::webhook_service::sync_client_webhooks(&oauth_config, &pool, &config) {
+        panic!("Failed to configure client webhooks: {}", e);
+    }
+    actix_web::rt::spawn(services::webhook_service::deliver_periodically(
+        pool.clone(),
+        config.clone(),
+        Duration::from_secs(WEBHOOK_DELIVERY_INTERVAL_SECONDS),
+    ));
+    actix_web::rt::spawn(services::webhook_service::purge_deliveries_periodically(
+        pool.clone(),
+        Duration::from_secs(WEBHOOK_DELIVERY_PURGE_INTERVAL_SECONDS),
+    ));
+    let oauth_token_key = match create_or_load_oauth_token_key(&config) {
+        Ok(oauth_token_key) => oauth_token_key,
+        Err(e) => {
+            panic!("Failed to load oauth token signing key: {}", e);
+        }
+    };
+    let oauth2_state = match OAuth2State::preconfigured(config.clone(), &oauth_config, oauth_token_key.clone(), pool.clone()) {
+        Ok(oauth2_state) => oauth2_state.start(),
         Err(e) => {
-            panic!("Failed to load review signing key: {}", e);
+            panic!("Failed to configure oauth: {}", e);
         }
     };
 
-    let oauth2_state = OAuth2State::preconfigured(config.clone()).start();
+    #[cfg(feature = "consent-ui")]
+    let templates = match api::ui::Templates::load(&config) {
+        Ok(templates) => web::Data::new(templates),
+        Err(e) => {
+            panic!("Failed to load templates: {}", e);
+        }
+    };
+
+    let rate_limits = config::app::RateLimits::new(&config);
+
+    let review_stats = ReviewStatsCache::default();
+    let review_risk = Arc::new(RiskPipeline::default());
 
     let server = HttpServer::new(move || {
-        App::new()
+        let app = App::new()
             .wrap(
                 Cors::default()
                     // .allowed_origin(&config.cors_origin)
                     .allow_any_origin()
                     // .send_wildcard()
-                    .allowed_methods(vec!["GET", "POST", "PUT", "DELETE"])
+                    .allowed_methods(vec!["GET", "POST", "PUT", "PATCH", "DELETE"])
                     .allowed_headers(vec![
                         actix_web::http::header::AUTHORIZATION,
                         actix_web::http::header::CONTENT_TYPE,
                         actix_web::http::header::ACCEPT
                     ])
+                    .allowed_header(IDEMPOTENCY_KEY_HEADER)
+                    .expose_headers(vec![IDEMPOTENT_REPLAYED_HEADER])
                     .supports_credentials()
                     .max_age(3600)
             )
@@ -67,10 +173,18 @@ async fn main() -> std::io::Result<()> {
             .app_data(web::Data::new(AppState {
                 db: pool.clone(),
                 env: config.clone(),
-                review_signing_key: review_signing_key.clone(),
+                oauth_config: oauth_config.clone(),
+                oauth_token_key: oauth_token_key.clone(),
+                review_keys: review_keys.clone(),
+                review_stats: review_stats.clone(),
+                review_risk: review_risk.clone(),
             }))
-            .app_data(web::Data::new(oauth2_state.clone()))
-            .configure(config::app::config_services)
+            .app_data(web::Data::new(oauth2_state.clone()));
+
+        #[cfg(feature = "consent-ui")]
+        let app = app.app_data(templates.clone());
+
+        app.configure(|cfg| config::app::config_services(cfg, &rate_limits))
     })
     .bind(&app_url)?
     .run();
@@ -2,12 +2,13 @@ use std::future::{ready, Ready};
 
 use actix_web::{dev::Payload, Error as ActixWebError};
 use actix_web::{FromRequest, http, HttpMessage, HttpRequest, web};
-use actix_web::error::ErrorUnauthorized;
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use actix_web::error::{ErrorInternalServerError, ErrorUnauthorized};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
 
 use crate::AppState;
-use crate::auth::{JwtToken, JwtTokenScope, JwtTokenType};
-use crate::constants::{OAUTH_GET_API_PATH, REFRESH_API_PATH, UNPROTECTED_API_PATHS};
+use crate::auth::{JwtToken, JwtTokenScope, JwtTokenType, OAuthAccessToken};
+use crate::constants::{OAUTH_ACCESS_TOKEN_TYPE, OAUTH_GET_API_PATH, REFRESH_API_PATH, UNPROTECTED_API_PATHS};
+use crate::db::models::oauth_authorization::OAuthAuthorization;
 
 pub struct JwtMiddleware {
     pub user_id: uuid::Uuid,
@@ -71,31 +72,63 @@ impl FromRequest for JwtMiddleware {
             },
         };
 
-        let token = match decode::<JwtToken>(
-            &token_str,
-            &DecodingKey::from_secret(data.env.jwt_secret.as_ref()),
-            &Validation::default(),
-        ) {
-            Ok(c) => c,
-            Err(_) => return ready(Err(ErrorUnauthorized("Invalid token"))),
+        let token = match decode_token(&token_str, data) {
+            Some(token) => token,
+            None => return ready(Err(ErrorUnauthorized("Invalid token"))),
         };
 
-        if token.claims.type_ != expected_token_type {
+        if token.type_ != expected_token_type {
             return ready(Err(ErrorUnauthorized("Invalid token")));
         }
 
-        if token.claims.exp < chrono::Utc::now().timestamp() {
+        if token.exp < chrono::Utc::now().timestamp() {
             return ready(Err(ErrorUnauthorized("Token expired")));
-        }else if token.claims.iat > chrono::Utc::now().timestamp() {
+        }else if token.iat > chrono::Utc::now().timestamp() {
             return ready(Err(ErrorUnauthorized("Token used before issued")));
         }
 
-        let user_id = uuid::Uuid::parse_str(token.claims.sub.as_str()).unwrap();
+        // Tokens issued to an OAuth client are only valid as long as the user's authorization
+        // for that client exists and predates the token.
+        if let Some(client_id) = &token.client_id {
+            let mut conn = match data.db.get() {
+                Ok(conn) => conn,
+                Err(_) => return ready(Err(ErrorInternalServerError("Database connection is down"))),
+            };
+            let is_revoked = OAuthAuthorization::find(&token.sub, client_id, &mut conn)
+                .map(|authorization| token.is_issued_before(authorization.created_at))
+                .unwrap_or(true);
+
+            if is_revoked {
+                return ready(Err(ErrorUnauthorized("Token revoked")));
+            }
+        }
+
+        let user_id = uuid::Uuid::parse_str(token.sub.as_str()).unwrap();
         req.extensions_mut().insert::<uuid::Uuid>(user_id.to_owned());
 
         ready(Ok(JwtMiddleware {
             user_id,
-            scope: token.claims.scope,
+            scope: token.scope,
         }))
     }
-}
\ No newline at end of file
+}
+
+/// Access tokens of OAuth clients are signed with the OAuth token key and carry the service as their audience,
+/// all other tokens with the JWT secret
+fn decode_token(token: &str, data: &AppState) -> Option<JwtToken> {
+    let header = decode_header(token).ok()?;
+
+    if header.typ.as_deref() == Some(OAUTH_ACCESS_TOKEN_TYPE) {
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.set_audience(&[data.env.oauth_issuer()]);
+        validation.set_issuer(&[data.env.oauth_issuer()]);
+
+        decode::<OAuthAccessToken>(token, data.oauth_token_key.decoding_key(), &validation)
+            .ok()
+            .map(|token| token.claims.into())
+    } else {
+        decode::<JwtToken>(token, &DecodingKey::from_secret(data.env.jwt_secret.as_ref()), &Validation::default())
+            .ok()
+            .map(|token| token.claims)
+    }
+}
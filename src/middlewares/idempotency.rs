@@ -0,0 +1,316 @@
+use std::future::{ready, Ready};
+use std::rc::Rc;
+
+use actix_web::{web, Error as ActixWebError, HttpMessage, HttpResponse};
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::error::PayloadError;
+use actix_web::http::{header, Method, StatusCode};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use crate::AppState;
+use crate::config::Config;
+use crate::constants::{IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER, MAX_IDEMPOTENCY_KEY_LENGTH, MAX_IDEMPOTENT_REQUEST_SIZE};
+use crate::errors::ServiceError;
+use crate::services::idempotency_service::{self, Claim, KeptResponse};
+use crate::services::review_risk_service::network_hash;
+
+use super::auth::JwtMiddleware;
+
+
+/// Answers retries of mutating requests that carry an `Idempotency-Key` header with the response to the first
+/// request instead of processing them again. Keys are per authenticated user, unauthenticated requests have theirs
+/// per client credentials, or per network without credentials. A retry has to be the same request, with the same
+/// method, path, query and body. Responses are kept encrypted with the request's body and client credentials.
+pub struct Idempotency;
+
+impl<S, B> Transform<S, ServiceRequest> for Idempotency
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixWebError> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixWebError;
+    type Transform = IdempotencyMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IdempotencyMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct IdempotencyMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for IdempotencyMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = ActixWebError> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = ActixWebError;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let is_mutating = [Method::POST, Method::PUT, Method::PATCH, Method::DELETE].contains(req.method());
+            let Some(idempotency_key) = req.headers().get(IDEMPOTENCY_KEY_HEADER).filter(|_| is_mutating) else {
+                return service.call(req).await.map(ServiceResponse::map_into_boxed_body);
+            };
+            let idempotency_key = idempotency_key.to_str().ok()
+                .filter(|key| !key.is_empty() && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH)
+                .ok_or(ServiceError::BadRequest {
+                    error_message: format!("The idempotency key has to have 1 to {} visible ASCII characters.", MAX_IDEMPOTENCY_KEY_LENGTH),
+                })?
+                .to_string();
+
+            let data = req.app_data::<web::Data<AppState>>().unwrap().clone();
+            let owner = owner(&mut req, &data.env).await;
+
+            let body = read_body(&mut req).await?;
+            let fingerprint = fingerprint(&req, &body, &data.env);
+            let request_secret = request_secret(&req, &body);
+            let stream: Box<dyn futures::Stream<Item = Result<web::Bytes, PayloadError>>> = Box::new(futures::stream::once(ready(Ok(body))));
+            req.set_payload(Payload::from(Box::into_pin(stream)));
+
+            let mut key = match idempotency_service::claim(&owner, &idempotency_key, &fingerprint, &data.db, &data.env)? {
+                Claim::New(key) => key,
+                Claim::Replay(key) => {
+                    debug!("Replaying the response to the request with idempotency key {} of {}", idempotency_key, owner);
+                    let status = key.status_code
+                        .and_then(|status| u16::try_from(status).ok())
+                        .and_then(|status| StatusCode::from_u16(status).ok())
+                        .unwrap_or(StatusCode::OK);
+                    let response = idempotency_service::kept_response(&key, &request_secret, &data.env)?;
+                    return Ok(req.into_response(replay(status, response)));
+                },
+            };
+
+            let res = match service.call(req).await {
+                Ok(res) => res,
+                Err(e) => {
+                    idempotency_service::release(&key, &data.db)?;
+                    return Err(e);
+                },
+            };
+            if !is_kept(res.status()) {
+                idempotency_service::release(&key, &data.db)?;
+                return Ok(res.map_into_boxed_body());
+            }
+
+            let (req, res) = res.into_parts();
+            let (res, body) = res.into_parts();
+            let body = body::to_bytes(body).await
+                .map_err(|_| ServiceError::InternalServerError { error_message: "Failed to read the response".to_string() })?;
+            let response = KeptResponse {
+                headers: res.headers().iter()
+                    .filter(|(name, _)| *name != header::CONTENT_LENGTH && *name != header::TRANSFER_ENCODING)
+                    .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+                    .collect(),
+                body: body.to_vec(),
+            };
+            idempotency_service::save_response(&mut key, res.status().as_u16(), &response, &request_secret, &data.db, &data.env)?;
+
+            Ok(ServiceResponse::new(req, res.set_body(body).map_into_boxed_body()))
+        })
+    }
+}
+
+/// Server errors and responses that depend on the client's authentication or request rate aren't kept, their
+/// retries are processed again
+fn is_kept(status: StatusCode) -> bool {
+    !status.is_server_error() && ![StatusCode::UNAUTHORIZED, StatusCode::REQUEST_TIMEOUT, StatusCode::TOO_MANY_REQUESTS].contains(&status)
+}
+
+async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, ActixWebError> {
+    let mut payload = req.take_payload();
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        body.extend_from_slice(&chunk?);
+        if body.len() > MAX_IDEMPOTENT_REQUEST_SIZE {
+            return Err(ServiceError::BadRequest { error_message: "The request is too large.".to_string() }.into());
+        }
+    }
+    Ok(body.freeze())
+}
+
+/// The authenticated user, or a keyed hash of the client credentials the request carries, or of the client's network.
+/// Requests that fail to authenticate are rejected by their handler, their keys are per credentials.
+async fn owner(req: &mut ServiceRequest, config: &Config) -> String {
+    if let Ok(jwt) = req.extract::<JwtMiddleware>().await {
+        return jwt.user_id.to_string();
+    }
+    match req.headers().get(header::AUTHORIZATION) {
+        Some(credentials) => {
+            let hash = Sha256::new()
+                .chain_update(b"sidestore-id-idempotency-client\0")
+                .chain_update(config.jwt_secret.as_bytes())
+                .chain_update([0])
+                .chain_update(credentials.as_bytes())
+                .finalize();
+            format!("client:{}", hash.iter().map(|b| format!("{:02x}", b)).collect::<String>())
+        },
+        None => format!("network:{}", req.peer_addr().map(|addr| network_hash(addr.ip(), config)).unwrap_or_default()),
+    }
+}
+
+/// What only the client knows about the request: its body and the client credentials it carries. Bearer tokens
+/// aren't part of it, retries may come with a refreshed one.
+fn request_secret(req: &ServiceRequest, body: &[u8]) -> Vec<u8> {
+    let credentials = req.headers().get(header::AUTHORIZATION)
+        .map(|credentials| credentials.as_bytes())
+        .filter(|credentials| !credentials.to_ascii_lowercase().starts_with(b"bearer "))
+        .unwrap_or_default();
+    [credentials, &[0], body].concat()
+}
+
+/// SHA-256 of the request's method, path, query and body, keyed with the service's secret so the stored
+/// fingerprints don't give away what was sent
+fn fingerprint(req: &ServiceRequest, body: &[u8], config: &Config) -> String {
+    let hash = Sha256::new()
+        .chain_update(b"sidestore-id-idempotency-fingerprint\0")
+        .chain_update(config.jwt_secret.as_bytes())
+        .chain_update([0])
+        .chain_update(req.method().as_str())
+        .chain_update(b"\n")
+        .chain_update(req.uri().path_and_query().map(|path| path.as_str()).unwrap_or_default())
+        .chain_update(b"\n")
+        .chain_update(body)
+        .finalize();
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn replay(status: StatusCode, response: KeptResponse) -> HttpResponse {
+    let mut res = HttpResponse::build(status);
+    for header in response.headers {
+        res.append_header(header);
+    }
+    res.insert_header((IDEMPOTENT_REPLAYED_HEADER, "true"));
+    res.body(response.body)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::{test, App};
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use crate::api::oauth2::config::OAuthConfig;
+    use crate::auth::{create_jwt_token, JwtTokenScope, JwtTokenType, OAuthTokenKey};
+    use crate::config::Config;
+    use crate::db::models::idempotency_key::IdempotencyKey;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::review_risk_service::RiskPipeline;
+    use crate::services::review_stats_service::ReviewStatsCache;
+    use crate::util::review_signing::ReviewKeyRing;
+    use super::*;
+
+    #[actix_web::test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    async fn test_idempotency() {
+        let pool = test_pool();
+        let config = Config::for_tests();
+        let user_id = create_user(&pool);
+        let token = create_jwt_token(&user_id.to_string(), JwtTokenType::Access, &JwtTokenScope::Full, None, &config).unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let handler_calls = calls.clone();
+        let app = test::init_service(App::new()
+            .app_data(web::Data::new(AppState {
+                db: pool.clone(),
+                env: config.clone(),
+                oauth_config: OAuthConfig::default(),
+                oauth_token_key: OAuthTokenKey::new(&SigningKey::generate(&mut OsRng)).unwrap(),
+                review_keys: ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng)),
+                review_stats: ReviewStatsCache::default(),
+                review_risk: Arc::new(RiskPipeline::default()),
+            }))
+            .wrap(Idempotency)
+            .route("/items", web::post().to(move |body: String| {
+                let call = handler_calls.fetch_add(1, Ordering::SeqCst) + 1;
+                async move {
+                    match body.as_str() {
+                        "fail" => HttpResponse::InternalServerError().finish(),
+                        _ => HttpResponse::Created().insert_header(("X-Call", call.to_string())).body(format!("{} {}", body, call)),
+                    }
+                }
+            }))
+        ).await;
+        let key = uuid::Uuid::new_v4().to_string();
+        let post = |body: &str, key: Option<&str>, token: Option<&str>| {
+            let mut req = test::TestRequest::post().uri("/items").set_payload(body.to_string());
+            if let Some(key) = key {
+                req = req.insert_header((IDEMPOTENCY_KEY_HEADER, key.to_string()));
+            }
+            if let Some(token) = token {
+                req = req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+            }
+            req.to_request()
+        };
+
+        let first = test::call_service(&app, post("item", Some(&key), Some(&token))).await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+        assert_eq!(test::read_body(first).await, "item 1");
+
+        // The retry gets the first response, with its headers
+        let retry = test::call_service(&app, post("item", Some(&key), Some(&token))).await;
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers().get(IDEMPOTENT_REPLAYED_HEADER).unwrap(), "true");
+        assert_eq!(retry.headers().get("X-Call").unwrap(), "1");
+        assert_eq!(test::read_body(retry).await, "item 1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Requests the middleware refuses fail before they reach the handler
+        let refused = |req| async { test::try_call_service(&app, req).await.err().unwrap().as_response_error().status_code() };
+        assert_eq!(refused(post("other item", Some(&key), Some(&token))).await, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(refused(post("item", Some(""), Some(&token))).await, StatusCode::BAD_REQUEST);
+
+        // Responses aren't kept in plaintext
+        let stored = IdempotencyKey::find(&user_id.to_string(), &key, &mut pool.get().unwrap()).unwrap().unwrap();
+        assert!(!stored.response.unwrap().windows(4).any(|window| window == b"item"));
+
+        // Keys are per user, and requests without a key are always processed
+        assert_eq!(test::read_body(test::call_service(&app, post("item", Some(&key), None)).await).await, "item 2");
+        assert_eq!(test::read_body(test::call_service(&app, post("item", None, Some(&token))).await).await, "item 3");
+
+        // Unauthenticated keys are per client credentials, or per network without credentials
+        let anonymous = |credentials: Option<&str>, address: &str| {
+            let mut req = test::TestRequest::post().uri("/items").set_payload("anonymous item")
+                .insert_header((IDEMPOTENCY_KEY_HEADER, key.clone()))
+                .peer_addr(address.parse().unwrap());
+            if let Some(credentials) = credentials {
+                req = req.insert_header((header::AUTHORIZATION, format!("Basic {}", credentials)));
+            }
+            req.to_request()
+        };
+        let body = |req| async { test::read_body(test::call_service(&app, req).await).await };
+        assert_eq!(body(anonymous(None, "192.0.2.1:1000")).await, "anonymous item 4");
+        assert_eq!(body(anonymous(None, "192.0.2.1:2000")).await, "anonymous item 4");
+        assert_eq!(body(anonymous(None, "198.51.100.1:1000")).await, "anonymous item 5");
+        assert_eq!(body(anonymous(Some("Y2xpZW50OnNlY3JldA=="), "192.0.2.1:1000")).await, "anonymous item 6");
+        assert_eq!(body(anonymous(Some("Y2xpZW50OnNlY3JldA=="), "198.51.100.1:1000")).await, "anonymous item 6");
+        assert_eq!(body(anonymous(Some("b3RoZXI6c2VjcmV0"), "192.0.2.1:1000")).await, "anonymous item 7");
+
+        // Server errors aren't kept
+        let failing_key = uuid::Uuid::new_v4().to_string();
+        for _ in 0..2 {
+            let failed = test::call_service(&app, post("fail", Some(&failing_key), Some(&token))).await;
+            assert_eq!(failed.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 9);
+    }
+}
@@ -0,0 +1,765 @@
+use std::net::IpAddr;
+
+use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine, engine::general_purpose::URL_SAFE_NO_PAD as base64_url_engine};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{Connection as _, OptionalExtension};
+use log::{debug, info, warn};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sidestore_id_core::payload::{encode, FORMAT_VERSIONS, LEGACY_FORMAT_VERSION};
+use sidestore_id_core::review::content_commitment;
+
+use crate::api::models::app_reviews::{
+    AppReviewDeletionRequest, AppReviewPayloadSignature, AppReviewRevision, AppReviewSignatureData, AppReviewSignatureRequest,
+    AppReviewSignatureResponse, AppReviewStatus, ReviewVerificationRequest, ReviewVerificationResponse,
+    ReviewVerificationStatus, UserAppReview, UserAppReviewPage, UserAppReviewSort, UserAppReviewsQuery, review_data,
+};
+use crate::api::models::review_replies::ReviewReply;
+use crate::api::models::moderation::RiskDecision;
+use crate::config::Config;
+use crate::constants::{DEFAULT_USER_REVIEWS_PAGE_SIZE, MAX_USER_REVIEWS_PAGE_SIZE};
+use crate::db::{Connection, Pool};
+use crate::db::models::DbModel;
+use crate::db::models::app_review::{AppReviewSignature, ReviewPosition};
+use crate::db::models::app_review_reply::AppReviewReply;
+use crate::db::models::app_review_revision::AppReviewRevision as AppReviewRevisionRow;
+use crate::db::models::app_review_sequence::AppReviewSequence;
+use crate::db::models::source::Source;
+use crate::db::models::user::User;
+use crate::errors::ServiceError;
+use crate::services::{review_log_service, webhook_service};
+use crate::services::review_risk_service::{network_hash, RiskPipeline, Submission};
+use crate::util::review_signing::ReviewKeyRing;
+
+
+/// Save the user's review for an app, or for an app version if it is kept per version, and sign it.
+/// A new review gets the next sequence number of the app, an existing review keeps its number.
+/// The submission is checked for fraud first, risky reviews are held for moderation or rejected.
+pub fn sign(request: &AppReviewSignatureRequest, user_id: &uuid::Uuid, client_ip: Option<IpAddr>, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing, risk_pipeline: &RiskPipeline) -> Result<AppReviewSignatureResponse, ServiceError> {
+    sign_review(request, user_id, client_ip, None, &mut pool.get().unwrap(), config, review_keys, risk_pipeline)
+}
+
+/// Sign the user's review on the connection, in a nested transaction if one is open. `client_changed_at` is the
+/// time the user made the change on their device if it was queued offline.
+#[allow(clippy::too_many_arguments)]
+pub fn sign_review(request: &AppReviewSignatureRequest, user_id: &uuid::Uuid, client_ip: Option<IpAddr>, client_changed_at: Option<NaiveDateTime>, conn: &mut Connection, config: &Config, review_keys: &ReviewKeyRing, risk_pipeline: &RiskPipeline) -> Result<AppReviewSignatureResponse, ServiceError> {
+    let user = User::find_by_id(user_id, conn)?;
+    let network_hash = client_ip.map(|ip| network_hash(ip, config));
+    let submission = Submission {
+        user: &user,
+        source_id: &request.source_identifier,
+        app_bundle_id: &request.app_bundle_id,
+        review_rating: request.review_rating.into(),
+        network_hash: network_hash.as_deref(),
+        time: Utc::now().naive_utc(),
+    };
+
+    let signed = conn.transaction::<_, ServiceError, _>(|conn| {
+        // Holding the lock also makes sure concurrent requests of the same user can't
+        // create two reviews for the app.
+        let mut sequence = AppReviewSequence::lock(&request.source_identifier, &request.app_bundle_id, conn)?;
+        if !Source::accepts_reviews(&request.source_identifier, conn)? {
+            return Err(ServiceError::BadRequest { error_message: "The maintainers of this source don't accept reviews.".to_string() });
+        }
+
+        let assessment = risk_pipeline.assess(&submission, conn)?;
+        let decision = assessment.decision(config);
+        if decision == RiskDecision::Reject {
+            return Ok(Err(assessment));
+        }
+
+        let pinned_version = request.per_version.then_some(request.version_number.as_str());
+        let mut review = match AppReviewSignature::find_by_pinned_version(user_id, &request.source_identifier, &request.app_bundle_id, pinned_version, conn).optional()? {
+            Some(mut review) => {
+                debug!("User already has a review: {:?}. Update it.", review);
+                ensure_not_moderated(&review)?;
+                // A held review stays held until a moderator publishes it
+                review.status = match decision == RiskDecision::Hold || review.status() == AppReviewStatus::Held {
+                    true => AppReviewStatus::Held,
+                    false => AppReviewStatus::Published,
+                }.into();
+                review.review_rating = Some(request.review_rating.into());
+                review.app_version = Some(request.version_number.to_string());
+                review.risk_score = Some(assessment.score);
+                review.client_changed_at = client_changed_at;
+                review.update(conn)?
+            },
+            None => {
+                debug!("User didn't leave a review for this app yet. Create one.");
+                let mut review = AppReviewSignature::new(request, user_id);
+                if decision == RiskDecision::Hold {
+                    review.status = AppReviewStatus::Held.into();
+                }
+                review.risk_score = Some(assessment.score);
+                review.client_changed_at = client_changed_at;
+                review.sequence_number = sequence.next(conn)?;
+                debug!("Assigned sequence number: {}", review.sequence_number);
+                review.insert(conn)?
+            }
+        };
+        assessment.record(&submission, Some(&review.id), decision).insert(conn)?;
+        if review.status() == AppReviewStatus::Held {
+            info!("Holding review {} with risk score {} for moderation", review.id, assessment.score);
+        }
+
+        // A new salt for every change, so equal texts can't be recognized by their commitments
+        let mut salt = [0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+        review.content_salt = Some(base64_engine.encode(salt));
+        review.content_commitment = Some(content_commitment(&salt, &request.review_title, &request.review_body));
+
+        review.key_id = Some(review_keys.key_id().to_string());
+        review.format_version = config.review_payload_format_version();
+        let signed = sign_and_log(&mut review, config, review_keys, conn)?;
+        webhook_service::review_signed(&review, conn)?;
+        Ok(Ok(signed))
+    })?;
+
+    signed.or_else(|assessment| {
+        // The transaction ended without changing the review. On its own it committed and released the sequence
+        // lock; in a batch it was a savepoint, and the lock is held until the batch commits. Rejected submissions
+        // are recorded after it either way, they count for later checks unless the batch is rolled back.
+        assessment.record(&submission, None, RiskDecision::Reject).insert(conn)?;
+        warn!("Rejected review of {}/{} by user {} with risk score {}", request.source_identifier, request.app_bundle_id, user_id, assessment.score);
+        Err(ServiceError::BadRequest { error_message: "The review was rejected by the fraud checks.".to_string() })
+    })
+}
+
+/// Mark the user's review for an app, or the one kept for an app version, as deleted and sign the deletion
+pub fn delete(request: &AppReviewDeletionRequest, user_id: &uuid::Uuid, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing) -> Result<AppReviewSignatureResponse, ServiceError> {
+    delete_review(request, user_id, None, &mut pool.get().unwrap(), config, review_keys)
+}
+
+/// Delete the user's review on the connection, in a nested transaction if one is open
+pub fn delete_review(request: &AppReviewDeletionRequest, user_id: &uuid::Uuid, client_changed_at: Option<NaiveDateTime>, conn: &mut Connection, config: &Config, review_keys: &ReviewKeyRing) -> Result<AppReviewSignatureResponse, ServiceError> {
+    conn.transaction(|conn| {
+        AppReviewSequence::lock(&request.source_identifier, &request.app_bundle_id, conn)?;
+
+        let mut review = AppReviewSignature::find_by_pinned_version(user_id, &request.source_identifier, &request.app_bundle_id, request.version_number.as_deref(), conn)
+            .optional()?
+            .ok_or(ServiceError::NotFound { error_message: match request.version_number {
+                Some(_) => "You didn't review this app version yet.".to_string(),
+                None => "You didn't review this app yet.".to_string(),
+            } })?;
+
+        debug!("Found the user's review: {:?}. Delete it.", review);
+        ensure_not_moderated(&review)?;
+        review.status = AppReviewStatus::Deleted.into();
+        review.review_rating = None;
+        review.app_version = None;
+        review.content_salt = None;
+        review.content_commitment = None;
+        review.client_changed_at = client_changed_at;
+        let mut review = review.update(conn)?;
+
+        review.key_id = Some(review_keys.key_id().to_string());
+        review.format_version = config.review_payload_format_version();
+        let signed = sign_and_log(&mut review, config, review_keys, conn)?;
+        webhook_service::review_signed(&review, conn)?;
+        Ok(signed)
+    })
+}
+
+/// Sign all reviews again that weren't signed with the active review signing key, from the stored data
+/// alone. Returns the number of signed reviews.
+pub fn resign_reviews(pool: &Pool, config: &Config, review_keys: &ReviewKeyRing) -> Result<usize, ServiceError> {
+    let reviews = AppReviewSignature::find_all_not_signed_with(review_keys.key_id(), &mut pool.get().unwrap())?;
+    let mut resigned = 0;
+    for review in reviews {
+        if resign(&review.id, pool, config, review_keys)? {
+            resigned += 1;
+        }
+    }
+
+    info!("Signed {} reviews with key {}", resigned, review_keys.key_id());
+    Ok(resigned)
+}
+
+/// Sign a review again with the active review signing key and append it to the log. Published reviews
+/// signed before content commitments were introduced can't be signed without their title and body and
+/// are skipped. Returns whether the review was signed.
+pub fn resign(review_id: &str, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing) -> Result<bool, ServiceError> {
+    let review_id = uuid::Uuid::parse_str(review_id)
+        .map_err(|_| ServiceError::InternalServerError { error_message: format!("Invalid review id {}", review_id) })?;
+    let conn = &mut pool.get().unwrap();
+
+    conn.transaction(|conn| {
+        let review = AppReviewSignature::find_by_id(&review_id, conn)?;
+        AppReviewSequence::lock(&review.source_id, &review.app_bundle_id, conn)?;
+
+        // The review may have changed before the lock was taken
+        let mut review = AppReviewSignature::find_by_id(&review_id, conn)?;
+        if review.key_id.as_deref() == Some(review_keys.key_id()) {
+            return Ok(false);
+        }
+        if review.status() == AppReviewStatus::Published && review.content_commitment.is_none() {
+            warn!("Review {} has no content commitment and can't be signed again", review.id);
+            return Ok(false);
+        }
+
+        review.key_id = Some(review_keys.key_id().to_string());
+        review.format_version = config.review_payload_format_version();
+        sign_and_log(&mut review, config, review_keys, conn)?;
+        Ok(true)
+    })
+}
+
+/// Check the signature of a review payload and compare the review with the user's current review of the app
+pub fn verify(request: &ReviewVerificationRequest, pool: &Pool, review_keys: &ReviewKeyRing) -> Result<ReviewVerificationResponse, ServiceError> {
+    let invalid_payload = || ServiceError::BadRequest { error_message: "The payload isn't a signed review".to_string() };
+
+    let payload: serde_json::Value = serde_json::from_str(&request.payload).map_err(|_| invalid_payload())?;
+    let format_version = match payload.get("format_version") {
+        Some(format_version) => format_version.as_i64()
+            .and_then(|format_version| i32::try_from(format_version).ok())
+            .filter(|format_version| FORMAT_VERSIONS.contains(format_version))
+            .ok_or_else(invalid_payload)?,
+        None => LEGACY_FORMAT_VERSION,
+    };
+    let review_data: AppReviewSignatureData = serde_json::from_value(payload).map_err(|_| invalid_payload())?;
+
+    if !review_keys.verify(&request.payload, &request.signature, review_data.key_id.as_deref()) {
+        return Ok(ReviewVerificationResponse { valid: false, key_id: None, format_version: None, status: None });
+    }
+
+    let user_id = uuid::Uuid::parse_str(&review_data.sidestore_user_id).map_err(|_| invalid_payload())?;
+    let conn = &mut pool.get().unwrap();
+    let review = AppReviewSignature::find_by_sequence_number(&review_data.source_identifier, &review_data.app_bundle_identifier, review_data.sequence_number, conn)
+        .optional()?
+        .filter(|review| review.user_id == user_id.to_string());
+
+    // The payload is the current state of the review if the stored signature signs the same data
+    let status = review.map(|review| {
+        let is_current = review.signature.as_ref().is_some_and(|signature| {
+            encode(&review_data, review.format_version)
+                .is_ok_and(|payload| review_keys.verify(&payload, signature, review.key_id.as_deref()))
+        });
+        if is_current {
+            ReviewVerificationStatus::Current
+        } else {
+            match review.status() {
+                AppReviewStatus::Published => ReviewVerificationStatus::Superseded,
+                AppReviewStatus::Deleted => ReviewVerificationStatus::Deleted,
+                AppReviewStatus::Hidden => ReviewVerificationStatus::Hidden,
+                AppReviewStatus::Removed => ReviewVerificationStatus::Removed,
+                AppReviewStatus::Held => ReviewVerificationStatus::Held,
+            }
+        }
+    });
+
+    Ok(ReviewVerificationResponse {
+        valid: true,
+        // Signatures without a key id were made with the first key
+        key_id: review_data.key_id.or_else(|| review_keys.keys().first().map(|key| key.key_id.clone())),
+        format_version: Some(format_version),
+        status,
+    })
+}
+
+/// A page of the user's reviews that match the query, with the published replies to them
+pub fn reviews(user_id: &uuid::Uuid, query: &UserAppReviewsQuery, pool: &Pool) -> Result<UserAppReviewPage, ServiceError> {
+    let limit = match query.limit {
+        None => DEFAULT_USER_REVIEWS_PAGE_SIZE,
+        Some(limit) if (1..=MAX_USER_REVIEWS_PAGE_SIZE).contains(&limit) => limit,
+        Some(_) => return Err(ServiceError::BadRequest {
+            error_message: format!("The limit has to be between 1 and {}.", MAX_USER_REVIEWS_PAGE_SIZE),
+        }),
+    };
+    let after = query.cursor.as_deref().map(|cursor| parse_cursor(cursor, query.sort)).transpose()?;
+
+    let conn = &mut pool.get().unwrap();
+    let total_count = AppReviewSignature::count_by_user_id(user_id, query, conn)?;
+    // One more review than requested tells whether there is a next page
+    let mut reviews = AppReviewSignature::find_page_by_user_id(user_id, query, after, limit + 1, conn)?;
+    let next_cursor = match reviews.len() as i64 > limit {
+        true => {
+            reviews.truncate(limit as usize);
+            reviews.last().map(|review| page_cursor(review, query.sort))
+        },
+        false => None,
+    };
+
+    let review_ids: Vec<String> = reviews.iter().map(|review| review.id.clone()).collect();
+    let replies = AppReviewReply::find_published_by_review_ids(&review_ids, conn)?;
+    Ok(UserAppReviewPage {
+        reviews: reviews.iter()
+            .map(|review| UserAppReview::new(review, replies.get(&review.id).map(ReviewReply::from)))
+            .collect(),
+        total_count,
+        next_cursor,
+    })
+}
+
+/// One of the user's reviews with the published reply to it
+pub fn review(user_id: &uuid::Uuid, review_id: &str, pool: &Pool) -> Result<UserAppReview, ServiceError> {
+    let not_found = || ServiceError::NotFound { error_message: "Review not found.".to_string() };
+    let review_id = uuid::Uuid::parse_str(review_id).map_err(|_| not_found())?;
+
+    let conn = &mut pool.get().unwrap();
+    let review = AppReviewSignature::find_by_id(&review_id, conn)
+        .optional()?
+        .filter(|review| review.user_id == user_id.to_string())
+        .ok_or_else(not_found)?;
+    let mut replies = AppReviewReply::find_published_by_review_ids(std::slice::from_ref(&review.id), conn)?;
+    let reply = replies.remove(&review.id);
+    Ok(UserAppReview::new(&review, reply.as_ref().map(ReviewReply::from)))
+}
+
+/// Revisions of the user's reviews of an app, oldest first
+pub fn revisions(user_id: &uuid::Uuid, source_id: &str, app_bundle_id: &str, pool: &Pool) -> Result<Vec<AppReviewRevision>, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let reviews = AppReviewSignature::find_all_by_user_id(user_id, conn)?;
+    let revisions = AppReviewRevisionRow::find_all_by_user_id(user_id, source_id, app_bundle_id, conn)?;
+
+    Ok(revisions.into_iter()
+        .map(|revision| {
+            let pinned_version = reviews.iter()
+                .find(|review| review.id == revision.review_id)
+                .and_then(|review| review.pinned_version.clone());
+            AppReviewRevision::new(revision, pinned_version)
+        })
+        .collect())
+}
+
+/// Position after the last review of a page in the order it belongs to, opaque to clients
+#[derive(Serialize, Deserialize)]
+struct PageCursor {
+    sort: UserAppReviewSort,
+    rating: i32,
+    /// `updated_at` in microseconds, the precision of stored times
+    updated_at: i64,
+    id: String,
+}
+
+fn page_cursor(review: &AppReviewSignature, sort: UserAppReviewSort) -> String {
+    let (rating, updated_at, id) = review.position();
+    let cursor = PageCursor { sort, rating, updated_at: updated_at.timestamp_micros(), id };
+    base64_url_engine.encode(serde_json::to_vec(&cursor).unwrap_or_default())
+}
+
+fn parse_cursor(cursor: &str, sort: UserAppReviewSort) -> Result<ReviewPosition, ServiceError> {
+    let invalid_cursor = || ServiceError::BadRequest { error_message: "Invalid cursor.".to_string() };
+
+    let cursor: PageCursor = base64_url_engine.decode(cursor).ok()
+        .and_then(|cursor| serde_json::from_slice(&cursor).ok())
+        .ok_or_else(invalid_cursor)?;
+    if cursor.sort != sort {
+        return Err(ServiceError::BadRequest { error_message: "The cursor belongs to another order.".to_string() });
+    }
+    let updated_at = NaiveDateTime::from_timestamp_micros(cursor.updated_at).ok_or_else(invalid_cursor)?;
+    Ok((cursor.rating, updated_at, cursor.id))
+}
+
+/// Reviews that a moderator hid or removed can't be changed by their author
+fn ensure_not_moderated(review: &AppReviewSignature) -> Result<(), ServiceError> {
+    match review.status() {
+        AppReviewStatus::Hidden | AppReviewStatus::Removed => Err(ServiceError::BadRequest {
+            error_message: format!("This review was {} by a moderator and can't be changed.", review.status),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Sign the stored review data, store the signature and append it to the transparency log of the app.
+/// The stored signature is in the review's payload format, the response also contains signatures
+/// in all other formats the service is configured to sign in.
+pub fn sign_and_log(review: &mut AppReviewSignature, config: &Config, review_keys: &ReviewKeyRing, conn: &mut Connection) -> Result<AppReviewSignatureResponse, ServiceError> {
+    let review_data = &review_data(review);
+    let payload = encode(review_data, review.format_version)?;
+    let signature = review_keys.sign(&payload);
+
+    review.signature = Some(signature.clone());
+    review.save_signature(conn)?;
+    review_log_service::append(&payload, review, conn)?;
+    AppReviewRevisionRow::record(review, &signature, conn)?;
+
+    let signatures = config.review_payload_format_versions.iter()
+        .map(|&format_version| Ok(AppReviewPayloadSignature {
+            format_version,
+            signature: if format_version == review.format_version {
+                signature.clone()
+            } else {
+                review_keys.sign(&encode(review_data, format_version)?)
+            },
+        }))
+        .collect::<Result<Vec<_>, ServiceError>>()?;
+
+    Ok(AppReviewSignatureResponse {
+        sequence_number: review.sequence_number,
+        review_date: review_data.updated_at,
+        signature,
+        key_id: review_keys.key_id().to_string(),
+        format_version: review.format_version,
+        status: review.status(),
+        signatures,
+        content_salt: review.content_salt.clone(),
+        content_commitment: review.content_commitment.clone(),
+    })
+}
+
+
+#[cfg(test)]
+pub mod tests {
+    use std::thread;
+
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use crate::db::test_utils::{create_user, test_pool};
+    use super::*;
+
+    pub fn review_request(source_identifier: &str) -> AppReviewSignatureRequest {
+        AppReviewSignatureRequest {
+            source_identifier: source_identifier.to_string(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: "1.0".to_string(),
+            review_rating: 4,
+            review_title: "Title".to_string(),
+            review_body: "Body".to_string(),
+            per_version: false,
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_concurrent_reviews_get_consecutive_sequence_numbers() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let users: Vec<uuid::Uuid> = (0..12).map(|_| create_user(&pool)).collect();
+
+        let handles: Vec<_> = users.into_iter().map(|user_id| {
+            let (pool, config, review_keys, source_identifier) = (pool.clone(), config.clone(), review_keys.clone(), source_identifier.clone());
+            thread::spawn(move || sign(&review_request(&source_identifier), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap())
+        }).collect();
+
+        let mut sequence_numbers: Vec<i32> = handles.into_iter()
+            .map(|handle| handle.join().unwrap().sequence_number)
+            .collect();
+        sequence_numbers.sort();
+        assert_eq!(sequence_numbers, (1..=12).collect::<Vec<i32>>());
+
+        // Reviews of other apps have their own sequence
+        let user_id = create_user(&pool);
+        let mut request = review_request(&source_identifier);
+        request.app_bundle_id = "com.example.Other".to_string();
+        assert_eq!(sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap().sequence_number, 1);
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_concurrent_reviews_of_the_same_user() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let user_id = create_user(&pool);
+
+        let handles: Vec<_> = (0..8).map(|_| {
+            let (pool, config, review_keys, source_identifier) = (pool.clone(), config.clone(), review_keys.clone(), source_identifier.clone());
+            thread::spawn(move || sign(&review_request(&source_identifier), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap())
+        }).collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap().sequence_number, 1);
+        }
+
+        let reviews = AppReviewSignature::find_all_by_user_id(&user_id, &mut pool.get().unwrap()).unwrap();
+        assert_eq!(reviews.len(), 1);
+
+        // The next user continues the sequence without a gap
+        let other_user_id = create_user(&pool);
+        assert_eq!(sign(&review_request(&source_identifier), &other_user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap().sequence_number, 2);
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_verify() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let user_id = create_user(&pool);
+        let request = review_request(&uuid::Uuid::new_v4().to_string());
+
+        // Payloads of the review in every format the service signs in
+        let signed_payloads = |request: &AppReviewSignatureRequest, response: &AppReviewSignatureResponse| -> Vec<ReviewVerificationRequest> {
+            let review = AppReviewSignature::find_by_user_id(&user_id, &request.source_identifier, &request.app_bundle_id, &mut pool.get().unwrap()).unwrap();
+            let review_data = review_data(&review);
+            response.signatures.iter()
+                .map(|signature| ReviewVerificationRequest {
+                    payload: encode(&review_data, signature.format_version).unwrap(),
+                    signature: signature.signature.clone(),
+                })
+                .collect()
+        };
+
+        let response = sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        let first_payloads = signed_payloads(&request, &response);
+        assert_eq!(first_payloads.len(), config.review_payload_format_versions.len());
+        for (payload, format_version) in first_payloads.iter().zip(&config.review_payload_format_versions) {
+            let verification = verify(payload, &pool, &review_keys).unwrap();
+            assert!(verification.valid);
+            assert_eq!(verification.key_id.as_deref(), Some(review_keys.key_id()));
+            assert_eq!(verification.format_version, Some(*format_version));
+            assert_eq!(verification.status, Some(ReviewVerificationStatus::Current));
+        }
+
+        let tampered = ReviewVerificationRequest {
+            payload: first_payloads[0].payload.replace("\"review_rating\":4", "\"review_rating\":5"),
+            signature: first_payloads[0].signature.clone(),
+        };
+        let verification = verify(&tampered, &pool, &review_keys).unwrap();
+        assert!(!verification.valid);
+        assert!(verification.status.is_none());
+        let not_a_review = ReviewVerificationRequest { payload: "{}".to_string(), signature: first_payloads[0].signature.clone() };
+        assert!(verify(&not_a_review, &pool, &review_keys).is_err());
+
+        let changed_request = AppReviewSignatureRequest { review_body: "Changed".to_string(), ..review_request(&request.source_identifier) };
+        let response = sign(&changed_request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        let changed_payloads = signed_payloads(&changed_request, &response);
+        assert_eq!(verify(&first_payloads[1], &pool, &review_keys).unwrap().status, Some(ReviewVerificationStatus::Superseded));
+        assert_eq!(verify(&changed_payloads[1], &pool, &review_keys).unwrap().status, Some(ReviewVerificationStatus::Current));
+
+        let deletion_request = AppReviewDeletionRequest {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            version_number: None,
+        };
+        delete(&deletion_request, &user_id, &pool, &config, &review_keys).unwrap();
+        assert_eq!(verify(&changed_payloads[0], &pool, &review_keys).unwrap().status, Some(ReviewVerificationStatus::Deleted));
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_content_commitment() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let user_id = create_user(&pool);
+        let request = review_request(&uuid::Uuid::new_v4().to_string());
+        let find_review = || AppReviewSignature::find_by_user_id(&user_id, &request.source_identifier, &request.app_bundle_id, &mut pool.get().unwrap()).unwrap();
+
+        let response = sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        let salt = base64_engine.decode(response.content_salt.as_ref().unwrap()).unwrap();
+        let review = find_review();
+        assert_eq!(review.content_commitment, response.content_commitment);
+        assert_eq!(review.signature.as_ref(), Some(&response.signature));
+
+        // The signature covers the title and body through the commitment
+        let data = review_data(&review);
+        assert!(data.review_title.is_none() && data.review_body.is_none());
+        assert!(data.commits_to(&salt, &request.review_title, &request.review_body));
+        assert!(!data.commits_to(&salt, &request.review_title, "Changed"));
+        assert!(review_keys.verify(&encode(&data, review.format_version).unwrap(), &response.signature, Some(review_keys.key_id())));
+
+        // Every change gets a new salt
+        let response = sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        assert_ne!(base64_engine.decode(response.content_salt.unwrap()).unwrap(), salt);
+
+        let deletion_request = AppReviewDeletionRequest {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            version_number: None,
+        };
+        let response = delete(&deletion_request, &user_id, &pool, &config, &review_keys).unwrap();
+        assert!(response.content_salt.is_none() && response.content_commitment.is_none());
+        let review = find_review();
+        assert!(review.content_salt.is_none() && review.content_commitment.is_none());
+        assert!(review.review_rating.is_none());
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_resign() {
+        let pool = test_pool();
+        let old_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let new_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let users: Vec<uuid::Uuid> = (0..3).map(|_| create_user(&pool)).collect();
+        for user_id in &users {
+            sign(&review_request(&source_identifier), user_id, None, &pool, &config, &old_keys, &RiskPipeline::default()).unwrap();
+        }
+        delete(&AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: None,
+        }, &users[1], &pool, &config, &old_keys).unwrap();
+
+        // A review signed before content commitments were introduced
+        let conn = &mut pool.get().unwrap();
+        let mut legacy = AppReviewSignature::find_by_user_id(&users[2], &source_identifier, "com.example.App", conn).unwrap();
+        legacy.content_salt = None;
+        legacy.content_commitment = None;
+        legacy.save_signature(conn).unwrap();
+
+        for user_id in &users {
+            let review = AppReviewSignature::find_by_user_id(user_id, &source_identifier, "com.example.App", conn).unwrap();
+            let resigned = resign(&review.id, &pool, &config, &new_keys).unwrap();
+            assert_eq!(resigned, user_id != &users[2]);
+
+            let resigned_review = AppReviewSignature::find_by_user_id(user_id, &source_identifier, "com.example.App", conn).unwrap();
+            assert_eq!(resigned_review.updated_at, review.updated_at);
+            assert_eq!(resigned_review.content_commitment, review.content_commitment);
+            if resigned {
+                assert_eq!(resigned_review.key_id.as_deref(), Some(new_keys.key_id()));
+                let payload = encode(&review_data(&resigned_review), resigned_review.format_version).unwrap();
+                assert!(new_keys.verify(&payload, resigned_review.signature.as_ref().unwrap(), Some(new_keys.key_id())));
+                // Reviews signed with the active key are left alone
+                assert!(!resign(&review.id, &pool, &config, &new_keys).unwrap());
+            } else {
+                assert_eq!(resigned_review.signature, review.signature);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_revisions_and_per_version_reviews() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let user_id = create_user(&pool);
+        let request = review_request(&uuid::Uuid::new_v4().to_string());
+        let version_request = |version_number: &str, review_rating: u8| AppReviewSignatureRequest {
+            version_number: version_number.to_string(),
+            review_rating,
+            per_version: true,
+            ..review_request(&request.source_identifier)
+        };
+        let deletion_request = |version_number: Option<&str>| AppReviewDeletionRequest {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            version_number: version_number.map(str::to_string),
+        };
+
+        let first = sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        sign(&AppReviewSignatureRequest { review_rating: 2, ..review_request(&request.source_identifier) }, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+
+        // Reviews kept per version are separate reviews next to the review of the app
+        let version_1 = sign(&version_request("1.0", 5), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        let version_2 = sign(&version_request("2.0", 3), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        assert_eq!(first.sequence_number, 1);
+        assert_eq!(version_1.sequence_number, 2);
+        assert_eq!(version_2.sequence_number, 3);
+        assert_eq!(sign(&version_request("1.0", 4), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap().sequence_number, 2);
+        assert_eq!(AppReviewSignature::find_all_by_user_id(&user_id, &mut pool.get().unwrap()).unwrap().len(), 3);
+
+        assert!(delete(&deletion_request(Some("3.0")), &user_id, &pool, &config, &review_keys).is_err());
+        delete(&deletion_request(Some("1.0")), &user_id, &pool, &config, &review_keys).unwrap();
+        let conn = &mut pool.get().unwrap();
+        let review = AppReviewSignature::find_by_user_id(&user_id, &request.source_identifier, &request.app_bundle_id, conn).unwrap();
+        assert_eq!(review.status, String::from(AppReviewStatus::Published));
+        assert_eq!(review.review_rating, Some(2));
+
+        // The signed deletion of a review kept per version is current
+        let deleted = AppReviewSignature::find_by_pinned_version(&user_id, &request.source_identifier, &request.app_bundle_id, Some("1.0"), conn).unwrap();
+        let payload = ReviewVerificationRequest {
+            payload: encode(&review_data(&deleted), deleted.format_version).unwrap(),
+            signature: deleted.signature.clone().unwrap(),
+        };
+        assert_eq!(verify(&payload, &pool, &review_keys).unwrap().status, Some(ReviewVerificationStatus::Current));
+
+        let revisions = revisions(&user_id, &request.source_identifier, &request.app_bundle_id, &pool).unwrap();
+        let summary: Vec<_> = revisions.iter()
+            .map(|revision| (revision.sequence_number, revision.revision_number, revision.status.as_str(), revision.review_rating, revision.pinned_version.as_deref()))
+            .collect();
+        assert_eq!(summary, vec![
+            (1, 1, "published", Some(4), None),
+            (1, 2, "published", Some(2), None),
+            (2, 1, "published", Some(5), Some("1.0")),
+            (3, 1, "published", Some(3), Some("2.0")),
+            (2, 2, "published", Some(4), Some("1.0")),
+            (2, 3, "deleted", None, Some("1.0")),
+        ]);
+        assert_eq!(revisions[0].signature, first.signature);
+        assert_eq!(revisions[0].content_commitment, first.content_commitment);
+        assert_eq!(revisions[0].version_number.as_deref(), Some("1.0"));
+
+        // Revisions can't be changed
+        use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+        use crate::db::schema::app_review_revisions;
+        assert!(diesel::update(app_review_revisions::table.filter(app_review_revisions::review_id.eq(&revisions[0].review_id)))
+            .set(app_review_revisions::review_rating.eq(5))
+            .execute(conn)
+            .is_err());
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_review_pages() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let user_id = create_user(&pool);
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        for review_rating in [3, 5, 1, 4, 2] {
+            let request = AppReviewSignatureRequest {
+                app_bundle_id: format!("com.example.App{}", review_rating),
+                review_rating,
+                ..review_request(&source_identifier)
+            };
+            sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        }
+        delete(&AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App2".to_string(),
+            version_number: None,
+        }, &user_id, &pool, &config, &review_keys).unwrap();
+        // Another source's review
+        sign(&review_request(&uuid::Uuid::new_v4().to_string()), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+
+        let query = |sort: UserAppReviewSort, cursor: Option<String>| UserAppReviewsQuery {
+            source_identifier: Some(source_identifier.clone()),
+            app_bundle_id: None,
+            status: None,
+            from: None,
+            until: None,
+            sort,
+            cursor,
+            limit: Some(2),
+        };
+        let all_pages = |sort: UserAppReviewSort| {
+            let (mut ratings, mut cursor) = (Vec::new(), None);
+            loop {
+                let page = reviews(&user_id, &query(sort, cursor), &pool).unwrap();
+                assert_eq!(page.total_count, 5);
+                assert!(page.reviews.len() <= 2);
+                ratings.extend(page.reviews.iter().map(|review| review.review_rating));
+                match page.next_cursor {
+                    Some(next_cursor) => cursor = Some(next_cursor),
+                    None => return ratings,
+                }
+            }
+        };
+        assert_eq!(all_pages(UserAppReviewSort::Newest), vec![None, Some(4), Some(1), Some(5), Some(3)]);
+        assert_eq!(all_pages(UserAppReviewSort::Oldest), vec![Some(3), Some(5), Some(1), Some(4), None]);
+        assert_eq!(all_pages(UserAppReviewSort::HighestRated), vec![Some(5), Some(4), Some(3), Some(1), None]);
+        assert_eq!(all_pages(UserAppReviewSort::LowestRated), vec![None, Some(1), Some(3), Some(4), Some(5)]);
+
+        let first_page = reviews(&user_id, &query(UserAppReviewSort::Newest, None), &pool).unwrap();
+        assert!(matches!(reviews(&user_id, &query(UserAppReviewSort::Oldest, first_page.next_cursor), &pool), Err(ServiceError::BadRequest { .. })));
+        assert!(matches!(reviews(&user_id, &query(UserAppReviewSort::Newest, Some("invalid".to_string())), &pool), Err(ServiceError::BadRequest { .. })));
+        assert!(matches!(reviews(&user_id, &UserAppReviewsQuery { limit: Some(0), ..query(UserAppReviewSort::Newest, None) }, &pool), Err(ServiceError::BadRequest { .. })));
+
+        let deleted = reviews(&user_id, &UserAppReviewsQuery { status: Some(AppReviewStatus::Deleted), ..query(UserAppReviewSort::Newest, None) }, &pool).unwrap();
+        assert_eq!(deleted.total_count, 1);
+        assert_eq!(deleted.reviews[0].app_bundle_identifier, "com.example.App2");
+        let app = reviews(&user_id, &UserAppReviewsQuery { app_bundle_id: Some("com.example.App5".to_string()), ..query(UserAppReviewSort::Newest, None) }, &pool).unwrap();
+        assert_eq!(app.total_count, 1);
+        assert_eq!(app.next_cursor, None);
+        let now = Utc::now().timestamp();
+        assert_eq!(reviews(&user_id, &UserAppReviewsQuery { from: Some(now - 60), until: Some(now + 60), ..query(UserAppReviewSort::Newest, None) }, &pool).unwrap().total_count, 5);
+        assert_eq!(reviews(&user_id, &UserAppReviewsQuery { until: Some(now - 60), ..query(UserAppReviewSort::Newest, None) }, &pool).unwrap().total_count, 0);
+        let everything = UserAppReviewsQuery { source_identifier: None, limit: None, ..query(UserAppReviewSort::Newest, None) };
+        assert_eq!(reviews(&user_id, &everything, &pool).unwrap().total_count, 6);
+
+        // Only the author gets a review by its id
+        let id = &app.reviews[0].id;
+        assert_eq!(review(&user_id, id, &pool).unwrap().review_rating, Some(5));
+        assert!(matches!(review(&create_user(&pool), id, &pool), Err(ServiceError::NotFound { .. })));
+        assert!(matches!(review(&user_id, "invalid", &pool), Err(ServiceError::NotFound { .. })));
+    }
+}
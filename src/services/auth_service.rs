@@ -26,7 +26,7 @@ pub fn signup(user_dto: UserDTO, pool: &Pool, config: &Config) -> Result<UserAnd
         Err(e) => return Err(ServiceError::InternalServerError { error_message: e })
     };
 
-    return Ok((user, access_token, refresh_token))
+    Ok((user, access_token, refresh_token))
 }
 
 pub fn login(user_dto: UserDTO, pool: &Pool, config: &Config) -> Result<UserAndTokens, ServiceError> {
@@ -36,11 +36,10 @@ pub fn login(user_dto: UserDTO, pool: &Pool, config: &Config) -> Result<UserAndT
         Err(_) => return Err(ServiceError::Unauthorized { error_message: "User not found".to_string() })
     };
 
-    let password_matches = bcrypt::verify(&user_dto.password, &user.password_hash);
-    if password_matches.is_err() {
-        return Err(ServiceError::InternalServerError { error_message: "Error verifying password".to_string() })
-    } else if password_matches.is_ok() && !password_matches.unwrap() {
-        return Err(ServiceError::Unauthorized { error_message: "Password is incorrect".to_string() })
+    match bcrypt::verify(&user_dto.password, &user.password_hash) {
+        Ok(true) => {},
+        Ok(false) => return Err(ServiceError::Unauthorized { error_message: "Password is incorrect".to_string() }),
+        Err(_) => return Err(ServiceError::InternalServerError { error_message: "Error verifying password".to_string() })
     }
 
     let (access_token, refresh_token) = match create_auth_tokens(&user, config, JwtTokenScope::Full) {
@@ -74,4 +73,4 @@ pub fn user_details(pool: &Pool, user_id: uuid::Uuid) -> Result<User, ServiceErr
         Ok(user) => Ok(user),
         Err(_) => Err(ServiceError::Unauthorized { error_message: "User not found".to_string() })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,216 @@
+use std::time::Duration;
+
+use actix_web::web;
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{error, info};
+use sha2::Sha256;
+
+use crate::config::Config;
+use crate::constants::IDEMPOTENT_REQUEST_TIMEOUT_SECONDS;
+use crate::db::Pool;
+use crate::db::models::idempotency_key::IdempotencyKey;
+use crate::errors::ServiceError;
+
+const NONCE_LENGTH: usize = 24;
+
+/// Outcome of taking an idempotency key for a request
+pub enum Claim {
+    /// The request is the first with the key and has to be processed
+    New(IdempotencyKey),
+    /// The request is a retry, the key holds the response to the first request
+    Replay(IdempotencyKey),
+}
+
+/// Headers and body of a kept response
+#[derive(Debug, PartialEq)]
+pub struct KeptResponse {
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl KeptResponse {
+    /// Length of the JSON list of headers as 4 bytes, the list, then the body
+    fn encode(&self) -> Vec<u8> {
+        let headers = serde_json::to_vec(&self.headers).unwrap_or_default();
+        [&(headers.len() as u32).to_be_bytes(), headers.as_slice(), &self.body].concat()
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        let (length, rest) = encoded.split_first_chunk::<4>()?;
+        let (headers, body) = rest.split_at_checked(u32::from_be_bytes(*length) as usize)?;
+        Some(KeptResponse { headers: serde_json::from_slice(headers).ok()?, body: body.to_vec() })
+    }
+}
+
+/// Take the owner's idempotency key for a request with the fingerprint, or find the response to the first request
+/// with the key. Keys that expired are taken again, keys of other requests or of requests that are still
+/// processed are refused.
+pub fn claim(owner: &str, idempotency_key: &str, fingerprint: &str, pool: &Pool, config: &Config) -> Result<Claim, ServiceError> {
+    let now = Utc::now().naive_utc();
+    let key = IdempotencyKey {
+        owner: owner.to_string(),
+        idempotency_key: idempotency_key.to_string(),
+        fingerprint: fingerprint.to_string(),
+        status_code: None,
+        response: None,
+        created_at: now,
+    };
+
+    let conn = &mut pool.get().unwrap();
+    let expired_before = now - chrono::Duration::seconds(config.idempotency_key_ttl);
+    let abandoned_before = now - chrono::Duration::seconds(IDEMPOTENT_REQUEST_TIMEOUT_SECONDS);
+    if key.claim(expired_before, abandoned_before, conn)? {
+        return Ok(Claim::New(key));
+    }
+
+    let in_progress = || ServiceError::Conflict {
+        error_message: "A request with this idempotency key is still being processed.".to_string(),
+    };
+    // The first request may have given up the key in the meantime
+    let stored = IdempotencyKey::find(&key.owner, idempotency_key, conn)?.ok_or_else(in_progress)?;
+    if stored.fingerprint != fingerprint {
+        return Err(ServiceError::UnprocessableEntity {
+            error_message: "The idempotency key was used for a different request.".to_string(),
+        });
+    }
+    match stored.status_code {
+        Some(_) => Ok(Claim::Replay(stored)),
+        None => Err(in_progress()),
+    }
+}
+
+/// Keep the response to the request that took the key. It's encrypted with a key derived from the service's secret
+/// and the request's secret, so that only retries of the request can read it.
+pub fn save_response(key: &mut IdempotencyKey, status_code: u16, response: &KeptResponse, request_secret: &[u8], pool: &Pool, config: &Config) -> Result<(), ServiceError> {
+    key.status_code = Some(status_code.into());
+    key.response = Some(seal(&response.encode(), &response_key(key, request_secret, config)));
+    key.save_response(&mut pool.get().unwrap())?;
+    Ok(())
+}
+
+/// Decrypt the response kept for a retry with the request's secret
+pub fn kept_response(key: &IdempotencyKey, request_secret: &[u8], config: &Config) -> Result<KeptResponse, ServiceError> {
+    key.response.as_deref()
+        .and_then(|sealed| open(sealed, &response_key(key, request_secret, config)))
+        .and_then(|encoded| KeptResponse::decode(&encoded))
+        .ok_or(ServiceError::InternalServerError { error_message: "Failed to read the kept response".to_string() })
+}
+
+/// Give up the key of a request whose response isn't kept, so a retry is processed again
+pub fn release(key: &IdempotencyKey, pool: &Pool) -> Result<(), ServiceError> {
+    key.release(&mut pool.get().unwrap())?;
+    Ok(())
+}
+
+/// Key of the response to a request, from the service's secret, the owner and idempotency key, and the request's secret
+fn response_key(key: &IdempotencyKey, request_secret: &[u8], config: &Config) -> [u8; 32] {
+    hmac_sha256(config.jwt_secret.as_bytes(), &[
+        b"sidestore-id-idempotent-response\0", key.owner.as_bytes(), &[0], key.idempotency_key.as_bytes(), &[0], request_secret,
+    ])
+}
+
+fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes keys of any size");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Encrypt with XChaCha20-Poly1305. Returns the random nonce followed by the ciphertext and its tag.
+fn seal(plaintext: &[u8], key: &[u8; 32]) -> Vec<u8> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = XChaCha20Poly1305::new(key.into()).encrypt(&nonce, plaintext)
+        .expect("XChaCha20-Poly1305 encrypts messages of any size that fits in memory");
+    [nonce.as_slice(), &ciphertext].concat()
+}
+
+/// Check the tag of the sealed message and decrypt it
+fn open(sealed: &[u8], key: &[u8; 32]) -> Option<Vec<u8>> {
+    let (nonce, ciphertext) = sealed.split_at_checked(NONCE_LENGTH)?;
+    XChaCha20Poly1305::new(key.into()).decrypt(XNonce::from_slice(nonce), ciphertext).ok()
+}
+
+pub async fn purge_expired_periodically(pool: Pool, ttl: i64, interval: Duration) {
+    let mut interval = actix_web::rt::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        let expired_before = Utc::now().naive_utc() - chrono::Duration::seconds(ttl);
+        match web::block(move || IdempotencyKey::delete_created_before(expired_before, &mut pool.get().unwrap())).await {
+            Ok(Ok(0)) => {},
+            Ok(Ok(deleted)) => info!("Deleted {} expired idempotency keys", deleted),
+            Ok(Err(e)) => error!("Failed to delete expired idempotency keys: {:?}", e),
+            Err(e) => error!("Failed to delete expired idempotency keys: {:?}", e),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::db::test_utils::test_pool;
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_claims() {
+        let pool = test_pool();
+        let mut config = Config::for_tests();
+        let (owner, other_owner) = (uuid::Uuid::new_v4().to_string(), uuid::Uuid::new_v4().to_string());
+        let key = uuid::Uuid::new_v4().to_string();
+
+        let Ok(Claim::New(mut first)) = claim(&owner, &key, "a", &pool, &config) else { panic!("The key wasn't taken") };
+        assert!(matches!(claim(&owner, &key, "a", &pool, &config), Err(ServiceError::Conflict { .. })));
+        assert!(matches!(claim(&owner, &key, "b", &pool, &config), Err(ServiceError::UnprocessableEntity { .. })));
+        // Keys are per owner
+        assert!(matches!(claim(&other_owner, &key, "b", &pool, &config), Ok(Claim::New(_))));
+
+        let response = KeptResponse { headers: vec![("Set-Cookie".to_string(), "token".to_string())], body: b"created".to_vec() };
+        save_response(&mut first, 201, &response, b"secret", &pool, &config).unwrap();
+        let Ok(Claim::Replay(stored)) = claim(&owner, &key, "a", &pool, &config) else { panic!("The response wasn't replayed") };
+        assert_eq!(stored.status_code, Some(201));
+        assert_eq!(kept_response(&stored, b"secret", &config).unwrap(), response);
+        // Only the request's secret reads the response
+        assert!(kept_response(&stored, b"other secret", &config).is_err());
+        // Stored responses aren't released
+        release(&stored, &pool).unwrap();
+        assert!(matches!(claim(&owner, &key, "a", &pool, &config), Ok(Claim::Replay(_))));
+
+        // Released keys are taken again
+        let other_key = uuid::Uuid::new_v4().to_string();
+        let Ok(Claim::New(taken)) = claim(&owner, &other_key, "c", &pool, &config) else { panic!("The key wasn't taken") };
+        release(&taken, &pool).unwrap();
+        assert!(matches!(claim(&owner, &other_key, "d", &pool, &config), Ok(Claim::New(_))));
+
+        // Expired keys are taken again, even by other requests
+        config.idempotency_key_ttl = -1;
+        assert!(matches!(claim(&owner, &key, "e", &pool, &config), Ok(Claim::New(_))));
+    }
+
+    #[test]
+    fn test_seal() {
+        const TAG_LENGTH: usize = 16;
+        let key = [1u8; 32];
+        let plaintext = b"{\"access_token\":\"token\",\"token_type\":\"Bearer\"}";
+        let sealed = seal(plaintext, &key);
+        assert_eq!(sealed.len(), NONCE_LENGTH + plaintext.len() + TAG_LENGTH);
+        assert!(!sealed.windows(5).any(|window| window == b"token"));
+        assert_eq!(open(&sealed, &key).unwrap(), plaintext);
+        // Nonces are random
+        assert_ne!(seal(plaintext, &key), sealed);
+
+        assert!(open(&sealed, &[2u8; 32]).is_none());
+        for position in [0, NONCE_LENGTH, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[position] ^= 1;
+            assert!(open(&tampered, &key).is_none(), "{}", position);
+        }
+        assert!(open(&sealed[..NONCE_LENGTH + TAG_LENGTH - 1], &key).is_none());
+        assert!(open(&sealed[..NONCE_LENGTH - 1], &key).is_none());
+    }
+}
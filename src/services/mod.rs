@@ -1 +1,14 @@
-pub mod auth_service;
\ No newline at end of file
+pub mod auth_service;
+pub mod app_review_service;
+pub mod idempotency_service;
+pub mod moderation_service;
+pub mod review_batch_service;
+pub mod review_log_service;
+pub mod review_reply_service;
+pub mod review_risk_service;
+pub mod review_snapshot_service;
+pub mod review_stats_service;
+pub mod settings_service;
+pub mod settings_sync_service;
+pub mod source_service;
+pub mod webhook_service;
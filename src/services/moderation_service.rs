@@ -0,0 +1,299 @@
+use chrono::Utc;
+use diesel::{Connection as _, OptionalExtension};
+use log::info;
+
+use crate::api::models::app_reviews::AppReviewStatus;
+use crate::api::models::moderation::{
+    HeldReview, ModerationAction, ModerationActionRecord, ModerationActionRequest, ModerationActionResponse,
+    ModerationActionsQuery, ReviewReportRequest, ReviewReportResponse,
+};
+use crate::config::Config;
+use crate::constants::{
+    MAX_MODERATION_REASON_LENGTH, MAX_REVIEW_REPORT_COMMENT_LENGTH, MODERATION_HELD_REVIEWS_LIMIT, MODERATION_REPORTS_LIMIT,
+};
+use crate::db::{Connection, Pool};
+use crate::db::models::DbModel;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::app_review_sequence::AppReviewSequence;
+use crate::db::models::review_moderation::{ReviewModerationAction, ReviewReport};
+use crate::db::models::review_risk_assessment::ReviewRiskAssessment;
+use crate::db::models::user::User;
+use crate::errors::ServiceError;
+use crate::services::app_review_service::sign_and_log;
+use crate::services::webhook_service;
+use crate::util::review_signing::ReviewKeyRing;
+
+
+/// Fail unless the user has the moderator role
+pub fn require_moderator(user_id: &uuid::Uuid, pool: &Pool) -> Result<(), ServiceError> {
+    let user = User::find_by_id(user_id, &mut pool.get().unwrap())?;
+    if user.is_moderator {
+        Ok(())
+    } else {
+        Err(ServiceError::Unauthorized { error_message: "Only moderators can do this.".to_string() })
+    }
+}
+
+/// Report a published review to the moderators. A user can report a review once, reporting it
+/// again returns the first report.
+pub fn report(request: &ReviewReportRequest, reporter_id: &uuid::Uuid, pool: &Pool) -> Result<ReviewReportResponse, ServiceError> {
+    if request.comment.as_ref().is_some_and(|comment| comment.chars().count() > MAX_REVIEW_REPORT_COMMENT_LENGTH) {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("The comment can't be longer than {} characters.", MAX_REVIEW_REPORT_COMMENT_LENGTH),
+        });
+    }
+
+    let conn = &mut pool.get().unwrap();
+    let review = find_review(&request.source_identifier, &request.app_bundle_id, request.sequence_number, conn)?;
+    if review.user_id == reporter_id.to_string() {
+        return Err(ServiceError::BadRequest { error_message: "You can't report your own review.".to_string() });
+    }
+    if review.status() != AppReviewStatus::Published {
+        return Err(ServiceError::BadRequest { error_message: "Only published reviews can be reported.".to_string() });
+    }
+
+    let report = ReviewReport {
+        id: uuid::Uuid::new_v4().to_string(),
+        review_id: review.id.clone(),
+        reporter_id: reporter_id.to_string(),
+        reason: request.reason.into(),
+        comment: request.comment.clone(),
+        created_at: Utc::now().naive_utc(),
+        resolved_by: None,
+    };
+    let report = match report.insert(conn)? {
+        true => report,
+        false => ReviewReport::find_by_reporter_id(&review.id, &reporter_id.to_string(), conn)?,
+    };
+    Ok(ReviewReportResponse::new(report, &review))
+}
+
+/// Open reports, oldest first
+pub fn open_reports(pool: &Pool) -> Result<Vec<ReviewReportResponse>, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    ReviewReport::find_open(MODERATION_REPORTS_LIMIT, conn)?
+        .into_iter()
+        .map(|report| {
+            let review = AppReviewSignature::find_by_id(&parse_review_id(&report.review_id)?, conn)?;
+            Ok(ReviewReportResponse::new(report, &review))
+        })
+        .collect()
+}
+
+/// Reviews that the fraud checks held for moderation, the longest held first, with the signals of their latest
+/// submission
+pub fn held_reviews(pool: &Pool) -> Result<Vec<HeldReview>, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let reviews = AppReviewSignature::find_held(MODERATION_HELD_REVIEWS_LIMIT, conn)?;
+    let review_ids: Vec<String> = reviews.iter().map(|review| review.id.clone()).collect();
+    let assessments = ReviewRiskAssessment::find_latest_by_review_ids(&review_ids, conn)?;
+    Ok(reviews.iter()
+        .map(|review| HeldReview::new(review, assessments.get(&review.id)))
+        .collect())
+}
+
+/// Change the status of a review, sign it again with the new status and record the action with
+/// the moderator's reason. Resolves all open reports of the review.
+pub fn moderate(request: &ModerationActionRequest, moderator_id: &uuid::Uuid, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing) -> Result<ModerationActionResponse, ServiceError> {
+    let reason = request.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_MODERATION_REASON_LENGTH {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("A reason of at most {} characters is required.", MAX_MODERATION_REASON_LENGTH),
+        });
+    }
+
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        AppReviewSequence::lock(&request.source_identifier, &request.app_bundle_id, conn)?;
+        let mut review = find_review(&request.source_identifier, &request.app_bundle_id, request.sequence_number, conn)?;
+
+        let previous_status = review.status();
+        let status = next_status(request.action, previous_status).ok_or_else(|| ServiceError::BadRequest {
+            error_message: format!("A {} review can't be changed with {}.", previous_status.as_str(), String::from(request.action)),
+        })?;
+        // The review is signed again from the stored data, which has no title and body before content commitments
+        if status == AppReviewStatus::Published && review.content_commitment.is_none() {
+            return Err(ServiceError::BadRequest {
+                error_message: "Reviews signed before content commitments were introduced can't be restored.".to_string(),
+            });
+        }
+
+        review.status = status.into();
+        let mut review = review.update(conn)?;
+        review.key_id = Some(review_keys.key_id().to_string());
+        review.format_version = config.review_payload_format_version();
+        let signature = sign_and_log(&mut review, config, review_keys, conn)?;
+        webhook_service::review_signed(&review, conn)?;
+
+        let action = ReviewModerationAction {
+            id: uuid::Uuid::new_v4().to_string(),
+            review_id: review.id.clone(),
+            moderator_id: moderator_id.to_string(),
+            action: request.action.into(),
+            reason: reason.to_string(),
+            previous_status: previous_status.into(),
+            status: status.into(),
+            created_at: Utc::now().naive_utc(),
+        };
+        action.insert(conn)?;
+        let resolved_reports = ReviewReport::resolve(&review.id, &action.id, conn)?;
+
+        info!("Moderator {} changed review {} from {} to {}", moderator_id, review.id, action.previous_status, action.status);
+        Ok(ModerationActionResponse {
+            action: action.into(),
+            review: signature,
+            resolved_reports,
+        })
+    })
+}
+
+/// Moderation actions on a review, oldest first
+pub fn actions(query: &ModerationActionsQuery, pool: &Pool) -> Result<Vec<ModerationActionRecord>, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let review = find_review(&query.source_identifier, &query.app_bundle_id, query.sequence_number, conn)?;
+    Ok(ReviewModerationAction::find_all_by_review_id(&review.id, conn)?
+        .into_iter()
+        .map(ModerationActionRecord::from)
+        .collect())
+}
+
+/// Status of a review after a moderation action, `None` if the action can't be taken on the review
+fn next_status(action: ModerationAction, status: AppReviewStatus) -> Option<AppReviewStatus> {
+    match (action, status) {
+        (ModerationAction::Hide, AppReviewStatus::Published) => Some(AppReviewStatus::Hidden),
+        (ModerationAction::Restore, AppReviewStatus::Hidden | AppReviewStatus::Removed | AppReviewStatus::Held) => Some(AppReviewStatus::Published),
+        (ModerationAction::Remove, AppReviewStatus::Published | AppReviewStatus::Hidden | AppReviewStatus::Held) => Some(AppReviewStatus::Removed),
+        _ => None,
+    }
+}
+
+/// The review with the sequence number, or a not found error
+pub fn find_review(source_id: &str, app_bundle_id: &str, sequence_number: i32, conn: &mut Connection) -> Result<AppReviewSignature, ServiceError> {
+    AppReviewSignature::find_by_sequence_number(source_id, app_bundle_id, sequence_number, conn)
+        .optional()?
+        .ok_or(ServiceError::NotFound { error_message: "Review not found.".to_string() })
+}
+
+fn parse_review_id(review_id: &str) -> Result<uuid::Uuid, ServiceError> {
+    uuid::Uuid::parse_str(review_id)
+        .map_err(|_| ServiceError::InternalServerError { error_message: format!("Invalid review id {}", review_id) })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use sidestore_id_core::payload::encode;
+
+    use crate::api::models::app_reviews::{review_data, AppReviewDeletionRequest, ReviewVerificationRequest, ReviewVerificationStatus};
+    use crate::api::models::moderation::ReviewReportReason;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use super::*;
+
+    #[test]
+    fn test_next_status() {
+        use AppReviewStatus::*;
+        assert_eq!(next_status(ModerationAction::Hide, Published), Some(Hidden));
+        assert_eq!(next_status(ModerationAction::Hide, Removed), None);
+        assert_eq!(next_status(ModerationAction::Restore, Hidden), Some(Published));
+        assert_eq!(next_status(ModerationAction::Restore, Removed), Some(Published));
+        assert_eq!(next_status(ModerationAction::Restore, Published), None);
+        assert_eq!(next_status(ModerationAction::Remove, Hidden), Some(Removed));
+        assert_eq!(next_status(ModerationAction::Restore, Held), Some(Published));
+        assert_eq!(next_status(ModerationAction::Remove, Held), Some(Removed));
+        assert_eq!(next_status(ModerationAction::Hide, Held), None);
+        for action in [ModerationAction::Hide, ModerationAction::Restore, ModerationAction::Remove] {
+            assert_eq!(next_status(action, Deleted), None);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_moderation() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let request = review_request(&uuid::Uuid::new_v4().to_string());
+        let (author_id, reporter_id) = (create_user(&pool), create_user(&pool));
+        let mut moderator = User::new(&format!("{}@example.com", uuid::Uuid::new_v4()), "hash");
+        moderator.is_moderator = true;
+        moderator.insert(&mut pool.get().unwrap()).unwrap();
+        let moderator_id: uuid::Uuid = moderator.id.parse().unwrap();
+
+        let signed = app_review_service::sign(&request, &author_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        let published = AppReviewSignature::find_by_user_id(&author_id, &request.source_identifier, &request.app_bundle_id, &mut pool.get().unwrap()).unwrap();
+        assert!(require_moderator(&moderator_id, &pool).is_ok());
+        assert!(require_moderator(&reporter_id, &pool).is_err());
+
+        let report_request = ReviewReportRequest {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            sequence_number: signed.sequence_number,
+            reason: ReviewReportReason::Fraud,
+            comment: Some("Bought rating".to_string()),
+        };
+        assert!(report(&report_request, &author_id, &pool).is_err());
+        let first_report = report(&report_request, &reporter_id, &pool).unwrap();
+        assert_eq!(report(&report_request, &reporter_id, &pool).unwrap().id, first_report.id);
+        assert!(open_reports(&pool).unwrap().iter().any(|open| open.id == first_report.id));
+
+        let action_request = |action: ModerationAction| ModerationActionRequest {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            sequence_number: signed.sequence_number,
+            action,
+            reason: "Rating fraud".to_string(),
+        };
+        assert!(moderate(&ModerationActionRequest { reason: " ".to_string(), ..action_request(ModerationAction::Hide) }, &moderator_id, &pool, &config, &review_keys).is_err());
+        assert!(moderate(&action_request(ModerationAction::Restore), &moderator_id, &pool, &config, &review_keys).is_err());
+
+        // Hidden reviews are signed with their status and without their content
+        let hidden = moderate(&action_request(ModerationAction::Hide), &moderator_id, &pool, &config, &review_keys).unwrap();
+        assert_eq!(hidden.action.status, "hidden");
+        assert_eq!(hidden.resolved_reports, 1);
+        assert!(!open_reports(&pool).unwrap().iter().any(|open| open.id == first_report.id));
+        let conn = &mut pool.get().unwrap();
+        let review = AppReviewSignature::find_by_user_id(&author_id, &request.source_identifier, &request.app_bundle_id, conn).unwrap();
+        let data = review_data(&review);
+        assert_eq!(data.status, AppReviewStatus::Hidden);
+        assert!(data.review_rating.is_none() && data.content_commitment.is_none());
+        let payload = encode(&data, review.format_version).unwrap();
+        assert!(review_keys.verify(&payload, &hidden.review.signature, Some(review_keys.key_id())));
+
+        // Clients holding the published review learn that it was hidden
+        let published_payload = ReviewVerificationRequest {
+            payload: encode(&review_data(&published), published.format_version).unwrap(),
+            signature: signed.signature.clone(),
+        };
+        assert_eq!(app_review_service::verify(&published_payload, &pool, &review_keys).unwrap().status, Some(ReviewVerificationStatus::Hidden));
+
+        // The author can't change a hidden review
+        assert!(app_review_service::sign(&request, &author_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).is_err());
+        assert!(app_review_service::delete(&AppReviewDeletionRequest {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            version_number: None,
+        }, &author_id, &pool, &config, &review_keys).is_err());
+
+        // Restored reviews are signed from the stored data with the same content commitment
+        let restored = moderate(&action_request(ModerationAction::Restore), &moderator_id, &pool, &config, &review_keys).unwrap();
+        assert_eq!(restored.review.content_commitment, signed.content_commitment);
+        let review = AppReviewSignature::find_by_user_id(&author_id, &request.source_identifier, &request.app_bundle_id, conn).unwrap();
+        assert_eq!(review_data(&review).review_rating, Some(request.review_rating));
+
+        moderate(&action_request(ModerationAction::Remove), &moderator_id, &pool, &config, &review_keys).unwrap();
+        let history = actions(&ModerationActionsQuery {
+            source_identifier: request.source_identifier.clone(),
+            app_bundle_id: request.app_bundle_id.clone(),
+            sequence_number: signed.sequence_number,
+        }, &pool).unwrap();
+        let history: Vec<_> = history.iter().map(|action| (action.action.as_str(), action.previous_status.as_str(), action.status.as_str())).collect();
+        assert_eq!(history, vec![("hide", "published", "hidden"), ("restore", "hidden", "published"), ("remove", "published", "removed")]);
+
+        let revisions = app_review_service::revisions(&author_id, &request.source_identifier, &request.app_bundle_id, &pool).unwrap();
+        assert_eq!(revisions.iter().map(|revision| revision.status.as_str()).collect::<Vec<_>>(), vec!["published", "hidden", "published", "removed"]);
+    }
+}
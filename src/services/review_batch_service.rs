@@ -0,0 +1,204 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{Connection as _, OptionalExtension};
+use log::info;
+use sha2::{Digest, Sha256};
+
+use crate::api::models::app_reviews::AppReviewSignatureResponse;
+use crate::api::models::review_batch::{
+    AppReviewBatchAction, AppReviewBatchOperation, AppReviewBatchRequest, AppReviewBatchResponse, AppReviewBatchResult,
+    AppReviewBatchStatus,
+};
+use crate::config::Config;
+use crate::constants::{MAX_IDEMPOTENCY_KEY_LENGTH, MAX_REVIEW_BATCH_OPERATIONS, REVIEW_BATCH_KEY_RETENTION_DAYS};
+use crate::db::Pool;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::app_review_sequence::AppReviewSequence;
+use crate::db::models::review_batch_operation::ReviewBatchOperation;
+use crate::errors::ServiceError;
+use crate::services::app_review_service;
+use crate::services::review_risk_service::RiskPipeline;
+use crate::util::review_signing::ReviewKeyRing;
+
+
+/// Apply the operations of a batch in their order in one transaction and sign them. Every operation runs in a
+/// nested transaction, so an operation that fails doesn't undo the others, while internal errors roll back the
+/// whole batch. Operations made on the device before the review's latest change aren't applied.
+pub fn apply(request: &AppReviewBatchRequest, user_id: &uuid::Uuid, client_ip: Option<IpAddr>, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing, risk_pipeline: &RiskPipeline) -> Result<AppReviewBatchResponse, ServiceError> {
+    if request.operations.is_empty() || request.operations.len() > MAX_REVIEW_BATCH_OPERATIONS {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("A batch has to have 1 to {} operations.", MAX_REVIEW_BATCH_OPERATIONS),
+        });
+    }
+    if request.operations.iter().any(|operation| operation.idempotency_key.is_empty() || operation.idempotency_key.len() > MAX_IDEMPOTENCY_KEY_LENGTH) {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("Idempotency keys have to have 1 to {} characters.", MAX_IDEMPOTENCY_KEY_LENGTH),
+        });
+    }
+
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        // Concurrent batches locking their apps in the order of their operations could deadlock
+        let apps: BTreeSet<(&str, &str)> = request.operations.iter().map(|operation| app(&operation.action)).collect();
+        for (source_id, app_bundle_id) in apps {
+            AppReviewSequence::lock(source_id, app_bundle_id, conn)?;
+        }
+
+        let now = Utc::now().naive_utc();
+        ReviewBatchOperation::delete_applied_before(user_id, now - Duration::days(REVIEW_BATCH_KEY_RETENTION_DAYS), conn)?;
+
+        let mut results = Vec::with_capacity(request.operations.len());
+        for operation in &request.operations {
+            let request_hash = request_hash(operation)?;
+            let result = |status: AppReviewBatchStatus, review: Option<AppReviewSignatureResponse>, error_message: Option<String>| AppReviewBatchResult {
+                idempotency_key: operation.idempotency_key.clone(),
+                status,
+                review,
+                error_message,
+            };
+
+            if let Some(applied) = ReviewBatchOperation::find(user_id, &operation.idempotency_key, conn)? {
+                results.push(match applied.request_hash == request_hash {
+                    true => result(AppReviewBatchStatus::Replayed, Some(parse_response(&applied.response)?), None),
+                    false => result(AppReviewBatchStatus::Failed, None, Some("The idempotency key was used for another operation.".to_string())),
+                });
+                continue;
+            }
+
+            // Clocks of devices that are ahead don't get to overrule later changes
+            let Some(client_changed_at) = NaiveDateTime::from_timestamp_opt(operation.client_timestamp, 0).map(|time| time.min(now)) else {
+                results.push(result(AppReviewBatchStatus::Failed, None, Some("Invalid client timestamp.".to_string())));
+                continue;
+            };
+            let (source_id, app_bundle_id, pinned_version) = target(&operation.action);
+            let is_stale = AppReviewSignature::find_by_pinned_version(user_id, source_id, app_bundle_id, pinned_version, conn)
+                .optional()?
+                .is_some_and(|review| review.last_changed_at() > client_changed_at);
+            if is_stale {
+                results.push(result(AppReviewBatchStatus::Stale, None, None));
+                continue;
+            }
+
+            let applied = match &operation.action {
+                AppReviewBatchAction::Sign(request) => app_review_service::sign_review(request, user_id, client_ip, Some(client_changed_at), conn, config, review_keys, risk_pipeline),
+                AppReviewBatchAction::Delete(request) => app_review_service::delete_review(request, user_id, Some(client_changed_at), conn, config, review_keys),
+            };
+            results.push(match applied {
+                Ok(response) => {
+                    ReviewBatchOperation {
+                        user_id: user_id.to_string(),
+                        idempotency_key: operation.idempotency_key.clone(),
+                        request_hash,
+                        response: serde_json::to_string(&response)
+                            .map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })?,
+                        created_at: now,
+                    }.insert(conn)?;
+                    result(AppReviewBatchStatus::Applied, Some(response), None)
+                },
+                Err(e @ ServiceError::InternalServerError { .. }) => return Err(e),
+                Err(e) => result(AppReviewBatchStatus::Failed, None, Some(e.to_string())),
+            });
+        }
+
+        info!("Applied a batch of {} review operations of user {}", results.len(), user_id);
+        Ok(AppReviewBatchResponse { results })
+    })
+}
+
+/// Source and app of the operation
+pub fn app(action: &AppReviewBatchAction) -> (&str, &str) {
+    let (source_id, app_bundle_id, _) = target(action);
+    (source_id, app_bundle_id)
+}
+
+/// Source, app and pinned version of the review the operation changes
+fn target(action: &AppReviewBatchAction) -> (&str, &str, Option<&str>) {
+    match action {
+        AppReviewBatchAction::Sign(request) => (&request.source_identifier, &request.app_bundle_id, request.per_version.then_some(request.version_number.as_str())),
+        AppReviewBatchAction::Delete(request) => (&request.source_identifier, &request.app_bundle_id, request.version_number.as_deref()),
+    }
+}
+
+fn request_hash(operation: &AppReviewBatchOperation) -> Result<String, ServiceError> {
+    let operation = serde_json::to_vec(operation)
+        .map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })?;
+    Ok(Sha256::digest(operation).iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+fn parse_response(response: &str) -> Result<AppReviewSignatureResponse, ServiceError> {
+    serde_json::from_str(response).map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use crate::api::models::app_reviews::{AppReviewDeletionRequest, AppReviewSignatureRequest, AppReviewStatus};
+    use crate::db::models::app_review_revision::AppReviewRevision;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::tests::review_request;
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_batches() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let user_id = create_user(&pool);
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let now = Utc::now().timestamp();
+        let sign = |key: &str, client_timestamp: i64, review_rating: u8| AppReviewBatchOperation {
+            idempotency_key: key.to_string(),
+            client_timestamp,
+            action: AppReviewBatchAction::Sign(AppReviewSignatureRequest { review_rating, ..review_request(&source_identifier) }),
+        };
+        let delete_version = |key: &str, client_timestamp: i64, version_number: Option<&str>| AppReviewBatchOperation {
+            idempotency_key: key.to_string(),
+            client_timestamp,
+            action: AppReviewBatchAction::Delete(AppReviewDeletionRequest {
+                source_identifier: source_identifier.clone(),
+                app_bundle_id: "com.example.App".to_string(),
+                version_number: version_number.map(str::to_string),
+            }),
+        };
+        let delete = |key: &str, client_timestamp: i64| delete_version(key, client_timestamp, None);
+        let apply_batch = |operations: Vec<AppReviewBatchOperation>| {
+            apply(&AppReviewBatchRequest { operations }, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default())
+        };
+        let statuses = |response: &AppReviewBatchResponse| response.results.iter().map(|result| result.status).collect::<Vec<_>>();
+        let revision_count = || AppReviewRevision::find_all_by_user_id(&user_id, &source_identifier, "com.example.App", &mut pool.get().unwrap()).unwrap().len();
+
+        assert!(apply_batch(vec![]).is_err());
+        assert!(apply_batch(vec![sign("", now - 300, 5)]).is_err());
+
+        let first = apply_batch(vec![sign("a", now - 300, 5), sign("b", now - 200, 3), delete("c", now - 400)]).unwrap();
+        use AppReviewBatchStatus::*;
+        assert_eq!(statuses(&first), vec![Applied, Applied, Stale]);
+        assert_eq!(first.results[0].review.as_ref().unwrap().sequence_number, 1);
+        assert_eq!(revision_count(), 2);
+
+        // Replays return the first results and don't add revisions
+        let replay = apply_batch(vec![sign("a", now - 300, 5), sign("b", now - 200, 3), sign("a", now - 300, 4)]).unwrap();
+        assert_eq!(statuses(&replay), vec![Replayed, Replayed, Failed]);
+        assert_eq!(replay.results[1].review.as_ref().unwrap().signature, first.results[1].review.as_ref().unwrap().signature);
+        assert_eq!(revision_count(), 2);
+
+        // A later batch of the same device applies, an operation that failed doesn't stop the others
+        let later = apply_batch(vec![delete("d", now - 100), delete_version("e", now - 50, Some("9.0")), sign("f", now + 3600, 1)]).unwrap();
+        assert_eq!(statuses(&later), vec![Applied, Failed, Applied]);
+        assert_eq!(later.results[0].review.as_ref().unwrap().status, AppReviewStatus::Deleted);
+        assert_eq!(revision_count(), 4);
+
+        // Changes made online win over older offline changes
+        app_review_service::sign(&review_request(&source_identifier), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        assert_eq!(statuses(&apply_batch(vec![delete("g", now - 10)]).unwrap()), vec![Stale]);
+        let review = AppReviewSignature::find_by_user_id(&user_id, &source_identifier, "com.example.App", &mut pool.get().unwrap()).unwrap();
+        assert_eq!(review.status(), AppReviewStatus::Published);
+        assert_eq!(review.client_changed_at, None);
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::web;
+use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
+use chrono::Utc;
+use log::{debug, error, info};
+use sidestore_id_core::payload::encode;
+
+use crate::api::models::review_log::{
+    ReviewLogConsistencyProof, ReviewLogEntry as ReviewLogEntryResponse, ReviewLogInclusionProof,
+    ReviewLogTreeHead as ReviewLogTreeHeadResponse, ReviewLogTreeHeadData,
+};
+use crate::db::{Connection, Pool};
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::review_log::{ReviewLogEntry, ReviewLogSubtree, ReviewLogTreeHead};
+use crate::errors::ServiceError;
+use crate::util::merkle::{self, Hash, SubtreeRecorder, Subtrees};
+use crate::util::review_signing::ReviewKeyRing;
+
+const MAX_ENTRIES_PER_REQUEST: i64 = 1000;
+
+
+/// Append a signed review to the log of its app, `payload` is the review data that was signed in the
+/// review's format. Must be called in the transaction that holds the lock on the app's review sequence,
+/// so entries are appended one after another. Stores the hashes of the subtrees the entry completes.
+pub fn append(payload: &str, review: &AppReviewSignature, conn: &mut Connection) -> Result<ReviewLogEntry, diesel::result::Error> {
+    let leaf_hash = merkle::leaf_hash(payload.as_bytes());
+    let entry = ReviewLogEntry {
+        source_id: review.source_id.clone(),
+        app_bundle_id: review.app_bundle_id.clone(),
+        leaf_index: ReviewLogEntry::count(&review.source_id, &review.app_bundle_id, conn)?,
+        leaf_hash: leaf_hash.to_vec(),
+        sequence_number: review.sequence_number,
+        status: review.status.clone(),
+        signature: review.signature.clone().unwrap_or_default(),
+        created_at: Utc::now().naive_utc(),
+        key_id: review.key_id.clone(),
+        format_version: review.format_version,
+    };
+    entry.insert(conn)?;
+
+    let subtree = |level: u32, subtree_index: u64, hash: Hash| ReviewLogSubtree {
+        source_id: entry.source_id.clone(),
+        app_bundle_id: entry.app_bundle_id.clone(),
+        level: level as i32,
+        subtree_index: subtree_index as i64,
+        hash: hash.to_vec(),
+    };
+    subtree(0, entry.leaf_index as u64, leaf_hash).insert(conn)?;
+
+    // The new entry is the rightmost leaf of every subtree it completes
+    let mut hash = leaf_hash;
+    for (level, subtree_index) in merkle::completed_subtrees(entry.leaf_index as u64) {
+        let left = ReviewLogSubtree::find(&entry.source_id, &entry.app_bundle_id, level as i32 - 1, 2 * subtree_index as i64, conn)?;
+        hash = merkle::node_hash(&Hash::try_from(left.hash.as_slice()).map_err(|e| diesel::result::Error::DeserializationError(Box::new(e)))?, &hash);
+        subtree(level, subtree_index, hash).insert(conn)?;
+    }
+
+    Ok(entry)
+}
+
+/// Sign a new tree head for every app whose log grew since its latest signed tree head
+pub fn sign_tree_heads(pool: &Pool, review_keys: &ReviewKeyRing, format_version: i32) -> Result<usize, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let latest_sizes = ReviewLogTreeHead::latest_sizes(conn)?;
+
+    let mut signed = 0;
+    for ((source_id, app_bundle_id), size) in ReviewLogEntry::sizes(conn)? {
+        if latest_sizes.get(&(source_id.clone(), app_bundle_id.clone())).is_some_and(|latest| *latest >= size) {
+            continue;
+        }
+
+        let root_hash = with_subtrees(&source_id, &app_bundle_id, conn, |tree| merkle::root(tree, size as u64))?;
+
+        // Never sign a tree that doesn't extend the previously signed one
+        if let Some(previous) = ReviewLogTreeHead::find_latest(&source_id, &app_bundle_id, conn)? {
+            let proof = with_subtrees(&source_id, &app_bundle_id, conn, |tree| {
+                merkle::consistency_proof(tree, previous.tree_size as u64, size as u64)
+            })?;
+            let consistent = Hash::try_from(previous.root_hash.as_slice()).is_ok_and(|previous_root| merkle::verify_consistency(
+                previous.tree_size as u64, size as u64, &previous_root, &root_hash, &proof,
+            ));
+            if !consistent {
+                error!("The review log of {}/{} is inconsistent with its latest signed tree head", source_id, app_bundle_id);
+                continue;
+            }
+        }
+
+        let created_at = Utc::now().naive_utc();
+        let payload = encode(&ReviewLogTreeHeadData {
+            source_identifier: source_id.clone(),
+            app_bundle_identifier: app_bundle_id.clone(),
+            tree_size: size,
+            root_hash: base64_engine.encode(root_hash),
+            timestamp: created_at.timestamp(),
+            key_id: Some(review_keys.key_id().to_string()),
+        }, format_version)?;
+
+        ReviewLogTreeHead {
+            source_id,
+            app_bundle_id,
+            tree_size: size,
+            root_hash: root_hash.to_vec(),
+            signature: review_keys.sign(&payload),
+            created_at,
+            key_id: Some(review_keys.key_id().to_string()),
+            format_version,
+        }.insert(conn)?;
+        signed += 1;
+    }
+
+    Ok(signed)
+}
+
+pub async fn sign_tree_heads_periodically(pool: Pool, review_keys: ReviewKeyRing, format_version: i32, interval: Duration) {
+    let mut interval = actix_web::rt::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        let (pool, review_keys) = (pool.clone(), review_keys.clone());
+        match web::block(move || sign_tree_heads(&pool, &review_keys, format_version)).await {
+            Ok(Ok(0)) => {},
+            Ok(Ok(signed)) => info!("Signed {} review log tree heads", signed),
+            Ok(Err(e)) => error!("Failed to sign review log tree heads: {:?}", e),
+            Err(e) => error!("Failed to sign review log tree heads: {:?}", e),
+        }
+    }
+}
+
+pub fn latest_tree_head(source_id: &str, app_bundle_id: &str, pool: &Pool) -> Result<ReviewLogTreeHeadResponse, ServiceError> {
+    let tree_head = find_latest_tree_head(source_id, app_bundle_id, &mut pool.get().unwrap())?;
+    Ok(tree_head_response(tree_head))
+}
+
+pub fn tree_head_response(tree_head: ReviewLogTreeHead) -> ReviewLogTreeHeadResponse {
+    ReviewLogTreeHeadResponse {
+        source_identifier: tree_head.source_id,
+        app_bundle_identifier: tree_head.app_bundle_id,
+        tree_size: tree_head.tree_size,
+        root_hash: base64_engine.encode(tree_head.root_hash),
+        timestamp: tree_head.created_at.timestamp(),
+        signature: tree_head.signature,
+        key_id: tree_head.key_id,
+        format_version: tree_head.format_version,
+    }
+}
+
+pub fn entries(source_id: &str, app_bundle_id: &str, start: i64, end: i64, pool: &Pool) -> Result<Vec<ReviewLogEntryResponse>, ServiceError> {
+    if start < 0 || end < start {
+        return Err(ServiceError::BadRequest { error_message: "Invalid range of log entries".to_string() });
+    }
+
+    let entries = ReviewLogEntry::find_range(
+        source_id, app_bundle_id, start, end.min(start + MAX_ENTRIES_PER_REQUEST), &mut pool.get().unwrap()
+    )?;
+
+    Ok(entries.into_iter().map(|entry| ReviewLogEntryResponse {
+        leaf_index: entry.leaf_index,
+        leaf_hash: base64_engine.encode(entry.leaf_hash),
+        sequence_number: entry.sequence_number,
+        status: entry.status,
+        signature: entry.signature,
+        key_id: entry.key_id,
+        format_version: entry.format_version,
+        created_at: entry.created_at.timestamp(),
+    }).collect())
+}
+
+pub fn inclusion_proof(source_id: &str, app_bundle_id: &str, leaf_hash: &str, tree_size: Option<i64>, pool: &Pool) -> Result<ReviewLogInclusionProof, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let leaf_hash = base64_engine.decode(leaf_hash)
+        .map_err(|_| ServiceError::BadRequest { error_message: "Invalid leaf hash".to_string() })?;
+    let tree_size = resolve_tree_size(source_id, app_bundle_id, tree_size, conn)?;
+
+    let entry = ReviewLogEntry::find_by_leaf_hash(source_id, app_bundle_id, &leaf_hash, conn)?
+        .filter(|entry| entry.leaf_index < tree_size)
+        .ok_or(ServiceError::NotFound { error_message: "The review isn't included in this tree".to_string() })?;
+
+    let audit_path = with_subtrees(source_id, app_bundle_id, conn, |tree| {
+        merkle::inclusion_proof(tree, entry.leaf_index as u64, tree_size as u64)
+    })?;
+
+    Ok(ReviewLogInclusionProof {
+        leaf_index: entry.leaf_index,
+        tree_size,
+        audit_path: audit_path.iter().map(|hash| base64_engine.encode(hash)).collect(),
+    })
+}
+
+pub fn consistency_proof(source_id: &str, app_bundle_id: &str, first_tree_size: i64, second_tree_size: Option<i64>, pool: &Pool) -> Result<ReviewLogConsistencyProof, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let second_tree_size = resolve_tree_size(source_id, app_bundle_id, second_tree_size, conn)?;
+    if first_tree_size < 1 || first_tree_size > second_tree_size {
+        return Err(ServiceError::BadRequest { error_message: "The first tree size must be between 1 and the second tree size".to_string() });
+    }
+
+    let proof = with_subtrees(source_id, app_bundle_id, conn, |tree| {
+        merkle::consistency_proof(tree, first_tree_size as u64, second_tree_size as u64)
+    })?;
+
+    Ok(ReviewLogConsistencyProof {
+        first_tree_size,
+        second_tree_size,
+        proof: proof.iter().map(|hash| base64_engine.encode(hash)).collect(),
+    })
+}
+
+fn find_latest_tree_head(source_id: &str, app_bundle_id: &str, conn: &mut Connection) -> Result<ReviewLogTreeHead, ServiceError> {
+    ReviewLogTreeHead::find_latest(source_id, app_bundle_id, conn)?
+        .ok_or(ServiceError::NotFound { error_message: "There is no signed tree head for this app yet".to_string() })
+}
+
+/// Use the size of the latest signed tree head unless a size was requested, which must not
+/// exceed the size of the log.
+fn resolve_tree_size(source_id: &str, app_bundle_id: &str, tree_size: Option<i64>, conn: &mut Connection) -> Result<i64, ServiceError> {
+    match tree_size {
+        Some(tree_size) => {
+            let size = ReviewLogEntry::count(source_id, app_bundle_id, conn)?;
+            if tree_size < 1 || tree_size > size {
+                debug!("Requested tree size {} of log with {} entries", tree_size, size);
+                return Err(ServiceError::BadRequest { error_message: "Invalid tree size".to_string() });
+            }
+            Ok(tree_size)
+        },
+        None => Ok(find_latest_tree_head(source_id, app_bundle_id, conn)?.tree_size),
+    }
+}
+
+/// Compute something of the log of an app from the subtree hashes, which are loaded in a single query
+/// instead of rebuilding the tree from all leaves
+fn with_subtrees<T>(source_id: &str, app_bundle_id: &str, conn: &mut Connection, compute: impl Fn(&dyn Subtrees) -> T) -> Result<T, ServiceError> {
+    let recorder = SubtreeRecorder::default();
+    compute(&recorder);
+    let required = recorder.into_subtrees();
+
+    let levels: Vec<i32> = required.iter().map(|(level, _)| *level as i32).collect();
+    let subtree_indices: Vec<i64> = required.iter().map(|(_, index)| *index as i64).collect();
+    let subtrees: HashMap<(u32, u64), Hash> = ReviewLogSubtree::find_all(source_id, app_bundle_id, &levels, &subtree_indices, conn)?
+        .into_iter()
+        .filter_map(|subtree| Some(((subtree.level as u32, subtree.subtree_index as u64), Hash::try_from(subtree.hash.as_slice()).ok()?)))
+        .collect();
+
+    if required.iter().any(|subtree| !subtrees.contains_key(subtree)) {
+        error!("Missing or invalid subtree hashes in the review log of {}/{}", source_id, app_bundle_id);
+        return Err(ServiceError::InternalServerError { error_message: "The review log is corrupted".to_string() });
+    }
+
+    Ok(compute(&subtrees))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use diesel::{ExpressionMethods, RunQueryDsl};
+    use ed25519_dalek::{Signature, SigningKey, Verifier};
+    use rand::rngs::OsRng;
+
+    use crate::api::models::app_reviews::AppReviewDeletionRequest;
+    use crate::db::schema::review_log_entries;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::config::Config;
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use sidestore_id_core::payload::CANONICAL_FORMAT_VERSION;
+    use super::*;
+
+    fn decode_hash(hash: &str) -> Hash {
+        base64_engine.decode(hash).unwrap().try_into().unwrap()
+    }
+
+    fn verify_tree_head(tree_head: &ReviewLogTreeHeadResponse, review_keys: &ReviewKeyRing) {
+        let payload = encode(&ReviewLogTreeHeadData {
+            source_identifier: tree_head.source_identifier.clone(),
+            app_bundle_identifier: tree_head.app_bundle_identifier.clone(),
+            tree_size: tree_head.tree_size,
+            root_hash: tree_head.root_hash.clone(),
+            timestamp: tree_head.timestamp,
+            key_id: tree_head.key_id.clone(),
+        }, tree_head.format_version).unwrap();
+        let signature = Signature::from_slice(&base64_engine.decode(&tree_head.signature).unwrap()).unwrap();
+        review_keys.verifying_key(tree_head.key_id.as_deref()).unwrap().verify(payload.as_bytes(), &signature).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_review_log() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let app_bundle_id = review_request(&source_identifier).app_bundle_id;
+        let users: Vec<uuid::Uuid> = (0..3).map(|_| create_user(&pool)).collect();
+
+        for user_id in &users {
+            app_review_service::sign(&review_request(&source_identifier), user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        }
+        let deletion = AppReviewDeletionRequest { source_identifier: source_identifier.clone(), app_bundle_id: app_bundle_id.clone(), version_number: None };
+        app_review_service::delete(&deletion, &users[0], &pool, &config, &review_keys).unwrap();
+
+        assert!(matches!(latest_tree_head(&source_identifier, &app_bundle_id, &pool), Err(ServiceError::NotFound { .. })));
+        sign_tree_heads(&pool, &review_keys, CANONICAL_FORMAT_VERSION).unwrap();
+
+        let first_head = latest_tree_head(&source_identifier, &app_bundle_id, &pool).unwrap();
+        assert_eq!(first_head.tree_size, 4);
+        verify_tree_head(&first_head, &review_keys);
+
+        let entries = entries(&source_identifier, &app_bundle_id, 0, 100, &pool).unwrap();
+        assert_eq!(entries.iter().map(|e| e.status.as_str()).collect::<Vec<_>>(), vec!["published", "published", "published", "deleted"]);
+        for entry in &entries {
+            let proof = inclusion_proof(&source_identifier, &app_bundle_id, &entry.leaf_hash, None, &pool).unwrap();
+            assert_eq!(proof.leaf_index, entry.leaf_index);
+            let audit_path: Vec<Hash> = proof.audit_path.iter().map(|h| decode_hash(h)).collect();
+            assert!(merkle::verify_inclusion(
+                proof.leaf_index as u64, proof.tree_size as u64, &decode_hash(&entry.leaf_hash), &audit_path, &decode_hash(&first_head.root_hash)
+            ));
+        }
+
+        // Nothing to sign without new entries
+        sign_tree_heads(&pool, &review_keys, CANONICAL_FORMAT_VERSION).unwrap();
+        assert_eq!(latest_tree_head(&source_identifier, &app_bundle_id, &pool).unwrap().tree_size, 4);
+
+        app_review_service::sign(&review_request(&source_identifier), &users[0], None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        sign_tree_heads(&pool, &review_keys, CANONICAL_FORMAT_VERSION).unwrap();
+        let second_head = latest_tree_head(&source_identifier, &app_bundle_id, &pool).unwrap();
+        assert_eq!(second_head.tree_size, 5);
+        verify_tree_head(&second_head, &review_keys);
+
+        let proof = consistency_proof(&source_identifier, &app_bundle_id, 4, None, &pool).unwrap();
+        let proof: Vec<Hash> = proof.proof.iter().map(|h| decode_hash(h)).collect();
+        assert!(merkle::verify_consistency(4, 5, &decode_hash(&first_head.root_hash), &decode_hash(&second_head.root_hash), &proof));
+
+        assert!(matches!(consistency_proof(&source_identifier, &app_bundle_id, 6, None, &pool), Err(ServiceError::BadRequest { .. })));
+        assert!(matches!(
+            inclusion_proof(&source_identifier, &app_bundle_id, &base64_engine.encode([0u8; 32]), None, &pool),
+            Err(ServiceError::NotFound { .. })
+        ));
+
+        // Entries can't be rewritten
+        let rewrite = diesel::update(review_log_entries::table)
+            .filter(review_log_entries::source_id.eq(&source_identifier))
+            .set(review_log_entries::status.eq("published"))
+            .execute(&mut pool.get().unwrap());
+        assert!(rewrite.is_err());
+    }
+}
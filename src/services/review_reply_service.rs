@@ -0,0 +1,198 @@
+use chrono::Utc;
+use diesel::Connection as _;
+use log::info;
+use sidestore_id_core::payload::encode;
+
+use crate::api::models::app_reviews::AppReviewStatus;
+use crate::api::models::review_replies::{reply_data, ReviewReply, ReviewReplyDeletionRequest, ReviewReplyRequest};
+use crate::config::Config;
+use crate::constants::MAX_REVIEW_REPLY_LENGTH;
+use crate::db::{Connection, Pool};
+use crate::db::models::app_review_reply::AppReviewReply;
+use crate::db::models::app_review_sequence::AppReviewSequence;
+use crate::errors::ServiceError;
+use crate::services::moderation_service::find_review;
+use crate::util::review_signing::ReviewKeyRing;
+
+
+/// Reply to a published review of an app of the maintainer's source, or change the reply, and sign it.
+/// A review has at most one reply, which any maintainer of the source can change.
+pub fn reply(request: &ReviewReplyRequest, maintainer_id: &uuid::Uuid, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing) -> Result<ReviewReply, ServiceError> {
+    let body = request.body.trim();
+    if body.is_empty() || body.chars().count() > MAX_REVIEW_REPLY_LENGTH {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("The reply has to have 1 to {} characters.", MAX_REVIEW_REPLY_LENGTH),
+        });
+    }
+
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        AppReviewSequence::lock(&request.source_identifier, &request.app_bundle_id, conn)?;
+        let review = find_review(&request.source_identifier, &request.app_bundle_id, request.sequence_number, conn)?;
+        if review.status() != AppReviewStatus::Published {
+            return Err(ServiceError::BadRequest { error_message: "Only published reviews can be answered.".to_string() });
+        }
+
+        let now = Utc::now().naive_utc();
+        let change_number = AppReviewReply::next_change_number(&review.source_id, &review.app_bundle_id, conn)?;
+        let (mut reply, is_new) = match AppReviewReply::find_by_review_id(&review.id, conn)? {
+            Some(reply) => (reply, false),
+            None => (AppReviewReply {
+                id: uuid::Uuid::new_v4().to_string(),
+                review_id: review.id.clone(),
+                source_id: review.source_id.clone(),
+                app_bundle_id: review.app_bundle_id.clone(),
+                review_sequence_number: review.sequence_number,
+                maintainer_id: maintainer_id.to_string(),
+                status: AppReviewStatus::Published.into(),
+                body: None,
+                signature: String::new(),
+                key_id: String::new(),
+                format_version: config.review_payload_format_version(),
+                change_number,
+                created_at: now,
+                updated_at: now,
+            }, true),
+        };
+
+        reply.maintainer_id = maintainer_id.to_string();
+        reply.status = AppReviewStatus::Published.into();
+        reply.body = Some(body.to_string());
+        reply.change_number = change_number;
+        reply.updated_at = now;
+        sign(&mut reply, config, review_keys)?;
+        save(&reply, is_new, conn)?;
+
+        info!("Maintainer {} replied to review {} of {}/{}", maintainer_id, reply.review_sequence_number, reply.source_id, reply.app_bundle_id);
+        Ok(ReviewReply::from(&reply))
+    })
+}
+
+/// Mark the reply to a review as deleted and sign the deletion
+pub fn delete(request: &ReviewReplyDeletionRequest, maintainer_id: &uuid::Uuid, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing) -> Result<ReviewReply, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        AppReviewSequence::lock(&request.source_identifier, &request.app_bundle_id, conn)?;
+        let review = find_review(&request.source_identifier, &request.app_bundle_id, request.sequence_number, conn)?;
+        let mut reply = AppReviewReply::find_by_review_id(&review.id, conn)?
+            .filter(|reply| reply.status == String::from(AppReviewStatus::Published))
+            .ok_or(ServiceError::NotFound { error_message: "The review has no reply.".to_string() })?;
+
+        reply.maintainer_id = maintainer_id.to_string();
+        reply.status = AppReviewStatus::Deleted.into();
+        reply.body = None;
+        reply.change_number = AppReviewReply::next_change_number(&reply.source_id, &reply.app_bundle_id, conn)?;
+        reply.updated_at = Utc::now().naive_utc();
+        sign(&mut reply, config, review_keys)?;
+        save(&reply, false, conn)?;
+
+        info!("Maintainer {} deleted the reply to review {} of {}/{}", maintainer_id, reply.review_sequence_number, reply.source_id, reply.app_bundle_id);
+        Ok(ReviewReply::from(&reply))
+    })
+}
+
+fn sign(reply: &mut AppReviewReply, config: &Config, review_keys: &ReviewKeyRing) -> Result<(), ServiceError> {
+    reply.key_id = review_keys.key_id().to_string();
+    reply.format_version = config.review_payload_format_version();
+    reply.signature = review_keys.sign(&encode(&reply_data(reply), reply.format_version)?);
+    Ok(())
+}
+
+fn save(reply: &AppReviewReply, is_new: bool, conn: &mut Connection) -> Result<(), ServiceError> {
+    if is_new {
+        reply.insert(conn)?;
+    } else {
+        reply.save(conn)?;
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use sidestore_id_core::reply::SignedReviewReply;
+
+    use crate::api::models::app_reviews::AppReviewDeletionRequest;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use super::*;
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_replies() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let (author_id, maintainer_id) = (create_user(&pool), create_user(&pool));
+        let review = app_review_service::sign(&review_request(&source_identifier), &author_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+
+        let request = |body: &str| ReviewReplyRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            sequence_number: review.sequence_number,
+            body: body.to_string(),
+        };
+        let verify = |reply: &ReviewReply| {
+            let signed: SignedReviewReply = serde_json::from_value(serde_json::json!({
+                "data": {
+                    "type": "review_reply",
+                    "status": reply.status,
+                    "source_identifier": reply.source_identifier,
+                    "app_bundle_identifier": reply.app_bundle_identifier,
+                    "review_sequence_number": reply.review_sequence_number,
+                    "maintainer_id": reply.maintainer_id,
+                    "reply_body": reply.body,
+                    "created_at": reply.created_at,
+                    "updated_at": reply.updated_at,
+                    "key_id": reply.key_id,
+                },
+                "format_version": reply.format_version,
+                "signature": reply.signature,
+            })).unwrap();
+            review_keys.verify(&signed.payload().unwrap(), &signed.signature, Some(&reply.key_id))
+        };
+
+        assert!(reply(&request(" "), &maintainer_id, &pool, &config, &review_keys).is_err());
+        assert!(reply(&request(&"a".repeat(MAX_REVIEW_REPLY_LENGTH + 1)), &maintainer_id, &pool, &config, &review_keys).is_err());
+
+        let first = reply(&request("Thanks! "), &maintainer_id, &pool, &config, &review_keys).unwrap();
+        assert_eq!(first.body.as_deref(), Some("Thanks!"));
+        assert_eq!(first.review_sequence_number, review.sequence_number);
+        assert!(verify(&first));
+
+        // Editing the reply signs it again
+        let edited = reply(&request("Fixed in 1.1"), &maintainer_id, &pool, &config, &review_keys).unwrap();
+        assert_ne!(edited.signature, first.signature);
+        assert_eq!(edited.created_at, first.created_at);
+        assert!(verify(&edited));
+
+        let conn = &mut pool.get().unwrap();
+        let review_row = find_review(&source_identifier, "com.example.App", review.sequence_number, conn).unwrap();
+        let replies = AppReviewReply::find_published_by_review_ids(std::slice::from_ref(&review_row.id), conn).unwrap();
+        assert_eq!(replies[&review_row.id].change_number, 2);
+
+        let deletion = ReviewReplyDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            sequence_number: review.sequence_number,
+        };
+        let deleted = delete(&deletion, &maintainer_id, &pool, &config, &review_keys).unwrap();
+        assert_eq!(deleted.status, AppReviewStatus::Deleted);
+        assert_eq!(deleted.body, None);
+        assert!(verify(&deleted));
+        assert!(matches!(delete(&deletion, &maintainer_id, &pool, &config, &review_keys), Err(ServiceError::NotFound { .. })));
+        assert!(AppReviewReply::find_published_by_review_ids(std::slice::from_ref(&review_row.id), conn).unwrap().is_empty());
+
+        // Deleted reviews can't be answered
+        app_review_service::delete(&AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: None,
+        }, &author_id, &pool, &config, &review_keys).unwrap();
+        assert!(matches!(reply(&request("Thanks!"), &maintainer_id, &pool, &config, &review_keys), Err(ServiceError::BadRequest { .. })));
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use chrono::{Duration, NaiveDateTime};
+use sha2::{Digest, Sha256};
+
+use crate::api::models::moderation::RiskDecision;
+use crate::config::Config;
+use crate::constants::{
+    RISK_NEW_ACCOUNT_HOURS, RISK_NEW_ACCOUNT_SCORE, RISK_RATING_OUTLIER_DISTANCE, RISK_RATING_OUTLIER_MIN_REVIEWS,
+    RISK_RATING_OUTLIER_SCORE, RISK_RECENT_ACCOUNT_DAYS, RISK_RECENT_ACCOUNT_SCORE, RISK_SHARED_NETWORK_DAYS,
+    RISK_SHARED_NETWORK_MAX_SCORE, RISK_SHARED_NETWORK_SCORE, RISK_VELOCITY_BASELINE_DAYS, RISK_VELOCITY_FACTOR,
+    RISK_VELOCITY_MIN_SUBMISSIONS, RISK_VELOCITY_SCORE,
+};
+use crate::db::Connection;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::review_risk_assessment::ReviewRiskAssessment;
+use crate::db::models::user::User;
+use crate::errors::ServiceError;
+
+
+/// A review submission as the fraud signals see it
+pub struct Submission<'a> {
+    pub user: &'a User,
+    pub source_id: &'a str,
+    pub app_bundle_id: &'a str,
+    pub review_rating: i32,
+    /// Keyed hash of the client's network, see [`network_hash`]
+    pub network_hash: Option<&'a str>,
+    pub time: NaiveDateTime,
+}
+
+/// A fraud signal, evaluated on every review submission while the app's review sequence is locked
+pub trait RiskSignal: Send + Sync {
+    /// Name of the signal in assessments
+    fn name(&self) -> &'static str;
+
+    /// Risk the signal sees in the submission, from 0 to 100
+    fn score(&self, submission: &Submission, conn: &mut Connection) -> Result<i32, ServiceError>;
+}
+
+/// The signals that review submissions are checked with
+pub struct RiskPipeline {
+    signals: Vec<Box<dyn RiskSignal>>,
+}
+
+/// Risk score of a submission and the scores of the signals that saw a risk
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskAssessment {
+    pub score: i32,
+    pub signals: BTreeMap<&'static str, i32>,
+}
+
+impl RiskPipeline {
+    pub fn new(signals: Vec<Box<dyn RiskSignal>>) -> Self {
+        RiskPipeline { signals }
+    }
+
+    /// Evaluate all signals. The risk score is the sum of their scores, at most 100.
+    pub fn assess(&self, submission: &Submission, conn: &mut Connection) -> Result<RiskAssessment, ServiceError> {
+        let mut signals = BTreeMap::new();
+        for signal in &self.signals {
+            let score = signal.score(submission, conn)?.clamp(0, 100);
+            if score > 0 {
+                signals.insert(signal.name(), score);
+            }
+        }
+
+        Ok(RiskAssessment {
+            score: signals.values().sum::<i32>().min(100),
+            signals,
+        })
+    }
+}
+
+impl Default for RiskPipeline {
+    fn default() -> Self {
+        RiskPipeline::new(vec![
+            Box::new(AccountAge),
+            Box::new(UnverifiedEmail),
+            Box::new(SharedNetwork),
+            Box::new(SharedDevice),
+            Box::new(ReviewVelocity),
+            Box::new(RatingOutlier),
+        ])
+    }
+}
+
+impl RiskAssessment {
+    pub fn decision(&self, config: &Config) -> RiskDecision {
+        if self.score >= config.review_risk_reject_threshold {
+            RiskDecision::Reject
+        } else if self.score >= config.review_risk_hold_threshold {
+            RiskDecision::Hold
+        } else {
+            RiskDecision::Publish
+        }
+    }
+
+    /// The assessment as it is stored, for the review it was made for unless the submission was rejected
+    pub fn record(&self, submission: &Submission, review_id: Option<&str>, decision: RiskDecision) -> ReviewRiskAssessment {
+        ReviewRiskAssessment {
+            id: uuid::Uuid::new_v4().to_string(),
+            review_id: review_id.map(str::to_string),
+            user_id: submission.user.id.clone(),
+            source_id: submission.source_id.to_string(),
+            app_bundle_id: submission.app_bundle_id.to_string(),
+            network_hash: submission.network_hash.map(str::to_string),
+            review_rating: submission.review_rating,
+            risk_score: self.score,
+            signals: serde_json::to_string(&self.signals).unwrap_or_default(),
+            decision: decision.into(),
+            created_at: submission.time,
+        }
+    }
+}
+
+/// Hash of the client's network, keyed with the service's secret so the stored hashes don't reveal addresses.
+/// IPv6 clients are grouped by their /64 prefix, which usually belongs to a single household.
+pub fn network_hash(ip: IpAddr, config: &Config) -> String {
+    let network = match ip {
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip.to_string(),
+            None => {
+                let segments = ip.segments();
+                format!("{:x}:{:x}:{:x}:{:x}::/64", segments[0], segments[1], segments[2], segments[3])
+            },
+        },
+        IpAddr::V4(ip) => ip.to_string(),
+    };
+    let hash = Sha256::new()
+        .chain_update(b"sidestore-id-review-network\0")
+        .chain_update(config.jwt_secret.as_bytes())
+        .chain_update([0])
+        .chain_update(network.as_bytes())
+        .finalize();
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+
+/// New accounts are cheap to create in bulk
+pub struct AccountAge;
+
+impl RiskSignal for AccountAge {
+    fn name(&self) -> &'static str {
+        "account_age"
+    }
+
+    fn score(&self, submission: &Submission, _conn: &mut Connection) -> Result<i32, ServiceError> {
+        let age = submission.time - submission.user.created_at;
+        Ok(if age < Duration::hours(RISK_NEW_ACCOUNT_HOURS) {
+            RISK_NEW_ACCOUNT_SCORE
+        } else if age < Duration::days(RISK_RECENT_ACCOUNT_DAYS) {
+            RISK_RECENT_ACCOUNT_SCORE
+        } else {
+            0
+        })
+    }
+}
+
+/// The account's email address isn't verified. Not evaluated yet: emails aren't verified at signup, so every
+/// account would score the same.
+pub struct UnverifiedEmail;
+
+impl RiskSignal for UnverifiedEmail {
+    fn name(&self) -> &'static str {
+        "unverified_email"
+    }
+
+    fn score(&self, _submission: &Submission, _conn: &mut Connection) -> Result<i32, ServiceError> {
+        Ok(0)
+    }
+}
+
+/// Other accounts reviewed the app from the same device. Not evaluated yet: review submissions don't identify the
+/// device they're sent from, the devices of settings sync are per account.
+pub struct SharedDevice;
+
+impl RiskSignal for SharedDevice {
+    fn name(&self) -> &'static str {
+        "shared_device"
+    }
+
+    fn score(&self, _submission: &Submission, _conn: &mut Connection) -> Result<i32, ServiceError> {
+        Ok(0)
+    }
+}
+
+/// Other accounts reviewed the app from the same network
+pub struct SharedNetwork;
+
+impl RiskSignal for SharedNetwork {
+    fn name(&self) -> &'static str {
+        "shared_network"
+    }
+
+    fn score(&self, submission: &Submission, conn: &mut Connection) -> Result<i32, ServiceError> {
+        let Some(network_hash) = submission.network_hash else {
+            return Ok(0);
+        };
+        let users = ReviewRiskAssessment::count_network_users(
+            submission.source_id, submission.app_bundle_id, network_hash, &submission.user.id,
+            submission.time - Duration::days(RISK_SHARED_NETWORK_DAYS), conn,
+        )?;
+        Ok((users.min(100) as i32 * RISK_SHARED_NETWORK_SCORE).min(RISK_SHARED_NETWORK_MAX_SCORE))
+    }
+}
+
+/// The app gets far more reviews than usual
+pub struct ReviewVelocity;
+
+impl RiskSignal for ReviewVelocity {
+    fn name(&self) -> &'static str {
+        "review_velocity"
+    }
+
+    fn score(&self, submission: &Submission, conn: &mut Connection) -> Result<i32, ServiceError> {
+        let hour_ago = submission.time - Duration::hours(1);
+        let last_hour = ReviewRiskAssessment::count_app_submissions(
+            submission.source_id, submission.app_bundle_id, &submission.user.id, hour_ago, submission.time, conn,
+        )?;
+        if last_hour <= RISK_VELOCITY_MIN_SUBMISSIONS {
+            return Ok(0);
+        }
+
+        let baseline = ReviewRiskAssessment::count_app_submissions(
+            submission.source_id, submission.app_bundle_id, &submission.user.id,
+            hour_ago - Duration::days(RISK_VELOCITY_BASELINE_DAYS), hour_ago, conn,
+        )?;
+        let baseline_hours = RISK_VELOCITY_BASELINE_DAYS * 24;
+        Ok(if last_hour * baseline_hours > RISK_VELOCITY_FACTOR * baseline { RISK_VELOCITY_SCORE } else { 0 })
+    }
+}
+
+/// The rating is far from the app's average rating
+pub struct RatingOutlier;
+
+impl RiskSignal for RatingOutlier {
+    fn name(&self) -> &'static str {
+        "rating_outlier"
+    }
+
+    fn score(&self, submission: &Submission, conn: &mut Connection) -> Result<i32, ServiceError> {
+        let (mut count, mut sum) = (0, 0);
+        for (_, rating, rating_count) in AppReviewSignature::count_ratings(submission.source_id, submission.app_bundle_id, false, conn)? {
+            if let Some(rating) = rating {
+                count += rating_count;
+                sum += i64::from(rating) * rating_count;
+            }
+        }
+        if count < RISK_RATING_OUTLIER_MIN_REVIEWS {
+            return Ok(0);
+        }
+
+        let average = sum as f64 / count as f64;
+        Ok(if (f64::from(submission.review_rating) - average).abs() >= RISK_RATING_OUTLIER_DISTANCE { RISK_RATING_OUTLIER_SCORE } else { 0 })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use chrono::Utc;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use crate::api::models::app_reviews::{AppReviewSignatureRequest, AppReviewStatus};
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::util::review_signing::ReviewKeyRing;
+    use super::*;
+
+    struct FixedScore(&'static str, i32);
+
+    impl RiskSignal for FixedScore {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn score(&self, _submission: &Submission, _conn: &mut Connection) -> Result<i32, ServiceError> {
+            Ok(self.1)
+        }
+    }
+
+    #[test]
+    fn test_network_hash() {
+        let config = Config::for_tests();
+        let hash = |ip: &str| network_hash(ip.parse().unwrap(), &config);
+        assert_eq!(hash("2001:db8:1:2::1"), hash("2001:db8:1:2:ffff::2"));
+        assert_ne!(hash("2001:db8:1:2::1"), hash("2001:db8:1:3::1"));
+        assert_eq!(hash("::ffff:192.0.2.1"), network_hash(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), &config));
+        assert_ne!(hash("192.0.2.1"), hash("192.0.2.2"));
+        assert_eq!(hash("192.0.2.1").len(), 64);
+        assert_ne!(network_hash(IpAddr::V6(Ipv6Addr::LOCALHOST), &config), network_hash(IpAddr::V6(Ipv6Addr::LOCALHOST), &Config {
+            jwt_secret: "other".to_string(),
+            ..Config::for_tests()
+        }));
+    }
+
+    #[test]
+    fn test_decision() {
+        let config = Config { review_risk_hold_threshold: 50, review_risk_reject_threshold: 90, ..Config::for_tests() };
+        let assessment = |score| RiskAssessment { score, signals: BTreeMap::new() };
+        assert_eq!(assessment(0).decision(&config), RiskDecision::Publish);
+        assert_eq!(assessment(49).decision(&config), RiskDecision::Publish);
+        assert_eq!(assessment(50).decision(&config), RiskDecision::Hold);
+        assert_eq!(assessment(90).decision(&config), RiskDecision::Reject);
+        assert_eq!(assessment(100).decision(&Config::for_tests()), RiskDecision::Publish);
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_fraud_checks() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config { review_risk_hold_threshold: 50, review_risk_reject_threshold: 90, ..Config::for_tests() };
+        let pipeline = RiskPipeline::default();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let conn = &mut pool.get().unwrap();
+
+        // Established accounts on their own networks agree on the rating
+        let mut established = Vec::new();
+        for i in 0..5 {
+            let mut user = User::new(&format!("{}@example.com", uuid::Uuid::new_v4()), "hash");
+            user.created_at -= Duration::days(30);
+            user.insert(conn).unwrap();
+            let user_id = user.id.parse().unwrap();
+            let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, i));
+            let response = app_review_service::sign(&review_request(&source_identifier), &user_id, Some(ip), &pool, &config, &review_keys, &pipeline).unwrap();
+            assert_eq!(response.status, AppReviewStatus::Published);
+            established.push(user_id);
+        }
+        let review = AppReviewSignature::find_by_user_id(&established[0], &source_identifier, "com.example.App", conn).unwrap();
+        assert_eq!(review.risk_score, Some(0));
+
+        // A new account with an outlier rating is held, and signed without its content
+        let shared_ip = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+        let new_user = create_user(&pool);
+        let one_star = AppReviewSignatureRequest { review_rating: 1, ..review_request(&source_identifier) };
+        let held = app_review_service::sign(&one_star, &new_user, Some(shared_ip), &pool, &config, &review_keys, &pipeline).unwrap();
+        assert_eq!(held.status, AppReviewStatus::Held);
+        let review = AppReviewSignature::find_by_user_id(&new_user, &source_identifier, "com.example.App", conn).unwrap();
+        assert_eq!(review.risk_score, Some(RISK_NEW_ACCOUNT_SCORE + RISK_RATING_OUTLIER_SCORE));
+        assert!(crate::api::models::app_reviews::review_data(&review).review_rating.is_none());
+
+        let assessments = ReviewRiskAssessment::find_latest_by_review_ids(std::slice::from_ref(&review.id), conn).unwrap();
+        let signals: BTreeMap<String, i32> = serde_json::from_str(&assessments[&review.id].signals).unwrap();
+        assert_eq!(signals.keys().collect::<Vec<_>>(), vec!["account_age", "rating_outlier"]);
+        assert_eq!(assessments[&review.id].decision, "hold");
+
+        // Editing a held review keeps it held, even if its risk is low now
+        let held_again = app_review_service::sign(&review_request(&source_identifier), &new_user, None, &pool, &config, &review_keys, &pipeline).unwrap();
+        assert_eq!(held_again.status, AppReviewStatus::Held);
+
+        // Held reviews only count in the statistics if they are included
+        let ratings = |include_held| AppReviewSignature::count_ratings(&source_identifier, "com.example.App", include_held, &mut pool.get().unwrap())
+            .unwrap().iter().map(|(_, _, count)| count).sum::<i64>();
+        assert_eq!((ratings(false), ratings(true)), (5, 6));
+
+        // More new accounts on the same network add to the risk, until submissions are rejected and not stored
+        let second_user = create_user(&pool);
+        let second = app_review_service::sign(&one_star, &second_user, Some(shared_ip), &pool, &config, &review_keys, &pipeline).unwrap();
+        assert_eq!(second.status, AppReviewStatus::Held);
+        let third_user = create_user(&pool);
+        let rejected = app_review_service::sign(&one_star, &third_user, Some(shared_ip), &pool, &config, &review_keys, &pipeline);
+        assert!(matches!(rejected, Err(ServiceError::BadRequest { .. })));
+        assert!(AppReviewSignature::find_by_user_id(&third_user, &source_identifier, "com.example.App", conn).is_err());
+        let network_users = ReviewRiskAssessment::count_network_users(
+            &source_identifier, "com.example.App", &network_hash(shared_ip, &config), &established[0].to_string(),
+            Utc::now().naive_utc() - Duration::hours(1), conn,
+        ).unwrap();
+        assert_eq!(network_users, 3);
+
+        // Custom signals can be plugged in
+        let pipeline = RiskPipeline::new(vec![Box::new(FixedScore("first", 60)), Box::new(FixedScore("second", 70)), Box::new(FixedScore("zero", 0))]);
+        let user = User::find_by_id(&established[1], conn).unwrap();
+        let submission = Submission {
+            user: &user,
+            source_id: &source_identifier,
+            app_bundle_id: "com.example.App",
+            review_rating: 4,
+            network_hash: None,
+            time: Utc::now().naive_utc(),
+        };
+        let assessment = pipeline.assess(&submission, conn).unwrap();
+        assert_eq!(assessment.score, 100);
+        assert_eq!(assessment.signals.keys().copied().collect::<Vec<_>>(), vec!["first", "second"]);
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sidestore_id_core::payload::encode;
+
+use crate::api::models::app_reviews::AppReviewStatus;
+use crate::api::models::review_replies::ReviewReply;
+use crate::api::models::review_snapshot::{AppReviewSnapshot, ReviewSnapshot, ReviewSnapshotData, ReviewSnapshotEntry};
+use crate::config::Config;
+use crate::db::Pool;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::app_review_reply::AppReviewReply;
+use crate::db::models::review_log::{ReviewLogEntry, ReviewLogTreeHead};
+use crate::errors::ServiceError;
+use crate::services::review_log_service::tree_head_response;
+use crate::util::review_signing::ReviewKeyRing;
+
+
+pub enum SnapshotResult {
+    /// The client's copy with this ETag is still current
+    NotModified { etag: String },
+    Modified { snapshot: ReviewSnapshot, etag: String },
+}
+
+/// Signed snapshot of the current published reviews of all apps of a source. With the cursor of a previous
+/// snapshot as `since`, only the reviews that changed since are included. `is_current` is called with the
+/// ETag of the snapshot before it's built, to skip building snapshots the client already has.
+pub fn snapshot(
+    source_id: &str,
+    since: Option<&str>,
+    is_current: impl Fn(&str) -> bool,
+    pool: &Pool,
+    config: &Config,
+    review_keys: &ReviewKeyRing,
+) -> Result<SnapshotResult, ServiceError> {
+    let since_positions = since.map(parse_cursor).transpose()?;
+    let format_version = config.review_payload_format_version();
+    let conn = &mut pool.get().unwrap();
+
+    // All reads see the same state of the database, so the reviews match the log sizes in the cursor
+    conn.build_transaction().repeatable_read().read_only().run(|conn| {
+        let app_bundle_ids = AppReviewSignature::find_app_bundle_ids(source_id, conn)?;
+        let log_sizes = ReviewLogEntry::source_sizes(source_id, conn)?;
+        let reply_changes = AppReviewReply::source_change_numbers(source_id, conn)?;
+        let tree_heads = app_bundle_ids.iter()
+            .map(|app_bundle_id| ReviewLogTreeHead::find_latest(source_id, app_bundle_id, conn))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let log_size = |app_bundle_id: &str| log_sizes.get(app_bundle_id).copied().unwrap_or(0);
+        let reply_change = |app_bundle_id: &str| reply_changes.get(app_bundle_id).copied().unwrap_or(0);
+        let cursor = app_bundle_ids.iter()
+            .map(|app_bundle_id| cursor_position(app_bundle_id, CursorPosition {
+                log_size: log_size(app_bundle_id),
+                reply_change: reply_change(app_bundle_id),
+            }))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        // Every change of a review is appended to the log and every change of a reply is numbered, so the
+        // cursor and the signed tree heads identify the content. Snapshots of the same content differ in their
+        // timestamp and signature, so it's sent as a weak ETag.
+        let mut etag_data = format!("{}\n{}\n{}\n{}\n{}", source_id, since.unwrap_or_default(), cursor, review_keys.key_id(), format_version);
+        for tree_head in tree_heads.iter().flatten() {
+            etag_data.push_str(&format!("\n{}:{}", tree_head.app_bundle_id, tree_head.tree_size));
+        }
+        let etag = Sha256::digest(etag_data.as_bytes())[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        if is_current(&etag) {
+            return Ok(SnapshotResult::NotModified { etag });
+        }
+
+        let mut apps = Vec::new();
+        for (app_bundle_id, tree_head) in app_bundle_ids.into_iter().zip(tree_heads) {
+            let log_size = log_size(&app_bundle_id);
+            let reviews = match &since_positions {
+                None => AppReviewSignature::find_all_by_app(source_id, &app_bundle_id, None, conn)?,
+                Some(since_positions) => {
+                    let start = since_positions.get(&app_bundle_id).copied().unwrap_or_default();
+                    if start.log_size >= log_size && start.reply_change >= reply_change(&app_bundle_id) {
+                        continue;
+                    }
+                    let mut sequence_numbers = ReviewLogEntry::changed_sequence_numbers(source_id, &app_bundle_id, start.log_size, conn)?;
+                    sequence_numbers.extend(AppReviewReply::changed_sequence_numbers(source_id, &app_bundle_id, start.reply_change, conn)?);
+                    sequence_numbers.sort();
+                    sequence_numbers.dedup();
+                    AppReviewSignature::find_all_by_app(source_id, &app_bundle_id, Some(&sequence_numbers), conn)?
+                },
+            };
+
+            let (published, deleted): (Vec<_>, Vec<_>) = reviews.into_iter()
+                .partition(|review| review.status() == AppReviewStatus::Published);
+            let review_ids: Vec<String> = published.iter().map(|review| review.id.clone()).collect();
+            let mut replies = AppReviewReply::find_published_by_review_ids(&review_ids, conn)?;
+            apps.push(AppReviewSnapshot {
+                app_bundle_identifier: app_bundle_id,
+                log_size,
+                tree_head: tree_head.map(tree_head_response),
+                reviews: published.into_iter()
+                    .map(|review| {
+                        let reply = replies.remove(&review.id);
+                        snapshot_entry(review, reply.as_ref().map(ReviewReply::from))
+                    })
+                    .collect(),
+                // A full snapshot only contains the current reviews
+                deleted_sequence_numbers: match since_positions {
+                    Some(_) => deleted.into_iter().map(|review| review.sequence_number).collect(),
+                    None => Vec::new(),
+                },
+            });
+        }
+
+        let data = ReviewSnapshotData {
+            source_identifier: source_id.to_string(),
+            since: since.map(str::to_string),
+            cursor,
+            timestamp: Utc::now().timestamp(),
+            apps,
+            key_id: review_keys.key_id().to_string(),
+        };
+        let snapshot = ReviewSnapshot {
+            signature: review_keys.sign(&encode(&data, format_version)?),
+            snapshot: data,
+            format_version,
+        };
+        Ok(SnapshotResult::Modified { snapshot, etag })
+    })
+}
+
+fn snapshot_entry(review: AppReviewSignature, reply: Option<ReviewReply>) -> ReviewSnapshotEntry {
+    ReviewSnapshotEntry {
+        sequence_number: review.sequence_number,
+        version_number: review.app_version,
+        review_rating: review.review_rating,
+        content_commitment: review.content_commitment,
+        created_at: review.created_at.timestamp(),
+        updated_at: review.updated_at.timestamp(),
+        signature: review.signature,
+        key_id: review.key_id,
+        format_version: review.format_version,
+        reply,
+    }
+}
+
+/// Position of a snapshot in the changes of an app's reviews and replies
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CursorPosition {
+    /// Size of the app's review log
+    log_size: i64,
+    /// Number of the last change of a reply to a review of the app
+    reply_change: i64,
+}
+
+/// Cursor of an app, `app_bundle_id:log_size`, followed by `:reply_change` once a review of the app was answered
+fn cursor_position(app_bundle_id: &str, position: CursorPosition) -> String {
+    match position.reply_change {
+        0 => format!("{}:{}", app_bundle_id, position.log_size),
+        reply_change => format!("{}:{}:{}", app_bundle_id, position.log_size, reply_change),
+    }
+}
+
+/// Parse a cursor of the form `app_bundle_id:log_size[:reply_change],...`
+fn parse_cursor(cursor: &str) -> Result<HashMap<String, CursorPosition>, ServiceError> {
+    cursor.split(',')
+        .filter(|app| !app.is_empty())
+        .map(|app| {
+            let mut parts = app.rsplitn(3, ':').collect::<Vec<_>>();
+            parts.reverse();
+            let position = match parts[..] {
+                [app_bundle_id, log_size, reply_change] => log_size.parse().ok().zip(reply_change.parse().ok())
+                    .map(|(log_size, reply_change)| (app_bundle_id, CursorPosition { log_size, reply_change })),
+                [app_bundle_id, log_size] => log_size.parse().ok()
+                    .map(|log_size| (app_bundle_id, CursorPosition { log_size, reply_change: 0 })),
+                _ => None,
+            };
+            position
+                .map(|(app_bundle_id, position)| (app_bundle_id.to_string(), position))
+                .ok_or(ServiceError::BadRequest { error_message: "Invalid snapshot cursor".to_string() })
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use crate::api::models::app_reviews::{AppReviewDeletionRequest, AppReviewSignatureRequest};
+    use crate::api::models::review_replies::ReviewReplyRequest;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use crate::services::review_reply_service;
+    use super::*;
+
+    #[test]
+    fn test_parse_cursor() {
+        let positions = parse_cursor("com.example.App:3,com.example.Other:12:4").unwrap();
+        assert_eq!(positions.get("com.example.App"), Some(&CursorPosition { log_size: 3, reply_change: 0 }));
+        assert_eq!(positions.get("com.example.Other"), Some(&CursorPosition { log_size: 12, reply_change: 4 }));
+        assert!(parse_cursor("").unwrap().is_empty());
+        assert!(parse_cursor("com.example.App").is_err());
+        assert!(parse_cursor("com.example.App:x").is_err());
+        assert!(parse_cursor("com.example.App:3:x").is_err());
+
+        for position in [CursorPosition { log_size: 3, reply_change: 0 }, CursorPosition { log_size: 3, reply_change: 2 }] {
+            assert_eq!(parse_cursor(&cursor_position("com.example.App", position)).unwrap()["com.example.App"], position);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_snapshot() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let sign = |app_bundle_id: &str, user_id: &uuid::Uuid| {
+            let request = AppReviewSignatureRequest { app_bundle_id: app_bundle_id.to_string(), ..review_request(&source_identifier) };
+            app_review_service::sign(&request, user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap()
+        };
+        let snapshot = |since: Option<&str>, etag: Option<&str>| {
+            match super::snapshot(&source_identifier, since, |current| Some(current) == etag, &pool, &config, &review_keys).unwrap() {
+                SnapshotResult::Modified { snapshot, etag } => (Some(snapshot), etag),
+                SnapshotResult::NotModified { etag } => (None, etag),
+            }
+        };
+
+        let users: Vec<uuid::Uuid> = (0..3).map(|_| create_user(&pool)).collect();
+        sign("com.example.App", &users[0]);
+        sign("com.example.App", &users[1]);
+        sign("com.example.Other", &users[2]);
+        app_review_service::delete(&AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: None,
+        }, &users[0], &pool, &config, &review_keys).unwrap();
+
+        let (full, etag) = snapshot(None, None);
+        let full = full.unwrap();
+        assert!(review_keys.verify(&encode(&full.snapshot, full.format_version).unwrap(), &full.signature, Some(review_keys.key_id())));
+        assert_eq!(full.snapshot.cursor, "com.example.App:3,com.example.Other:1");
+        assert_eq!(full.snapshot.apps.len(), 2);
+        assert_eq!(full.snapshot.apps[0].reviews.iter().map(|review| review.sequence_number).collect::<Vec<_>>(), vec![2]);
+        assert!(full.snapshot.apps[0].deleted_sequence_numbers.is_empty());
+        assert_eq!(full.snapshot.apps[1].reviews.len(), 1);
+
+        // Unchanged snapshots aren't built again
+        assert!(snapshot(None, Some(&etag)).0.is_none());
+
+        let (delta, _) = snapshot(Some(&full.snapshot.cursor), None);
+        assert!(delta.unwrap().snapshot.apps.is_empty());
+
+        sign("com.example.App", &users[0]);
+        sign("com.example.App", &users[1]);
+        let (changed, changed_etag) = snapshot(None, Some(&etag));
+        assert_ne!(changed_etag, etag);
+        assert_eq!(changed.unwrap().snapshot.apps[0].reviews.len(), 2);
+
+        let (delta, _) = snapshot(Some(&full.snapshot.cursor), None);
+        let delta = delta.unwrap();
+        assert_eq!(delta.snapshot.since.as_deref(), Some(full.snapshot.cursor.as_str()));
+        assert_eq!(delta.snapshot.apps.len(), 1);
+        assert_eq!(delta.snapshot.apps[0].log_size, 5);
+        assert_eq!(delta.snapshot.apps[0].reviews.iter().map(|review| review.sequence_number).collect::<Vec<_>>(), vec![1, 2]);
+
+        app_review_service::delete(&AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.Other".to_string(),
+            version_number: None,
+        }, &users[2], &pool, &config, &review_keys).unwrap();
+        let (delta, _) = snapshot(Some(&delta.snapshot.cursor), None);
+        let delta = delta.unwrap();
+        assert_eq!(delta.snapshot.apps.len(), 1);
+        assert!(delta.snapshot.apps[0].reviews.is_empty());
+        assert_eq!(delta.snapshot.apps[0].deleted_sequence_numbers, vec![1]);
+
+        // Replies are part of the reviews' entries, and a changed reply brings its review into the delta
+        let cursor = delta.snapshot.cursor.clone();
+        let reply_request = ReviewReplyRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            sequence_number: 2,
+            body: "Thanks!".to_string(),
+        };
+        review_reply_service::reply(&reply_request, &users[2], &pool, &config, &review_keys).unwrap();
+        let (delta, _) = snapshot(Some(&cursor), None);
+        let delta = delta.unwrap();
+        assert_eq!(delta.snapshot.cursor, "com.example.App:5:1,com.example.Other:2");
+        assert_eq!(delta.snapshot.apps.len(), 1);
+        assert_eq!(delta.snapshot.apps[0].reviews.len(), 1);
+        assert_eq!(delta.snapshot.apps[0].reviews[0].reply.as_ref().unwrap().body.as_deref(), Some("Thanks!"));
+
+        let (full, _) = snapshot(None, None);
+        let full = full.unwrap();
+        let replies: Vec<_> = full.snapshot.apps[0].reviews.iter().map(|review| review.reply.is_some()).collect();
+        assert_eq!(replies, vec![false, true]);
+
+        let (delta, _) = snapshot(Some(&full.snapshot.cursor), None);
+        assert!(delta.unwrap().snapshot.apps.is_empty());
+    }
+}
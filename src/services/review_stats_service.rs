@@ -0,0 +1,238 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use sidestore_id_core::payload::encode;
+
+use crate::api::models::review_stats::{RatingStats, ReviewStats, ReviewStatsData, VersionRatingStats};
+use crate::config::Config;
+use crate::db::Pool;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::errors::ServiceError;
+use crate::util::review_signing::ReviewKeyRing;
+
+
+/// Signed rating statistics per app, with and without held reviews, shared by all workers. Entries are
+/// removed when a review of the app is signed or deleted.
+#[derive(Clone, Default)]
+pub struct ReviewStatsCache {
+    inner: Arc<Mutex<ReviewStatsCacheInner>>,
+}
+
+#[derive(Default)]
+struct ReviewStatsCacheInner {
+    stats: HashMap<(String, String, bool), ReviewStats>,
+    /// Incremented on every invalidation, so statistics computed before it aren't cached after it
+    generation: u64,
+}
+
+impl ReviewStatsCache {
+    pub fn invalidate(&self, source_id: &str, app_bundle_id: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        for include_held in [false, true] {
+            inner.stats.remove(&(source_id.to_string(), app_bundle_id.to_string(), include_held));
+        }
+        inner.generation += 1;
+    }
+
+    fn get(&self, source_id: &str, app_bundle_id: &str, include_held: bool) -> (Option<ReviewStats>, u64) {
+        let inner = self.inner.lock().unwrap();
+        (inner.stats.get(&(source_id.to_string(), app_bundle_id.to_string(), include_held)).cloned(), inner.generation)
+    }
+
+    fn insert(&self, stats: &ReviewStats, generation: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation == generation {
+            let key = (stats.stats.source_identifier.clone(), stats.stats.app_bundle_identifier.clone(), stats.stats.held_reviews_included);
+            inner.stats.insert(key, stats.clone());
+        }
+    }
+}
+
+
+/// Rating statistics of an app's published reviews, and of its held reviews if they are included, signed
+/// with the review signing key
+pub fn stats(source_id: &str, app_bundle_id: &str, include_held: bool, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing, cache: &ReviewStatsCache) -> Result<ReviewStats, ServiceError> {
+    let (cached, generation) = cache.get(source_id, app_bundle_id, include_held);
+    if let Some(stats) = cached {
+        return Ok(stats);
+    }
+
+    let mut ratings = RatingCounts::default();
+    let mut version_ratings: BTreeMap<Option<String>, RatingCounts> = BTreeMap::new();
+    for (version_number, rating, count) in AppReviewSignature::count_ratings(source_id, app_bundle_id, include_held, &mut pool.get().unwrap())? {
+        let Some(rating) = rating else { continue };
+        ratings.add(rating, count);
+        version_ratings.entry(version_number).or_default().add(rating, count);
+    }
+
+    let mut versions: Vec<VersionRatingStats> = version_ratings.into_iter()
+        .map(|(version_number, ratings)| VersionRatingStats { version_number, ratings: ratings.into() })
+        .collect();
+    versions.sort_by(|a, b| compare_versions(a.version_number.as_deref(), b.version_number.as_deref()));
+
+    let data = ReviewStatsData {
+        source_identifier: source_id.to_string(),
+        app_bundle_identifier: app_bundle_id.to_string(),
+        ratings: ratings.into(),
+        versions,
+        held_reviews_included: include_held,
+        timestamp: Utc::now().timestamp(),
+        key_id: review_keys.key_id().to_string(),
+    };
+    let format_version = config.review_payload_format_version();
+    let stats = ReviewStats {
+        signature: review_keys.sign(&encode(&data, format_version)?),
+        stats: data,
+        format_version,
+    };
+
+    // Apps without reviews aren't cached, the cache would grow with every requested app otherwise
+    if stats.stats.ratings.review_count > 0 {
+        cache.insert(&stats, generation);
+    }
+    Ok(stats)
+}
+
+
+/// Number of reviews with 1 to 5 stars
+#[derive(Default)]
+struct RatingCounts([i64; 5]);
+
+impl RatingCounts {
+    fn add(&mut self, rating: i32, count: i64) {
+        if let Some(rating_count) = usize::try_from(rating - 1).ok().and_then(|i| self.0.get_mut(i)) {
+            *rating_count += count;
+        }
+    }
+}
+
+impl From<RatingCounts> for RatingStats {
+    fn from(counts: RatingCounts) -> Self {
+        let review_count: i64 = counts.0.iter().sum();
+        let rating_sum: i64 = counts.0.iter().zip(1..).map(|(count, rating)| count * rating).sum();
+
+        RatingStats {
+            review_count,
+            average_rating: (review_count > 0).then(|| {
+                // Rounded half up in hundredths, without floating point numbers
+                let hundredths = (rating_sum * 200 + review_count) / (review_count * 2);
+                format!("{}.{:02}", hundredths / 100, hundredths % 100)
+            }),
+            rating_counts: counts.0.to_vec(),
+        }
+    }
+}
+
+/// Order version numbers by their numeric components, so 1.10 comes after 1.9
+fn compare_versions(a: Option<&str>, b: Option<&str>) -> Ordering {
+    let (Some(a), Some(b)) = (a, b) else {
+        return a.cmp(&b);
+    };
+
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        let ordering = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return a.cmp(b),
+            (a_part, b_part) => match (a_part.and_then(|p| p.parse::<u64>().ok()), b_part.and_then(|p| p.parse::<u64>().ok())) {
+                (Some(a_number), Some(b_number)) => a_number.cmp(&b_number),
+                _ => a_part.cmp(&b_part),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+
+    use crate::api::models::app_reviews::{AppReviewDeletionRequest, AppReviewStatus};
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use super::*;
+
+    #[test]
+    fn test_rating_stats() {
+        let stats = RatingStats::from(RatingCounts([1, 0, 0, 2, 0]));
+        assert_eq!(stats.review_count, 3);
+        assert_eq!(stats.average_rating.as_deref(), Some("3.00"));
+
+        assert_eq!(RatingStats::from(RatingCounts([0, 0, 1, 0, 2])).average_rating.as_deref(), Some("4.33"));
+        assert_eq!(RatingStats::from(RatingCounts([0, 0, 0, 1, 2])).average_rating.as_deref(), Some("4.67"));
+        assert_eq!(RatingStats::from(RatingCounts([0, 0, 0, 1, 7])).average_rating.as_deref(), Some("4.88"));
+        assert_eq!(RatingStats::from(RatingCounts::default()), RatingStats { rating_counts: vec![0; 5], ..Default::default() });
+
+        let mut versions = vec![Some("1.10"), None, Some("1.9"), Some("1.9.1"), Some("beta")];
+        versions.sort_by(|a, b| compare_versions(*a, *b));
+        assert_eq!(versions, vec![None, Some("1.9"), Some("1.9.1"), Some("1.10"), Some("beta")]);
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_stats() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let cache = ReviewStatsCache::default();
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+
+        let mut users = Vec::new();
+        for (version_number, review_rating) in [("1.0", 5), ("1.0", 4), ("1.1", 1), ("1.1", 5)] {
+            let user_id = create_user(&pool);
+            let mut request = review_request(&source_identifier);
+            request.version_number = version_number.to_string();
+            request.review_rating = review_rating;
+            app_review_service::sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+            users.push(user_id);
+        }
+
+        let stats = stats(&source_identifier, "com.example.App", false, &pool, &config, &review_keys, &cache).unwrap();
+        assert_eq!(stats.stats.ratings.review_count, 4);
+        assert_eq!(stats.stats.ratings.average_rating.as_deref(), Some("3.75"));
+        assert_eq!(stats.stats.ratings.rating_counts, vec![1, 0, 0, 1, 2]);
+        assert_eq!(stats.stats.versions.len(), 2);
+        assert_eq!(stats.stats.versions[0].version_number.as_deref(), Some("1.0"));
+        assert_eq!(stats.stats.versions[0].ratings.rating_counts, vec![0, 0, 0, 1, 1]);
+        assert_eq!(stats.stats.versions[1].ratings.average_rating.as_deref(), Some("3.00"));
+        assert!(review_keys.verify(&encode(&stats.stats, stats.format_version).unwrap(), &stats.signature, Some(review_keys.key_id())));
+
+        // Deleted reviews don't count once the cached statistics are invalidated
+        let deletion_request = AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: None,
+        };
+        app_review_service::delete(&deletion_request, &users[2], &pool, &config, &review_keys).unwrap();
+        let cached = super::stats(&source_identifier, "com.example.App", false, &pool, &config, &review_keys, &cache).unwrap();
+        assert_eq!(cached.signature, stats.signature);
+
+        cache.invalidate(&source_identifier, "com.example.App");
+        let stats = super::stats(&source_identifier, "com.example.App", false, &pool, &config, &review_keys, &cache).unwrap();
+        assert_eq!(stats.stats.ratings.review_count, 3);
+        assert_eq!(stats.stats.ratings.average_rating.as_deref(), Some("4.67"));
+        assert_eq!(stats.stats.versions[1].ratings.rating_counts, vec![0, 0, 0, 0, 1]);
+
+        // Held reviews only count when they are asked for
+        let held_config = Config { review_risk_hold_threshold: 0, ..config.clone() };
+        let held = app_review_service::sign(&review_request(&source_identifier), &create_user(&pool), None, &pool, &held_config, &review_keys, &RiskPipeline::default()).unwrap();
+        assert_eq!(held.status, AppReviewStatus::Held);
+        cache.invalidate(&source_identifier, "com.example.App");
+        let published = super::stats(&source_identifier, "com.example.App", false, &pool, &config, &review_keys, &cache).unwrap();
+        assert_eq!((published.stats.ratings.review_count, published.stats.held_reviews_included), (3, false));
+        let with_held = super::stats(&source_identifier, "com.example.App", true, &pool, &config, &review_keys, &cache).unwrap();
+        assert_eq!((with_held.stats.ratings.review_count, with_held.stats.held_reviews_included), (4, true));
+        assert!(review_keys.verify(&encode(&with_held.stats, with_held.format_version).unwrap(), &with_held.signature, Some(review_keys.key_id())));
+
+        let empty = super::stats(&source_identifier, "com.example.Other", false, &pool, &config, &review_keys, &cache).unwrap();
+        assert_eq!(empty.stats.ratings.review_count, 0);
+        assert!(empty.stats.versions.is_empty());
+    }
+}
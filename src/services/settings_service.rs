@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use diesel::Connection as _;
+
+use crate::api::models::settings::{Setting, SettingRequest, SettingsPatchRequest};
+use crate::constants::{
+    MAX_SETTING_KEY_LENGTH, MAX_SETTING_NAMESPACE_LENGTH, MAX_SETTING_VALUE_SIZE, MAX_SETTINGS_PER_PATCH, MAX_SETTINGS_PER_USER,
+    SETTINGS_API_CLOCK_ID,
+};
+use crate::db::{Connection, Pool};
+use crate::db::models::user_setting::{UserSetting, UserSettingsSync};
+use crate::errors::ServiceError;
+use crate::util::vector_clock;
+
+
+/// A change of a setting. Without a value the setting is deleted, with a version it's only changed if it's still
+/// at that version.
+struct Change<'a> {
+    key: &'a str,
+    value: Option<&'a serde_json::Value>,
+    version: Option<i64>,
+}
+
+/// Settings of the user, of all namespaces or of one
+pub fn settings(user_id: &uuid::Uuid, namespace: Option<&str>, pool: &Pool) -> Result<Vec<Setting>, ServiceError> {
+    if let Some(namespace) = namespace {
+        validate_namespace(namespace)?;
+    }
+
+    let settings = UserSetting::find_all_by_user_id(&user_id.to_string(), namespace, &mut pool.get().unwrap())?;
+    Ok(settings.into_iter().map(Setting::from).collect())
+}
+
+pub fn setting(user_id: &uuid::Uuid, namespace: &str, key: &str, pool: &Pool) -> Result<Setting, ServiceError> {
+    validate_namespace(namespace)?;
+    validate_key(key)?;
+
+    UserSetting::find(&user_id.to_string(), namespace, key, &mut pool.get().unwrap())?
+        .map(Setting::from)
+        .ok_or_else(not_found)
+}
+
+/// Set the value of a setting
+pub fn put(user_id: &uuid::Uuid, namespace: &str, key: &str, request: &SettingRequest, pool: &Pool) -> Result<Setting, ServiceError> {
+    if request.value.is_null() {
+        return Err(ServiceError::BadRequest { error_message: "Settings can't be null, delete them instead.".to_string() });
+    }
+
+    let change = Change { key, value: Some(&request.value), version: request.version };
+    let mut saved = pool.get().unwrap().transaction(|conn| apply(user_id, namespace, &[change], conn))?;
+    Ok(saved.remove(0).into())
+}
+
+/// Set or delete several settings of a namespace at once. Returns all settings of the namespace.
+pub fn patch(user_id: &uuid::Uuid, namespace: &str, request: &SettingsPatchRequest, pool: &Pool) -> Result<Vec<Setting>, ServiceError> {
+    if request.settings.is_empty() || request.settings.len() > MAX_SETTINGS_PER_PATCH {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("A patch has to change 1 to {} settings.", MAX_SETTINGS_PER_PATCH),
+        });
+    }
+
+    let changes: Vec<Change> = request.settings.iter()
+        .map(|(key, value)| Change {
+            key,
+            value: value.as_ref().filter(|value| !value.is_null()),
+            version: request.versions.get(key).copied(),
+        })
+        .collect();
+    pool.get().unwrap().transaction(|conn| apply(user_id, namespace, &changes, conn))?;
+    settings(user_id, Some(namespace), pool)
+}
+
+pub fn delete(user_id: &uuid::Uuid, namespace: &str, key: &str, version: Option<i64>, pool: &Pool) -> Result<(), ServiceError> {
+    validate_namespace(namespace)?;
+    validate_key(key)?;
+
+    pool.get().unwrap().transaction(|conn| {
+        if UserSetting::find(&user_id.to_string(), namespace, key, conn)?.is_none() {
+            return Err(not_found());
+        }
+        apply(user_id, namespace, &[Change { key, value: None, version }], conn).map(|_| ())
+    })
+}
+
+/// Delete all settings of a namespace. Returns the number of deleted settings.
+pub fn delete_namespace(user_id: &uuid::Uuid, namespace: &str, pool: &Pool) -> Result<usize, ServiceError> {
+    validate_namespace(namespace)?;
+
+    pool.get().unwrap().transaction(|conn| {
+        let settings = UserSetting::find_all_by_user_id(&user_id.to_string(), Some(namespace), conn)?;
+        let changes: Vec<Change> = settings.iter().map(|setting| Change { key: &setting.key, value: None, version: None }).collect();
+        apply(user_id, namespace, &changes, conn)?;
+        Ok(changes.len())
+    })
+}
+
+/// Make the changes, all or none of them, in a transaction. Values that don't change keep their version. The
+/// changes are counted for devices that sync, as changes of the settings API. Returns the settings that were set.
+fn apply(user_id: &uuid::Uuid, namespace: &str, changes: &[Change], conn: &mut Connection) -> Result<Vec<UserSetting>, ServiceError> {
+    validate_namespace(namespace)?;
+    let values = changes.iter()
+        .map(|change| {
+            validate_key(change.key)?;
+            change.value.map(serialize_value).transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let user_id = user_id.to_string();
+    let mut sync = UserSettingsSync::lock(&user_id, conn)?;
+    let keys: Vec<&str> = changes.iter().map(|change| change.key).collect();
+    let mut current: HashMap<String, UserSetting> = UserSetting::find_all_by_keys_for_update(&user_id, namespace, &keys, conn)?
+        .into_iter()
+        .map(|setting| (setting.key.clone(), setting))
+        .collect();
+
+    let now = Utc::now().naive_utc();
+    let mut saved = vec![];
+    for (change, value) in changes.iter().zip(values) {
+        let mut setting = current.remove(change.key).unwrap_or_else(|| UserSetting::new(&user_id, namespace, change.key, now));
+        let version = if setting.is_deleted() { 0 } else { setting.version };
+        if change.version.is_some_and(|expected| expected != version) {
+            return Err(ServiceError::Conflict {
+                error_message: format!("The setting {} was changed by another device, it's at version {}.", change.key, version),
+            });
+        }
+        if setting.is_deleted() && value.is_none() {
+            continue;
+        }
+        if !setting.is_deleted() && value.as_ref() == Some(&setting.value) {
+            saved.push(setting);
+            continue;
+        }
+
+        let mut clock = setting.vector_clock();
+        vector_clock::increment(&mut clock, SETTINGS_API_CLOCK_ID).ok_or_else(|| ServiceError::Conflict {
+            error_message: format!("The setting {} can't be changed anymore.", change.key),
+        })?;
+        setting.change(value, &clock, sync.next_change_seq(), now);
+        setting.save(conn)?;
+        if !setting.is_deleted() {
+            saved.push(setting);
+        }
+    }
+    sync.update(conn)?;
+
+    if UserSetting::count_by_user_id(&user_id, conn)? > MAX_SETTINGS_PER_USER {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("Users can have at most {} settings.", MAX_SETTINGS_PER_USER),
+        });
+    }
+    Ok(saved)
+}
+
+pub fn serialize_value(value: &serde_json::Value) -> Result<String, ServiceError> {
+    let json = serde_json::to_string(value)
+        .map_err(|_| ServiceError::InternalServerError { error_message: "Failed to serialize the setting".to_string() })?;
+    if json.len() > MAX_SETTING_VALUE_SIZE {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("Settings can have at most {} bytes of JSON.", MAX_SETTING_VALUE_SIZE),
+        });
+    }
+    Ok(json)
+}
+
+/// Namespaces are at least two dot separated parts of lowercase letters, digits, `-` and `_`
+pub fn validate_namespace(namespace: &str) -> Result<(), ServiceError> {
+    let is_valid = namespace.len() <= MAX_SETTING_NAMESPACE_LENGTH
+        && namespace.contains('.')
+        && namespace.split('.').all(|part| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        });
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(ServiceError::BadRequest {
+            error_message: format!("Namespaces have to be dotted lowercase names like sidestore.app with up to {} characters.", MAX_SETTING_NAMESPACE_LENGTH),
+        }),
+    }
+}
+
+/// Keys have ASCII letters, digits, `.`, `-` and `_`
+pub fn validate_key(key: &str) -> Result<(), ServiceError> {
+    let is_valid = !key.is_empty()
+        && key.len() <= MAX_SETTING_KEY_LENGTH
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_');
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(ServiceError::BadRequest {
+            error_message: format!("Keys have to have 1 to {} letters, digits, dots, dashes or underscores.", MAX_SETTING_KEY_LENGTH),
+        }),
+    }
+}
+
+fn not_found() -> ServiceError {
+    ServiceError::NotFound { error_message: "Setting not found".to_string() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::db::test_utils::{create_user, test_pool};
+    use super::*;
+
+    fn put_request(value: serde_json::Value, version: Option<i64>) -> SettingRequest {
+        SettingRequest { value, version }
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(validate_namespace("sidestore.app").is_ok());
+        assert!(validate_namespace("com.example.my-app_2").is_ok());
+        for namespace in ["sidestore", "sidestore..app", ".app", "SideStore.app", "sidestore.app/x", &format!("{}a", "a.".repeat(40))] {
+            assert!(validate_namespace(namespace).is_err(), "{}", namespace);
+        }
+
+        assert!(validate_key("theme").is_ok());
+        assert!(validate_key("Sources.Order-2_a").is_ok());
+        for key in ["", "a b", "a/b", &"a".repeat(MAX_SETTING_KEY_LENGTH + 1)] {
+            assert!(validate_key(key).is_err(), "{}", key);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_settings() {
+        let pool = test_pool();
+        let (user_id, other_user_id) = (create_user(&pool), create_user(&pool));
+
+        // Versions count the changes, unchanged values keep theirs
+        let created = put(&user_id, "sidestore.app", "theme", &put_request(json!("dark"), Some(0)), &pool).unwrap();
+        assert_eq!((created.value.clone(), created.version), (json!("dark"), 1));
+        let changed = put(&user_id, "sidestore.app", "theme", &put_request(json!("light"), None), &pool).unwrap();
+        assert_eq!(changed.version, 2);
+        assert_eq!(put(&user_id, "sidestore.app", "theme", &put_request(json!("light"), Some(2)), &pool).unwrap().version, 2);
+        assert_eq!(setting(&user_id, "sidestore.app", "theme", &pool).unwrap().value, json!("light"));
+        assert!(matches!(setting(&other_user_id, "sidestore.app", "theme", &pool), Err(ServiceError::NotFound { .. })));
+
+        // Changes based on an old version are refused
+        let stale = put(&user_id, "sidestore.app", "theme", &put_request(json!("blue"), Some(1)), &pool);
+        assert!(matches!(stale, Err(ServiceError::Conflict { .. })));
+        assert!(matches!(put(&user_id, "sidestore.app", "theme", &put_request(json!("blue"), Some(0)), &pool), Err(ServiceError::Conflict { .. })));
+        assert!(put(&user_id, "sidestore.app", "theme", &put_request(json!(null), None), &pool).is_err());
+        let too_large = json!("a".repeat(MAX_SETTING_VALUE_SIZE));
+        assert!(matches!(put(&user_id, "sidestore.app", "theme", &put_request(too_large, None), &pool), Err(ServiceError::BadRequest { .. })));
+
+        // Patches change all settings or none
+        let patch_request = |settings: serde_json::Value, versions: serde_json::Value| -> SettingsPatchRequest {
+            serde_json::from_value(json!({ "settings": settings, "versions": versions })).unwrap()
+        };
+        let refused = patch(&user_id, "sidestore.app", &patch_request(json!({ "theme": "blue", "language": "de" }), json!({ "theme": 1 })), &pool);
+        assert!(matches!(refused, Err(ServiceError::Conflict { .. })));
+        assert_eq!(settings(&user_id, Some("sidestore.app"), &pool).unwrap().len(), 1);
+
+        let patched = patch(&user_id, "sidestore.app", &patch_request(json!({ "theme": null, "language": "de", "sources": { "order": ["a", "b"] } }), json!({ "theme": 2 })), &pool).unwrap();
+        let patched: Vec<(&str, i64)> = patched.iter().map(|setting| (setting.key.as_str(), setting.version)).collect();
+        assert_eq!(patched, vec![("language", 1), ("sources", 1)]);
+        assert_eq!(setting(&user_id, "sidestore.app", "sources", &pool).unwrap().value, json!({ "order": ["a", "b"] }));
+
+        put(&user_id, "sidestore.sources", "refresh", &put_request(json!(true), None), &pool).unwrap();
+        let namespaces: Vec<String> = settings(&user_id, None, &pool).unwrap().into_iter().map(|setting| setting.namespace).collect();
+        assert_eq!(namespaces, vec!["sidestore.app", "sidestore.app", "sidestore.sources"]);
+
+        // Deleting
+        assert!(matches!(delete(&user_id, "sidestore.app", "language", Some(2), &pool), Err(ServiceError::Conflict { .. })));
+        delete(&user_id, "sidestore.app", "language", Some(1), &pool).unwrap();
+        assert!(matches!(delete(&user_id, "sidestore.app", "language", None, &pool), Err(ServiceError::NotFound { .. })));
+        assert_eq!(delete_namespace(&user_id, "sidestore.app", &pool).unwrap(), 1);
+        assert_eq!(settings(&user_id, None, &pool).unwrap().len(), 1);
+    }
+}
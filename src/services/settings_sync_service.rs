@@ -0,0 +1,421 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use actix_web::web;
+use chrono::Utc;
+use diesel::Connection as _;
+use log::{error, info};
+
+use crate::api::models::settings::{Device, DeviceRequest, SettingConflict, SettingsSyncRequest, SettingsSyncResponse, SyncedSetting};
+use crate::constants::{
+    DELETED_SETTINGS_RETENTION_DAYS, MAX_DEVICE_NAME_LENGTH, MAX_DEVICES_PER_USER, MAX_SETTING_CLOCK_DEVICES,
+    MAX_SETTING_CLOCK_INCREMENT, MAX_SETTINGS_PER_USER, MAX_SETTINGS_SYNC_CHANGES, MAX_SETTINGS_SYNC_SERVER_CHANGES,
+};
+use crate::db::Pool;
+use crate::db::models::user_device::UserDevice;
+use crate::db::models::user_setting::{UserSetting, UserSettingsSync};
+use crate::errors::ServiceError;
+use crate::services::settings_service::{serialize_value, validate_key, validate_namespace};
+use crate::util::vector_clock::{self, VectorClock};
+
+
+pub fn register_device(user_id: &uuid::Uuid, request: &DeviceRequest, pool: &Pool) -> Result<Device, ServiceError> {
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_DEVICE_NAME_LENGTH {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("Device names have to have 1 to {} characters.", MAX_DEVICE_NAME_LENGTH),
+        });
+    }
+
+    let conn = &mut pool.get().unwrap();
+    if UserDevice::count_by_user_id(&user_id.to_string(), conn)? >= MAX_DEVICES_PER_USER {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("Users can have at most {} devices, delete one first.", MAX_DEVICES_PER_USER),
+        });
+    }
+
+    let device = UserDevice {
+        id: uuid::Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        name: name.to_string(),
+        created_at: Utc::now().naive_utc(),
+        last_synced_at: None,
+    };
+    device.insert(conn)?;
+    Ok(device.into())
+}
+
+pub fn devices(user_id: &uuid::Uuid, pool: &Pool) -> Result<Vec<Device>, ServiceError> {
+    let devices = UserDevice::find_all_by_user_id(&user_id.to_string(), &mut pool.get().unwrap())?;
+    Ok(devices.into_iter().map(Device::from).collect())
+}
+
+pub fn delete_device(user_id: &uuid::Uuid, device_id: &str, pool: &Pool) -> Result<Device, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let device = UserDevice::find(device_id, &user_id.to_string(), conn)?.ok_or_else(device_not_found)?;
+    device.delete(conn)?;
+    Ok(device.into())
+}
+
+/// Apply the local changes of a device and return the changes of the server since the device's last sync.
+///
+/// A local change is applied if its vector clock shows that it was made with knowledge of the setting on the
+/// server. Changes the server already has are skipped. Changes made concurrently with the setting on the server
+/// are conflicts: the server keeps its setting and returns it with a clock that includes the local change, so
+/// the device can keep the server's value or change it again.
+pub fn sync(user_id: &uuid::Uuid, request: &SettingsSyncRequest, pool: &Pool) -> Result<SettingsSyncResponse, ServiceError> {
+    if request.changes.len() > MAX_SETTINGS_SYNC_CHANGES {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("A sync can have at most {} changes.", MAX_SETTINGS_SYNC_CHANGES),
+        });
+    }
+    let change_token = request.change_token.as_deref()
+        .map(|token| token.parse::<i64>().map_err(|_| ServiceError::BadRequest { error_message: "Invalid change token".to_string() }))
+        .transpose()?;
+
+    let mut keys = HashSet::new();
+    let values = request.changes.iter()
+        .map(|change| {
+            validate_namespace(&change.namespace)?;
+            validate_key(&change.key)?;
+            validate_clock(&change.clock, &request.device_id)?;
+            if !keys.insert((change.namespace.as_str(), change.key.as_str())) {
+                return Err(ServiceError::BadRequest { error_message: format!("The setting {} changed twice.", change.key) });
+            }
+            change.value.as_ref().filter(|value| !value.is_null()).map(serialize_value).transpose()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let user_id = user_id.to_string();
+    pool.get().unwrap().transaction(|conn| {
+        let device = UserDevice::find(&request.device_id, &user_id, conn)?.ok_or_else(device_not_found)?;
+        let mut sync = UserSettingsSync::lock(&user_id, conn)?;
+        let now = Utc::now().naive_utc();
+
+        // Settings the device has the server's state of after the sync, which aren't returned as changes
+        let mut known = HashSet::new();
+        let mut stale = vec![];
+        let mut conflicts = vec![];
+        for (change, value) in request.changes.iter().zip(values) {
+            let stored = UserSetting::find_all_by_keys_for_update(&user_id, &change.namespace, &[&change.key], conn)?.pop();
+            let stored_clock = stored.as_ref().map(UserSetting::vector_clock).unwrap_or_default();
+            validate_clock_counts(&change.clock, &request.device_id, stored.is_some().then_some(&stored_clock))?;
+
+            match (vector_clock::compare(&change.clock, &stored_clock), stored) {
+                (Some(Ordering::Greater), None) if value.is_none() => {},
+                (Some(Ordering::Greater), stored) => {
+                    let mut setting = stored.unwrap_or_else(|| UserSetting::new(&user_id, &change.namespace, &change.key, now));
+                    setting.change(value, &change.clock, sync.next_change_seq(), now);
+                    setting.save(conn)?;
+                    known.insert((change.namespace.clone(), change.key.clone()));
+                },
+                // The device missed the setting on the server, which has its change already
+                (Some(Ordering::Less), Some(stored)) => stale.push(stored),
+                (None, Some(stored)) => {
+                    let mut server = SyncedSetting::from(stored);
+                    server.clock = vector_clock::merge(&server.clock, &change.clock);
+                    known.insert((change.namespace.clone(), change.key.clone()));
+                    conflicts.push(SettingConflict { change: change.clone(), server });
+                },
+                _ => {},
+            }
+        }
+        sync.update(conn)?;
+        if UserSetting::count_by_user_id(&user_id, conn)? > MAX_SETTINGS_PER_USER {
+            return Err(ServiceError::BadRequest {
+                error_message: format!("Users can have at most {} settings.", MAX_SETTINGS_PER_USER),
+            });
+        }
+        device.mark_synced(now, conn)?;
+
+        // Devices that didn't sync since deleted settings were forgotten, or that send an unknown token, start over
+        let full_sync = change_token.is_none_or(|token| token < sync.purged_change_seq || token > sync.change_seq);
+        let after = if full_sync { 0 } else { change_token.unwrap_or_default() };
+        let mut changed = UserSetting::find_changed_after(&user_id, after, !full_sync, MAX_SETTINGS_SYNC_SERVER_CHANGES + 1, conn)?;
+        let has_more = changed.len() as i64 > MAX_SETTINGS_SYNC_SERVER_CHANGES;
+        changed.truncate(MAX_SETTINGS_SYNC_SERVER_CHANGES as usize);
+        let change_token = match has_more {
+            true => changed.last().map_or(after, |setting| setting.change_seq),
+            false => sync.change_seq,
+        };
+
+        let changes = changed.into_iter()
+            .chain(stale)
+            .filter(|setting| known.insert((setting.namespace.clone(), setting.key.clone())))
+            .map(SyncedSetting::from)
+            .collect();
+
+        Ok(SettingsSyncResponse {
+            change_token: change_token.to_string(),
+            full_sync,
+            has_more,
+            changes,
+            conflicts,
+        })
+    })
+}
+
+pub async fn purge_deleted_periodically(pool: Pool, interval: Duration) {
+    let mut interval = actix_web::rt::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        let deleted_before = Utc::now().naive_utc() - chrono::Duration::days(DELETED_SETTINGS_RETENTION_DAYS);
+        match web::block(move || purge_deleted(deleted_before, &pool)).await {
+            Ok(Ok(0)) => {},
+            Ok(Ok(purged)) => info!("Forgot {} deleted settings", purged),
+            Ok(Err(e)) => error!("Failed to forget deleted settings: {:?}", e),
+            Err(e) => error!("Failed to forget deleted settings: {:?}", e),
+        }
+    }
+}
+
+/// Forget the settings that were deleted before the time. Devices that last synced before their deletion have to
+/// sync everything again. Returns the number of forgotten settings.
+pub fn purge_deleted(deleted_before: chrono::NaiveDateTime, pool: &Pool) -> Result<usize, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let mut purged = 0;
+    for user_id in UserSetting::find_user_ids_deleted_before(deleted_before, conn)? {
+        purged += conn.transaction::<_, ServiceError, _>(|conn| {
+            let mut sync = UserSettingsSync::lock(&user_id, conn)?;
+            let change_seqs = UserSetting::purge_deleted_before(&user_id, deleted_before, conn)?;
+            sync.purged_change_seq = change_seqs.iter().copied().fold(sync.purged_change_seq, i64::max);
+            sync.update(conn)?;
+            Ok(change_seqs.len())
+        })?;
+    }
+    Ok(purged)
+}
+
+/// Clocks count the change on the device that sends it, and only count devices so far
+fn validate_clock(clock: &VectorClock, device_id: &str) -> Result<(), ServiceError> {
+    let is_valid = clock.get(device_id).is_some_and(|count| *count > 0)
+        && clock.len() <= MAX_SETTING_CLOCK_DEVICES
+        && clock.values().all(|count| *count >= 0);
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(ServiceError::BadRequest {
+            error_message: format!("The clock of a change has to count the change on the device and at most {} devices.", MAX_SETTING_CLOCK_DEVICES),
+        }),
+    }
+}
+
+/// Clocks only count changes of other devices the server knows of, and a limited number of changes on the device
+/// since the server's clock. Settings that were forgotten after their deletion have no clock to compare to, the
+/// counts of their changes are only capped.
+fn validate_clock_counts(clock: &VectorClock, device_id: &str, stored_clock: Option<&VectorClock>) -> Result<(), ServiceError> {
+    let is_valid = clock.iter().all(|(device, count)| {
+        let stored_count = stored_clock.and_then(|stored| stored.get(device)).copied().unwrap_or(0);
+        match stored_clock.is_none() || device == device_id {
+            true => *count - stored_count <= MAX_SETTING_CLOCK_INCREMENT,
+            false => *count <= stored_count,
+        }
+    });
+
+    match is_valid {
+        true => Ok(()),
+        false => Err(ServiceError::BadRequest {
+            error_message: "The clock of a change counts changes the server doesn't know of.".to_string(),
+        }),
+    }
+}
+
+fn device_not_found() -> ServiceError {
+    ServiceError::NotFound { error_message: "Device not found".to_string() }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::thread;
+
+    use serde_json::json;
+
+    use crate::api::models::settings::{SettingChange, SettingRequest};
+    use crate::constants::SETTINGS_API_CLOCK_ID;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::settings_service;
+    use super::*;
+
+    const NAMESPACE: &str = "sidestore.app";
+
+    /// A device that keeps its settings with their clocks and syncs its local changes like an app would
+    struct TestDevice {
+        id: String,
+        user_id: uuid::Uuid,
+        change_token: Option<String>,
+        /// Values by key, `None` if deleted, with the clocks of their latest changes
+        settings: BTreeMap<String, (Option<serde_json::Value>, VectorClock)>,
+        changed: Vec<String>,
+    }
+
+    impl TestDevice {
+        fn register(user_id: uuid::Uuid, name: &str, pool: &Pool) -> Self {
+            let device = register_device(&user_id, &DeviceRequest { name: name.to_string() }, pool).unwrap();
+            TestDevice { id: device.id, user_id, change_token: None, settings: BTreeMap::new(), changed: vec![] }
+        }
+
+        fn set(&mut self, key: &str, value: Option<serde_json::Value>) {
+            let (_, mut clock) = self.settings.remove(key).unwrap_or_default();
+            vector_clock::increment(&mut clock, &self.id).unwrap();
+            self.settings.insert(key.to_string(), (value, clock));
+            self.changed.push(key.to_string());
+        }
+
+        fn value(&self, key: &str) -> Option<serde_json::Value> {
+            self.settings.get(key).and_then(|(value, _)| value.clone())
+        }
+
+        /// Send the local changes and take the server's changes. Conflicts keep the server's value.
+        fn sync(&mut self, pool: &Pool) -> SettingsSyncResponse {
+            let request = SettingsSyncRequest {
+                device_id: self.id.clone(),
+                change_token: self.change_token.clone(),
+                changes: std::mem::take(&mut self.changed).into_iter()
+                    .map(|key| SettingChange {
+                        namespace: NAMESPACE.to_string(),
+                        value: self.settings[&key].0.clone(),
+                        clock: self.settings[&key].1.clone(),
+                        key,
+                    })
+                    .collect(),
+            };
+            let response = sync(&self.user_id, &request, pool).unwrap();
+
+            if response.full_sync {
+                let sent: HashSet<&str> = request.changes.iter().map(|change| change.key.as_str()).collect();
+                self.settings.retain(|key, _| sent.contains(key.as_str()));
+            }
+            let server = response.changes.iter().chain(response.conflicts.iter().map(|conflict| &conflict.server));
+            for setting in server.filter(|setting| setting.namespace == NAMESPACE) {
+                self.settings.insert(setting.key.clone(), (setting.value.clone(), setting.clock.clone()));
+            }
+            self.change_token = Some(response.change_token.clone());
+            response
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_devices() {
+        let pool = test_pool();
+        let (user_id, other_user_id) = (create_user(&pool), create_user(&pool));
+
+        assert!(register_device(&user_id, &DeviceRequest { name: " ".to_string() }, &pool).is_err());
+        let device = register_device(&user_id, &DeviceRequest { name: " iPhone ".to_string() }, &pool).unwrap();
+        assert_eq!(device.name, "iPhone");
+        assert_eq!(devices(&user_id, &pool).unwrap().len(), 1);
+        assert!(devices(&other_user_id, &pool).unwrap().is_empty());
+
+        // Devices only sync the settings of their user, with clocks that count their changes
+        let request = |device_id: &str, clock: VectorClock| SettingsSyncRequest {
+            device_id: device_id.to_string(),
+            change_token: None,
+            changes: vec![SettingChange { namespace: NAMESPACE.to_string(), key: "theme".to_string(), value: Some(json!("dark")), clock }],
+        };
+        let clock = |device_id: &str| VectorClock::from([(device_id.to_string(), 1)]);
+        assert!(matches!(sync(&other_user_id, &request(&device.id, clock(&device.id)), &pool), Err(ServiceError::NotFound { .. })));
+        assert!(matches!(sync(&user_id, &request(&device.id, clock("another device")), &pool), Err(ServiceError::BadRequest { .. })));
+        assert!(matches!(sync(&user_id, &SettingsSyncRequest { change_token: Some("token".to_string()), ..request(&device.id, clock(&device.id)) }, &pool), Err(ServiceError::BadRequest { .. })));
+        sync(&user_id, &request(&device.id, clock(&device.id)), &pool).unwrap();
+        assert!(devices(&user_id, &pool).unwrap()[0].last_synced_at.is_some());
+
+        // Clocks can't count changes of other devices the server doesn't know of, or skip too far on the device
+        let counts = |counts: &[(&str, i64)]| counts.iter().map(|(device_id, count)| (device_id.to_string(), *count)).collect::<VectorClock>();
+        let unknown_change = counts(&[(&device.id, 2), ("another device", 1)]);
+        assert!(matches!(sync(&user_id, &request(&device.id, unknown_change), &pool), Err(ServiceError::BadRequest { .. })));
+        assert!(matches!(sync(&user_id, &request(&device.id, counts(&[(&device.id, i64::MAX)])), &pool), Err(ServiceError::BadRequest { .. })));
+        sync(&user_id, &request(&device.id, counts(&[(&device.id, 1 + MAX_SETTING_CLOCK_INCREMENT)])), &pool).unwrap();
+
+        assert!(delete_device(&other_user_id, &device.id, &pool).is_err());
+        delete_device(&user_id, &device.id, &pool).unwrap();
+        assert!(matches!(sync(&user_id, &request(&device.id, clock(&device.id)), &pool), Err(ServiceError::NotFound { .. })));
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_three_devices() {
+        let pool = test_pool();
+        let user_id = create_user(&pool);
+        let mut phone = TestDevice::register(user_id, "Phone", &pool);
+        let mut tablet = TestDevice::register(user_id, "Tablet", &pool);
+        let mut laptop = TestDevice::register(user_id, "Laptop", &pool);
+
+        phone.set("theme", Some(json!("dark")));
+        phone.set("language", Some(json!("en")));
+        assert!(phone.sync(&pool).changes.is_empty());
+        for device in [&mut tablet, &mut laptop] {
+            let response = device.sync(&pool);
+            assert!(response.full_sync);
+            assert_eq!((device.value("theme"), device.value("language")), (Some(json!("dark")), Some(json!("en"))));
+        }
+
+        // Two devices change the theme concurrently while the third changes the language, all syncing at once
+        tablet.set("theme", Some(json!("light")));
+        laptop.set("theme", Some(json!("blue")));
+        phone.set("language", Some(json!("de")));
+        let responses: Vec<SettingsSyncResponse> = thread::scope(|scope| {
+            let syncs: Vec<_> = [&mut tablet, &mut laptop, &mut phone].into_iter()
+                .map(|device| {
+                    let pool = pool.clone();
+                    scope.spawn(move || device.sync(&pool))
+                })
+                .collect();
+            syncs.into_iter().map(|sync| sync.join().unwrap()).collect()
+        });
+
+        // The theme of the device that synced first is kept, the other device's change is a conflict
+        assert!(!responses[2].full_sync && responses[2].conflicts.is_empty());
+        let conflicts: Vec<&SettingConflict> = responses[..2].iter().flat_map(|response| &response.conflicts).collect();
+        assert_eq!(conflicts.len(), 1);
+        let (winner, loser) = match responses[0].conflicts.is_empty() {
+            true => (&mut tablet, &mut laptop),
+            false => (&mut laptop, &mut tablet),
+        };
+        let (winning_theme, losing_theme) = (winner.value("theme").unwrap(), conflicts[0].change.value.clone().unwrap());
+        assert_ne!(winning_theme, losing_theme);
+        assert_eq!(conflicts[0].server.value, Some(winning_theme.clone()));
+        assert_eq!(loser.value("theme"), Some(winning_theme.clone()));
+
+        // Replaying a change the server has already doesn't overwrite newer changes
+        let replayed = SettingsSyncRequest { device_id: loser.id.clone(), change_token: loser.change_token.clone(), changes: vec![conflicts[0].change.clone()] };
+        assert_eq!(sync(&user_id, &replayed, &pool).unwrap().conflicts.len(), 1);
+        let old_change = SettingChange { value: Some(json!("stale")), clock: VectorClock::from([(phone.id.clone(), 1)]), ..conflicts[0].change.clone() };
+        let stale = sync(&user_id, &SettingsSyncRequest { device_id: phone.id.clone(), change_token: phone.change_token.clone(), changes: vec![old_change] }, &pool).unwrap();
+        assert_eq!(stale.changes.iter().find(|setting| setting.key == "theme").unwrap().value, Some(winning_theme.clone()));
+
+        // The device that lost picks its own theme again, based on the server's, which replaces it everywhere
+        loser.set("theme", Some(losing_theme.clone()));
+        assert!(loser.sync(&pool).conflicts.is_empty());
+        for device in [&mut phone, &mut tablet, &mut laptop] {
+            assert!(device.sync(&pool).conflicts.is_empty());
+            assert_eq!((device.value("theme"), device.value("language")), (Some(losing_theme.clone()), Some(json!("de"))));
+        }
+
+        // Deletions and changes with the settings API are synced too
+        let token_before_deletion = tablet.change_token.clone();
+        tablet.set("language", None);
+        tablet.sync(&pool);
+        let put = SettingRequest { value: json!("system"), version: None };
+        settings_service::put(&user_id, NAMESPACE, "theme", &put, &pool).unwrap();
+        for device in [&mut phone, &mut laptop] {
+            let response = device.sync(&pool);
+            assert_eq!(response.changes.len(), 2);
+            assert_eq!((device.value("theme"), device.value("language")), (Some(json!("system")), None));
+            assert_eq!(device.settings["theme"].1[SETTINGS_API_CLOCK_ID], 1);
+        }
+        assert!(settings_service::setting(&user_id, NAMESPACE, "language", &pool).is_err());
+
+        // Devices that didn't sync since deleted settings were forgotten have to sync everything again
+        purge_deleted(Utc::now().naive_utc() + chrono::Duration::days(1), &pool).unwrap();
+        let mut outdated = TestDevice { change_token: token_before_deletion, ..TestDevice::register(user_id, "Outdated", &pool) };
+        let response = outdated.sync(&pool);
+        assert!(response.full_sync);
+        assert_eq!(response.changes.iter().map(|setting| setting.key.as_str()).collect::<Vec<_>>(), vec!["theme"]);
+        assert!(!phone.sync(&pool).full_sync);
+    }
+}
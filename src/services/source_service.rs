@@ -0,0 +1,479 @@
+use std::io::Read;
+use std::time::Duration;
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as base64_engine};
+use chrono::Utc;
+use diesel::Connection as _;
+use log::{debug, info};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use url::Url;
+
+use crate::api::models::sources::{
+    SourceClaim, SourceClaimRequest, SourceReview, SourceReviewsQuery, SourceSettings, SourceStatsQuery, SourceVerificationMethod,
+    SourceVerificationRequest,
+};
+use crate::api::models::review_replies::ReviewReply;
+use crate::api::models::review_stats::ReviewStats;
+use crate::config::Config;
+use crate::constants::{
+    MAX_SOURCE_VERIFICATION_RESPONSE_SIZE, SOURCE_VERIFICATION_FIELD, SOURCE_VERIFICATION_TIMEOUT_SECONDS,
+    SOURCE_VERIFICATION_WELL_KNOWN_PATH,
+};
+use crate::db::Pool;
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::app_review_reply::AppReviewReply;
+use crate::db::models::source::{Source, SourceMaintainer};
+use crate::errors::ServiceError;
+use crate::services::review_stats_service::{self, ReviewStatsCache};
+use crate::util::network::{check_public_host, public_resolver};
+use crate::util::review_signing::ReviewKeyRing;
+
+
+/// Claim to maintain a source. The claim keeps its challenge until it is verified, claiming the
+/// source with another URL starts over with a new challenge. Once a source is verified, it can only
+/// be claimed with URLs at the origin it was verified at.
+pub fn claim(request: &SourceClaimRequest, user_id: &uuid::Uuid, pool: &Pool, config: &Config) -> Result<SourceClaim, ServiceError> {
+    if request.source_identifier.trim().is_empty() {
+        return Err(ServiceError::BadRequest { error_message: "The source identifier can't be empty.".to_string() });
+    }
+    let source_url = parse_source_url(&request.source_url, config)?;
+
+    let conn = &mut pool.get().unwrap();
+    check_origin(Source::find_by_id(&request.source_identifier, conn)?.as_ref(), &source_url)?;
+    let claim = match SourceMaintainer::find_by_user_id(&request.source_identifier, user_id, conn)? {
+        Some(claim) if claim.source_url == source_url.as_str() => claim,
+        Some(mut claim) => {
+            debug!("User {} claims source {} with a new URL", user_id, claim.source_id);
+            claim.source_url = source_url.to_string();
+            claim.challenge = new_challenge();
+            claim.method = None;
+            claim.verified_at = None;
+            claim.update(conn)?
+        },
+        None => {
+            let claim = SourceMaintainer::new(&request.source_identifier, user_id, source_url.as_str(), &new_challenge());
+            claim.insert(conn)?;
+            claim
+        },
+    };
+    Ok(source_claim(claim))
+}
+
+/// Verify the user's claim to maintain a source. The source's JSON has to have the claimed identifier, and
+/// the challenge has to be in its verification field or in the well-known file at the source's origin. The
+/// first verified claim binds the identifier to its origin, so nobody can claim it with a copy of the source
+/// at another origin.
+pub fn verify(request: &SourceVerificationRequest, user_id: &uuid::Uuid, pool: &Pool, config: &Config) -> Result<SourceClaim, ServiceError> {
+    let mut claim = SourceMaintainer::find_by_user_id(&request.source_identifier, user_id, &mut pool.get().unwrap())?
+        .ok_or(ServiceError::NotFound { error_message: "You didn't claim this source yet.".to_string() })?;
+    if claim.is_verified() {
+        return Ok(source_claim(claim));
+    }
+    let source_url = parse_source_url(&claim.source_url, config)?;
+    check_origin(Source::find_by_id(&claim.source_id, &mut pool.get().unwrap())?.as_ref(), &source_url)?;
+
+    // The connection isn't held while the source is fetched
+    let method = check_challenge(&claim, config).map_err(|reason| ServiceError::BadRequest {
+        error_message: format!("The claim couldn't be verified: {}", reason),
+    })?;
+
+    let conn = &mut pool.get().unwrap();
+    let claim = conn.transaction(|conn| {
+        claim.method = Some(method.into());
+        claim.verified_at = Some(Utc::now().naive_utc());
+        let claim = claim.update(conn)?;
+        Source::insert_default(&claim.source_id, conn)?;
+        // Another claim could have been verified at another origin in the meantime
+        check_origin(Some(&Source::bind_origin(&claim.source_id, &origin(&source_url), conn)?), &source_url)?;
+        Ok::<_, ServiceError>(claim)
+    })?;
+
+    info!("User {} verified as maintainer of source {}", user_id, claim.source_id);
+    Ok(source_claim(claim))
+}
+
+/// The user's claims, oldest first
+pub fn claims(user_id: &uuid::Uuid, pool: &Pool) -> Result<Vec<SourceClaim>, ServiceError> {
+    Ok(SourceMaintainer::find_all_by_user_id(user_id, &mut pool.get().unwrap())?
+        .into_iter()
+        .map(source_claim)
+        .collect())
+}
+
+/// Fail unless the user is a verified maintainer of the source
+pub fn require_maintainer(source_id: &str, user_id: &uuid::Uuid, pool: &Pool) -> Result<(), ServiceError> {
+    match SourceMaintainer::find_by_user_id(source_id, user_id, &mut pool.get().unwrap())? {
+        Some(claim) if claim.is_verified() => Ok(()),
+        _ => Err(ServiceError::Unauthorized { error_message: "Only verified maintainers of the source can do this.".to_string() }),
+    }
+}
+
+/// Reviews of the source's apps with their status and replies, ordered by app and sequence number
+pub fn reviews(query: &SourceReviewsQuery, pool: &Pool) -> Result<Vec<SourceReview>, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let app_bundle_ids = match &query.app_bundle_id {
+        Some(app_bundle_id) => vec![app_bundle_id.clone()],
+        None => AppReviewSignature::find_app_bundle_ids(&query.source_identifier, conn)?,
+    };
+
+    let mut reviews = Vec::new();
+    for app_bundle_id in app_bundle_ids {
+        let app_reviews = AppReviewSignature::find_all_by_app(&query.source_identifier, &app_bundle_id, None, conn)?;
+        let review_ids: Vec<String> = app_reviews.iter().map(|review| review.id.clone()).collect();
+        let replies = AppReviewReply::find_published_by_review_ids(&review_ids, conn)?;
+        reviews.extend(app_reviews.iter()
+            .map(|review| SourceReview::new(review, replies.get(&review.id).map(ReviewReply::from))));
+    }
+    Ok(reviews)
+}
+
+/// Signed rating statistics of every reviewed app of the source
+pub fn stats(query: &SourceStatsQuery, pool: &Pool, config: &Config, review_keys: &ReviewKeyRing, cache: &ReviewStatsCache) -> Result<Vec<ReviewStats>, ServiceError> {
+    AppReviewSignature::find_app_bundle_ids(&query.source_identifier, &mut pool.get().unwrap())?
+        .iter()
+        .map(|app_bundle_id| review_stats_service::stats(&query.source_identifier, app_bundle_id, query.include_held, pool, config, review_keys, cache))
+        .collect()
+}
+
+pub fn settings(source_id: &str, pool: &Pool) -> Result<SourceSettings, ServiceError> {
+    Ok(SourceSettings {
+        source_identifier: source_id.to_string(),
+        accepts_reviews: Source::accepts_reviews(source_id, &mut pool.get().unwrap())?,
+    })
+}
+
+pub fn update_settings(request: &SourceSettings, pool: &Pool) -> Result<SourceSettings, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    let mut source = Source::insert_default(&request.source_identifier, conn)?;
+    source.accepts_reviews = request.accepts_reviews;
+    let source = source.update(conn)?;
+
+    info!("Source {} {} reviews", source.id, if source.accepts_reviews { "accepts" } else { "doesn't accept" });
+    Ok(SourceSettings { source_identifier: source.id, accepts_reviews: source.accepts_reviews })
+}
+
+
+/// Look for the claim's challenge at the source's URL. The error is a reason for the maintainer.
+fn check_challenge(claim: &SourceMaintainer, config: &Config) -> Result<SourceVerificationMethod, String> {
+    let source_url = parse_source_url(&claim.source_url, config).map_err(|e| e.to_string())?;
+    let mut agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(SOURCE_VERIFICATION_TIMEOUT_SECONDS))
+        .https_only(!config.source_verification_allow_http);
+    if !config.allow_private_network_addresses {
+        agent = agent.resolver(public_resolver);
+    }
+    let agent = agent.build();
+
+    let source: serde_json::Value = serde_json::from_str(&fetch(&agent, &source_url)?)
+        .map_err(|_| format!("{} isn't a source's JSON.", source_url))?;
+    if source.get("identifier").and_then(|identifier| identifier.as_str()) != Some(claim.source_id.as_str()) {
+        return Err(format!("The identifier of the source at {} isn't {}.", source_url, claim.source_id));
+    }
+
+    let in_source = match source.get(SOURCE_VERIFICATION_FIELD) {
+        Some(serde_json::Value::String(challenge)) => *challenge == claim.challenge,
+        Some(serde_json::Value::Array(challenges)) => challenges.iter().any(|challenge| challenge.as_str() == Some(&claim.challenge)),
+        _ => false,
+    };
+    if in_source {
+        return Ok(SourceVerificationMethod::SourceField);
+    }
+
+    let well_known_url = well_known_url(&source_url);
+    match fetch(&agent, &well_known_url) {
+        Ok(file) if file.lines().any(|line| line.trim() == claim.challenge) => Ok(SourceVerificationMethod::WellKnownFile),
+        _ => Err(format!("The challenge is neither in the {} field of the source nor in {}.", SOURCE_VERIFICATION_FIELD, well_known_url)),
+    }
+}
+
+fn fetch(agent: &ureq::Agent, url: &Url) -> Result<String, String> {
+    let response = agent.request_url("GET", url).call().map_err(|e| {
+        debug!("Failed to fetch {}: {}", url, e);
+        match e {
+            ureq::Error::Status(status, _) => format!("{} responded with status {}.", url, status),
+            ureq::Error::Transport(_) => format!("{} couldn't be fetched.", url),
+        }
+    })?;
+
+    let mut body = String::new();
+    response.into_reader()
+        .take(MAX_SOURCE_VERIFICATION_RESPONSE_SIZE + 1)
+        .read_to_string(&mut body)
+        .map_err(|_| format!("{} couldn't be read as text.", url))?;
+    if body.len() as u64 > MAX_SOURCE_VERIFICATION_RESPONSE_SIZE {
+        return Err(format!("{} is larger than {} bytes.", url, MAX_SOURCE_VERIFICATION_RESPONSE_SIZE));
+    }
+    Ok(body)
+}
+
+fn parse_source_url(source_url: &str, config: &Config) -> Result<Url, ServiceError> {
+    let invalid = |reason: &str| ServiceError::BadRequest { error_message: format!("Invalid source URL: {}", reason) };
+
+    let url = Url::parse(source_url).map_err(|e| invalid(&e.to_string()))?;
+    match url.scheme() {
+        "https" => {},
+        "http" if config.source_verification_allow_http => {},
+        _ => return Err(invalid("sources have to be served over HTTPS")),
+    }
+    if url.host().is_none() {
+        return Err(invalid("the URL has no host"));
+    }
+    if !config.allow_private_network_addresses {
+        check_public_host(&url).map_err(|reason| invalid(&reason))?;
+    }
+    Ok(url)
+}
+
+fn origin(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Fail if the source was verified at another origin than the URL's
+fn check_origin(source: Option<&Source>, source_url: &Url) -> Result<(), ServiceError> {
+    match source.and_then(|source| source.origin.as_ref()) {
+        Some(source_origin) if *source_origin != origin(source_url) => Err(ServiceError::BadRequest {
+            error_message: format!("The source is verified at {}, claim it with its URL there.", source_origin),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn well_known_url(source_url: &Url) -> Url {
+    let mut url = source_url.clone();
+    url.set_path(SOURCE_VERIFICATION_WELL_KNOWN_PATH);
+    url.set_query(None);
+    url.set_fragment(None);
+    url
+}
+
+fn new_challenge() -> String {
+    let mut challenge = [0u8; 32];
+    OsRng.fill_bytes(&mut challenge);
+    format!("sidestore-id-{}", base64_engine.encode(challenge))
+}
+
+fn source_claim(claim: SourceMaintainer) -> SourceClaim {
+    let well_known_url = Url::parse(&claim.source_url).ok().map(|url| well_known_url(&url).to_string());
+    SourceClaim::new(claim, well_known_url)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use ed25519_dalek::SigningKey;
+
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use super::*;
+
+    /// HTTP server on a local port that serves the bodies of its paths, and 404 for all other paths
+    #[derive(Clone)]
+    struct StubServer {
+        url: String,
+        files: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    impl StubServer {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let server = StubServer {
+                url: format!("http://{}", listener.local_addr().unwrap()),
+                files: Arc::default(),
+            };
+
+            let files = server.files.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else { continue };
+                    let mut request_line = String::new();
+                    let mut reader = BufReader::new(&mut stream);
+                    if reader.read_line(&mut request_line).is_err() {
+                        continue;
+                    }
+                    // Skip the headers
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                        line.clear();
+                    }
+
+                    let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+                    let path = target.split('?').next().unwrap_or_default().to_string();
+                    let response = match files.lock().unwrap().get(&path) {
+                        Some(body) => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body),
+                        None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                    };
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            });
+            server
+        }
+
+        fn serve(&self, path: &str, body: &str) {
+            self.files.lock().unwrap().insert(path.to_string(), body.to_string());
+        }
+    }
+
+    fn claim_request(source_identifier: &str, server: &StubServer) -> SourceClaimRequest {
+        SourceClaimRequest {
+            source_identifier: source_identifier.to_string(),
+            source_url: format!("{}/apps.json?channel=stable", server.url),
+        }
+    }
+
+    #[test]
+    fn test_source_urls() {
+        let mut config = Config::for_tests();
+        assert!(parse_source_url("https://example.com/apps.json", &config).is_ok());
+        assert!(parse_source_url("http://localhost:8000/apps.json", &config).is_ok());
+        assert!(parse_source_url("file:///etc/passwd", &config).is_err());
+        assert!(parse_source_url("apps.json", &config).is_err());
+        config.source_verification_allow_http = false;
+        assert!(parse_source_url("http://example.com/apps.json", &config).is_err());
+
+        // Sources can't be fetched from the service's own network
+        config.source_verification_allow_http = true;
+        config.allow_private_network_addresses = false;
+        for url in ["http://localhost:8000/apps.json", "http://127.0.0.1/apps.json", "http://10.0.0.1/apps.json", "http://169.254.169.254/latest", "http://[::1]/apps.json"] {
+            assert!(parse_source_url(url, &config).is_err(), "{}", url);
+        }
+        assert!(parse_source_url("https://1.1.1.1/apps.json", &config).is_ok());
+
+        let url = Url::parse("https://example.com/sources/apps.json?channel=beta#apps").unwrap();
+        assert_eq!(well_known_url(&url).as_str(), "https://example.com/.well-known/sidestore-id-source-verification");
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_verify_claims() {
+        let pool = test_pool();
+        let config = Config::for_tests();
+        let server = StubServer::start();
+        let source_identifier = format!("com.example.{}", uuid::Uuid::new_v4());
+        let (maintainer_id, other_id, impostor_id) = (create_user(&pool), create_user(&pool), create_user(&pool));
+
+        // The challenge in the source's JSON
+        let claim = claim(&claim_request(&source_identifier, &server), &maintainer_id, &pool, &config).unwrap();
+        assert!(!claim.verified);
+        assert_eq!(claim.well_known_url, Some(format!("{}{}", server.url, SOURCE_VERIFICATION_WELL_KNOWN_PATH)));
+        let verification_request = SourceVerificationRequest { source_identifier: source_identifier.clone() };
+        assert!(verify(&verification_request, &maintainer_id, &pool, &config).is_err());
+        assert!(require_maintainer(&source_identifier, &maintainer_id, &pool).is_err());
+
+        server.serve("/apps.json", &serde_json::json!({
+            "name": "Example",
+            "identifier": source_identifier,
+            SOURCE_VERIFICATION_FIELD: [claim.challenge],
+        }).to_string());
+        let verified = verify(&verification_request, &maintainer_id, &pool, &config).unwrap();
+        assert!(verified.verified);
+        assert_eq!(verified.verification_method, Some(String::from(SourceVerificationMethod::SourceField)));
+        assert_eq!(verified.challenge, claim.challenge);
+        require_maintainer(&source_identifier, &maintainer_id, &pool).unwrap();
+
+        // Claiming again keeps the verified claim
+        let claimed_again = super::claim(&claim_request(&source_identifier, &server), &maintainer_id, &pool, &config).unwrap();
+        assert!(claimed_again.verified);
+
+        // The challenge in the well-known file, next to other maintainers' challenges
+        let other_claim = super::claim(&claim_request(&source_identifier, &server), &other_id, &pool, &config).unwrap();
+        assert_ne!(other_claim.challenge, claim.challenge);
+        server.serve(SOURCE_VERIFICATION_WELL_KNOWN_PATH, &format!("{}\n{}\n", claim.challenge, other_claim.challenge));
+        let verified = verify(&verification_request, &other_id, &pool, &config).unwrap();
+        assert_eq!(verified.verification_method, Some(String::from(SourceVerificationMethod::WellKnownFile)));
+
+        // A source with another identifier doesn't verify the claim, even with the challenge
+        let impostor_claim = super::claim(&claim_request("com.example.other", &server), &impostor_id, &pool, &config).unwrap();
+        server.serve(SOURCE_VERIFICATION_WELL_KNOWN_PATH, &impostor_claim.challenge);
+        let request = SourceVerificationRequest { source_identifier: "com.example.other".to_string() };
+        assert!(matches!(verify(&request, &impostor_id, &pool, &config), Err(ServiceError::BadRequest { .. })));
+
+        // Only the user's own claims are listed
+        let claims = claims(&other_id, &pool).unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].source_identifier, source_identifier);
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_impostor_claims() {
+        let pool = test_pool();
+        let config = Config::for_tests();
+        let (server, impostor_server) = (StubServer::start(), StubServer::start());
+        let source_identifier = format!("com.example.{}", uuid::Uuid::new_v4());
+        let (maintainer_id, impostor_id) = (create_user(&pool), create_user(&pool));
+        let source = |challenge: &str| serde_json::json!({
+            "identifier": source_identifier,
+            SOURCE_VERIFICATION_FIELD: challenge,
+        }).to_string();
+
+        // Both claim the identifier before it's verified
+        let claim = claim(&claim_request(&source_identifier, &server), &maintainer_id, &pool, &config).unwrap();
+        let impostor_claim = super::claim(&claim_request(&source_identifier, &impostor_server), &impostor_id, &pool, &config).unwrap();
+        server.serve("/apps.json", &source(&claim.challenge));
+        let request = SourceVerificationRequest { source_identifier: source_identifier.clone() };
+        verify(&request, &maintainer_id, &pool, &config).unwrap();
+
+        // A copy of the source with the same identifier at another origin doesn't verify the impostor
+        impostor_server.serve("/apps.json", &source(&impostor_claim.challenge));
+        assert!(matches!(verify(&request, &impostor_id, &pool, &config), Err(ServiceError::BadRequest { .. })));
+        assert!(require_maintainer(&source_identifier, &impostor_id, &pool).is_err());
+        assert!(matches!(
+            super::claim(&claim_request(&source_identifier, &impostor_server), &impostor_id, &pool, &config),
+            Err(ServiceError::BadRequest { .. })
+        ));
+
+        // Other maintainers claim it at its origin
+        let impostor_claim = super::claim(&claim_request(&source_identifier, &server), &impostor_id, &pool, &config).unwrap();
+        assert!(!impostor_claim.verified);
+    }
+
+    #[test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    fn test_source_settings() {
+        let pool = test_pool();
+        let config = Config::for_tests();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let user_id = create_user(&pool);
+
+        // Sources without maintainers accept reviews
+        assert!(settings(&source_identifier, &pool).unwrap().accepts_reviews);
+        app_review_service::sign(&review_request(&source_identifier), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+
+        let mut request = review_request(&source_identifier);
+        request.app_bundle_id = "com.example.Other".to_string();
+        app_review_service::sign(&request, &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+
+        let reviews = reviews(&SourceReviewsQuery { source_identifier: source_identifier.clone(), app_bundle_id: None }, &pool).unwrap();
+        assert_eq!(reviews.iter().map(|review| review.app_bundle_identifier.as_str()).collect::<Vec<_>>(), ["com.example.App", "com.example.Other"]);
+        let stats = stats(&SourceStatsQuery { source_identifier: source_identifier.clone(), include_held: false }, &pool, &config, &review_keys, &ReviewStatsCache::default()).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert!(stats.iter().all(|stats| stats.stats.ratings.review_count == 1));
+
+        update_settings(&SourceSettings { source_identifier: source_identifier.clone(), accepts_reviews: false }, &pool).unwrap();
+        assert!(!settings(&source_identifier, &pool).unwrap().accepts_reviews);
+        assert!(matches!(
+            app_review_service::sign(&review_request(&source_identifier), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()),
+            Err(ServiceError::BadRequest { .. })
+        ));
+
+        // Reviews can still be deleted
+        let deletion = crate::api::models::app_reviews::AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: None,
+        };
+        app_review_service::delete(&deletion, &user_id, &pool, &config, &review_keys).unwrap();
+
+        update_settings(&SourceSettings { source_identifier: source_identifier.clone(), accepts_reviews: true }, &pool).unwrap();
+        app_review_service::sign(&review_request(&source_identifier), &user_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+    }
+}
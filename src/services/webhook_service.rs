@@ -0,0 +1,637 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::web;
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD as base64_url_engine};
+use chrono::{NaiveDateTime, Utc};
+use diesel::Connection as _;
+use hmac::{Hmac, Mac};
+use log::{debug, error, info, warn};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use sidestore_id_core::payload::encode;
+use url::Url;
+
+use crate::api::models::app_reviews::{review_data, AppReviewStatus};
+use crate::api::models::webhooks::{
+    Webhook, WebhookAccountData, WebhookDeliveriesQuery, WebhookDeliveryRecord, WebhookDeliveryStatus, WebhookEvent,
+    WebhookEventData, WebhookEventType, WebhookRequest, WebhookReviewData,
+};
+use crate::api::oauth2::config::OAuthConfig;
+use crate::config::Config;
+use crate::constants::{
+    DEFAULT_WEBHOOK_DELIVERIES_PAGE_SIZE, MAX_WEBHOOKS_PER_SOURCE, MAX_WEBHOOK_DELIVERIES_PAGE_SIZE,
+    MAX_WEBHOOK_DELIVERY_ATTEMPTS, MAX_WEBHOOK_ERROR_LENGTH, WEBHOOK_DELIVERY_BATCH_SIZE, WEBHOOK_DELIVERY_HEADER,
+    WEBHOOK_DELIVERY_LEASE_SECONDS, WEBHOOK_DELIVERY_RETENTION_DAYS, WEBHOOK_DELIVERY_TIMEOUT_SECONDS,
+    WEBHOOK_EVENT_HEADER, WEBHOOK_RETRY_BASE_DELAY_SECONDS, WEBHOOK_RETRY_MAX_DELAY_SECONDS, WEBHOOK_SIGNATURE_HEADER,
+};
+use crate::db::{Connection, Pool};
+use crate::db::models::app_review::AppReviewSignature;
+use crate::db::models::webhook::{WebhookDelivery, WebhookSubscription};
+use crate::errors::ServiceError;
+use crate::util::network::{check_public_host, public_resolver};
+
+
+/// Subscribe a webhook to events of the maintainer's source. The secret of its signatures is only returned now.
+pub fn subscribe(request: &WebhookRequest, maintainer_id: &uuid::Uuid, pool: &Pool, config: &Config) -> Result<Webhook, ServiceError> {
+    let url = parse_webhook_url(&request.url, config)?;
+    // Deliveries are checked again when they're sent, the host could resolve to another address by then
+    if !config.allow_private_network_addresses {
+        check_public_host(&url).map_err(|reason| ServiceError::BadRequest { error_message: format!("Invalid webhook URL: {}", reason) })?;
+    }
+    if request.events.is_empty() || request.events.iter().any(|event_type| !event_type.is_source_event()) {
+        return Err(ServiceError::BadRequest {
+            error_message: "Webhooks of sources can subscribe to review.published and review.deleted.".to_string(),
+        });
+    }
+
+    let conn = &mut pool.get().unwrap();
+    if WebhookSubscription::find_all_by_source_id(&request.source_identifier, conn)?.len() >= MAX_WEBHOOKS_PER_SOURCE {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("A source can have at most {} webhooks.", MAX_WEBHOOKS_PER_SOURCE),
+        });
+    }
+
+    let now = Utc::now().naive_utc();
+    let subscription = WebhookSubscription {
+        id: uuid::Uuid::new_v4().to_string(),
+        source_id: Some(request.source_identifier.clone()),
+        client_id: None,
+        created_by: Some(maintainer_id.to_string()),
+        url: url.to_string(),
+        secret: new_secret(),
+        events: events(&request.events),
+        created_at: now,
+        updated_at: now,
+    };
+    subscription.insert(conn)?;
+
+    info!("Maintainer {} subscribed {} to events of source {}", maintainer_id, subscription.url, request.source_identifier);
+    Ok(Webhook::new(subscription, true))
+}
+
+/// Webhooks of the source, oldest first
+pub fn webhooks(source_id: &str, pool: &Pool) -> Result<Vec<Webhook>, ServiceError> {
+    let subscriptions = WebhookSubscription::find_all_by_source_id(source_id, &mut pool.get().unwrap())?;
+    Ok(subscriptions.into_iter().map(|subscription| Webhook::new(subscription, false)).collect())
+}
+
+/// Find a webhook of a source, whose maintainers can manage it
+pub fn find_source_webhook(id: &str, pool: &Pool) -> Result<WebhookSubscription, ServiceError> {
+    WebhookSubscription::find_by_id(id, &mut pool.get().unwrap())?
+        .filter(|subscription| subscription.source_id.is_some())
+        .ok_or(ServiceError::NotFound { error_message: "Webhook not found.".to_string() })
+}
+
+/// Delete the webhook with its delivery log
+pub fn unsubscribe(subscription: WebhookSubscription, pool: &Pool) -> Result<Webhook, ServiceError> {
+    subscription.delete(&mut pool.get().unwrap())?;
+    info!("Deleted webhook {} of {}", subscription.id, subscription.url);
+    Ok(Webhook::new(subscription, false))
+}
+
+/// Deliveries of the webhook, newest first
+pub fn deliveries(subscription_id: &str, query: &WebhookDeliveriesQuery, pool: &Pool) -> Result<Vec<WebhookDeliveryRecord>, ServiceError> {
+    let limit = query.limit.unwrap_or(DEFAULT_WEBHOOK_DELIVERIES_PAGE_SIZE);
+    if !(1..=MAX_WEBHOOK_DELIVERIES_PAGE_SIZE).contains(&limit) {
+        return Err(ServiceError::BadRequest {
+            error_message: format!("The limit has to be between 1 and {}.", MAX_WEBHOOK_DELIVERIES_PAGE_SIZE),
+        });
+    }
+
+    let deliveries = WebhookDelivery::find_all_by_subscription_id(subscription_id, query.status, limit, &mut pool.get().unwrap())?;
+    Ok(deliveries.into_iter().map(WebhookDeliveryRecord::from).collect())
+}
+
+/// Deliveries of the webhook of the client from the OAuth configuration, newest first
+pub fn client_deliveries(client_id: &str, query: &WebhookDeliveriesQuery, pool: &Pool) -> Result<Vec<WebhookDeliveryRecord>, ServiceError> {
+    let subscription = WebhookSubscription::find_by_client_id(client_id, &mut pool.get().unwrap())?
+        .ok_or(ServiceError::NotFound { error_message: "The client has no webhook.".to_string() })?;
+    deliveries(&subscription.id, query, pool)
+}
+
+/// Save the webhooks of the clients from the OAuth configuration and delete those of clients that don't have one
+/// anymore, with their delivery logs
+pub fn sync_client_webhooks(oauth_config: &OAuthConfig, pool: &Pool, config: &Config) -> Result<(), ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        let now = Utc::now().naive_utc();
+        let mut client_ids = Vec::new();
+        for client in &oauth_config.clients {
+            let Some(webhook) = &client.webhook else { continue };
+            let url = parse_webhook_url(&webhook.url, config)?;
+            WebhookSubscription {
+                id: uuid::Uuid::new_v4().to_string(),
+                source_id: None,
+                client_id: Some(client.client_id.clone()),
+                created_by: None,
+                url: url.to_string(),
+                secret: webhook.secret.clone(),
+                events: events(&webhook.events),
+                created_at: now,
+                updated_at: now,
+            }.upsert_client(conn)?;
+            client_ids.push(client.client_id.as_str());
+        }
+
+        let deleted = WebhookSubscription::delete_clients_except(&client_ids, conn)?;
+        info!("Configured the webhooks of {} clients, deleted {}", client_ids.len(), deleted);
+        Ok(())
+    })
+}
+
+
+/// Queue the event of a review's new signature for the webhooks of its source and of the clients, in the
+/// transaction that signed it. Reviews that aren't published anymore, including held ones, are deleted for
+/// receivers.
+pub fn review_signed(review: &AppReviewSignature, conn: &mut Connection) -> Result<(), ServiceError> {
+    let event_type = match review.status() {
+        AppReviewStatus::Published => WebhookEventType::ReviewPublished,
+        _ => WebhookEventType::ReviewDeleted,
+    };
+    let data = WebhookEventData::Review(WebhookReviewData {
+        source_identifier: review.source_id.clone(),
+        app_bundle_identifier: review.app_bundle_id.clone(),
+        sequence_number: review.sequence_number,
+        status: review.status(),
+        payload: encode(&review_data(review), review.format_version)?,
+        signature: review.signature.clone().unwrap_or_default(),
+        key_id: review.key_id.clone().unwrap_or_default(),
+        format_version: review.format_version,
+    });
+
+    let subscriptions = WebhookSubscription::find_all_for_source(&review.source_id, conn)?;
+    enqueue(event_type, data, &subscriptions, conn)
+}
+
+/// Queue the event of a deleted account for the webhooks of the clients, in the transaction that deleted it.
+/// Accounts can't be deleted yet, the signed reviews, replies and moderation actions refer to them.
+#[allow(dead_code)]
+pub fn account_deleted(user_id: &uuid::Uuid, conn: &mut Connection) -> Result<(), ServiceError> {
+    let data = WebhookEventData::Account(WebhookAccountData { user_id: user_id.to_string() });
+    let subscriptions = WebhookSubscription::find_all_of_clients(conn)?;
+    enqueue(WebhookEventType::AccountDeleted, data, &subscriptions, conn)
+}
+
+fn enqueue(event_type: WebhookEventType, data: WebhookEventData, subscriptions: &[WebhookSubscription], conn: &mut Connection) -> Result<(), ServiceError> {
+    let subscriptions: Vec<&WebhookSubscription> = subscriptions.iter()
+        .filter(|subscription| subscription.subscribes_to(event_type))
+        .collect();
+    if subscriptions.is_empty() {
+        return Ok(());
+    }
+
+    let now = Utc::now().naive_utc();
+    let event = WebhookEvent { id: uuid::Uuid::new_v4().to_string(), event_type, created_at: now.timestamp(), data };
+    let payload = serde_json::to_string(&event)
+        .map_err(|e| ServiceError::InternalServerError { error_message: e.to_string() })?;
+    let deliveries: Vec<WebhookDelivery> = subscriptions.iter()
+        .map(|subscription| WebhookDelivery {
+            id: uuid::Uuid::new_v4().to_string(),
+            subscription_id: subscription.id.clone(),
+            event_id: event.id.clone(),
+            event_type: event_type.as_str().to_string(),
+            payload: payload.clone(),
+            status: WebhookDeliveryStatus::Pending.into(),
+            attempts: 0,
+            next_attempt_at: now,
+            last_attempt_at: None,
+            last_response_status: None,
+            last_error: None,
+            created_at: now,
+            delivered_at: None,
+        })
+        .collect();
+    WebhookDelivery::insert_all(&deliveries, conn)?;
+
+    debug!("Queued {} event {} for {} webhooks", event_type.as_str(), event.id, deliveries.len());
+    Ok(())
+}
+
+
+pub async fn deliver_periodically(pool: Pool, config: Config, interval: Duration) {
+    let mut interval = actix_web::rt::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = deliver_due(&pool, &config).await {
+            error!("Failed to deliver webhook events: {:?}", e);
+        }
+    }
+}
+
+pub async fn purge_deliveries_periodically(pool: Pool, interval: Duration) {
+    let mut interval = actix_web::rt::time::interval(interval);
+    loop {
+        interval.tick().await;
+
+        let pool = pool.clone();
+        let created_before = Utc::now().naive_utc() - chrono::Duration::days(WEBHOOK_DELIVERY_RETENTION_DAYS);
+        match web::block(move || WebhookDelivery::delete_created_before(created_before, &mut pool.get().unwrap())).await {
+            Ok(Ok(0)) => {},
+            Ok(Ok(deleted)) => info!("Deleted {} old webhook deliveries", deleted),
+            Ok(Err(e)) => error!("Failed to delete old webhook deliveries: {:?}", e),
+            Err(e) => error!("Failed to delete old webhook deliveries: {:?}", e),
+        }
+    }
+}
+
+/// Send the deliveries whose next attempt is due, all at once, and record the outcomes. Returns the number
+/// of attempts.
+pub async fn deliver_due(pool: &Pool, config: &Config) -> Result<usize, ServiceError> {
+    let blocking_error = |e: actix_web::error::BlockingError| ServiceError::InternalServerError { error_message: e.to_string() };
+
+    let claim_pool = pool.clone();
+    let due = web::block(move || claim_due(&claim_pool)).await.map_err(blocking_error)??;
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let mut agent = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(WEBHOOK_DELIVERY_TIMEOUT_SECONDS))
+        .https_only(!config.webhook_allow_http)
+        .redirects(0);
+    if !config.allow_private_network_addresses {
+        agent = agent.resolver(public_resolver);
+    }
+    let agent = agent.build();
+    let attempts = due.into_iter().map(|(delivery, subscription)| {
+        let agent = agent.clone();
+        web::block(move || {
+            let outcome = send(&agent, &delivery, &subscription);
+            (delivery, outcome)
+        })
+    });
+    let outcomes = futures::future::join_all(attempts).await;
+
+    let pool = pool.clone();
+    web::block(move || {
+        let conn = &mut pool.get().unwrap();
+        let now = Utc::now().naive_utc();
+        let mut recorded = 0;
+        for (mut delivery, outcome) in outcomes.into_iter().flatten() {
+            record_attempt(&mut delivery, outcome, now);
+            delivery.update(conn)?;
+            recorded += 1;
+        }
+        Ok(recorded)
+    }).await.map_err(blocking_error)?
+}
+
+/// Take the deliveries that are due with their webhooks and postpone them while they are sent, so other
+/// instances of the service don't send them too
+fn claim_due(pool: &Pool) -> Result<Vec<(WebhookDelivery, WebhookSubscription)>, ServiceError> {
+    let conn = &mut pool.get().unwrap();
+    conn.transaction(|conn| {
+        let now = Utc::now().naive_utc();
+        let due = WebhookDelivery::find_due_for_update(now, WEBHOOK_DELIVERY_BATCH_SIZE, conn)?;
+        if due.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<&str> = due.iter().map(|delivery| delivery.id.as_str()).collect();
+        WebhookDelivery::postpone(&ids, now + chrono::Duration::seconds(WEBHOOK_DELIVERY_LEASE_SECONDS), conn)?;
+        let subscription_ids: Vec<&str> = due.iter().map(|delivery| delivery.subscription_id.as_str()).collect();
+        let subscriptions: HashMap<String, WebhookSubscription> = WebhookSubscription::find_all_by_ids(&subscription_ids, conn)?
+            .into_iter()
+            .map(|subscription| (subscription.id.clone(), subscription))
+            .collect();
+
+        Ok(due.into_iter()
+            .filter_map(|delivery| {
+                let subscription = subscriptions.get(&delivery.subscription_id)?.clone();
+                Some((delivery, subscription))
+            })
+            .collect())
+    })
+}
+
+/// Outcome of an attempt, the HTTP status of a successful response or the status and the reason of a failure
+type Attempt = Result<u16, (Option<u16>, String)>;
+
+fn send(agent: &ureq::Agent, delivery: &WebhookDelivery, subscription: &WebhookSubscription) -> Attempt {
+    let timestamp = Utc::now().timestamp();
+    let signature = signature(&subscription.secret, timestamp, &delivery.payload);
+    let response = agent.post(&subscription.url)
+        .set("Content-Type", "application/json")
+        .set(WEBHOOK_EVENT_HEADER, &delivery.event_type)
+        .set(WEBHOOK_DELIVERY_HEADER, &delivery.id)
+        .set(WEBHOOK_SIGNATURE_HEADER, &format!("t={},v1={}", timestamp, signature))
+        .send_string(&delivery.payload);
+
+    match response {
+        Ok(response) if (200..300).contains(&response.status()) => Ok(response.status()),
+        // Redirects aren't followed
+        Ok(response) => Err((Some(response.status()), format!("Responded with status {}", response.status()))),
+        Err(ureq::Error::Status(status, _)) => Err((Some(status), format!("Responded with status {}", status))),
+        Err(ureq::Error::Transport(e)) => {
+            debug!("Failed to deliver {} to {}: {}", delivery.id, subscription.url, e);
+            Err((None, e.to_string()))
+        },
+    }
+}
+
+/// Record the outcome of an attempt, and schedule the next attempt of a failed delivery or dead-letter it
+fn record_attempt(delivery: &mut WebhookDelivery, outcome: Attempt, now: NaiveDateTime) {
+    delivery.attempts += 1;
+    delivery.last_attempt_at = Some(now);
+    match outcome {
+        Ok(status) => {
+            delivery.status = WebhookDeliveryStatus::Delivered.into();
+            delivery.last_response_status = Some(status.into());
+            delivery.last_error = None;
+            delivery.delivered_at = Some(now);
+        },
+        Err((status, error)) => {
+            delivery.last_response_status = status.map(i32::from);
+            delivery.last_error = Some(error.chars().take(MAX_WEBHOOK_ERROR_LENGTH).collect());
+            if delivery.attempts >= MAX_WEBHOOK_DELIVERY_ATTEMPTS {
+                warn!("Giving up delivery {} of event {} after {} attempts", delivery.id, delivery.event_id, delivery.attempts);
+                delivery.status = WebhookDeliveryStatus::Dead.into();
+            } else {
+                delivery.next_attempt_at = now + retry_delay(delivery.attempts);
+            }
+        },
+    }
+}
+
+/// Delay after the given number of failed attempts
+fn retry_delay(attempts: i32) -> chrono::Duration {
+    let delay = WEBHOOK_RETRY_BASE_DELAY_SECONDS.saturating_mul(1 << (attempts - 1).clamp(0, 30));
+    chrono::Duration::seconds(delay.min(WEBHOOK_RETRY_MAX_DELAY_SECONDS))
+}
+
+/// Hex encoded HMAC-SHA256 of the time of the attempt, a dot and the body
+pub fn signature(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_webhook_url(url: &str, config: &Config) -> Result<Url, ServiceError> {
+    let invalid = |reason: &str| ServiceError::BadRequest { error_message: format!("Invalid webhook URL: {}", reason) };
+
+    let url = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    match url.scheme() {
+        "https" => {},
+        "http" if config.webhook_allow_http => {},
+        _ => return Err(invalid("webhooks have to use HTTPS")),
+    }
+    if url.host().is_none() {
+        return Err(invalid("the URL has no host"));
+    }
+    if url.as_str().len() > 2048 {
+        return Err(invalid("the URL is longer than 2048 characters"));
+    }
+    Ok(url)
+}
+
+fn events(event_types: &[WebhookEventType]) -> String {
+    let mut events: Vec<&str> = event_types.iter().map(WebhookEventType::as_str).collect();
+    events.sort();
+    events.dedup();
+    events.join(" ")
+}
+
+fn new_secret() -> String {
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    format!("whsec_{}", base64_url_engine.encode(secret))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::thread;
+
+    use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+    use ed25519_dalek::SigningKey;
+
+    use crate::api::models::app_reviews::AppReviewDeletionRequest;
+    use crate::db::schema::webhook_deliveries;
+    use crate::db::test_utils::{create_user, test_pool};
+    use crate::services::app_review_service::{self, tests::review_request};
+    use crate::services::review_risk_service::RiskPipeline;
+    use crate::util::review_signing::ReviewKeyRing;
+    use super::*;
+
+    /// A received delivery, with the headers in lowercase
+    struct Request {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// HTTP server on a local port that records the requests it receives and responds with a settable status
+    struct MockReceiver {
+        url: String,
+        status: Arc<AtomicU16>,
+        requests: Arc<Mutex<Vec<Request>>>,
+    }
+
+    impl MockReceiver {
+        fn start() -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let receiver = MockReceiver {
+                url: format!("http://{}/webhook", listener.local_addr().unwrap()),
+                status: Arc::new(AtomicU16::new(200)),
+                requests: Arc::default(),
+            };
+
+            let (status, requests) = (receiver.status.clone(), receiver.requests.clone());
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(mut stream) = stream else { continue };
+                    let mut reader = BufReader::new(&mut stream);
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).is_err() {
+                        continue;
+                    }
+
+                    let mut headers = HashMap::new();
+                    line.clear();
+                    while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                        if let Some((name, value)) = line.split_once(':') {
+                            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+                        }
+                        line.clear();
+                    }
+                    let length = headers.get("content-length").and_then(|length| length.parse().ok()).unwrap_or(0);
+                    let mut body = vec![0; length];
+                    if reader.read_exact(&mut body).is_err() {
+                        continue;
+                    }
+                    requests.lock().unwrap().push(Request { headers, body: String::from_utf8_lossy(&body).to_string() });
+
+                    let response = format!("HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status.load(Ordering::SeqCst));
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            });
+            receiver
+        }
+
+        fn take_requests(&self) -> Vec<Request> {
+            std::mem::take(&mut self.requests.lock().unwrap())
+        }
+    }
+
+    /// Check the HMAC signature of a received delivery and parse its event
+    fn verified_event(request: &Request, secret: &str) -> serde_json::Value {
+        let header = &request.headers[&WEBHOOK_SIGNATURE_HEADER.to_lowercase()];
+        let (timestamp, signature_hex) = header.split_once(',').unwrap();
+        let timestamp: i64 = timestamp.strip_prefix("t=").unwrap().parse().unwrap();
+        assert_eq!(signature_hex.strip_prefix("v1=").unwrap(), signature(secret, timestamp, &request.body));
+        assert_ne!(signature_hex.strip_prefix("v1=").unwrap(), signature("another secret", timestamp, &request.body));
+        serde_json::from_str(&request.body).unwrap()
+    }
+
+    /// Make the pending deliveries of the webhook due now
+    fn make_due(subscription_id: &str, pool: &Pool) {
+        diesel::update(webhook_deliveries::table.filter(webhook_deliveries::subscription_id.eq(subscription_id)))
+            .set(webhook_deliveries::next_attempt_at.eq(Utc::now().naive_utc()))
+            .execute(&mut pool.get().unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_retry_delays() {
+        assert_eq!(retry_delay(1), chrono::Duration::seconds(30));
+        assert_eq!(retry_delay(2), chrono::Duration::seconds(60));
+        assert_eq!(retry_delay(5), chrono::Duration::seconds(480));
+        assert_eq!(retry_delay(20), chrono::Duration::seconds(WEBHOOK_RETRY_MAX_DELAY_SECONDS));
+        assert_eq!(retry_delay(i32::MAX), chrono::Duration::seconds(WEBHOOK_RETRY_MAX_DELAY_SECONDS));
+    }
+
+    /// Deliveries of all webhooks are sent at once, so the webhooks of sources and clients are tested together
+    #[actix_web::test]
+    #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+    async fn test_webhooks() {
+        let pool = test_pool();
+        let review_keys = ReviewKeyRing::with_signing_key(SigningKey::generate(&mut OsRng));
+        let config = Config::for_tests();
+        let (author_id, maintainer_id) = (create_user(&pool), create_user(&pool));
+        let source_identifier = uuid::Uuid::new_v4().to_string();
+        let receiver = MockReceiver::start();
+        let request = |url: &str, events: Vec<WebhookEventType>| WebhookRequest {
+            source_identifier: source_identifier.clone(),
+            url: url.to_string(),
+            events,
+        };
+
+        assert!(subscribe(&request(&receiver.url, vec![]), &maintainer_id, &pool, &config).is_err());
+        assert!(subscribe(&request(&receiver.url, vec![WebhookEventType::AccountDeleted]), &maintainer_id, &pool, &config).is_err());
+        assert!(subscribe(&request("ftp://example.com/webhook", vec![WebhookEventType::ReviewPublished]), &maintainer_id, &pool, &config).is_err());
+        // Webhooks can't be sent to the service's own network
+        let public_only = Config { allow_private_network_addresses: false, ..config.clone() };
+        for url in [receiver.url.as_str(), "http://localhost/webhook", "http://10.0.0.1/webhook", "http://169.254.169.254/latest"] {
+            let subscribed = subscribe(&request(url, vec![WebhookEventType::ReviewPublished]), &maintainer_id, &pool, &public_only);
+            assert!(matches!(subscribed, Err(ServiceError::BadRequest { .. })), "{}", url);
+        }
+        let webhook = subscribe(&request(&receiver.url, vec![WebhookEventType::ReviewPublished, WebhookEventType::ReviewDeleted]), &maintainer_id, &pool, &config).unwrap();
+        let secret = webhook.secret.clone().unwrap();
+        assert_eq!(webhooks(&source_identifier, &pool).unwrap()[0].secret, None);
+
+        // Published reviews are delivered with their signature
+        let signed = app_review_service::sign(&review_request(&source_identifier), &author_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        deliver_due(&pool, &config).await.unwrap();
+        let requests = receiver.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers[&WEBHOOK_EVENT_HEADER.to_lowercase()], "review.published");
+        let event = verified_event(&requests[0], &secret);
+        assert_eq!(event["type"], "review.published");
+        assert_eq!(event["data"]["sequence_number"], 1);
+        assert_eq!(event["data"]["signature"], signed.signature.as_str());
+
+        let query = |status: Option<WebhookDeliveryStatus>| WebhookDeliveriesQuery { status, limit: None };
+        let log = deliveries(&webhook.id, &query(None), &pool).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].status, log[0].attempts, log[0].last_response_status), (WebhookDeliveryStatus::Delivered, 1, Some(200)));
+        assert_eq!(requests[0].headers[&WEBHOOK_DELIVERY_HEADER.to_lowercase()], log[0].id);
+
+        // Nor delivered there, whatever the host resolves to when they're sent
+        app_review_service::sign(&review_request(&source_identifier), &author_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        deliver_due(&pool, &public_only).await.unwrap();
+        assert!(receiver.take_requests().is_empty());
+        let refused = &deliveries(&webhook.id, &query(Some(WebhookDeliveryStatus::Pending)), &pool).unwrap()[0];
+        assert_eq!((refused.attempts, refused.last_response_status), (1, None));
+        assert!(refused.last_error.as_deref().unwrap().contains("isn't a public address"), "{:?}", refused.last_error);
+        make_due(&webhook.id, &pool);
+        deliver_due(&pool, &config).await.unwrap();
+        assert_eq!(receiver.take_requests().len(), 1);
+
+        // Failed deliveries are retried later, with the same event, until they run out of attempts
+        receiver.status.store(500, Ordering::SeqCst);
+        let deletion = AppReviewDeletionRequest {
+            source_identifier: source_identifier.clone(),
+            app_bundle_id: "com.example.App".to_string(),
+            version_number: None,
+        };
+        app_review_service::delete(&deletion, &author_id, &pool, &config, &review_keys).unwrap();
+        deliver_due(&pool, &config).await.unwrap();
+        let pending = &deliveries(&webhook.id, &query(Some(WebhookDeliveryStatus::Pending)), &pool).unwrap()[0];
+        assert_eq!((pending.event_type.as_str(), pending.attempts, pending.last_response_status), ("review.deleted", 1, Some(500)));
+        assert!(pending.next_attempt_at.unwrap() >= Utc::now().timestamp() + WEBHOOK_RETRY_BASE_DELAY_SECONDS - 5);
+        // Not due yet
+        deliver_due(&pool, &config).await.unwrap();
+        assert_eq!(receiver.take_requests().len(), 1);
+
+        for _ in 1..MAX_WEBHOOK_DELIVERY_ATTEMPTS {
+            make_due(&webhook.id, &pool);
+            deliver_due(&pool, &config).await.unwrap();
+        }
+        let requests = receiver.take_requests();
+        assert_eq!(requests.len() as i32, MAX_WEBHOOK_DELIVERY_ATTEMPTS - 1);
+        assert!(requests.iter().all(|request| verified_event(request, &secret)["id"] == verified_event(&requests[0], &secret)["id"]));
+        let dead = deliveries(&webhook.id, &query(Some(WebhookDeliveryStatus::Dead)), &pool).unwrap();
+        assert_eq!((dead.len(), dead[0].attempts, dead[0].next_attempt_at), (1, MAX_WEBHOOK_DELIVERY_ATTEMPTS, None));
+        make_due(&webhook.id, &pool);
+        deliver_due(&pool, &config).await.unwrap();
+        assert!(receiver.take_requests().is_empty());
+
+        // Unsubscribed webhooks get no more events
+        unsubscribe(find_source_webhook(&webhook.id, &pool).unwrap(), &pool).unwrap();
+        app_review_service::sign(&review_request(&source_identifier), &author_id, None, &pool, &config, &review_keys, &RiskPipeline::default()).unwrap();
+        deliver_due(&pool, &config).await.unwrap();
+        assert!(receiver.take_requests().is_empty());
+        assert!(deliveries(&webhook.id, &query(None), &pool).unwrap().is_empty());
+
+        // Clients only get account events here, the other tests publish reviews
+        receiver.status.store(200, Ordering::SeqCst);
+        let oauth_config: OAuthConfig = toml::from_str(&format!(r#"
+            [[clients]]
+            client_id = "webhook-test-client"
+            client_secret = "secret"
+            scope = "profile"
+            redirect_uri = "https://client.example/callback"
+
+            [clients.webhook]
+            url = "{}"
+            secret = "webhook-secret"
+            events = ["account.deleted"]
+        "#, receiver.url)).unwrap();
+        sync_client_webhooks(&oauth_config, &pool, &config).unwrap();
+        // Syncing again keeps the webhook and its log
+        sync_client_webhooks(&oauth_config, &pool, &config).unwrap();
+
+        let user_id = uuid::Uuid::new_v4();
+        pool.get().unwrap().transaction(|conn| account_deleted(&user_id, conn)).unwrap();
+        deliver_due(&pool, &config).await.unwrap();
+        let requests = receiver.take_requests();
+        assert_eq!(requests.len(), 1);
+        let event = verified_event(&requests[0], "webhook-secret");
+        assert_eq!(event["type"], "account.deleted");
+        assert_eq!(event["data"]["user_id"], user_id.to_string());
+
+        let log = client_deliveries("webhook-test-client", &query(None), &pool).unwrap();
+        assert_eq!((log[0].event_id.as_str(), log[0].status), (event["id"].as_str().unwrap(), WebhookDeliveryStatus::Delivered));
+
+        sync_client_webhooks(&OAuthConfig::default(), &pool, &config).unwrap();
+        assert!(client_deliveries("webhook-test-client", &query(None), &pool).is_err());
+    }
+}
@@ -1,21 +1,92 @@
 
 pub mod review_signing {
-    use base64::{Engine as _, engine::general_purpose::STANDARD as base64_engine};
-    use ed25519_dalek::{Signer, SigningKey};
+    use std::error::Error;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use diesel::Connection as _;
+    use ed25519_dalek::{SigningKey, VerifyingKey};
     use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
-    use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey, DecodePrivateKey};
-    use log::{info, debug};
-    use serde_json;
+    use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey, DecodePrivateKey, DecodePublicKey};
+    use log::info;
+    use rand::rngs::OsRng;
+    use sidestore_id_core::signature::{self, key_id};
+
+    use crate::{config::Config, constants};
+    use crate::db::Connection;
+    use crate::db::models::review_signing_key::ReviewSigningKey;
+
+    /// A public review signing key with the time it was used to sign new reviews
+    #[derive(Debug, Clone)]
+    pub struct ReviewKey {
+        pub key_id: String,
+        pub verifying_key: VerifyingKey,
+        pub public_key_pem: String,
+        pub valid_from: NaiveDateTime,
+        pub valid_until: Option<NaiveDateTime>,
+    }
+
+    /// All keys that ever signed reviews, and the private part of the active one
+    #[derive(Clone)]
+    pub struct ReviewKeyRing {
+        key_id: String,
+        signing_key: SigningKey,
+        keys: Vec<ReviewKey>,
+    }
+
+    impl ReviewKeyRing {
+        /// Identifier of the active key
+        pub fn key_id(&self) -> &str {
+            &self.key_id
+        }
 
-    use crate::{config::Config, constants, errors::ServiceError, api::models::app_reviews::AppReviewSignatureData};
+        pub fn keys(&self) -> &[ReviewKey] {
+            &self.keys
+        }
+
+        /// Find the key that made a signature. Signatures without a key id were made with the first key.
+        pub fn verifying_key(&self, key_id: Option<&str>) -> Option<&VerifyingKey> {
+            match key_id {
+                Some(key_id) => self.keys.iter().find(|key| key.key_id == key_id),
+                None => self.keys.first(),
+            }.map(|key| &key.verifying_key)
+        }
+
+        /// Sign a payload with the active key
+        pub fn sign(&self, payload: &str) -> String {
+            signature::sign(payload, &self.signing_key)
+        }
+
+        /// Check a base64 encoded signature of a payload with the key that made it
+        pub fn verify(&self, payload: &str, signature: &str, key_id: Option<&str>) -> bool {
+            self.verifying_key(key_id)
+                .is_some_and(|verifying_key| signature::verify(payload, signature, verifying_key))
+        }
+
+        #[cfg(test)]
+        pub fn with_signing_key(signing_key: SigningKey) -> Self {
+            let verifying_key = signing_key.verifying_key();
+            let key_id = key_id(&verifying_key);
+            ReviewKeyRing {
+                key_id: key_id.clone(),
+                keys: vec![ReviewKey {
+                    key_id,
+                    verifying_key,
+                    public_key_pem: verifying_key.to_public_key_pem(LineEnding::LF).unwrap(),
+                    valid_from: Utc::now().naive_utc(),
+                    valid_until: None,
+                }],
+                signing_key,
+            }
+        }
+    }
 
     pub fn create_or_load_review_signing_key(config: &Config) -> Result<SigningKey, String> {
         let storage_path = std::path::Path::new(&config.storage_path);
         let private_key_path = storage_path.join(constants::REVIEWS_SIGNING_PRIVATE_KEY_NAME);
         let public_key_path = storage_path.join(constants::REVIEWS_SIGNING_PUBLIC_KEY_NAME);
     
-        use rand::rngs::OsRng;
-    
         let signing_key: SigningKey;
         if !private_key_path.exists() || !public_key_path.exists() {
             info!("Generating new review signing keypair...");
@@ -63,31 +134,184 @@ pub mod review_signing {
     }
     
 
-    pub fn sign_review(review_data: &AppReviewSignatureData, signing_key: &SigningKey) -> Result<String, ServiceError> {
-        let review_data_object = match serde_json::to_value(review_data) {
-            Ok(object) => object.as_object().unwrap().clone(),
-            Err(e) => {
-                debug!("Failed to create BTreeMap from review data: {}", e);
-                return Err(ServiceError::InternalServerError { error_message: "Failed to serialize review data".to_string() })
+    fn private_key_path(config: &Config, key_id: &str) -> PathBuf {
+        std::path::Path::new(&config.storage_path)
+            .join(constants::REVIEW_KEYS_DIRECTORY_NAME)
+            .join(format!("{}.pem", key_id))
+    }
+
+    /// Load all review signing keys. The first key ring is made from the single key pair that was used
+    /// before key rotation existed, which is generated if there is none yet. The newest key that isn't retired
+    /// signs, a key added by a rotation retires the previously active key when it's loaded for the first time.
+    pub fn load_review_key_ring(config: &Config, conn: &mut Connection) -> Result<ReviewKeyRing, String> {
+        let keys = conn.transaction::<_, Box<dyn Error>, _>(|conn| {
+            let mut keys = ReviewSigningKey::find_all(conn)?;
+            if keys.is_empty() {
+                keys.push(import_first_key(config, conn)?);
             }
-        };
-        let review_data_json = match serde_json::to_string(&review_data_object) {
-            Ok(json) => json,
-            Err(e) => {
-                debug!("Error serializing review data: {}", e);
-                return Err(ServiceError::InternalServerError { error_message: "Failed to serialize review data".to_string() })
+
+            let Some(active_index) = keys.iter().rposition(|key| key.valid_until.is_none()) else {
+                return Err("There is no active review signing key".into());
+            };
+            let now = Utc::now().naive_utc();
+            let (previous_keys, active_key) = keys.split_at_mut(active_index);
+            let mut previous_active_keys = previous_keys.iter_mut().filter(|key| key.valid_until.is_none()).peekable();
+            if previous_active_keys.peek().is_some() {
+                active_key[0].activate(now, conn)?;
+            }
+            for key in previous_active_keys {
+                key.retire(now, conn)?;
             }
+
+            Ok(keys)
+        }).map_err(|e| format!("Failed to load review signing keys: {}", e))?;
+
+        let active_key = keys.iter().rev()
+            .find(|key| key.valid_until.is_none())
+            .ok_or("There is no active review signing key")?;
+        let private_key_pem = fs::read_to_string(private_key_path(config, &active_key.key_id))
+            .map_err(|e| format!("Failed to read private key of review signing key {}: {}", active_key.key_id, e))?;
+        let signing_key = SigningKey::from_pkcs8_pem(&private_key_pem)
+            .map_err(|e| format!("Failed to decode private key: {}", e))?;
+        if key_id(&signing_key.verifying_key()) != active_key.key_id {
+            return Err(format!("The private key of review signing key {} doesn't match its public key", active_key.key_id));
+        }
+
+        let keys = keys.iter()
+            .map(|key| Ok(ReviewKey {
+                key_id: key.key_id.clone(),
+                verifying_key: VerifyingKey::from_public_key_pem(&key.public_key)
+                    .map_err(|e| format!("Failed to decode public key {}: {}", key.key_id, e))?,
+                public_key_pem: key.public_key.clone(),
+                valid_from: key.valid_from,
+                valid_until: key.valid_until,
+            }))
+            .collect::<Result<Vec<ReviewKey>, String>>()?;
+
+        info!("Loaded {} review signing keys, active key is {}", keys.len(), active_key.key_id);
+        Ok(ReviewKeyRing { key_id: active_key.key_id.clone(), signing_key, keys })
+    }
+
+    /// Generate a new review signing key. Running servers keep signing with the active key until they are
+    /// restarted, which then retires it and keeps verifying the reviews it signed. Returns the id of the new key.
+    pub fn rotate_review_signing_key(config: &Config, conn: &mut Connection) -> Result<String, String> {
+        conn.transaction::<_, Box<dyn Error>, _>(|conn| {
+            if ReviewSigningKey::find_all(conn)?.is_empty() {
+                import_first_key(config, conn)?;
+            }
+
+            let key = add_key(config, &SigningKey::generate(&mut OsRng), Utc::now().naive_utc(), conn)?;
+
+            // Keep serving the active key where the single key was published before rotation existed
+            let public_key_path = std::path::Path::new(&config.storage_path).join(constants::REVIEWS_SIGNING_PUBLIC_KEY_NAME);
+            fs::write(public_key_path, &key.public_key)?;
+
+            Ok(key.key_id)
+        }).map_err(|e| e.to_string())
+    }
+
+    fn import_first_key(config: &Config, conn: &mut Connection) -> Result<ReviewSigningKey, Box<dyn Error>> {
+        let signing_key = create_or_load_review_signing_key(config)?;
+        let private_key_path = std::path::Path::new(&config.storage_path).join(constants::REVIEWS_SIGNING_PRIVATE_KEY_NAME);
+        let valid_from = fs::metadata(private_key_path)
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| DateTime::<Utc>::from(modified).naive_utc())
+            .unwrap_or_else(|_| Utc::now().naive_utc());
+
+        add_key(config, &signing_key, valid_from, conn)
+    }
+
+    fn add_key(config: &Config, signing_key: &SigningKey, valid_from: NaiveDateTime, conn: &mut Connection) -> Result<ReviewSigningKey, Box<dyn Error>> {
+        let key = ReviewSigningKey {
+            key_id: key_id(&signing_key.verifying_key()),
+            public_key: signing_key.verifying_key().to_public_key_pem(LineEnding::LF)?,
+            valid_from,
+            valid_until: None,
         };
-        debug!("Review data: {}", review_data_json);
-        let signature = signing_key.sign(review_data_json.as_bytes());
-        Ok(base64_engine.encode(&signature.to_bytes()))
+
+        let private_key_path = private_key_path(config, &key.key_id);
+        if let Some(directory) = private_key_path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(&private_key_path, signing_key.to_pkcs8_pem(LineEnding::LF)?.as_bytes())?;
+        key.insert(conn)?;
+
+        info!("Added review signing key {}", key.key_id);
+        Ok(key)
     }
 
     #[cfg(test)]
     mod tests {
-        use crate::api::models::app_reviews::AppReviewStatus;
+        use crate::api::models::app_reviews::{AppReviewSignatureData, AppReviewStatus};
+        use sidestore_id_core::payload::{encode, LEGACY_FORMAT_VERSION};
         use super::*;
 
+        #[test]
+        #[ignore = "needs a database, set TEST_DATABASE_URL and run with --ignored"]
+        fn test_review_key_rotation() {
+            use diesel::RunQueryDsl;
+            use crate::db::schema::review_signing_keys;
+
+            let mut conn = crate::db::test_utils::test_connection();
+            diesel::delete(review_signing_keys::table).execute(&mut conn).unwrap();
+
+            let storage_path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+            fs::create_dir_all(&storage_path).unwrap();
+            let config = Config { storage_path: storage_path.to_string_lossy().to_string(), ..Config::for_tests() };
+
+            // The first key is the key pair that was used before rotation existed
+            let first_key = create_or_load_review_signing_key(&config).unwrap();
+            let ring = load_review_key_ring(&config, &mut conn).unwrap();
+            assert_eq!(ring.key_id(), key_id(&first_key.verifying_key()));
+            assert_eq!(ring.keys().len(), 1);
+            let payload = "signed before rotation";
+            let old_signature = ring.sign(payload);
+
+            // The active key isn't retired before a server starts signing with the new one
+            let new_key_id = rotate_review_signing_key(&config, &mut conn).unwrap();
+            assert!(ReviewSigningKey::find_all(&mut conn).unwrap().iter().all(|key| key.valid_until.is_none()));
+            let ring = load_review_key_ring(&config, &mut conn).unwrap();
+            assert_eq!(ring.key_id(), new_key_id);
+            assert_eq!(ring.keys().len(), 2);
+            assert!(ring.keys()[0].valid_until.is_some());
+            assert!(ring.keys()[1].valid_until.is_none());
+            assert_eq!(ring.keys()[0].valid_until, Some(ring.keys()[1].valid_from));
+            let retired_at = ReviewSigningKey::find_all(&mut conn).unwrap()[0].valid_until;
+            assert_eq!(load_review_key_ring(&config, &mut conn).unwrap().keys()[0].valid_until, retired_at);
+            assert_eq!(
+                fs::read_to_string(storage_path.join(constants::REVIEWS_SIGNING_PUBLIC_KEY_NAME)).unwrap(),
+                ring.keys()[1].public_key_pem
+            );
+
+            // Old signatures still verify with the retired key
+            let old_key_id = key_id(&first_key.verifying_key());
+            assert!(ring.verify(payload, &old_signature, Some(&old_key_id)));
+            assert!(ring.verify(payload, &old_signature, None));
+            assert!(!ring.verify(payload, &old_signature, Some(&new_key_id)));
+            assert!(!ring.verify("tampered", &old_signature, Some(&old_key_id)));
+            assert!(!ring.verify(payload, &old_signature, Some("unknown")));
+
+            fs::remove_dir_all(storage_path).unwrap();
+        }
+
+        /// The published test vectors are verified in `sidestore-id-core`, this checks that the server makes them
+        #[test]
+        fn test_golden_vectors() {
+            use serde_json::Value;
+            use sidestore_id_core::review::SignedReview;
+
+            let vectors: Value = serde_json::from_str(include_str!("../docs/review-signature-vectors.json")).unwrap();
+            let signing_key = create_or_load_review_signing_key(&Config::for_tests()).unwrap();
+            let verifying_key = VerifyingKey::from_public_key_pem(vectors["public_key"].as_str().unwrap()).unwrap();
+            assert_eq!(verifying_key, signing_key.verifying_key());
+
+            for vector in vectors["vectors"].as_array().unwrap() {
+                let review: SignedReview = serde_json::from_value(vector.clone()).unwrap();
+                let signature = signature::sign(&review.payload().unwrap(), &signing_key);
+                assert_eq!(signature, review.signature, "{}", vector["description"]);
+            }
+        }
+
         #[test]
         fn test_create_or_loead_review_signing_key() {
             
@@ -96,21 +320,11 @@ pub mod review_signing {
         #[test]
         fn test_sign_review() {
     
-            let config = Config {
-                host: "localhost".to_string(),
-                port: 8080,
-                jwt_secret: "secret".to_string(),
-                jwt_issuer: "io.sidestore.SideStore-ID".to_string(),
-                jwt_expiration: 3600,
-                jwt_refresh_expiration: 86400,
-                cors_origin: "*".to_string(),
-                database_url: "sqlite://test.db".to_string(),
-                storage_path: "./test-storage".to_string(),
-            };
+            let config = Config::for_tests();
             let signing_key = create_or_load_review_signing_key(&config).unwrap();
             let review_data = AppReviewSignatureData {
                 sidestore_user_id: "uuid-1234-5678-9012-3456".to_string(),
-                status: AppReviewStatus::Published.into(),
+                status: AppReviewStatus::Published,
                 sequence_number: 69,
                 source_identifier: "io.sidestore.Connect".to_string(),
                 app_bundle_identifier: "com.SideStore.SideStore".to_string(),
@@ -118,16 +332,514 @@ pub mod review_signing {
                 review_rating: Some(5),
                 review_title: Some("This is a test review".to_string()),
                 review_body: Some("This is a test review body".to_string()),
+                content_commitment: None,
                 created_at: 1682007600,
-                updated_at: 1682007600
+                updated_at: 1682007600,
+                key_id: None,
             };
             
             let review_data_json = serde_json::to_string(&review_data).unwrap();
             assert_eq!(review_data_json, "{\"sidestore_user_id\":\"uuid-1234-5678-9012-3456\",\"status\":\"published\",\"sequence_number\":69,\"source_identifier\":\"io.sidestore.Connect\",\"app_bundle_identifier\":\"com.SideStore.SideStore\",\"version_number\":\"4.2.0\",\"review_rating\":5,\"review_title\":\"This is a test review\",\"review_body\":\"This is a test review body\",\"created_at\":1682007600,\"updated_at\":1682007600}" );
     
-            let signature = sign_review(&review_data, &signing_key).unwrap();
+            // The signature the server made of this review before format versions were introduced
+            let signature = signature::sign(&encode(&review_data, LEGACY_FORMAT_VERSION).unwrap(), &signing_key);
             println!("Signature: {}", signature);
-            assert_eq!(signature, "W+XULDRHnhOFxWi5NJS0sMr11+9128XqZADIFp3NPmNky6sgIZ5MCR8NPn0Ee64W7KFhozlydendO1LAE8SWDA==".to_string())
+            assert_eq!(signature, "3oJ116GUsEsBkwmnye0vSWoXPLRj6P/c+Nb6NRPJUuOcGBJmm9l8LV66YR8/ZwkR9r7nZmTjFB1F9eWWsDc0Bg==".to_string());
+
+            // Signatures made with a key of the key ring name the key in the signed data
+            let review_data = AppReviewSignatureData { key_id: Some(key_id(&signing_key.verifying_key())), ..review_data };
+            assert!(encode(&review_data, LEGACY_FORMAT_VERSION).unwrap().contains(&format!(",\"key_id\":\"{}\",\"review_body\"", key_id(&signing_key.verifying_key()))));
+        }
+
+        #[test]
+        fn test_verify_signature_before_format_versions() {
+            // Payload and signature as the server made them before format versions were introduced
+            let payload = "{\"app_bundle_identifier\":\"com.SideStore.SideStore\",\"created_at\":1682007600,\"review_body\":\"This is a test review body\",\"review_rating\":5,\"review_title\":\"This is a test review\",\"sequence_number\":69,\"sidestore_user_id\":\"uuid-1234-5678-9012-3456\",\"source_identifier\":\"io.sidestore.Connect\",\"status\":\"published\",\"updated_at\":1682007600,\"version_number\":\"4.2.0\"}";
+            let signature = "3oJ116GUsEsBkwmnye0vSWoXPLRj6P/c+Nb6NRPJUuOcGBJmm9l8LV66YR8/ZwkR9r7nZmTjFB1F9eWWsDc0Bg==";
+            let review_keys = ReviewKeyRing::with_signing_key(create_or_load_review_signing_key(&Config::for_tests()).unwrap());
+            assert!(review_keys.verify(payload, signature, None));
+
+            let review_data: AppReviewSignatureData = serde_json::from_str(payload).unwrap();
+            assert_eq!(encode(&review_data, LEGACY_FORMAT_VERSION).unwrap(), payload);
+        }
+    }
+}
+
+/// Merkle trees as used by Certificate Transparency (RFC 9162, section 2.1)
+pub mod merkle {
+    use std::cell::RefCell;
+    use std::collections::{BTreeSet, HashMap};
+
+    use sha2::{Digest, Sha256};
+
+    pub type Hash = [u8; 32];
+
+    pub fn leaf_hash(data: &[u8]) -> Hash {
+        Sha256::new().chain_update([0x00]).chain_update(data).finalize().into()
+    }
+
+    pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+        Sha256::new().chain_update([0x01]).chain_update(left).chain_update(right).finalize().into()
+    }
+
+    /// Hashes of the complete subtrees of a tree. The subtree at `level` and `index` is the root of the
+    /// `2^level` leaves from `index * 2^level` on, every other node of a tree is made of those.
+    pub trait Subtrees {
+        fn subtree(&self, level: u32, index: u64) -> Hash;
+    }
+
+    impl Subtrees for [Hash] {
+        fn subtree(&self, level: u32, index: u64) -> Hash {
+            match level {
+                0 => self[index as usize],
+                _ => node_hash(&self.subtree(level - 1, 2 * index), &self.subtree(level - 1, 2 * index + 1)),
+            }
+        }
+    }
+
+    /// Subtrees that were loaded for a computation, which must not read any other subtree
+    impl Subtrees for HashMap<(u32, u64), Hash> {
+        fn subtree(&self, level: u32, index: u64) -> Hash {
+            self[&(level, index)]
+        }
+    }
+
+    /// Records the subtrees a computation reads, so they can be loaded at once before computing it
+    #[derive(Default)]
+    pub struct SubtreeRecorder(RefCell<BTreeSet<(u32, u64)>>);
+
+    impl SubtreeRecorder {
+        pub fn into_subtrees(self) -> BTreeSet<(u32, u64)> {
+            self.0.into_inner()
+        }
+    }
+
+    impl Subtrees for SubtreeRecorder {
+        fn subtree(&self, level: u32, index: u64) -> Hash {
+            self.0.borrow_mut().insert((level, index));
+            [0; 32]
+        }
+    }
+
+    /// Complete subtrees that are finished by appending the leaf at `index`, from the bottom up
+    pub fn completed_subtrees(index: u64) -> impl Iterator<Item = (u32, u64)> {
+        (1..=(index + 1).trailing_zeros()).map(move |level| (level, ((index + 1) >> level) - 1))
+    }
+
+    /// Largest power of two smaller than `n`, for `n > 1`
+    fn split(n: u64) -> u64 {
+        1 << (u64::BITS - (n - 1).leading_zeros() - 1)
+    }
+
+    /// Root of the leaves from `start` to `end`. Every range the proofs split a tree into starts at a
+    /// multiple of a power of two that is at least its length, so complete ranges are subtrees.
+    fn range_root(tree: &(impl Subtrees + ?Sized), start: u64, end: u64) -> Hash {
+        let n = end - start;
+        if n == 0 {
+            Sha256::digest([]).into()
+        } else if n.is_power_of_two() {
+            tree.subtree(n.trailing_zeros(), start >> n.trailing_zeros())
+        } else {
+            let k = split(n);
+            node_hash(&range_root(tree, start, start + k), &range_root(tree, start + k, end))
+        }
+    }
+
+    pub fn root(tree: &(impl Subtrees + ?Sized), size: u64) -> Hash {
+        range_root(tree, 0, size)
+    }
+
+    /// Audit path of the leaf at `index` in the tree of the first `size` leaves
+    pub fn inclusion_proof(tree: &(impl Subtrees + ?Sized), index: u64, size: u64) -> Vec<Hash> {
+        range_inclusion_proof(tree, index, 0, size)
+    }
+
+    fn range_inclusion_proof(tree: &(impl Subtrees + ?Sized), index: u64, start: u64, end: u64) -> Vec<Hash> {
+        let n = end - start;
+        if n <= 1 {
+            return vec![];
+        }
+
+        let k = split(n);
+        if index < k {
+            let mut proof = range_inclusion_proof(tree, index, start, start + k);
+            proof.push(range_root(tree, start + k, end));
+            proof
+        } else {
+            let mut proof = range_inclusion_proof(tree, index - k, start + k, end);
+            proof.push(range_root(tree, start, start + k));
+            proof
+        }
+    }
+
+    /// Proof that the tree of the first `first_size` leaves is a prefix of the tree of the first `size` leaves
+    pub fn consistency_proof(tree: &(impl Subtrees + ?Sized), first_size: u64, size: u64) -> Vec<Hash> {
+        if first_size == 0 || first_size >= size {
+            return vec![];
+        }
+        subproof(tree, first_size, 0, size, true)
+    }
+
+    fn subproof(tree: &(impl Subtrees + ?Sized), m: u64, start: u64, end: u64, complete_subtree: bool) -> Vec<Hash> {
+        let n = end - start;
+        if m == n {
+            return if complete_subtree { vec![] } else { vec![range_root(tree, start, end)] };
+        }
+
+        let k = split(n);
+        if m <= k {
+            let mut proof = subproof(tree, m, start, start + k, complete_subtree);
+            proof.push(range_root(tree, start + k, end));
+            proof
+        } else {
+            let mut proof = subproof(tree, m - k, start + k, end, false);
+            proof.push(range_root(tree, start, start + k));
+            proof
+        }
+    }
+
+    /// Verify an audit path as described in RFC 9162, section 2.1.3.2
+    #[cfg(test)]
+    pub fn verify_inclusion(index: u64, tree_size: u64, leaf: &Hash, proof: &[Hash], root: &Hash) -> bool {
+        if index >= tree_size {
+            return false;
+        }
+
+        let (mut f_n, mut s_n) = (index, tree_size - 1);
+        let mut r = *leaf;
+        for p in proof {
+            if s_n == 0 {
+                return false;
+            }
+            if f_n & 1 == 1 || f_n == s_n {
+                r = node_hash(p, &r);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                r = node_hash(&r, p);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        s_n == 0 && r == *root
+    }
+
+    /// Verify a consistency proof as described in RFC 9162, section 2.1.4.2
+    pub fn verify_consistency(first_size: u64, second_size: u64, first_root: &Hash, second_root: &Hash, proof: &[Hash]) -> bool {
+        if first_size > second_size {
+            return false;
+        }
+        if first_size == second_size {
+            return proof.is_empty() && first_root == second_root;
+        }
+        if first_size == 0 {
+            return proof.is_empty();
+        }
+
+        let mut path = proof.to_vec();
+        if first_size.is_power_of_two() {
+            path.insert(0, *first_root);
+        }
+
+        let (mut f_n, mut s_n) = (first_size - 1, second_size - 1);
+        while f_n & 1 == 1 {
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        let Some((first, rest)) = path.split_first() else {
+            return false;
+        };
+        let (mut f_r, mut s_r) = (*first, *first);
+        for c in rest {
+            if s_n == 0 {
+                return false;
+            }
+            if f_n & 1 == 1 || f_n == s_n {
+                f_r = node_hash(c, &f_r);
+                s_r = node_hash(c, &s_r);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                s_r = node_hash(&s_r, c);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        f_r == *first_root && s_r == *second_root && s_n == 0
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn leaves(n: usize) -> Vec<Hash> {
+            (0..n).map(|i| leaf_hash(format!("review {}", i).as_bytes())).collect()
+        }
+
+        #[test]
+        fn test_root() {
+            // Test vectors from the Certificate Transparency reference implementation
+            let data: Vec<&[u8]> = vec![
+                b"", b"\x00", b"\x10", b"\x20\x21", b"\x30\x31", b"\x40\x41\x42\x43",
+                b"\x50\x51\x52\x53\x54\x55\x56\x57",
+                b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+            ];
+            let leaves: Vec<Hash> = data.iter().map(|d| leaf_hash(d)).collect();
+
+            assert_eq!(hex(&root(&leaves[..], 0)), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+            assert_eq!(hex(&root(&leaves[..], 1)), "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d");
+            assert_eq!(hex(&root(&leaves[..], leaves.len() as u64)), "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328");
+        }
+
+        #[test]
+        fn test_inclusion_proofs() {
+            for n in 1..=20 {
+                let leaves = leaves(n);
+                let root = root(&leaves[..], n as u64);
+                for index in 0..n {
+                    let proof = inclusion_proof(&leaves[..], index as u64, n as u64);
+                    assert!(verify_inclusion(index as u64, n as u64, &leaves[index], &proof, &root));
+                    assert!(!verify_inclusion(index as u64, n as u64, &leaf_hash(b"forged"), &proof, &root));
+                }
+            }
+        }
+
+        #[test]
+        fn test_consistency_proofs() {
+            for n in 1..=20 {
+                let leaves = leaves(n);
+                let second_root = root(&leaves[..], n as u64);
+                for m in 1..=n {
+                    let first_root = root(&leaves[..], m as u64);
+                    let proof = consistency_proof(&leaves[..], m as u64, n as u64);
+                    assert!(verify_consistency(m as u64, n as u64, &first_root, &second_root, &proof), "{} -> {}", m, n);
+
+                    if m < n {
+                        let mut rewritten = leaves[..m].to_vec();
+                        rewritten[m - 1] = leaf_hash(b"rewritten");
+                        assert!(!verify_consistency(m as u64, n as u64, &root(&rewritten[..], m as u64), &second_root, &proof));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn test_completed_subtrees() {
+            assert_eq!(completed_subtrees(0).collect::<Vec<_>>(), vec![]);
+            assert_eq!(completed_subtrees(1).collect::<Vec<_>>(), vec![(1, 0)]);
+            assert_eq!(completed_subtrees(6).collect::<Vec<_>>(), vec![]);
+            assert_eq!(completed_subtrees(7).collect::<Vec<_>>(), vec![(1, 3), (2, 1), (3, 0)]);
+        }
+
+        #[test]
+        fn test_recorded_subtrees() {
+            // A proof only reads a logarithmic number of subtrees instead of every leaf
+            let recorder = SubtreeRecorder::default();
+            inclusion_proof(&recorder, 5, 1000);
+            let subtrees = recorder.into_subtrees();
+            assert!(subtrees.len() <= 2 * 10);
+
+            let leaves = leaves(1000);
+            let loaded: HashMap<(u32, u64), Hash> = subtrees.into_iter()
+                .map(|(level, index)| ((level, index), leaves[..].subtree(level, index)))
+                .collect();
+            assert_eq!(inclusion_proof(&loaded, 5, 1000), inclusion_proof(&leaves[..], 5, 1000));
+        }
+
+        fn hex(hash: &Hash) -> String {
+            hash.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+    }
+}
+
+/// Vector clocks count the changes every device made to a value, to tell whether a change was made with knowledge
+/// of another or concurrently
+pub mod vector_clock {
+    use std::cmp::Ordering;
+    use std::collections::BTreeMap;
+
+    /// Number of changes by device id
+    pub type VectorClock = BTreeMap<String, i64>;
+
+    /// `Less` if the change of `a` happened before the change of `b`, `None` if they were concurrent
+    pub fn compare(a: &VectorClock, b: &VectorClock) -> Option<Ordering> {
+        let (mut is_less, mut is_greater) = (false, false);
+        for device in a.keys().chain(b.keys()) {
+            let (a_count, b_count) = (a.get(device).copied().unwrap_or(0), b.get(device).copied().unwrap_or(0));
+            is_less |= a_count < b_count;
+            is_greater |= a_count > b_count;
+        }
+
+        match (is_less, is_greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+
+    /// Clock of a change that saw both changes
+    pub fn merge(a: &VectorClock, b: &VectorClock) -> VectorClock {
+        let mut merged = a.clone();
+        for (device, count) in b {
+            let merged_count = merged.entry(device.clone()).or_insert(0);
+            *merged_count = (*merged_count).max(*count);
+        }
+        merged
+    }
+
+    /// Count a change of the device, `None` if its count can't grow anymore
+    pub fn increment(clock: &mut VectorClock, device: &str) -> Option<i64> {
+        let count = clock.entry(device.to_string()).or_insert(0);
+        *count = count.checked_add(1)?;
+        Some(*count)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn clock(counts: &[(&str, i64)]) -> VectorClock {
+            counts.iter().map(|(device, count)| (device.to_string(), *count)).collect()
+        }
+
+        #[test]
+        fn test_compare() {
+            assert_eq!(compare(&clock(&[]), &clock(&[])), Some(Ordering::Equal));
+            assert_eq!(compare(&clock(&[("a", 1)]), &clock(&[("a", 1), ("b", 0)])), Some(Ordering::Equal));
+            assert_eq!(compare(&clock(&[("a", 1)]), &clock(&[("a", 2)])), Some(Ordering::Less));
+            assert_eq!(compare(&clock(&[("a", 1), ("b", 2)]), &clock(&[("a", 1)])), Some(Ordering::Greater));
+            assert_eq!(compare(&clock(&[]), &clock(&[("b", 1)])), Some(Ordering::Less));
+            assert_eq!(compare(&clock(&[("a", 2)]), &clock(&[("a", 1), ("b", 1)])), None);
+        }
+
+        #[test]
+        fn test_merge() {
+            let mut merged = merge(&clock(&[("a", 2), ("b", 1)]), &clock(&[("b", 3), ("c", 1)]));
+            assert_eq!(merged, clock(&[("a", 2), ("b", 3), ("c", 1)]));
+            assert_eq!(increment(&mut merged, "a"), Some(3));
+            assert_eq!(increment(&mut merged, "d"), Some(1));
+            assert_eq!(merged, clock(&[("a", 3), ("b", 3), ("c", 1), ("d", 1)]));
+        }
+
+        #[test]
+        fn test_increment_overflow() {
+            let mut full = clock(&[("a", i64::MAX)]);
+            assert_eq!(increment(&mut full, "a"), None);
+            assert_eq!(full, clock(&[("a", i64::MAX)]));
+        }
+    }
+}
+
+/// Checks of the hosts the service sends requests to on behalf of users, so they can't make it reach
+/// services on its own network
+pub mod network {
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs};
+
+    use url::Url;
+
+    /// Whether an address is reachable on the public internet, and not loopback, private, link-local,
+    /// shared, multicast or reserved for documentation
+    pub fn is_public_address(ip: &IpAddr) -> bool {
+        match ip {
+            IpAddr::V4(ip) => is_public_ipv4(ip),
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => is_public_ipv4(&ip),
+                None => {
+                    let segments = ip.segments();
+                    !(ip.is_unspecified()
+                        || ip.is_loopback()
+                        || ip.is_multicast()
+                        // Unique local addresses
+                        || segments[0] & 0xfe00 == 0xfc00
+                        // Link-local addresses
+                        || segments[0] & 0xffc0 == 0xfe80
+                        // Documentation addresses
+                        || (segments[0] == 0x2001 && segments[1] == 0x0db8)
+                        // IPv4 addresses translated by NAT64
+                        || (segments[0] == 0x0064 && segments[1] == 0xff9b))
+                },
+            },
+        }
+    }
+
+    fn is_public_ipv4(ip: &Ipv4Addr) -> bool {
+        let octets = ip.octets();
+        !(ip.is_unspecified()
+            || ip.is_loopback()
+            || ip.is_private()
+            || ip.is_link_local()
+            || ip.is_broadcast()
+            || ip.is_multicast()
+            || ip.is_documentation()
+            // "This network"
+            || octets[0] == 0
+            // Shared address space of carrier-grade NATs
+            || (octets[0] == 100 && octets[1] & 0xc0 == 64)
+            // IETF protocol assignments
+            || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+            // Benchmarking
+            || (octets[0] == 198 && octets[1] & 0xfe == 18)
+            // Reserved
+            || octets[0] >= 240)
+    }
+
+    /// Resolve the host of a URL and fail if any of its addresses isn't public. The error is a reason for the user.
+    pub fn check_public_host(url: &Url) -> Result<(), String> {
+        let addresses = url.socket_addrs(|| None)
+            .map_err(|_| format!("{} couldn't be resolved", url.host_str().unwrap_or_default()))?;
+        if addresses.iter().all(|address| is_public_address(&address.ip())) {
+            Ok(())
+        } else {
+            Err(format!("{} isn't a public address", url.host_str().unwrap_or_default()))
+        }
+    }
+
+    /// Resolver for HTTP agents that refuses hosts with addresses that aren't public. A host that's checked before
+    /// could resolve to another address when it's connected to.
+    pub fn public_resolver(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+        let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+        if addresses.iter().all(|address| is_public_address(&address.ip())) {
+            Ok(addresses)
+        } else {
+            Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} isn't a public address", netloc)))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_public_addresses() {
+            for address in ["93.184.215.14", "2606:2800:21f:cb07:6820:80da:af6b:8b2c", "1.1.1.1"] {
+                assert!(is_public_address(&address.parse().unwrap()), "{}", address);
+            }
+            for address in [
+                "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0", "100.64.0.1",
+                "255.255.255.255", "224.0.0.1", "::1", "::", "fe80::1", "fd00::1", "::ffff:127.0.0.1", "::ffff:10.0.0.1",
+                "64:ff9b::a00:1",
+            ] {
+                assert!(!is_public_address(&address.parse().unwrap()), "{}", address);
+            }
+        }
+
+        #[test]
+        fn test_public_hosts() {
+            assert!(check_public_host(&Url::parse("http://127.0.0.1:8080/apps.json").unwrap()).is_err());
+            assert!(check_public_host(&Url::parse("http://[::1]/apps.json").unwrap()).is_err());
+            assert!(check_public_host(&Url::parse("http://localhost/apps.json").unwrap()).is_err());
+            assert!(check_public_host(&Url::parse("https://1.1.1.1/apps.json").unwrap()).is_ok());
+            assert!(public_resolver("169.254.169.254:80").is_err());
+            assert!(public_resolver("1.1.1.1:443").is_ok());
         }
     }
-}
\ No newline at end of file
+}